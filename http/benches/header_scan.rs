@@ -0,0 +1,63 @@
+// 比较逐字节查找和 scan 模块（打开 simd 时是 memchr）查找请求头分隔符的耗时
+// 用法：cargo bench -p http --features simd --bench header_scan
+// 不打开 simd 时两边是同一种实现，可以用来确认差别来自 SIMD
+use http::httprequest::HttpRequest;
+use http::scan;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: usize = 200_000;
+
+// 浏览器的典型请求，Cookie 大小不同
+fn request(cookie_len: usize) -> Vec<u8> {
+    let mut raw = b"GET /api/shipping/orders?page=2 HTTP/1.1\r\n\
+Host: shop.example.com\r\n\
+User-Agent: Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0 Safari/537.36\r\n\
+Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+Accept-Language: en-US,en;q=0.9\r\n\
+Accept-Encoding: gzip, deflate, br\r\n\
+Referer: https://shop.example.com/orders\r\n\
+Connection: keep-alive\r\n"
+        .to_vec();
+    raw.extend_from_slice(b"Cookie: session=");
+    raw.extend((0..cookie_len).map(|i| b'a' + (i % 26) as u8));
+    raw.extend_from_slice(b"\r\n\r\n");
+    raw
+}
+
+// 每轮的平均纳秒数
+fn time(mut f: impl FnMut()) -> f64 {
+    f();
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    started.elapsed().as_nanos() as f64 / ROUNDS as f64
+}
+
+fn main() {
+    println!("scan backend: {}", scan::backend());
+    for cookie_len in [0, 512, 4096] {
+        let raw = request(cookie_len);
+        let scalar = time(|| {
+            black_box(scan::scalar::head_end(black_box(&raw)));
+            black_box(scan::scalar::count(b'\n', black_box(&raw)));
+        });
+        let fast = time(|| {
+            black_box(scan::head_end(black_box(&raw)));
+            black_box(scan::count(b'\n', black_box(&raw)));
+        });
+        let parse = time(|| {
+            black_box(HttpRequest::try_from(black_box(&raw[..])).unwrap());
+        });
+        println!(
+            "{:>5} byte head  scalar {:>7.1} ns  {} {:>7.1} ns  ({:.1}x)  full parse {:>7.1} ns",
+            raw.len(),
+            scalar,
+            scan::backend(),
+            fast,
+            scalar / fast,
+            parse
+        );
+    }
+}
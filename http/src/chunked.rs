@@ -0,0 +1,222 @@
+// chunked 编码：<16进制长度>[;扩展]\r\n<数据>\r\n ... 0\r\n[trailer]\r\n
+// 解码用于请求体：服务器边读边判断 body 是否收全，所以数据不完整时返回 None 而不是错误
+// 编码用于长度未知的响应体，见 ChunkedWriter
+use std::fmt;
+use std::io::{self, Write};
+
+// 长度行和 trailer 行的上限，防止一直发不带换行的字节
+const MAX_LINE: usize = 4096;
+
+#[derive(Debug, PartialEq)]
+pub enum ChunkError {
+    InvalidSize(String),
+    // 数据后面不是 \r\n
+    MissingCrlf,
+    BadTrailer(String),
+    // 解码后的 body 超过上限，携带上限值
+    TooLarge(usize),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::InvalidSize(s) => write!(f, "invalid chunk size {:?}", s),
+            ChunkError::MissingCrlf => write!(f, "chunk data is not followed by CRLF"),
+            ChunkError::BadTrailer(line) => write!(f, "malformed trailer {:?}", line),
+            ChunkError::TooLarge(max) => write!(f, "chunked body exceeds {} bytes", max),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+#[derive(Debug, PartialEq)]
+pub struct Chunked {
+    pub body: Vec<u8>,
+    pub trailers: Vec<(String, String)>,
+    // 编码后占用的字节数，后面的数据不属于这个 body
+    pub consumed: usize,
+}
+
+// 只判断是否完整，不复制数据；完整时返回编码后的长度
+pub fn scan(data: &[u8], max_body_size: usize) -> Result<Option<usize>, ChunkError> {
+    Ok(walk(data, max_body_size, None)?.map(|(_, end)| end))
+}
+
+pub fn decode(data: &[u8], max_body_size: usize) -> Result<Option<Chunked>, ChunkError> {
+    let mut body = Vec::new();
+    let Some((trailer_start, end)) = walk(data, max_body_size, Some(&mut body))? else {
+        return Ok(None);
+    };
+    // walk 已经检查过每一行都是 UTF-8 且带冒号
+    let trailers = String::from_utf8_lossy(&data[trailer_start..end])
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Ok(Some(Chunked {
+        body,
+        trailers,
+        consumed: end,
+    }))
+}
+
+// 返回 (trailer 开始位置, 结束位置)，数据不完整时返回 None
+fn walk(
+    data: &[u8],
+    max_body_size: usize,
+    mut sink: Option<&mut Vec<u8>>,
+) -> Result<Option<(usize, usize)>, ChunkError> {
+    let mut pos = 0;
+    let mut total = 0usize;
+    loop {
+        let Some(line) = next_line(data, pos)? else {
+            return Ok(None);
+        };
+        let size_line = std::str::from_utf8(line)
+            .map_err(|_| ChunkError::InvalidSize(String::from_utf8_lossy(line).into()))?;
+        // 忽略 chunk 扩展
+        let size_str = size_line.split(';').next().unwrap_or("").trim();
+        // from_str_radix 会接受 "+1"，这里只允许十六进制数字
+        if size_str.is_empty() || !size_str.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ChunkError::InvalidSize(size_str.into()));
+        }
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| ChunkError::InvalidSize(size_str.into()))?;
+        total = total.saturating_add(size);
+        if total > max_body_size {
+            return Err(ChunkError::TooLarge(max_body_size));
+        }
+        pos += line.len() + 2;
+        if size == 0 {
+            return trailers(data, pos);
+        }
+        if data.len() < pos + size + 2 {
+            return Ok(None);
+        }
+        if &data[pos + size..pos + size + 2] != b"\r\n" {
+            return Err(ChunkError::MissingCrlf);
+        }
+        if let Some(out) = sink.as_deref_mut() {
+            out.extend_from_slice(&data[pos..pos + size]);
+        }
+        pos += size + 2;
+    }
+}
+
+// trailer 一直到空行为止
+fn trailers(data: &[u8], start: usize) -> Result<Option<(usize, usize)>, ChunkError> {
+    let mut pos = start;
+    loop {
+        let Some(line) = next_line(data, pos)? else {
+            return Ok(None);
+        };
+        if line.is_empty() {
+            return Ok(Some((start, pos + 2)));
+        }
+        let text = std::str::from_utf8(line)
+            .map_err(|_| ChunkError::BadTrailer(String::from_utf8_lossy(line).into()))?;
+        if !text.contains(':') || text.starts_with([' ', '\t']) {
+            return Err(ChunkError::BadTrailer(text.into()));
+        }
+        pos += line.len() + 2;
+    }
+}
+
+// pos 开始的一行（不含 \r\n），还没收到换行时返回 None
+fn next_line(data: &[u8], pos: usize) -> Result<Option<&[u8]>, ChunkError> {
+    let rest = &data[pos.min(data.len())..];
+    match rest.windows(2).position(|w| w == b"\r\n") {
+        Some(end) if end <= MAX_LINE => Ok(Some(&rest[..end])),
+        None if rest.len() <= MAX_LINE => Ok(None),
+        _ => Err(ChunkError::InvalidSize("line too long".into())),
+    }
+}
+
+// 每次 write 输出一个 chunk，finish 写出结尾的零长度 chunk
+// 忘记调用 finish 时客户端会一直等待后续数据，所以 finish 消耗 self 并返回内部的 writer
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // 零长度的 chunk 表示结束，空写入不能输出
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let data = b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Checksum: abc\r\n\r\nGET";
+        let chunked = decode(data, 100).unwrap().unwrap();
+        assert_eq!(chunked.body, b"Wikipedia");
+        assert_eq!(
+            chunked.trailers,
+            vec![("X-Checksum".to_string(), "abc".to_string())]
+        );
+        // 后面的 GET 属于下一个请求
+        assert_eq!(chunked.consumed, data.len() - 3);
+        assert_eq!(scan(data, 100), Ok(Some(data.len() - 3)));
+    }
+
+    #[test]
+    fn test_chunked_writer_round_trip() {
+        let mut w = ChunkedWriter::new(Vec::new());
+        w.write_all(b"Wiki").unwrap();
+        w.write_all(b"").unwrap();
+        w.write_all(b"pedia").unwrap();
+        let out = w.finish().unwrap();
+        assert_eq!(out, b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n");
+        assert_eq!(decode(&out, 100).unwrap().unwrap().body, b"Wikipedia");
+    }
+
+    #[test]
+    fn test_incomplete_and_invalid() {
+        for partial in [
+            &b"4\r\nWi"[..],
+            b"4\r\nWiki\r\n",
+            b"0\r\n",
+            b"0\r\nX: 1\r\n",
+        ] {
+            assert_eq!(scan(partial, 100), Ok(None));
+        }
+        assert_eq!(
+            decode(b"+4\r\nWiki\r\n0\r\n\r\n", 100),
+            Err(ChunkError::InvalidSize("+4".into()))
+        );
+        assert_eq!(
+            decode(b"4\r\nWikiX\r\n0\r\n\r\n", 100),
+            Err(ChunkError::MissingCrlf)
+        );
+        assert_eq!(
+            decode(b"0\r\nbroken\r\n\r\n", 100),
+            Err(ChunkError::BadTrailer("broken".into()))
+        );
+        assert_eq!(scan(b"ffffffff\r\n", 100), Err(ChunkError::TooLarge(100)));
+    }
+}
@@ -0,0 +1,502 @@
+// 请求体和响应体的编解码器，按媒体类型注册
+//
+//     let order: Order = req.decode()?;              // 按 Content-Type 选解码器
+//     HttpResponse::encode(&req, &order)             // 按 Accept 选编码器
+//
+// 处理器只和 serde 类型打交道，新增一种格式只需要实现 Codec 并注册，
+// 不用改处理器。JSON（json feature）和表单（form feature）是内置的
+use crate::headers::names;
+use crate::httprequest::HttpRequest;
+use crate::httpresponse::HttpResponse;
+use crate::status::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+// 在别的 crate 里实现 Codec 时不用再单独依赖 erased-serde
+pub use erased_serde;
+
+// 解码时由编解码器创建具体格式的 Deserializer，交给 visit 反序列化成目标类型
+pub type Visit<'v> =
+    dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error> + 'v;
+
+pub trait Codec: Send + Sync {
+    // 编码结果的 Content-Type，同时也是 Accept 里匹配的名字
+    fn media_type(&self) -> &'static str;
+    // 请求的媒体类型（小写、不含参数）是否由这个编解码器解码
+    fn decodes(&self, media: &str) -> bool {
+        media == self.media_type()
+    }
+    fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError>;
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CodecError {
+    // 没有编解码器认识请求的 Content-Type，值为请求里的 Content-Type（没有时为空）
+    UnsupportedMediaType(String),
+    // Accept 里列出的类型都不支持，值为请求里的 Accept
+    NotAcceptable(String),
+    // 请求体格式错误，或者和目标类型对不上
+    Invalid(String),
+    // 值不能用这种格式表示，是程序错误
+    Encode(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::UnsupportedMediaType(t) if t.is_empty() => {
+                write!(f, "request body has no Content-Type")
+            }
+            CodecError::UnsupportedMediaType(t) => write!(f, "unsupported Content-Type {:?}", t),
+            CodecError::NotAcceptable(a) => write!(f, "no supported type in Accept {:?}", a),
+            CodecError::Invalid(e) => write!(f, "invalid request body: {}", e),
+            CodecError::Encode(e) => write!(f, "cannot encode response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl CodecError {
+    // 415 / 406 / 400 / 500，body 是纯文本的原因，处理器可以直接返回
+    pub fn into_response(self) -> HttpResponse<'static> {
+        let status = match self {
+            CodecError::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            CodecError::NotAcceptable(_) => StatusCode::NotAcceptable,
+            CodecError::Invalid(_) => StatusCode::BadRequest,
+            CodecError::Encode(_) => StatusCode::InternalServerError,
+        };
+        let mut headers = HashMap::new();
+        headers.insert(names::CONTENT_TYPE, "text/plain");
+        HttpResponse::new("200", Some(headers), Some(self.to_string())).with_status(status)
+    }
+}
+
+// 已注册的编解码器，按注册顺序；没有 Accept 时用第一个
+#[derive(Clone, Default)]
+pub struct Codecs {
+    codecs: Vec<Arc<dyn Codec>>,
+}
+
+impl Codecs {
+    // 空的注册表，不含内置的编解码器
+    pub fn new() -> Self {
+        Codecs::default()
+    }
+    // 编译进来的内置编解码器，JSON 在前
+    pub fn builtin() -> Self {
+        #[allow(unused_mut)]
+        let mut codecs = Codecs::new();
+        #[cfg(feature = "json")]
+        codecs.register(Arc::new(crate::json::JsonCodec));
+        #[cfg(feature = "form")]
+        codecs.register(Arc::new(FormCodec));
+        #[cfg(feature = "cbor")]
+        codecs.register(Arc::new(CborCodec));
+        #[cfg(feature = "msgpack")]
+        codecs.register(Arc::new(MsgpackCodec));
+        codecs
+    }
+    // 同一媒体类型已经注册过时替换掉，位置不变
+    pub fn register(&mut self, codec: Arc<dyn Codec>) {
+        match self
+            .codecs
+            .iter_mut()
+            .find(|c| c.media_type() == codec.media_type())
+        {
+            Some(old) => *old = codec,
+            None => self.codecs.push(codec),
+        }
+    }
+    pub fn media_types(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.codecs.iter().map(|c| c.media_type())
+    }
+    // 请求体的解码器，content_type 可以带参数（; charset=utf-8）
+    pub fn for_content_type(&self, content_type: &str) -> Option<&dyn Codec> {
+        let media = media_type(content_type);
+        self.codecs
+            .iter()
+            .find(|c| c.decodes(&media))
+            .map(|c| c.as_ref())
+    }
+    // 响应的编码器：按 Accept 的 q 值选，q 相同时按注册顺序；没有 Accept 时用第一个
+    pub fn for_accept(&self, accept: Option<&str>) -> Option<&dyn Codec> {
+        let accept = accept.map(str::trim).filter(|a| !a.is_empty());
+        let Some(accept) = accept else {
+            return self.codecs.first().map(|c| c.as_ref());
+        };
+        let ranges = parse_accept(accept);
+        let mut best: Option<(&dyn Codec, f32)> = None;
+        for codec in &self.codecs {
+            let q = quality(&ranges, codec.media_type());
+            if q > 0.0 && best.is_none_or(|(_, b)| q > b) {
+                best = Some((codec.as_ref(), q));
+            }
+        }
+        best.map(|(c, _)| c)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, req: &HttpRequest) -> Result<T, CodecError> {
+        let content_type = req.header(names::CONTENT_TYPE).unwrap_or_default();
+        let codec = self
+            .for_content_type(content_type)
+            .ok_or_else(|| CodecError::UnsupportedMediaType(content_type.to_string()))?;
+        let mut value = None;
+        codec.decode(&req.msg_body, &mut |de| {
+            value = Some(erased_serde::deserialize(de)?);
+            Ok(())
+        })?;
+        value.ok_or_else(|| CodecError::Invalid("decoder produced no value".into()))
+    }
+
+    // 200 + 选中的格式；选不出格式时 406，编码失败时 500
+    pub fn encode<'a, T: Serialize + ?Sized>(
+        &self,
+        req: &HttpRequest,
+        value: &T,
+    ) -> HttpResponse<'a> {
+        let accept = req.header(names::ACCEPT);
+        let encoded = self
+            .for_accept(accept)
+            .ok_or_else(|| CodecError::NotAcceptable(accept.unwrap_or_default().to_string()))
+            .and_then(|codec| Ok((codec.media_type(), codec.encode(&value)?)));
+        let response = match encoded {
+            Ok((media, body)) => {
+                let mut headers = HashMap::new();
+                headers.insert(names::CONTENT_TYPE, media);
+                HttpResponse::new("200", Some(headers), None).with_bytes(body)
+            }
+            Err(e) => e.into_response(),
+        };
+        // 同一个 URL 的响应格式随 Accept 变化，缓存要按 Accept 区分
+        response
+            .with_header(names::VARY, names::ACCEPT)
+            .expect("Vary: Accept is a valid header")
+    }
+
+    // 路由器没有注册自己的编解码器时用内置的
+    pub fn global() -> &'static Arc<Codecs> {
+        static BUILTIN: OnceLock<Arc<Codecs>> = OnceLock::new();
+        BUILTIN.get_or_init(|| Arc::new(Codecs::builtin()))
+    }
+}
+
+// 请求上的编解码器：路由器注册的（放在 req.extensions 里）或者内置的
+pub fn for_request(req: &HttpRequest) -> &Codecs {
+    req.extensions
+        .get::<Arc<Codecs>>()
+        .unwrap_or_else(|| Codecs::global())
+}
+
+impl HttpRequest {
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, CodecError> {
+        for_request(self).decode(self)
+    }
+}
+
+impl<'a> HttpResponse<'a> {
+    // 按请求的 Accept 选择格式；需要别的状态码时再调用 with_status
+    pub fn encode<T: Serialize + ?Sized>(req: &HttpRequest, value: &T) -> HttpResponse<'a> {
+        for_request(req).encode(req, value)
+    }
+}
+
+// 小写、去掉参数的媒体类型
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+// Accept: text/html, application/*;q=0.5, */*;q=0.1
+fn parse_accept(accept: &str) -> Vec<(String, f32)> {
+    accept
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let media = media_type(parts.next()?);
+            if media.is_empty() {
+                return None;
+            }
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media, q.clamp(0.0, 1.0)))
+        })
+        .collect()
+}
+
+// 最具体的匹配项决定 q 值：application/json 优先于 application/*，再优先于 */*
+fn quality(ranges: &[(String, f32)], media: &str) -> f32 {
+    let (kind, _) = media.split_once('/').unwrap_or((media, ""));
+    ranges
+        .iter()
+        .filter_map(|(range, q)| {
+            let specificity = if range == media {
+                2
+            } else if range.strip_suffix("/*") == Some(kind) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            Some((specificity, *q))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map_or(0.0, |(_, q)| q)
+}
+
+// application/x-www-form-urlencoded，和 HTML 表单提交的格式一致
+#[cfg(feature = "form")]
+pub struct FormCodec;
+
+#[cfg(feature = "form")]
+impl Codec for FormCodec {
+    fn media_type(&self) -> &'static str {
+        "application/x-www-form-urlencoded"
+    }
+    fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError> {
+        let de = serde_urlencoded::Deserializer::new(form_urlencoded::parse(body));
+        visit(&mut <dyn erased_serde::Deserializer>::erase(de))
+            .map_err(|e| CodecError::Invalid(e.to_string()))
+    }
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        serde_urlencoded::to_string(value)
+            .map(String::into_bytes)
+            .map_err(|e| CodecError::Encode(e.to_string()))
+    }
+}
+
+// application/cbor，结构体编码成以字段名为键的 map
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn media_type(&self) -> &'static str {
+        "application/cbor"
+    }
+    fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError> {
+        use cbor4ii::core::dec::Read;
+        let reader = cbor4ii::core::utils::SliceReader::new(body);
+        let mut de = cbor4ii::serde::Deserializer::new(reader);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))
+            .map_err(|e| CodecError::Invalid(e.to_string()))?;
+        // 和 JSON 一样，值后面不能还有别的内容
+        let mut rest = de.into_inner();
+        match rest.fill(1) {
+            Ok(rest) if rest.as_ref().is_empty() => Ok(()),
+            _ => Err(CodecError::Invalid(
+                "trailing bytes after CBOR value".into(),
+            )),
+        }
+    }
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        cbor4ii::serde::to_vec(Vec::new(), &value).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+}
+
+// application/msgpack，也接受 application/x-msgpack 和 application/vnd.msgpack
+// 结构体编码成 map 而不是数组，字段顺序变了也能解码
+#[cfg(feature = "msgpack")]
+pub struct MsgpackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgpackCodec {
+    fn media_type(&self) -> &'static str {
+        "application/msgpack"
+    }
+    fn decodes(&self, media: &str) -> bool {
+        matches!(
+            media,
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"
+        )
+    }
+    fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError> {
+        let mut de = rmp_serde::Deserializer::new(body);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))
+            .map_err(|e| CodecError::Invalid(e.to_string()))?;
+        if !de.into_inner().is_empty() {
+            return Err(CodecError::Invalid(
+                "trailing bytes after MessagePack value".into(),
+            ));
+        }
+        Ok(())
+    }
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        rmp_serde::to_vec_named(value).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+}
+
+#[cfg(all(test, feature = "json", feature = "form"))]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        status: String,
+    }
+
+    fn request(head: &str, body: &str) -> HttpRequest {
+        let raw = format!(
+            "POST /orders HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+            head,
+            body.len(),
+            body
+        );
+        HttpRequest::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_decode_by_content_type() {
+        let expected = Order {
+            id: 7,
+            status: "shipped".into(),
+        };
+        let req = request(
+            "Content-Type: application/json\r\n",
+            r#"{"id":7,"status":"shipped"}"#,
+        );
+        assert_eq!(req.decode::<Order>(), Ok(expected));
+        let req = request(
+            "Content-Type: application/x-www-form-urlencoded\r\n",
+            "id=7&status=in+transit",
+        );
+        assert_eq!(req.decode::<Order>().unwrap().status, "in transit");
+        let req = request("Content-Type: text/plain\r\n", "id=7");
+        assert_eq!(
+            req.decode::<Order>(),
+            Err(CodecError::UnsupportedMediaType("text/plain".into()))
+        );
+        // JSON 后面多出来的内容也算格式错误
+        let req = request(
+            "Content-Type: application/json\r\n",
+            r#"{"id":7,"status":""} x"#,
+        );
+        assert!(matches!(req.decode::<Order>(), Err(CodecError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_encode_by_accept() {
+        let order = Order {
+            id: 1,
+            status: "new".into(),
+        };
+        let encode = |accept: &str| {
+            let req = request(&format!("Accept: {}\r\n", accept), "");
+            HttpResponse::encode(&req, &order)
+        };
+        let resp = encode("");
+        assert_eq!(resp.header("Content-Type"), Some("application/json"));
+        assert_eq!(resp.header("Vary"), Some("Accept"));
+        let resp = encode("text/html, application/x-www-form-urlencoded;q=0.9, */*;q=0.1");
+        assert_eq!(resp.body_text(), Some("id=1&status=new"));
+        let resp = encode("application/*;q=0.5, application/json;q=0");
+        assert_eq!(
+            resp.header("Content-Type"),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(encode("image/png").status(), StatusCode::NotAcceptable);
+    }
+
+    #[test]
+    fn test_register_codec() {
+        struct Upper;
+        impl Codec for Upper {
+            fn media_type(&self) -> &'static str {
+                "application/json"
+            }
+            fn decode(&self, _: &[u8], _: &mut Visit) -> Result<(), CodecError> {
+                Err(CodecError::Invalid("unused".into()))
+            }
+            fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+                let json = serde_json::to_string(value).unwrap();
+                Ok(json.to_uppercase().into_bytes())
+            }
+        }
+        let mut codecs = Codecs::builtin();
+        codecs.register(Arc::new(Upper));
+        assert_eq!(
+            codecs.media_types().take(2).collect::<Vec<_>>(),
+            vec!["application/json", "application/x-www-form-urlencoded"]
+        );
+        let mut req = request("", "");
+        req.extensions.insert(Arc::new(codecs));
+        let resp = HttpResponse::encode(&req, &["a"]);
+        assert_eq!(resp.body_text(), Some(r#"["A"]"#));
+    }
+}
+
+#[cfg(all(test, feature = "cbor", feature = "msgpack"))]
+mod binary_tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        celsius: f32,
+        tags: Vec<String>,
+    }
+
+    fn round_trip(media: &str) {
+        let reading = Reading {
+            sensor: "t-1".into(),
+            celsius: 21.5,
+            tags: vec!["roof".into()],
+        };
+        let req = HttpRequest::try_from(
+            format!("GET / HTTP/1.1\r\nAccept: {}\r\n\r\n", media).as_bytes(),
+        )
+        .unwrap();
+        let resp = HttpResponse::encode(&req, &reading);
+        assert_eq!(resp.header("Content-Type"), Some(media));
+        let body = resp.body_bytes().unwrap().to_vec();
+        let mut raw = format!(
+            "POST / HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            media,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(&body);
+        let req = HttpRequest::try_from(raw.as_slice()).unwrap();
+        assert_eq!(req.decode::<Reading>(), Ok(reading));
+    }
+
+    #[test]
+    fn test_binary_codecs() {
+        round_trip("application/cbor");
+        round_trip("application/msgpack");
+        // 二进制格式比 JSON 紧凑
+        let codecs = Codecs::builtin();
+        let value = vec![1u32, 2, 3];
+        let cbor = codecs.for_accept(Some("application/cbor")).unwrap();
+        assert_eq!(cbor.encode(&value).unwrap(), [0x83, 1, 2, 3]);
+        let msgpack = codecs.for_content_type("application/x-msgpack").unwrap();
+        assert_eq!(msgpack.encode(&value).unwrap(), [0x93, 1, 2, 3]);
+        // 值后面多出来的字节
+        for (codec, body) in [(cbor, [0x83, 1, 2, 3, 4]), (msgpack, [0x93, 1, 2, 3, 4])] {
+            let mut value = None;
+            let decoded = codec.decode(&body, &mut |de| {
+                value = Some(erased_serde::deserialize::<Vec<u32>>(de)?);
+                Ok(())
+            });
+            assert!(
+                matches!(decoded, Err(CodecError::Invalid(_))),
+                "{:?}",
+                decoded
+            );
+            assert_eq!(value, Some(vec![1, 2, 3]));
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// 出站连接池：HttpClient 按 host:port 复用连接，转发量大的时候不用每个请求都新建连接，
+// 也就不会留下一大堆 TIME_WAIT 把本机的临时端口用光
+//
+//     let pool = Arc::new(ConnectionPool::new(PoolConfig::default()));
+//     let client = HttpClient::new().pool(pool.clone());
+//     pool.stats()    // 新建了多少连接、复用了多少次
+//
+// 每个 host 同时使用的连接不超过 max_per_host，满了之后排队等别的请求归还，最多等客户端的 timeout；
+// 归还的连接每个 host 最多留 max_idle_per_host 个，空闲超过 idle_timeout 或者已经被对方关闭的不再使用
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+pub const DEFAULT_MAX_PER_HOST: usize = 64;
+pub const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_idle_per_host: usize,
+    // 0 表示不限
+    pub max_per_host: usize,
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+            max_per_host: DEFAULT_MAX_PER_HOST,
+            idle_timeout: Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolStats {
+    // 新建的连接数和复用空闲连接的次数
+    pub opened: u64,
+    pub reused: u64,
+    // 达到 max_per_host 之后排队的次数，以及其中等到超时的次数
+    pub waited: u64,
+    pub timed_out: u64,
+    // 当前借出去的和空闲的连接
+    pub active: usize,
+    pub idle: usize,
+}
+
+#[derive(Default)]
+struct Host {
+    // 后归还的在后面，优先复用，最早归还的最先过期
+    idle: Vec<(TcpStream, Instant)>,
+    active: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    opened: AtomicU64,
+    reused: AtomicU64,
+    waited: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+pub struct ConnectionPool {
+    config: PoolConfig,
+    hosts: Mutex<HashMap<(String, u16), Host>>,
+    // 有连接归还时唤醒排队的请求
+    returned: Condvar,
+    counters: Counters,
+}
+
+// 空闲期间对方关闭了连接（或者发来了不该有的数据）时返回 false
+fn is_alive(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let alive = matches!(stream.peek(&mut [0]), Err(e) if e.kind() == ErrorKind::WouldBlock);
+    stream.set_nonblocking(false).is_ok() && alive
+}
+
+impl ConnectionPool {
+    pub fn new(config: PoolConfig) -> Self {
+        ConnectionPool {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+            returned: Condvar::new(),
+            counters: Counters::default(),
+        }
+    }
+
+    // 借一个连接：有可用的空闲连接时返回 Some，否则返回 None，由调用方新建；
+    // 两种情况用完都要 checkin；排队超过 wait 时返回 TimedOut
+    pub(crate) fn checkout(
+        &self,
+        host: &str,
+        port: u16,
+        wait: Option<Duration>,
+    ) -> io::Result<Option<TcpStream>> {
+        let deadline = wait.map(|w| Instant::now() + w);
+        let key = (host.to_string(), port);
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut waited = false;
+        loop {
+            let entry = hosts.entry(key.clone()).or_default();
+            while let Some((stream, since)) = entry.idle.pop() {
+                if since.elapsed() < self.config.idle_timeout && is_alive(&stream) {
+                    entry.active += 1;
+                    self.counters.reused.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Some(stream));
+                }
+            }
+            if self.config.max_per_host == 0 || entry.active < self.config.max_per_host {
+                entry.active += 1;
+                self.counters.opened.fetch_add(1, Ordering::Relaxed);
+                return Ok(None);
+            }
+            if !waited {
+                waited = true;
+                self.counters.waited.fetch_add(1, Ordering::Relaxed);
+            }
+            hosts = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        self.counters.timed_out.fetch_add(1, Ordering::Relaxed);
+                        return Err(io::Error::new(
+                            ErrorKind::TimedOut,
+                            format!("no free connection to {}:{}", host, port),
+                        ));
+                    }
+                    self.returned.wait_timeout(hosts, left).unwrap().0
+                }
+                None => self.returned.wait(hosts).unwrap(),
+            };
+        }
+    }
+
+    // 归还 checkout 借的名额；stream 为 None 表示连接已经不能再用（出错、对方要求关闭等）
+    pub(crate) fn checkin(&self, host: &str, port: u16, stream: Option<TcpStream>) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(entry) = hosts.get_mut(&(host.to_string(), port)) {
+            entry.active = entry.active.saturating_sub(1);
+            if let Some(stream) = stream {
+                entry
+                    .idle
+                    .retain(|(_, since)| since.elapsed() < self.config.idle_timeout);
+                if entry.idle.len() < self.config.max_idle_per_host {
+                    entry.idle.push((stream, Instant::now()));
+                }
+            }
+        }
+        drop(hosts);
+        // 排队的可能是别的 host，全部叫醒各自检查
+        self.returned.notify_all();
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let hosts = self.hosts.lock().unwrap();
+        PoolStats {
+            opened: self.counters.opened.load(Ordering::Relaxed),
+            reused: self.counters.reused.load(Ordering::Relaxed),
+            waited: self.counters.waited.load(Ordering::Relaxed),
+            timed_out: self.counters.timed_out.load(Ordering::Relaxed),
+            active: hosts.values().map(|h| h.active).sum(),
+            idle: hosts.values().map(|h| h.idle.len()).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_pool_limits() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let pool = Arc::new(ConnectionPool::new(PoolConfig {
+            max_idle_per_host: 1,
+            max_per_host: 2,
+            idle_timeout: Duration::from_millis(200),
+        }));
+        let connect = || TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert!(pool.checkout("127.0.0.1", port, None).unwrap().is_none());
+        assert!(pool.checkout("127.0.0.1", port, None).unwrap().is_none());
+        let (a, b) = (connect(), connect());
+        let _server_side: Vec<_> = (0..2).map(|_| listener.accept().unwrap().0).collect();
+        // 满了，排队到超时
+        let short = Some(Duration::from_millis(20));
+        let err = pool.checkout("127.0.0.1", port, short).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+        // 归还之后排队的请求拿到刚归还的连接
+        let waiter = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.checkout("127.0.0.1", port, Some(Duration::from_secs(5))))
+        };
+        while pool.stats().waited < 2 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        pool.checkin("127.0.0.1", port, Some(a));
+        let reused = waiter.join().unwrap().unwrap();
+        assert!(reused.is_some());
+        // 空闲连接最多留一个
+        pool.checkin("127.0.0.1", port, reused);
+        pool.checkin("127.0.0.1", port, Some(b));
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                opened: 2,
+                reused: 1,
+                waited: 2,
+                timed_out: 1,
+                active: 0,
+                idle: 1,
+            }
+        );
+        // 空闲太久的不再使用
+        thread::sleep(Duration::from_millis(250));
+        assert!(pool.checkout("127.0.0.1", port, None).unwrap().is_none());
+        assert_eq!(pool.stats().opened, 3);
+    }
+
+    #[test]
+    fn test_closed_connection_is_not_reused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let pool = ConnectionPool::new(PoolConfig::default());
+        assert!(pool.checkout("127.0.0.1", port, None).unwrap().is_none());
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (server_side, _) = listener.accept().unwrap();
+        assert!(is_alive(&stream));
+        pool.checkin("127.0.0.1", port, Some(stream));
+        drop(server_side);
+        thread::sleep(Duration::from_millis(20));
+        assert!(pool.checkout("127.0.0.1", port, None).unwrap().is_none());
+        assert_eq!(pool.stats().reused, 0);
+    }
+}
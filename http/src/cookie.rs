@@ -0,0 +1,207 @@
+// Cookie：请求里的 Cookie 头部解析成名字 / 值，响应用 Set-Cookie 下发，见 RFC 6265
+// 请求里只有名字和值，属性（Path、Expires……）只在 Set-Cookie 里出现
+use crate::clock::http_date;
+use crate::headers::{self, HeaderError};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    // 跨站请求也带上，浏览器要求同时设置 Secure
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        };
+        f.write_str(s)
+    }
+}
+
+// Cookie::new("session", "abc").path("/").http_only(true).same_site(SameSite::Lax)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub expires: Option<SystemTime>,
+    // 同时设置时浏览器以 Max-Age 为准
+    pub max_age: Option<Duration>,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            expires: None,
+            max_age: None,
+            path: None,
+            domain: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    // 让浏览器删除这个 cookie；Path / Domain 要和设置时一致
+    pub fn removal(name: impl Into<String>) -> Self {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(UNIX_EPOCH)
+    }
+
+    pub fn expires(mut self, at: SystemTime) -> Self {
+        self.expires = Some(at);
+        self
+    }
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    // 名字必须是 token；值不能有空白、双引号、逗号、分号和反斜杠，需要时由调用方先编码
+    // Path / Domain 里不能有分号，否则可以伪造后面的属性
+    pub fn validate(&self) -> Result<(), HeaderError> {
+        headers::validate_name(&self.name)?;
+        let octet =
+            |b: u8| matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e);
+        if !self.value.bytes().all(octet) {
+            return Err(HeaderError::InvalidValue(self.value.clone()));
+        }
+        for attr in [&self.path, &self.domain].into_iter().flatten() {
+            headers::validate_value(attr)?;
+            if attr.contains(';') {
+                return Err(HeaderError::InvalidValue(attr.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // 解析请求的 Cookie 头部：a=1; b=2，没有等号的片段忽略，值两边的双引号去掉
+    pub fn parse_header(value: &str) -> Vec<Cookie> {
+        value
+            .split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                if name.is_empty() {
+                    return None;
+                }
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                Some(Cookie::new(name, value))
+            })
+            .collect()
+    }
+}
+
+// Set-Cookie 头部的值
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(at) = self.expires {
+            write!(f, "; Expires={}", http_date(at))?;
+        }
+        if let Some(age) = self.max_age {
+            write!(f, "; Max-Age={}", age.as_secs())?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_cookie_value() {
+        let cookie = Cookie::new("session", "abc123")
+            .path("/")
+            .domain("example.com")
+            .max_age(Duration::from_secs(3600))
+            .expires(UNIX_EPOCH + Duration::from_secs(784_111_777))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc123; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; \
+             Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            Cookie::removal("session").to_string(),
+            "session=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0"
+        );
+    }
+
+    #[test]
+    fn test_validate_cookie() {
+        assert!(Cookie::new("id", "a-b_c.1").validate().is_ok());
+        assert!(Cookie::new("bad name", "x").validate().is_err());
+        assert!(Cookie::new("id", "a;b").validate().is_err());
+        assert!(Cookie::new("id", "a b").validate().is_err());
+        assert!(Cookie::new("id", "x")
+            .path("/; Domain=evil")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_cookie_header() {
+        let cookies = Cookie::parse_header(" a=1; b=\"two\";flag; =x; c=");
+        let pairs: Vec<_> = cookies
+            .iter()
+            .map(|c| (c.name.as_str(), c.value.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("a", "1"), ("b", "two"), ("c", "")]);
+    }
+}
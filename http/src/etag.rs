@@ -0,0 +1,105 @@
+// ETag 和条件 GET（RFC 7232）：浏览器再次请求时带上上次拿到的 ETag / Last-Modified，
+// 资源没变就回 304，不用再发一遍 body
+//
+//     ETag: "65a1b2c3-4d2"              静态文件：修改时间和长度，不用读文件
+//     ETag: "8c5d2f1e0a9b3c47"          API 响应：body 的 FNV-1a 哈希
+//
+//     If-None-Match: "65a1b2c3-4d2"     和 ETag 对上就是没变
+//     If-Modified-Since: <HTTP-date>    没有 If-None-Match 时才看，Last-Modified 不晚于它就是没变
+use crate::clock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 文件的 ETag，和 nginx 的格式一样：修改时间（秒）和长度的十六进制
+pub fn from_metadata(len: u64, modified: SystemTime) -> String {
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", secs, len)
+}
+
+// 内存里的 body 按内容算 ETag，重启之后也不变
+pub fn from_bytes(body: &[u8]) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let hash = body
+        .iter()
+        .fold(OFFSET, |h, b| (h ^ *b as u64).wrapping_mul(PRIME));
+    format!("\"{:016x}\"", hash)
+}
+
+fn opaque(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/")
+}
+
+// 弱比较：忽略 W/ 前缀，If-None-Match 用这个；list 是逗号分隔的 ETag 列表或 *
+pub fn weak_match(list: &str, etag: &str) -> bool {
+    list.trim() == "*" || list.split(',').any(|t| opaque(t) == opaque(etag))
+}
+
+// 强比较：两边都不能是弱 ETag，If-Range 用这个
+pub fn strong_match(a: &str, b: &str) -> bool {
+    let (a, b) = (a.trim(), b.trim());
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+// 客户端缓存的版本是否还是最新的，是的话 GET / HEAD 回 304
+// 有 If-None-Match 时忽略 If-Modified-Since（RFC 7232 3.3）
+pub fn is_fresh(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> bool {
+    if let Some(list) = if_none_match {
+        return etag.is_some_and(|etag| weak_match(list, etag));
+    }
+    let since = if_modified_since.and_then(clock::parse_http_date);
+    let modified = last_modified.and_then(clock::parse_http_date);
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_is_fresh() {
+        let t = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(from_metadata(1234, t), "\"2ebc98a1-4d2\"");
+        assert_eq!(from_bytes(b""), "\"cbf29ce484222325\"");
+        assert_ne!(from_bytes(b"a"), from_bytes(b"b"));
+
+        let etag = Some("\"abc\"");
+        assert!(is_fresh(Some("\"abc\""), None, etag, None));
+        assert!(is_fresh(Some("\"x\", W/\"abc\""), None, etag, None));
+        assert!(is_fresh(Some("*"), None, etag, None));
+        assert!(!is_fresh(Some("\"x\""), None, etag, None));
+        assert!(!is_fresh(Some("\"abc\""), None, None, None));
+
+        let modified = Some("Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(is_fresh(None, modified, None, modified));
+        assert!(is_fresh(
+            None,
+            Some("Mon, 07 Nov 1994 00:00:00 GMT"),
+            None,
+            modified
+        ));
+        assert!(!is_fresh(
+            None,
+            Some("Sat, 05 Nov 1994 00:00:00 GMT"),
+            None,
+            modified
+        ));
+        assert!(!is_fresh(None, Some("yesterday"), None, modified));
+        // If-None-Match 对不上时不再看 If-Modified-Since
+        assert!(!is_fresh(Some("\"x\""), modified, etag, modified));
+
+        assert!(strong_match("\"abc\"", " \"abc\""));
+        assert!(!strong_match("W/\"abc\"", "\"abc\""));
+    }
+}
@@ -0,0 +1,71 @@
+// 请求上挂的附加数据，按类型存取，每种类型最多一个
+// 中间件（GeoIP、认证、session）把解析结果放进来，后面的处理器直接取用
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+    // 已有同类型的值时替换并返回旧值
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok().map(|b| *b))
+    }
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|b| b.downcast_ref())
+    }
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|b| b.downcast_mut())
+    }
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast().ok().map(|b| *b))
+    }
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+// 值不要求实现 Debug，只打印个数
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[derive(Debug, PartialEq)]
+    struct Country(&'static str);
+    #[test]
+    fn test_extensions() {
+        let mut ext = Extensions::new();
+        assert!(ext.insert(Country("NL")).is_none());
+        assert_eq!(ext.insert(Country("DE")), Some(Country("NL")));
+        ext.insert(42u32);
+        assert_eq!(ext.get::<Country>(), Some(&Country("DE")));
+        *ext.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(ext.remove::<u32>(), Some(43));
+        assert_eq!(ext.get::<u32>(), None);
+        assert_eq!(ext.len(), 1);
+    }
+}
@@ -0,0 +1,236 @@
+// 请求和响应的头部集合
+// 名字不区分大小写：客户端发 content-length 还是 Content-Length，get("Content-Length") 都能取到
+// 按插入顺序保存，同名头部可以有多个值（append），名字按写入时的写法原样发出
+// 请求里是 HeaderMap<'static>（解析出来的 String），响应里大部分是借用的字面量
+use std::borrow::Cow;
+use std::ops::Index;
+
+#[derive(Debug, Clone, Default)]
+pub struct HeaderMap<'a> {
+    entries: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> HeaderMap<'a> {
+    pub fn new() -> Self {
+        HeaderMap::default()
+    }
+    // 值的个数，同名的多个值分别计数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    // 第一个同名头部的值
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.position(name).map(|at| self.entries[at].1.as_ref())
+    }
+    // 同名头部的全部值，按出现顺序
+    pub fn get_all<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'s str> + 's {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_ref())
+    }
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+    // 替换同名头部的全部值，位置沿用第一个同名头部；返回原来的第一个值
+    pub fn insert(
+        &mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+    ) -> Option<Cow<'a, str>> {
+        let name = name.into();
+        let value = value.into();
+        let Some(at) = self.position(&name) else {
+            self.entries.push((name, value));
+            return None;
+        };
+        let mut i = 0;
+        self.entries.retain(|(k, _)| {
+            i += 1;
+            i <= at + 1 || !k.eq_ignore_ascii_case(&name)
+        });
+        let (_, old) = std::mem::replace(&mut self.entries[at], (name, value));
+        Some(old)
+    }
+    // 追加一个值，已有的同名头部保留
+    pub fn append(&mut self, name: impl Into<Cow<'a, str>>, value: impl Into<Cow<'a, str>>) {
+        self.entries.push((name.into(), value.into()));
+    }
+    // 删除同名头部的全部值，返回第一个
+    pub fn remove(&mut self, name: &str) -> Option<Cow<'a, str>> {
+        let at = self.position(name)?;
+        let (_, first) = self.entries.remove(at);
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        Some(first)
+    }
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, &str) -> bool) {
+        self.entries.retain(|(k, v)| keep(k, v));
+    }
+    // 按插入顺序
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+    // 不再借用原来的数据，可以放进 'static 的请求或响应里
+    pub fn into_owned(self) -> HeaderMap<'static> {
+        HeaderMap {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|(k, v)| (Cow::Owned(k.into_owned()), Cow::Owned(v.into_owned())))
+                .collect(),
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(name))
+    }
+}
+
+// 不同名字之间的顺序不影响语义，同名头部的值的顺序才有意义
+impl PartialEq for HeaderMap<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .entries
+                .iter()
+                .all(|(k, _)| self.get_all(k).eq(other.get_all(k)))
+    }
+}
+
+impl Eq for HeaderMap<'_> {}
+
+// headers["Host"]，没有这个头部时 panic，和 HashMap 一样
+impl Index<&str> for HeaderMap<'_> {
+    type Output = str;
+    fn index(&self, name: &str) -> &str {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no header named {:?}", name))
+    }
+}
+
+impl<'a, K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>> Extend<(K, V)> for HeaderMap<'a> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.append(k, v);
+        }
+    }
+}
+
+impl<'a, K: Into<Cow<'a, str>>, V: Into<Cow<'a, str>>> FromIterator<(K, V)> for HeaderMap<'a> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = HeaderMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+// 序列化成 [[名字, 值], ...]，同名的多个值和顺序都能保留
+// 反序列化也接受以前录制的 {"名字": "值"} 和 null
+#[cfg(feature = "serde")]
+impl serde::Serialize for HeaderMap<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HeaderMap<'_> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{MapAccess, SeqAccess, Visitor};
+        use std::fmt;
+
+        struct HeadersVisitor;
+
+        impl<'de> Visitor<'de> for HeadersVisitor {
+            type Value = HeaderMap<'static>;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of [name, value] pairs or a map of headers")
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut map = HeaderMap::new();
+                while let Some((k, v)) = seq.next_element::<(String, String)>()? {
+                    map.append(k, v);
+                }
+                Ok(map)
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut map = HeaderMap::new();
+                while let Some((k, v)) = access.next_entry::<String, String>()? {
+                    map.append(k, v);
+                }
+                Ok(map)
+            }
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(HeaderMap::new())
+            }
+        }
+
+        deserializer.deserialize_any(HeadersVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive_multi_value() {
+        let mut headers: HeaderMap = [
+            ("Content-Length", "5"),
+            ("Accept", "text/html"),
+            ("accept", "*/*"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(headers.get("content-length"), Some("5"));
+        assert_eq!(&headers["CONTENT-LENGTH"], "5");
+        assert_eq!(
+            headers.get_all("ACCEPT").collect::<Vec<_>>(),
+            vec!["text/html", "*/*"]
+        );
+        // insert 替换全部同名值，位置不变
+        headers.append("X-Id", "1");
+        assert_eq!(
+            headers.insert("ACCEPT", "text/plain"),
+            Some("text/html".into())
+        );
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![
+                ("Content-Length", "5"),
+                ("ACCEPT", "text/plain"),
+                ("X-Id", "1")
+            ]
+        );
+        assert_eq!(headers.remove("x-id"), Some("1".into()));
+        assert!(!headers.contains_key("X-Id"));
+        assert_eq!(headers.len(), 2);
+        // 不同名字的顺序不影响相等
+        let other: HeaderMap = [("accept", "text/plain"), ("content-length", "5")]
+            .into_iter()
+            .collect();
+        assert_eq!(headers, other);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let headers: HeaderMap = [("Set-Cookie", "a=1"), ("Set-Cookie", "b=2")]
+            .into_iter()
+            .collect();
+        let json = serde_json::to_string(&headers).unwrap();
+        assert_eq!(json, r#"[["Set-Cookie","a=1"],["Set-Cookie","b=2"]]"#);
+        assert_eq!(serde_json::from_str::<HeaderMap>(&json).unwrap(), headers);
+        // 以前的格式
+        let old: HeaderMap = serde_json::from_str(r#"{"Host":"a"}"#).unwrap();
+        assert_eq!(old.get("host"), Some("a"));
+        assert!(serde_json::from_str::<HeaderMap>("null")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::borrow::Cow;
+use std::fmt;
+
+// 常用头部名常量，避免手写字符串拼错（例如 "Content-Tvpe"）
+pub mod names {
+    pub const ACCEPT: &str = "Accept";
+    pub const ACCEPT_ENCODING: &str = "Accept-Encoding";
+    pub const ACCEPT_RANGES: &str = "Accept-Ranges";
+    pub const ACCEPT_VERSION: &str = "Accept-Version";
+    pub const ALLOW: &str = "Allow";
+    pub const API_VERSION: &str = "API-Version";
+    pub const AUTHORIZATION: &str = "Authorization";
+    pub const CACHE_CONTROL: &str = "Cache-Control";
+    pub const CONNECTION: &str = "Connection";
+    pub const CONTENT_DISPOSITION: &str = "Content-Disposition";
+    pub const CONTENT_ENCODING: &str = "Content-Encoding";
+    pub const CONTENT_LENGTH: &str = "Content-Length";
+    pub const CONTENT_RANGE: &str = "Content-Range";
+    pub const CONTENT_TYPE: &str = "Content-Type";
+    pub const COOKIE: &str = "Cookie";
+    pub const DATE: &str = "Date";
+    pub const DEPRECATION: &str = "Deprecation";
+    pub const ETAG: &str = "ETag";
+    pub const HOST: &str = "Host";
+    pub const IF_MODIFIED_SINCE: &str = "If-Modified-Since";
+    pub const IF_NONE_MATCH: &str = "If-None-Match";
+    pub const IF_RANGE: &str = "If-Range";
+    pub const LAST_MODIFIED: &str = "Last-Modified";
+    pub const LINK: &str = "Link";
+    pub const LOCATION: &str = "Location";
+    pub const RANGE: &str = "Range";
+    pub const RETRY_AFTER: &str = "Retry-After";
+    pub const SERVER: &str = "Server";
+    pub const SET_COOKIE: &str = "Set-Cookie";
+    pub const SUNSET: &str = "Sunset";
+    pub const TRANSFER_ENCODING: &str = "Transfer-Encoding";
+    pub const USER_AGENT: &str = "User-Agent";
+    pub const VARY: &str = "Vary";
+    pub const WWW_AUTHENTICATE: &str = "WWW-Authenticate";
+    pub const X_CONTENT_TYPE_OPTIONS: &str = "X-Content-Type-Options";
+    pub const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HeaderError {
+    InvalidName(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::InvalidName(n) => write!(f, "invalid header name {:?}", n),
+            HeaderError::InvalidValue(v) => write!(f, "invalid header value {:?}", v),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+// RFC 7230 token：字母数字加上 !#$%&'*+-.^_`|~
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+pub fn validate_name(name: &str) -> Result<(), HeaderError> {
+    if !name.is_empty() && name.bytes().all(is_tchar) {
+        Ok(())
+    } else {
+        Err(HeaderError::InvalidName(name.to_string()))
+    }
+}
+
+// 值里不允许出现 CR / LF / NUL 等控制字符（HTAB 除外），否则可以注入新的头部甚至伪造响应
+pub fn validate_value(value: &str) -> Result<(), HeaderError> {
+    if value
+        .bytes()
+        .all(|b| b == b'\t' || !(b < 0x20 || b == 0x7f))
+    {
+        Ok(())
+    } else {
+        Err(HeaderError::InvalidValue(value.to_string()))
+    }
+}
+
+// 集中的清洗逻辑：把控制字符（含 CR / LF）百分号编码，其余字符原样保留
+// 对 URL 来说编码后语义不变，对普通文本也不会再破坏响应的分帧
+pub fn sanitize_value(value: &str) -> Cow<'_, str> {
+    if validate_value(value).is_ok() && !value.contains('\t') {
+        return Cow::Borrowed(value);
+    }
+    let mut out = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        if c.is_ascii_control() {
+            out.push_str(&format!("%{:02X}", c as u8));
+        } else {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
+
+pub fn validate(name: &str, value: &str) -> Result<(), HeaderError> {
+    validate_name(name)?;
+    validate_value(value)
+}
+
+// 逗号分隔的选项列表（例如 Connection: keep-alive, Upgrade）里有没有 option，不区分大小写
+pub fn has_option(value: &str, option: &str) -> bool {
+    value
+        .split(',')
+        .any(|o| o.trim().eq_ignore_ascii_case(option))
+}
+
+// Content-Disposition 的值，disposition 是 "attachment" 或 "inline"
+// filename 只能放 ASCII，非 ASCII 的名字另外用 RFC 5987 的 filename*=UTF-8''... 给出，
+// 新浏览器用 filename*，老客户端退回到把非 ASCII 字符换成 _ 的 filename
+pub fn content_disposition(disposition: &str, filename: &str) -> String {
+    // 去掉路径部分，文件名里的 / 和 \ 不应该被客户端当成目录
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' && c != '%' => c,
+            _ => '_',
+        })
+        .collect();
+    if fallback == filename {
+        return format!("{}; filename=\"{}\"", disposition, filename);
+    }
+    // RFC 5987 attr-char 以外的字节都编码
+    let mut encoded = String::new();
+    for b in filename.bytes() {
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition, fallback, encoded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_validate_header() {
+        assert!(validate(names::CONTENT_TYPE, "text/html; charset=utf-8").is_ok());
+        assert!(validate("X-Tab", "a\tb").is_ok());
+        assert_eq!(
+            validate_name("Bad Name"),
+            Err(HeaderError::InvalidName("Bad Name".into()))
+        );
+        assert!(validate_name("").is_err());
+        assert!(validate_name("X:Y").is_err());
+        assert!(validate_value("ok\r\nSet-Cookie: evil=1").is_err());
+        assert!(validate_value("nul\0").is_err());
+    }
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("attachment", "report.pdf"),
+            "attachment; filename=\"report.pdf\""
+        );
+        assert_eq!(
+            content_disposition("attachment", "报告 2024.pdf"),
+            "attachment; filename=\"__ 2024.pdf\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A%202024.pdf"
+        );
+        assert_eq!(
+            content_disposition("inline", "../a\"b.txt"),
+            "inline; filename=\"a_b.txt\"; filename*=UTF-8''a%22b.txt"
+        );
+        assert!(validate_value(&content_disposition("attachment", "x\r\ny")).is_ok());
+    }
+    #[test]
+    fn test_sanitize_value() {
+        assert!(matches!(sanitize_value("/plain"), Cow::Borrowed("/plain")));
+        assert_eq!(sanitize_value("/a\r\nb"), "/a%0D%0Ab");
+        assert_eq!(sanitize_value("x\0\x7f\t"), "x%00%7F%09");
+        assert_eq!(sanitize_value("/caf\u{e9}"), "/caf\u{e9}");
+        assert!(validate_value(&sanitize_value("\r\n\r\n")).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+// HTML 转义和一个极简模板引擎
+// 模板里的 {{ name }} 默认转义，只有 SafeHtml 类型的值才会原样输出，
+// 这样订单数据之类用户可控的内容不会把 <script> 注入到页面里
+use std::fmt;
+
+// & < > " ' 五个字符转义，足够用于元素内容和带引号的属性值
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#x27;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// 已经是安全 HTML 的字符串，只能通过转义或显式的 trusted() 构造
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SafeHtml(String);
+
+impl SafeHtml {
+    pub fn escaped(s: &str) -> Self {
+        SafeHtml(escape(s))
+    }
+    // 调用方保证内容可信，例如程序里写死的标签或另一个模板的渲染结果
+    pub fn trusted(s: impl Into<String>) -> Self {
+        SafeHtml(s.into())
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl fmt::Display for SafeHtml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// 模板变量的值如何变成 HTML；普通字符串一律转义
+pub trait ToHtml {
+    fn to_html(&self) -> SafeHtml;
+}
+
+impl ToHtml for SafeHtml {
+    fn to_html(&self) -> SafeHtml {
+        self.clone()
+    }
+}
+impl ToHtml for str {
+    fn to_html(&self) -> SafeHtml {
+        SafeHtml::escaped(self)
+    }
+}
+impl ToHtml for String {
+    fn to_html(&self) -> SafeHtml {
+        SafeHtml::escaped(self)
+    }
+}
+macro_rules! to_html_display {
+    ($($t:ty),*) => {
+        $(impl ToHtml for $t {
+            fn to_html(&self) -> SafeHtml {
+                SafeHtml(self.to_string())
+            }
+        })*
+    };
+}
+to_html_display!(i32, i64, u32, u64, usize, f64, bool);
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    Unclosed(usize),
+    MissingVar(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unclosed(at) => write!(f, "unclosed {{{{ at byte {}", at),
+            TemplateError::MissingVar(v) => write!(f, "template variable {:?} not provided", v),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+enum Part {
+    Text(String),
+    Var(String),
+}
+
+// 解析一次，多次渲染
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = src;
+        let mut offset = 0;
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .ok_or(TemplateError::Unclosed(offset + start))?;
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            parts.push(Part::Var(rest[start + 2..start + end].trim().to_string()));
+            offset += start + end + 2;
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    // 模板里用到但没有提供的变量视为错误，避免页面上悄悄少一块
+    pub fn render(&self, vars: &[(&str, &dyn ToHtml)]) -> Result<SafeHtml, TemplateError> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(t) => out.push_str(t),
+                Part::Var(name) => {
+                    let (_, value) = vars
+                        .iter()
+                        .find(|(k, _)| k == name)
+                        .ok_or_else(|| TemplateError::MissingVar(name.clone()))?;
+                    out.push_str(value.to_html().as_str());
+                }
+            }
+        }
+        Ok(SafeHtml(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape(r#"<script>alert("x" & 'y')</script>"#),
+            "&lt;script&gt;alert(&quot;x&quot; &amp; &#x27;y&#x27;)&lt;/script&gt;"
+        );
+        assert_eq!(escape("订单 42"), "订单 42");
+    }
+    #[test]
+    fn test_template_escapes_by_default() {
+        let t = Template::parse("<td>{{ status }}</td><td>{{id}}</td>{{ raw }}").unwrap();
+        let html = t
+            .render(&[
+                ("status", &"<img onerror=x>".to_string()),
+                ("id", &42),
+                ("raw", &SafeHtml::trusted("<br>")),
+            ])
+            .unwrap();
+        assert_eq!(
+            html.as_str(),
+            "<td>&lt;img onerror=x&gt;</td><td>42</td><br>"
+        );
+        assert_eq!(
+            t.render(&[]).unwrap_err(),
+            TemplateError::MissingVar("status".into())
+        );
+        assert_eq!(
+            Template::parse("a {{ b").err(),
+            Some(TemplateError::Unclosed(2))
+        );
+    }
+}
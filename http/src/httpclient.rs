@@ -0,0 +1,823 @@
+use crate::chunked::{self, ChunkError};
+use crate::connpool::ConnectionPool;
+use crate::headermap::HeaderMap;
+use crate::socks::{Socks5Proxy, SocksError};
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// 解码后 body 的默认上限 10MB，防止恶意服务器用 gzip 炸弹把内存撑爆
+pub const DEFAULT_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+// 响应行 + 头部允许占用的额外字节
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+// 上传/下载时每次读写的块大小，也是进度回调的最小粒度
+const TRANSFER_CHUNK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    InvalidUrl(String),
+    BadResponse(String),
+    // 超过 max_body_size，携带上限值
+    BodyTooLarge(usize),
+    Decode(String),
+    Proxy(SocksError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "io error: {}", e),
+            ClientError::InvalidUrl(u) => write!(f, "invalid url: {}", u),
+            ClientError::BadResponse(m) => write!(f, "bad response: {}", m),
+            ClientError::BodyTooLarge(max) => write!(f, "body exceeds {} bytes", max),
+            ClientError::Decode(m) => write!(f, "decode error: {}", m),
+            ClientError::Proxy(e) => write!(f, "proxy error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+// ? 运算符会自动调用 From 把 io::Error 转成 ClientError
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> ClientError {
+        ClientError::Io(e)
+    }
+}
+
+impl From<SocksError> for ClientError {
+    fn from(e: SocksError) -> ClientError {
+        ClientError::Proxy(e)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    // 只支持 http://host[:port][/path]
+    pub fn parse(url: &str) -> Result<Url, ClientError> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| ClientError::InvalidUrl(url.to_string()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((h, p)) => (
+                h,
+                p.parse::<u16>()
+                    .map_err(|_| ClientError::InvalidUrl(url.to_string()))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(ClientError::InvalidUrl(url.to_string()));
+        }
+        Ok(Url {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientResponse {
+    pub version: String,
+    pub status_code: u16,
+    pub status_text: String,
+    pub headers: HeaderMap<'static>,
+    // 线上收到的原始 body（可能是 chunked / gzip）
+    raw_body: Vec<u8>,
+    // 解码后的 body
+    body: Vec<u8>,
+}
+
+impl ClientResponse {
+    // 从完整的响应字节解析，decode 为 false 时 body 与 raw_body 相同
+    pub fn parse(bytes: &[u8], max_body_size: usize, decode: bool) -> Result<Self, ClientError> {
+        let head_end = find_subslice(bytes, b"\r\n\r\n")
+            .ok_or_else(|| ClientError::BadResponse("missing header terminator".into()))?;
+        let head = std::str::from_utf8(&bytes[..head_end])
+            .map_err(|_| ClientError::BadResponse("non utf-8 header".into()))?;
+        let mut lines = head.split("\r\n");
+        // HTTP/1.1 200 OK
+        let status_line = lines.next().unwrap_or("");
+        let mut parts = status_line.splitn(3, ' ');
+        let version = parts.next().unwrap_or("").to_string();
+        let status_code = parts
+            .next()
+            .and_then(|c| c.parse::<u16>().ok())
+            .ok_or_else(|| ClientError::BadResponse(status_line.to_string()))?;
+        let status_text = parts.next().unwrap_or("").to_string();
+        let mut headers = HeaderMap::new();
+        for line in lines {
+            if let Some((k, v)) = line.split_once(':') {
+                headers.append(k.trim().to_string(), v.trim().to_string());
+            }
+        }
+        let mut response = ClientResponse {
+            version,
+            status_code,
+            status_text,
+            headers,
+            raw_body: bytes[head_end + 4..].to_vec(),
+            body: Vec::new(),
+        };
+        // 有 Content-Length 时只取声明的长度
+        if let Some(len) = response
+            .header("Content-Length")
+            .and_then(|l| l.parse::<usize>().ok())
+        {
+            response.raw_body.truncate(len);
+        }
+        if response.raw_body.len() > max_body_size && !response.is_chunked() {
+            return Err(ClientError::BodyTooLarge(max_body_size));
+        }
+        response.body = if decode {
+            response.decode_body(max_body_size)?
+        } else {
+            response.raw_body.clone()
+        };
+        Ok(response)
+    }
+
+    // 头部名大小写不敏感
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+    pub fn raw_body(&self) -> &[u8] {
+        &self.raw_body
+    }
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+    pub fn text(&self) -> Result<String, ClientError> {
+        String::from_utf8(self.body.clone())
+            .map_err(|_| ClientError::Decode("body is not valid utf-8".into()))
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding")
+            .map(|te| te.to_ascii_lowercase().contains("chunked"))
+            .unwrap_or(false)
+    }
+
+    // 先去掉 chunked 分块，再按 Content-Encoding 解压
+    fn decode_body(&self, max_body_size: usize) -> Result<Vec<u8>, ClientError> {
+        let mut body = if self.is_chunked() {
+            decode_chunked(&self.raw_body, max_body_size)?
+        } else {
+            self.raw_body.clone()
+        };
+        match self
+            .header("Content-Encoding")
+            .map(|e| e.to_ascii_lowercase())
+        {
+            Some(e) if e == "gzip" || e == "x-gzip" => {
+                body = decode_gzip(&body, max_body_size)?;
+            }
+            Some(e) if e == "identity" => {}
+            Some(e) => return Err(ClientError::Decode(format!("unsupported encoding {}", e))),
+            None => {}
+        }
+        Ok(body)
+    }
+}
+
+// 解析 chunked 编码：<16进制长度>[;扩展]\r\n<数据>\r\n ... 0\r\n\r\n
+pub fn decode_chunked(data: &[u8], max_body_size: usize) -> Result<Vec<u8>, ClientError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    loop {
+        let line_end = find_subslice(&data[pos..], b"\r\n")
+            .ok_or_else(|| ClientError::Decode("truncated chunk size".into()))?
+            + pos;
+        let size_line = std::str::from_utf8(&data[pos..line_end])
+            .map_err(|_| ClientError::Decode("invalid chunk size".into()))?;
+        // 忽略 chunk 扩展
+        let size_str = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| ClientError::Decode(format!("invalid chunk size {:?}", size_str)))?;
+        pos = line_end + 2;
+        if size == 0 {
+            // trailer 对客户端没有意义，直接忽略
+            return Ok(out);
+        }
+        if out.len() + size > max_body_size {
+            return Err(ClientError::BodyTooLarge(max_body_size));
+        }
+        if pos + size + 2 > data.len() {
+            return Err(ClientError::Decode("truncated chunk".into()));
+        }
+        out.extend_from_slice(&data[pos..pos + size]);
+        pos += size + 2;
+    }
+}
+
+// gzip 解压，最多读 max_body_size + 1 字节来判断是否超限
+pub fn decode_gzip(data: &[u8], max_body_size: usize) -> Result<Vec<u8>, ClientError> {
+    let mut out = Vec::new();
+    GzDecoder::new(data)
+        .take(max_body_size as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| ClientError::Decode(e.to_string()))?;
+    if out.len() > max_body_size {
+        return Err(ClientError::BodyTooLarge(max_body_size));
+    }
+    Ok(out)
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// 一次传输的进度快照
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    // 已传输的 body 字节数
+    pub transferred: u64,
+    // 总字节数，下载时没有 Content-Length（chunked）就是 None
+    pub total: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    // 平均速率 字节/秒
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.transferred as f64 / secs
+        } else {
+            0.0
+        }
+    }
+    // 0.0 ~ 1.0，总量未知时返回 None
+    pub fn fraction(&self) -> Option<f64> {
+        self.total.map(|t| {
+            if t == 0 {
+                1.0
+            } else {
+                self.transferred as f64 / t as f64
+            }
+        })
+    }
+}
+
+// 回调里可以画进度条，也可以 sleep 来限速
+pub type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+// 记录起始时间并在每次读写后调用回调
+struct ProgressTracker<'a> {
+    callback: Option<&'a ProgressCallback>,
+    start: Instant,
+    transferred: u64,
+    total: Option<u64>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    fn new(callback: Option<&'a ProgressCallback>, total: Option<u64>) -> Self {
+        ProgressTracker {
+            callback,
+            start: Instant::now(),
+            transferred: 0,
+            total,
+        }
+    }
+    fn advance(&mut self, n: usize) {
+        self.transferred += n as u64;
+        if let Some(cb) = self.callback {
+            cb(&Progress {
+                transferred: self.transferred,
+                total: self.total,
+                elapsed: self.start.elapsed(),
+            });
+        }
+    }
+}
+
+pub struct HttpClient {
+    max_body_size: usize,
+    decode: bool,
+    timeout: Option<Duration>,
+    upload_progress: Option<ProgressCallback>,
+    download_progress: Option<ProgressCallback>,
+    socks5: Option<Socks5Proxy>,
+    pool: Option<Arc<ConnectionPool>>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            decode: true,
+            timeout: Some(Duration::from_secs(30)),
+            upload_progress: None,
+            download_progress: None,
+            socks5: None,
+            pool: None,
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        HttpClient::default()
+    }
+    // 链式配置，消耗 self 再返回
+    pub fn max_body_size(mut self, max: usize) -> Self {
+        self.max_body_size = max;
+        self
+    }
+    // 关闭后 body() 返回原始字节
+    pub fn decode(mut self, decode: bool) -> Self {
+        self.decode = decode;
+        self
+    }
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    // 请求 body 每写出一块调用一次
+    pub fn on_upload_progress(mut self, f: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.upload_progress = Some(Box::new(f));
+        self
+    }
+    // 响应 body 每读入一块调用一次
+    pub fn on_download_progress(mut self, f: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.download_progress = Some(Box::new(f));
+        self
+    }
+
+    // 所有连接都通过这个 SOCKS5 代理建立
+    pub fn socks5(mut self, proxy: Socks5Proxy) -> Self {
+        self.socks5 = Some(proxy);
+        self
+    }
+
+    // 从连接池借连接并保持长连接，多个客户端可以共用一个池；不设置时每个请求一个连接
+    pub fn pool(mut self, pool: Arc<ConnectionPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn get(&self, url: &str) -> Result<ClientResponse, ClientError> {
+        self.send("GET", url, &[], None)
+    }
+    pub fn post(&self, url: &str, body: &[u8]) -> Result<ClientResponse, ClientError> {
+        self.send("POST", url, &[], Some(body))
+    }
+
+    pub fn send(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<ClientResponse, ClientError> {
+        let url = Url::parse(url)?;
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, url.path, url.host);
+        if self.decode {
+            head.push_str("Accept-Encoding: gzip\r\n");
+        }
+        // 不用连接池时每次请求一个连接，HTTP/1.1 默认就是长连接
+        if self.pool.is_none() {
+            head.push_str("Connection: close\r\n");
+        }
+        for (k, v) in headers {
+            head.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        if let Some(b) = body {
+            head.push_str(&format!("Content-Length: {}\r\n", b.len()));
+        }
+        head.push_str("\r\n");
+
+        let Some(pool) = &self.pool else {
+            let mut stream = self.connect(&url)?;
+            let (bytes, _) = self.exchange(&mut stream, &head, body, method)?;
+            return ClientResponse::parse(&bytes, self.max_body_size, self.decode);
+        };
+        // 空闲的连接可能刚好被对方关掉；还没收到任何响应的幂等请求换一个新连接再试一次
+        let mut retry = matches!(
+            method,
+            "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS" | "TRACE"
+        );
+        loop {
+            let (mut stream, reused) = match pool.checkout(&url.host, url.port, self.timeout)? {
+                Some(stream) => (stream, true),
+                None => match self.connect(&url) {
+                    Ok(stream) => (stream, false),
+                    Err(e) => {
+                        pool.checkin(&url.host, url.port, None);
+                        return Err(e);
+                    }
+                },
+            };
+            match self.exchange(&mut stream, &head, body, method) {
+                Ok((bytes, keep_alive)) => {
+                    let resp = ClientResponse::parse(&bytes, self.max_body_size, self.decode);
+                    let stream = (keep_alive && resp.is_ok()).then_some(stream);
+                    pool.checkin(&url.host, url.port, stream);
+                    return resp;
+                }
+                Err(e) => {
+                    pool.checkin(&url.host, url.port, None);
+                    if reused && retry && is_stale(&e) {
+                        retry = false;
+                        continue;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn connect(&self, url: &Url) -> Result<TcpStream, ClientError> {
+        let stream = match &self.socks5 {
+            Some(proxy) => proxy.connect(&url.host, url.port, self.timeout)?,
+            None => TcpStream::connect((url.host.as_str(), url.port))?,
+        };
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        Ok(stream)
+    }
+
+    // 发送请求并读完响应，返回原始字节和连接能否继续使用
+    fn exchange(
+        &self,
+        stream: &mut TcpStream,
+        head: &str,
+        body: Option<&[u8]>,
+        method: &str,
+    ) -> Result<(Vec<u8>, bool), ClientError> {
+        stream.write_all(head.as_bytes())?;
+        if let Some(b) = body {
+            self.write_body(stream, b)?;
+        }
+        self.read_response(stream, method)
+    }
+
+    fn write_body(&self, stream: &mut impl Write, body: &[u8]) -> Result<(), ClientError> {
+        let mut tracker =
+            ProgressTracker::new(self.upload_progress.as_ref(), Some(body.len() as u64));
+        for chunk in body.chunks(TRANSFER_CHUNK_SIZE) {
+            stream.write_all(chunk)?;
+            tracker.advance(chunk.len());
+        }
+        Ok(())
+    }
+
+    // 读一个完整的响应：长度由 Content-Length 或 chunked 确定时读完就停，连接可以接着用；
+    // 都没有时读到 EOF。头部读完后才知道 Content-Length，之后按 body 字节报告进度
+    // 返回原始字节和连接能否复用
+    fn read_response(
+        &self,
+        stream: &mut impl Read,
+        method: &str,
+    ) -> Result<(Vec<u8>, bool), ClientError> {
+        // chunked 开销不可预知，原始字节的上限额外给一倍余量
+        let limit = self.max_body_size * 2 + MAX_HEAD_SIZE;
+        let mut bytes = Vec::new();
+        let mut buf = [0; TRANSFER_CHUNK_SIZE];
+        let mut framing: Option<(usize, Framing)> = None;
+        let mut tracker = ProgressTracker::new(self.download_progress.as_ref(), None);
+        loop {
+            if let Some((head_end, framing)) = &framing {
+                let end = match framing {
+                    Framing::Length(len) => {
+                        (bytes.len() >= head_end + len).then_some(head_end + len)
+                    }
+                    // 结尾的空行收到之前不可能完整，不用每次都从头扫描
+                    Framing::Chunked if bytes.ends_with(b"\r\n\r\n") => {
+                        match chunked::scan(&bytes[*head_end..], self.max_body_size) {
+                            Ok(end) => end.map(|end| head_end + end),
+                            Err(ChunkError::TooLarge(_)) => {
+                                return Err(ClientError::BodyTooLarge(self.max_body_size))
+                            }
+                            Err(e) => return Err(ClientError::Decode(e.to_string())),
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some(end) = end {
+                    bytes.truncate(end);
+                    let keep_alive = keep_alive(&String::from_utf8_lossy(&bytes[..*head_end]));
+                    return Ok((bytes, keep_alive));
+                }
+            }
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                if bytes.is_empty() {
+                    return Err(ClientError::Io(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "connection closed before response",
+                    )));
+                }
+                return Ok((bytes, false));
+            }
+            bytes.extend_from_slice(&buf[..n]);
+            if bytes.len() > limit {
+                return Err(ClientError::BodyTooLarge(self.max_body_size));
+            }
+            match framing {
+                Some(_) => tracker.advance(n),
+                None => {
+                    // 103 Early Hints 等中间响应后面还有最终响应，丢掉继续读；101 是最终响应
+                    while let Some(i) = find_subslice(&bytes, b"\r\n\r\n") {
+                        let head = String::from_utf8_lossy(&bytes[..i]).into_owned();
+                        if is_interim(&head) {
+                            bytes.drain(..i + 4);
+                            continue;
+                        }
+                        let length =
+                            header_value(&head, "Content-Length").and_then(|v| v.parse().ok());
+                        tracker.total = length;
+                        tracker.advance(bytes.len() - (i + 4));
+                        framing = Some((i + 4, Framing::of(&head, method, length)));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 响应 body 的长度怎么确定
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Length(usize),
+    Chunked,
+    // 连接关闭为止，读完连接就不能再用
+    UntilEof,
+}
+
+impl Framing {
+    fn of(head: &str, method: &str, length: Option<u64>) -> Framing {
+        let status = head.split(' ').nth(1).and_then(|c| c.parse::<u16>().ok());
+        // HEAD 的响应、1xx、204 和 304 都没有 body
+        if method == "HEAD" || matches!(status, Some(100..=199 | 204 | 304)) {
+            return Framing::Length(0);
+        }
+        let chunked = header_value(head, "Transfer-Encoding")
+            .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+        match length {
+            _ if chunked => Framing::Chunked,
+            Some(len) => Framing::Length(len as usize),
+            None => Framing::UntilEof,
+        }
+    }
+}
+
+// 1xx 中除了 101 都是中间响应
+fn is_interim(head: &str) -> bool {
+    let status = head.split(' ').nth(1).and_then(|c| c.parse::<u16>().ok());
+    matches!(status, Some(100..=199)) && status != Some(101)
+}
+
+// HTTP/1.1 默认长连接，除非对方说了 Connection: close
+fn keep_alive(head: &str) -> bool {
+    head.starts_with("HTTP/1.1 ")
+        && !header_value(head, "Connection").is_some_and(|c| c.eq_ignore_ascii_case("close"))
+}
+
+// 复用的连接还没读到响应就断了，说明是在空闲期间被对方关闭的
+fn is_stale(e: &ClientError) -> bool {
+    matches!(e, ClientError::Io(e) if matches!(
+        e.kind(),
+        ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+    ))
+}
+
+// 从原始头部里找一个头部的值
+fn header_value<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        k.trim().eq_ignore_ascii_case(name).then(|| v.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = Url::parse("http://localhost:3000/api/shipping/orders").unwrap();
+        assert_eq!(url.host, "localhost");
+        assert_eq!(url.port, 3000);
+        assert_eq!(url.path, "/api/shipping/orders");
+        assert_eq!(Url::parse("http://example.com").unwrap().path, "/");
+        assert!(Url::parse("https://example.com").is_err());
+    }
+    #[test]
+    fn test_decode_chunked() {
+        let body = decode_chunked(b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\n\r\n", 100).unwrap();
+        assert_eq!(body, b"Wikipedia");
+        assert!(matches!(
+            decode_chunked(b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n", 6),
+            Err(ClientError::BodyTooLarge(6))
+        ));
+        assert!(decode_chunked(b"4\r\nWi", 100).is_err());
+    }
+    #[test]
+    fn test_parse_chunked_gzip_response() {
+        let compressed = gzip(b"hello gzip");
+        let mut raw = format!("{:x}\r\n", compressed.len()).into_bytes();
+        raw.extend_from_slice(&compressed);
+        raw.extend_from_slice(b"\r\n0\r\n\r\n");
+        let mut bytes =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Encoding: gzip\r\n\r\n"
+                .to_vec();
+        bytes.extend_from_slice(&raw);
+
+        let res = ClientResponse::parse(&bytes, 1024, true).unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.raw_body(), raw.as_slice());
+        assert_eq!(res.text().unwrap(), "hello gzip");
+
+        let undecoded = ClientResponse::parse(&bytes, 1024, false).unwrap();
+        assert_eq!(undecoded.body(), raw.as_slice());
+    }
+    #[test]
+    fn test_download_progress() {
+        use std::sync::{Arc, Mutex};
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_cb = Arc::clone(&seen);
+        let client = HttpClient::new().on_download_progress(move |p| {
+            seen_cb.lock().unwrap().push((p.transferred, p.total));
+        });
+        let body = vec![b'x'; TRANSFER_CHUNK_SIZE * 2];
+        let mut bytes =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        bytes.extend_from_slice(&body);
+        let (read, keep_alive) = client.read_response(&mut bytes.as_slice(), "GET").unwrap();
+        assert_eq!(read, bytes);
+        assert!(keep_alive);
+        let seen = seen.lock().unwrap();
+        let last = *seen.last().unwrap();
+        assert_eq!(last, (body.len() as u64, Some(body.len() as u64)));
+    }
+    #[test]
+    fn test_upload_progress() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+        let calls = Arc::new(AtomicU64::new(0));
+        let calls_cb = Arc::clone(&calls);
+        let client = HttpClient::new().on_upload_progress(move |p| {
+            assert_eq!(p.total, Some(20_000));
+            assert!(p.fraction().unwrap() <= 1.0);
+            calls_cb.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut sink = Vec::new();
+        client.write_body(&mut sink, &[0u8; 20_000]).unwrap();
+        assert_eq!(sink.len(), 20_000);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+    #[test]
+    fn test_get_through_socks5() {
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // 假代理：不真的转发，CONNECT 之后直接扮演目标服务器
+        let proxy = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut greeting = [0; 3];
+            s.read_exact(&mut greeting).unwrap();
+            s.write_all(&[5, 0]).unwrap();
+            let mut head = [0; 5];
+            s.read_exact(&mut head).unwrap();
+            let mut target = vec![0; head[4] as usize + 2];
+            s.read_exact(&mut target).unwrap();
+            s.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while find_subslice(&request, b"\r\n\r\n").is_none() {
+                let n = s.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            s.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+            (target, request)
+        });
+        let client = HttpClient::new().socks5(Socks5Proxy::new(&addr));
+        let res = client.get("http://internal.example:8080/x").unwrap();
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.body(), b"ok");
+        let (target, request) = proxy.join().unwrap();
+        assert_eq!(&target[..16], b"internal.example");
+        assert_eq!(&target[16..], &8080u16.to_be_bytes());
+        assert!(request.starts_with(b"GET /x HTTP/1.1\r\nHost: internal.example\r\n"));
+    }
+    #[test]
+    fn test_pooled_keep_alive() {
+        use crate::connpool::{ConnectionPool, PoolConfig};
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/n", listener.local_addr().unwrap());
+        // 第一个连接回答两个请求后关闭，第二个连接回答一个之后要求关闭
+        let server = std::thread::spawn(move || {
+            let mut heads = Vec::new();
+            for (replies, last) in [(2, ""), (1, "Connection: close\r\n")] {
+                let (mut s, _) = listener.accept().unwrap();
+                for i in 0..replies {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while find_subslice(&request, b"\r\n\r\n").is_none() {
+                        let n = s.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    heads.push(String::from_utf8(request).unwrap());
+                    let extra = if i + 1 == replies { last } else { "" };
+                    if i == 0 {
+                        write!(s, "HTTP/1.1 200 OK\r\n{}Content-Length: 3\r\n\r\none", extra)
+                    } else {
+                        write!(s, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\ntwo\r\n0\r\n\r\n")
+                    }
+                    .unwrap();
+                }
+            }
+            heads
+        });
+        let pool = Arc::new(ConnectionPool::new(PoolConfig::default()));
+        let client = HttpClient::new().pool(pool.clone());
+        assert_eq!(client.get(&url).unwrap().body(), b"one");
+        assert_eq!(client.get(&url).unwrap().body(), b"two");
+        // 服务器关掉了空闲连接，GET 换新连接重试
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(client.get(&url).unwrap().body(), b"one");
+        let heads = server.join().unwrap();
+        assert_eq!(heads.len(), 3);
+        assert!(heads.iter().all(|h| !h.contains("Connection: close")));
+        let stats = pool.stats();
+        assert_eq!((stats.opened, stats.reused, stats.idle), (2, 1, 0));
+    }
+    #[test]
+    fn test_interim_responses_are_skipped() {
+        use crate::connpool::{ConnectionPool, PoolConfig};
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/n", listener.local_addr().unwrap());
+        // 同一个连接上先回 103 再回 200，第二个请求应该拿到自己的响应
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            for body in ["one", "two"] {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while find_subslice(&request, b"\r\n\r\n").is_none() {
+                    let n = s.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                write!(
+                    s,
+                    "HTTP/1.1 103 Early Hints\r\nLink: </a.css>; rel=preload\r\n\r\n\
+                     HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{}",
+                    body
+                )
+                .unwrap();
+            }
+        });
+        let pool = Arc::new(ConnectionPool::new(PoolConfig::default()));
+        let client = HttpClient::new().pool(pool.clone());
+        let first = client.get(&url).unwrap();
+        assert_eq!(first.status_code, 200);
+        assert_eq!(first.body(), b"one");
+        assert_eq!(client.get(&url).unwrap().body(), b"two");
+        server.join().unwrap();
+        assert_eq!(pool.stats().reused, 1);
+    }
+    #[test]
+    fn test_gzip_bomb_is_capped() {
+        let compressed = gzip(&vec![b'a'; 10_000]);
+        let mut bytes = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            compressed.len()
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&compressed);
+        assert!(matches!(
+            ClientResponse::parse(&bytes, 1000, true),
+            Err(ClientError::BodyTooLarge(1000))
+        ));
+    }
+}
@@ -1,12 +1,30 @@
+use crate::chunked::{self, ChunkError};
+use crate::cookie::Cookie;
+use crate::extensions::Extensions;
+use crate::headermap::HeaderMap;
+use crate::headers::{self, names, validate};
+use crate::proxy::{split_host_port, ForwardedInfo};
+use crate::query::{DuplicatePolicy, QueryParams};
+use crate::scan;
 use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 // #[...]是Rust 中的属性语法，属性用于向编译器提供额外的信息或指令
 // derive 这是一个特殊的属性，用于自动生成特定 trait 的实现。它告诉编译器为标记的类型自动实现指定的 traits
 // Debug 这是 std::fmt::Debug trait，实现这个 trait 允许使用 {:?} 格式说明符来格式化和打印该类型的值。对于调试非常有用，可以轻松打印复杂的数据结构。
 // PartialEq std::cmp::PartialEq trait ， 实现这个 trait 允许使用 == 和 != 运算符来比较该类型的值
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Method {
     Get,
     Post,
+    Put,
+    Delete,
+    Patch,
+    Head,
+    Options,
+    Trace,
+    Connect,
     Uninitialized,
 }
 // 由于 From 是标准库的一部分并且在 prelude 中，我们可以直接使用它而无需引入。
@@ -18,13 +36,40 @@ impl From<&str> for Method {
         match s {
             "GET" => Method::Get,
             "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "HEAD" => Method::Head,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "CONNECT" => Method::Connect,
             _ => Method::Uninitialized,
         }
     }
 }
 
+impl Method {
+    // 请求行里的写法，Uninitialized 返回空字符串
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Head => "HEAD",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Connect => "CONNECT",
+            Method::Uninitialized => "",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Version {
+    V1_0,
     V1_1,
     V2_0,
     Uninitialized,
@@ -33,12 +78,15 @@ pub enum Version {
 impl From<&str> for Version {
     fn from(s: &str) -> Version {
         match s {
-            r"HTTP\1.1" => Version::V1_1,
+            "HTTP/1.0" => Version::V1_0,
+            "HTTP/1.1" => Version::V1_1,
+            "HTTP/2.0" => Version::V2_0,
             _ => Version::Uninitialized,
         }
     }
 }
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Resource {
     //  Path(String) 是 Rust 中枚举（enum）的一种变体（variant）定义方式，具体称为元组变体（tuple variant）。
     //  Path 是这个变体的名称,(String) 表示这个变体包含一个 String 类型的数据
@@ -47,71 +95,488 @@ pub enum Resource {
     Path(String),
 }
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HttpRequest {
     pub method: Method,
     pub version: Version,
     pub resource: Resource,
-    // HashMap 会在需要时自动增长和重新哈希
-    // 标准的 HashMap 不是线程安全的。对于并发场景，可以使用 std::sync::RwLock<HashMap> 或第三方库如 dashmap。
-    // HashMap 在堆上分配内存，可能比数组或向量使用更多内存
-    pub headers: HashMap<String, String>,
-    pub msg_body: String,
-}
-impl From<String> for HttpRequest {
-    fn from(req: String) -> HttpRequest {
-        // 初始化 变量
-        let mut parsed_method = Method::Uninitialized;
-        let mut parsed_version = Version::Uninitialized;
-        let mut parsed_resource = Resource::Path("".to_string());
-        let mut parsed_headers = HashMap::new();
-        let mut parsed_msg_body = "";
-        for line in req.lines() {
-            if line.contains("HTTP") {
-                let (method, resource, version) = process_req_line(line);
-                parsed_method = method;
-                parsed_version = version;
-                parsed_resource = resource;
-            } else if line.contains(":") {
-                let (key, value) = process_header_line(line);
-                parsed_headers.insert(key, value);
-            } else if line.len() == 0 {
-            } else {
-                parsed_msg_body = line;
+    // 名字大小写不敏感，同名头部按出现顺序都保留
+    pub headers: HeaderMap<'static>,
+    // 原始字节，CBOR、上传的文件等二进制请求体也能原样收到；文本用 body_text()
+    #[cfg_attr(feature = "serde", serde(with = "body_serde"))]
+    pub msg_body: Vec<u8>,
+    // chunked 请求体最后的 trailer 字段，和头部分开存放
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trailers: HeaderMap<'static>,
+    // 由服务器在 accept 之后填入，解析阶段拿不到
+    pub remote_addr: Option<SocketAddr>,
+    // 同样由服务器填入：请求是从 HTTPS 监听进来的
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tls: bool,
+    // 只有对端是可信代理时才会被 TrustedProxies::apply 填入
+    pub forwarded: Option<ForwardedInfo>,
+    // 中间件附加的数据，例如 GeoIP 的查询结果；类型擦除了，不参与序列化
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub extensions: Extensions,
+}
+// 请求体的上限，超过时直接拒绝，不再读取
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+// 请求行加头部的上限
+pub const MAX_HEAD_SIZE: usize = 16 * 1024;
+// 请求行（方法、路径和版本）的上限，主要限制的是 URL 的长度
+pub const MAX_REQUEST_LINE: usize = 8 * 1024;
+// 头部个数的上限，同名头部每个都算
+pub const MAX_HEADERS: usize = 100;
+
+// 请求各部分的大小上限，读取循环和解析器共用，超过时不再继续读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub request_line: usize,
+    pub headers: usize,
+    pub head: usize,
+    pub body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            request_line: MAX_REQUEST_LINE,
+            headers: MAX_HEADERS,
+            head: MAX_HEAD_SIZE,
+            body: MAX_BODY_SIZE,
+        }
+    }
+}
+
+// 解析失败的原因，服务器据此返回 400，而不是把解析了一半的请求交给路由
+// 超出 Limits 的几种错误带的是对应的上限
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    // 没有读到头部结束的空行
+    Incomplete,
+    InvalidUtf8,
+    BadRequestLine(String),
+    BadHeader(String),
+    UnsupportedVersion(String),
+    RequestLineTooLong(usize),
+    TooManyHeaders(usize),
+    HeadTooLarge(usize),
+    BodyTooLarge(usize),
+    // 同时带了 Transfer-Encoding 和 Content-Length，可能是请求走私
+    ConflictingLength,
+    // Transfer-Encoding 最后一个编码不是 chunked，无法确定 body 长度
+    LengthRequired,
+    BadChunk(ChunkError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty request"),
+            ParseError::Incomplete => write!(f, "request head is incomplete"),
+            ParseError::InvalidUtf8 => write!(f, "request is not valid UTF-8"),
+            ParseError::BadRequestLine(line) => write!(f, "malformed request line {:?}", line),
+            ParseError::BadHeader(line) => write!(f, "malformed header {:?}", line),
+            ParseError::UnsupportedVersion(v) => write!(f, "unsupported HTTP version {:?}", v),
+            ParseError::RequestLineTooLong(limit) => {
+                write!(f, "request line exceeds the limit of {} bytes", limit)
+            }
+            ParseError::TooManyHeaders(limit) => {
+                write!(f, "request has more than {} headers", limit)
+            }
+            ParseError::HeadTooLarge(limit) => {
+                write!(f, "request head exceeds the limit of {} bytes", limit)
+            }
+            ParseError::ConflictingLength => {
+                write!(f, "both Transfer-Encoding and Content-Length are present")
+            }
+            ParseError::LengthRequired => {
+                write!(f, "body length cannot be determined from Transfer-Encoding")
+            }
+            ParseError::BadChunk(e) => write!(f, "bad chunked body: {}", e),
+            ParseError::BodyTooLarge(limit) => {
+                write!(f, "body exceeds the limit of {} bytes", limit)
             }
         }
-        HttpRequest {
-            method: parsed_method,
-            version: parsed_version,
-            resource: parsed_resource,
-            headers: parsed_headers,
-            msg_body: parsed_msg_body.to_string(),
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl TryFrom<&[u8]> for HttpRequest {
+    type Error = ParseError;
+
+    // 按默认的 Limits 解析
+    fn try_from(raw: &[u8]) -> Result<HttpRequest, ParseError> {
+        HttpRequest::parse(raw, &Limits::default())
+    }
+}
+
+impl HttpRequest {
+    pub fn parse(raw: &[u8], limits: &Limits) -> Result<HttpRequest, ParseError> {
+        if raw.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        let split = split_head(raw);
+        check_head(raw, split.map(|(head, _)| head), limits)?;
+        let (head, body) = split.ok_or(ParseError::Incomplete)?;
+        let head = std::str::from_utf8(head).map_err(|_| ParseError::InvalidUtf8)?;
+        let mut lines = scan::lines(head);
+        let (method, resource, version) = process_req_line(lines.next().unwrap_or(""))?;
+        let mut headers = HeaderMap::new();
+        for line in lines {
+            let (key, value) = process_header_line(line)?;
+            headers.append(key, value);
         }
+        let fields: Vec<(&str, &str)> = headers.iter().collect();
+        let mut trailers = HeaderMap::new();
+        let body = match framing(&fields)? {
+            Framing::Length(n) if n > limits.body => {
+                return Err(ParseError::BodyTooLarge(limits.body))
+            }
+            // 连接在 body 收完之前就结束了
+            Framing::Length(n) if n > body.len() => return Err(ParseError::Incomplete),
+            // 只取声明的长度，多出来的字节不属于这个请求
+            Framing::Length(n) => body[..n].to_vec(),
+            Framing::Chunked => {
+                let decoded = chunked::decode(body, limits.body)
+                    .map_err(chunk_error)?
+                    .ok_or(ParseError::Incomplete)?;
+                trailers.extend(decoded.trailers);
+                decoded.body
+            }
+            Framing::None if body.len() > limits.body => {
+                return Err(ParseError::BodyTooLarge(limits.body))
+            }
+            Framing::None => body.to_vec(),
+        };
+        Ok(HttpRequest {
+            method,
+            version,
+            resource,
+            headers,
+            msg_body: body,
+            trailers,
+            remote_addr: None,
+            tls: false,
+            forwarded: None,
+            extensions: Extensions::new(),
+        })
     }
 }
 
-fn process_req_line(s: &str) -> (Method, Resource, Version) {
-    let mut words = s.split_whitespace();
-    let method = words.next().unwrap();
-    let resource = words.next().unwrap();
-    let version = words.next().unwrap();
+// 头部大小、请求行长度和头部个数；head 是完整的头部，还没收全时为 None，只检查已经收到的部分
+fn check_head(raw: &[u8], head: Option<&[u8]>, limits: &Limits) -> Result<(), ParseError> {
+    let received = head.unwrap_or(raw);
+    if received.len() > limits.head {
+        return Err(ParseError::HeadTooLarge(limits.head));
+    }
+    let line = &received[..scan::find(b'\n', received).unwrap_or(received.len())];
+    if line.strip_suffix(b"\r").unwrap_or(line).len() > limits.request_line {
+        return Err(ParseError::RequestLineTooLong(limits.request_line));
+    }
+    // 请求行和收完的头部各以一个 \n 结尾；完整的头部里最后一行后面的换行已经切掉了，
+    // 还没收全时要减去请求行的那个，正在收的最后一行不算
+    let lines = scan::count(b'\n', received);
+    let headers = match head {
+        Some(_) => lines,
+        None => lines.saturating_sub(1),
+    };
+    if headers > limits.headers {
+        return Err(ParseError::TooManyHeaders(limits.headers));
+    }
+    Ok(())
+}
+
+// 读取循环用：请求收全之后返回整个请求（头部 + body）的长度，
+// 还没收全时返回 None；出错时调用方停止读取，交给 try_from 报告具体原因
+pub fn message_len(raw: &[u8]) -> Result<Option<usize>, ParseError> {
+    message_len_with(raw, &Limits::default())
+}
+
+// 同 message_len，按给定的上限检查，超出时不用等请求收全就能停止读取
+pub fn message_len_with(raw: &[u8], limits: &Limits) -> Result<Option<usize>, ParseError> {
+    let split = split_head(raw);
+    check_head(raw, split.map(|(head, _)| head), limits)?;
+    let Some((head, body)) = split else {
+        return Ok(None);
+    };
+    let head_len = raw.len() - body.len();
+    let head = std::str::from_utf8(head).map_err(|_| ParseError::InvalidUtf8)?;
+    let fields: Vec<(&str, &str)> = scan::lines(head)
+        .skip(1)
+        .filter_map(|line| scan::split_once(line, b':'))
+        .collect();
+    match framing(&fields)? {
+        Framing::Length(n) if n > limits.body => Err(ParseError::BodyTooLarge(limits.body)),
+        Framing::Length(n) => Ok(Some(head_len + n)),
+        Framing::Chunked => Ok(chunked::scan(body, limits.body)
+            .map_err(chunk_error)?
+            .map(|n| head_len + n)),
+        Framing::None => Ok(Some(head_len)),
+    }
+}
+
+// body 的长度怎么确定
+enum Framing {
+    None,
+    Length(usize),
+    Chunked,
+}
+
+fn framing(fields: &[(&str, &str)]) -> Result<Framing, ParseError> {
+    let find = |name: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+    };
+    match (find("Transfer-Encoding"), find("Content-Length")) {
+        (Some(_), Some(_)) => Err(ParseError::ConflictingLength),
+        // 多个编码时 chunked 必须是最后一个
+        (Some((_, te)), None) => match te.rsplit(',').next() {
+            Some(last) if last.trim().eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked),
+            _ => Err(ParseError::LengthRequired),
+        },
+        (None, Some((k, v))) => {
+            // 同名头部现在都保留下来，多个 Content-Length 必须一致，否则无法确定 body 在哪结束
+            if let Some((k, other)) = fields.iter().find(|(name, other)| {
+                name.trim().eq_ignore_ascii_case("Content-Length") && other.trim() != v.trim()
+            }) {
+                return Err(ParseError::BadHeader(format!("{}: {}", k, other)));
+            }
+            v.trim()
+                .parse::<usize>()
+                .map(Framing::Length)
+                .map_err(|_| ParseError::BadHeader(format!("{}: {}", k, v)))
+        }
+        (None, None) => Ok(Framing::None),
+    }
+}
+
+fn chunk_error(e: ChunkError) -> ParseError {
+    match e {
+        ChunkError::TooLarge(limit) => ParseError::BodyTooLarge(limit),
+        e => ParseError::BadChunk(e),
+    }
+}
+
+// 按第一个空行把请求分成头部和 body，兼容只用 \n 换行的客户端
+fn split_head(raw: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (at, len) = scan::head_end(raw)?;
+    Some((&raw[..at], &raw[at + len..]))
+}
+
+impl HttpRequest {
+    // 头部名大小写不敏感
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    // 真实客户端地址：经过可信代理时取转发头里的地址，否则是 TCP 对端
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.forwarded
+            .as_ref()
+            .and_then(|f| f.client_ip)
+            .or(self.remote_addr.map(|a| a.ip()))
+    }
+
+    // 客户端看到的协议 / 主机 / 端口，用于拼接重定向和签名 URL 的绝对地址
+    pub fn effective_scheme(&self) -> String {
+        self.forwarded
+            .as_ref()
+            .and_then(|f| f.proto.clone())
+            .unwrap_or_else(|| if self.tls { "https" } else { "http" }.to_string())
+    }
+    pub fn effective_host(&self) -> Option<String> {
+        if let Some(host) = self.forwarded.as_ref().and_then(|f| f.host.clone()) {
+            return Some(host);
+        }
+        self.header("Host").and_then(|h| split_host_port(h).0)
+    }
+    pub fn effective_port(&self) -> u16 {
+        let forwarded = self.forwarded.as_ref();
+        let host_port = match forwarded.and_then(|f| f.host.as_ref()) {
+            Some(_) => forwarded.and_then(|f| f.port),
+            None => self.header("Host").and_then(|h| split_host_port(h).1),
+        };
+        host_port.or(forwarded.and_then(|f| f.port)).unwrap_or(
+            if self.effective_scheme() == "https" {
+                443
+            } else {
+                80
+            },
+        )
+    }
+    // scheme://host[:port]，默认端口省略
+    pub fn effective_origin(&self) -> String {
+        let scheme = self.effective_scheme();
+        let host = self
+            .effective_host()
+            .unwrap_or_else(|| "localhost".to_string());
+        let port = self.effective_port();
+        let default_port = if scheme == "https" { 443 } else { 80 };
+        if port == default_port {
+            format!("{}://{}", scheme, host)
+        } else {
+            format!("{}://{}:{}", scheme, host, port)
+        }
+    }
+
+    // HTTP/1.1 默认保持连接，HTTP/1.0 需要客户端显式要求 keep-alive
+    // Connection 按逗号分隔的选项匹配，不会把 X-close 之类当成 close
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header(names::CONNECTION).unwrap_or("");
+        match self.version {
+            Version::V1_1 => !headers::has_option(connection, "close"),
+            Version::V1_0 => headers::has_option(connection, "keep-alive"),
+            _ => false,
+        }
+    }
+
+    // HTTP/1.0 客户端不认识 chunked，响应必须带 Content-Length 或者以关闭连接结束
+    pub fn accepts_chunked(&self) -> bool {
+        self.version == Version::V1_1
+    }
+
+    // 不含查询字符串的路径，路由只按这部分匹配
+    pub fn path(&self) -> &str {
+        let Resource::Path(path) = &self.resource;
+        path.split('?').next().unwrap_or("")
+    }
+
+    // 请求体是 UTF-8 文本时返回文本，图片、CBOR 等返回 None
+    pub fn body_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.msg_body).ok()
+    }
+
+    // Cookie 头部里的全部 cookie，按出现顺序，只有名字和值
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.header(names::COOKIE)
+            .map(Cookie::parse_header)
+            .unwrap_or_default()
+    }
+
+    // 同名 cookie 出现多次时取第一个（通常是 Path 最长的那个）
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies()
+            .into_iter()
+            .find(|c| c.name == name)
+            .map(|c| c.value)
+    }
+
+    // 解码后的查询参数；同一个键出现多次时取第一个，需要全部值时用 query_params
+    pub fn query(&self) -> HashMap<String, String> {
+        self.query_params()
+            .to_map(DuplicatePolicy::FirstWins)
+            .expect("FirstWins never rejects duplicates")
+    }
+
+    // ? 后面的查询参数，保留所有重复的键，由调用方按 DuplicatePolicy 取值
+    pub fn query_params(&self) -> QueryParams {
+        let Resource::Path(path) = &self.resource;
+        match path.split_once('?') {
+            Some((_, query)) => QueryParams::parse(query),
+            None => QueryParams::default(),
+        }
+    }
+}
 
-    (
-        method.into(),
-        Resource::Path(resource.to_string()),
-        version.into(),
-    )
+fn process_req_line(s: &str) -> Result<(Method, Resource, Version), ParseError> {
+    let bad = || ParseError::BadRequestLine(s.to_string());
+    let mut words = s.split(' ');
+    let (Some(method), Some(resource), Some(version), None) =
+        (words.next(), words.next(), words.next(), words.next())
+    else {
+        return Err(bad());
+    };
+    let method: Method = method.into();
+    // 路径必须以 / 开头；* 只用于 OPTIONS；代理请求是完整的 URL，只保留路径部分
+    let target = match resource {
+        "*" if method == Method::Options => Some(resource.to_string()),
+        _ if resource.starts_with('/') => Some(resource.to_string()),
+        _ => origin_form(resource),
+    };
+    let Some(target) = target.filter(|_| method != Method::Uninitialized) else {
+        return Err(bad());
+    };
+    let parsed: Version = version.into();
+    match parsed {
+        Version::V1_0 | Version::V1_1 => {}
+        _ if version.starts_with("HTTP/") => {
+            return Err(ParseError::UnsupportedVersion(version.to_string()))
+        }
+        _ => return Err(bad()),
+    }
+    Ok((method, Resource::Path(target), parsed))
 }
-fn process_header_line(s: &str) -> (String, String) {
-    let mut header_items = s.split(":");
-    let mut key = String::from("");
-    let mut value = String::from("");
-    if let Some(k) = header_items.next() {
-        key = k.to_string();
+// http://host:3000/a?b -> /a?b，没有路径时是 /；只接受 http 和 https
+fn origin_form(target: &str) -> Option<String> {
+    let (scheme, rest) = target.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
     }
-    if let Some(v) = header_items.next() {
-        value = v.to_string();
+    let start = rest.find(['/', '?']).unwrap_or(rest.len());
+    if start == 0 {
+        return None;
+    }
+    match &rest[start..] {
+        path if path.starts_with('/') => Some(path.to_string()),
+        query => Some(format!("/{}", query)),
+    }
+}
+// 只按第一个冒号分开，Host: localhost:3000 的端口留在值里
+// 名字必须是 token，名字和冒号之间不能有空白；值去掉首尾的空格和制表符（RFC 7230 3.2）
+// 折叠的头部（以空白开头的续行）已被 RFC 7230 废弃，同样拒绝
+fn process_header_line(line: &str) -> Result<(String, String), ParseError> {
+    let bad = || ParseError::BadHeader(line.to_string());
+    let (name, value) = scan::split_once(line, b':').ok_or_else(bad)?;
+    let value = value.trim_matches([' ', '\t']);
+    // 值里的 CR、NUL 等控制字符可能被前后的代理解释成不同的东西
+    validate(name, value).map_err(|_| bad())?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+// 请求体序列化时是 UTF-8 文本就写成字符串，录制文件里可以直接读；否则写成字节数组
+// 反序列化两种都接受，以前录制的字符串也能读回来
+#[cfg(feature = "serde")]
+mod body_serde {
+    use serde::de::{Deserializer, Error, SeqAccess, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(body) {
+            Ok(text) => serializer.serialize_str(text),
+            Err(_) => serializer.collect_seq(body),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        struct BodyVisitor;
+
+        impl<'de> Visitor<'de> for BodyVisitor {
+            type Value = Vec<u8>;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or a list of bytes")
+            }
+            fn visit_str<E: Error>(self, v: &str) -> Result<Vec<u8>, E> {
+                Ok(v.as_bytes().to_vec())
+            }
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(v.to_vec())
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+                let mut body = Vec::new();
+                while let Some(b) = seq.next_element()? {
+                    body.push(b);
+                }
+                Ok(body)
+            }
+        }
+
+        deserializer.deserialize_any(BodyVisitor)
     }
-    (key, value)
 }
 
 // 这是一个条件编译属性。它告诉 Rust 编译器只在运行测试时编译这个模块,在正常的程序构建中，这个模块会被忽略。
@@ -127,22 +592,315 @@ mod tests {
         // 实现原理:当你实现 From<&str> for Method：Rust 自动为 &str 实现了 Into<Method>。将&str转换为Method
         let m: Method = "GET".into();
         assert_eq!(m, Method::Get);
+        for name in [
+            "GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS", "TRACE", "CONNECT",
+        ] {
+            let m: Method = name.into();
+            assert_eq!(m.as_str(), name);
+        }
+        // 方法名区分大小写
+        let m: Method = "get".into();
+        assert_eq!(m, Method::Uninitialized);
     }
     #[test]
     fn test_version_into() {
-        let v: Version = r"HTTP\1.1".into();
+        let v: Version = "HTTP/1.1".into();
         assert_eq!(v, Version::V1_1);
+        let v: Version = "HTTP/1.0".into();
+        assert_eq!(v, Version::V1_0);
+        let v: Version = r"HTTP\1.1".into();
+        assert_eq!(v, Version::Uninitialized);
     }
     #[test]
     fn test_read_http() {
         let s: String = String::from("GET /greeting HTTP/1.1\r\nHost: localhost:3000\r\nUser_Agent: curl/7.71.1\r\nAccept: */*\r\n\r\n");
-        let mut headers_expected: HashMap<String, String> = HashMap::new();
-        //
-        headers_expected.insert("Host".into(), " localhost".into());
-        headers_expected.insert("Accept".into(), " */*".into());
-        headers_expected.insert("User-Agent".into(), " curl/7.71.1".into());
-        let req: HttpRequest = s.into();
+        let req = HttpRequest::try_from(s.as_bytes()).unwrap();
         assert_eq!(Method::Get, req.method);
+        let headers: Vec<(&str, &str)> = req.headers.iter().collect();
+        assert_eq!(
+            headers,
+            vec![
+                ("Host", "localhost:3000"),
+                ("User_Agent", "curl/7.71.1"),
+                ("Accept", "*/*")
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_errors() {
+        let parse = |raw: &str| HttpRequest::try_from(raw.as_bytes()).err();
+        assert_eq!(parse(""), Some(ParseError::Empty));
+        assert_eq!(parse("GET / HT"), Some(ParseError::Incomplete));
+        assert_eq!(
+            HttpRequest::try_from(&b"GET /\xff HTTP/1.1\r\n\r\n"[..]).err(),
+            Some(ParseError::InvalidUtf8)
+        );
+        for line in [
+            "GET HTTP/1.1",
+            "FETCH / HTTP/1.1",
+            "GET nope HTTP/1.1",
+            "GET / FTP/1.0",
+            "GET * HTTP/1.1",
+            "GET ftp://x/a HTTP/1.1",
+            "GET http:///a HTTP/1.1",
+        ] {
+            assert_eq!(
+                parse(&format!("{}\r\n\r\n", line)),
+                Some(ParseError::BadRequestLine(line.into()))
+            );
+        }
+        // OPTIONS * 和完整 URL 形式的请求目标
+        let path = |raw: &str| HttpRequest::try_from(raw.as_bytes()).unwrap().resource;
+        assert_eq!(
+            path("OPTIONS * HTTP/1.1\r\n\r\n"),
+            Resource::Path("*".into())
+        );
+        for (target, expected) in [
+            ("http://x/health", "/health"),
+            ("HTTP://x:3000/a?b=1", "/a?b=1"),
+            ("https://x", "/"),
+            ("http://x?b=1", "/?b=1"),
+        ] {
+            let raw = format!("GET {} HTTP/1.1\r\n\r\n", target);
+            assert_eq!(path(&raw), Resource::Path(expected.into()), "{}", target);
+        }
+        assert_eq!(
+            parse("GET / HTTP/2.0\r\n\r\n"),
+            Some(ParseError::UnsupportedVersion("HTTP/2.0".into()))
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n"),
+            Some(ParseError::BadHeader(" folded".into()))
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nno colon\r\n\r\n"),
+            Some(ParseError::BadHeader("no colon".into()))
+        );
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n"),
+            Some(ParseError::BodyTooLarge(MAX_BODY_SIZE))
+        );
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            Some(ParseError::BadHeader(_))
+        ));
+    }
+    #[test]
+    fn test_header_lines() {
+        let parse = |raw: &str| HttpRequest::try_from(raw.as_bytes());
+        let req = parse("GET / HTTP/1.1\r\nHost:\t localhost:3000 \t\r\nX-Empty:\r\n\r\n").unwrap();
+        assert_eq!(req.header("host"), Some("localhost:3000"));
+        assert_eq!(req.header("X-Empty"), Some(""));
+        let req = parse("GET / HTTP/1.1\r\nReferer: http://a/b?c=d:e\r\n\r\n").unwrap();
+        assert_eq!(req.header("Referer"), Some("http://a/b?c=d:e"));
+        for line in [
+            "Host : a",
+            ": a",
+            "Bad Name: a",
+            "X-Tab\t: a",
+            "Caf\u{e9}: a",
+            "X-Nul: a\0b",
+            "X-Cr: a\rb",
+            "\tfolded",
+        ] {
+            let raw = format!("GET / HTTP/1.1\r\nHost: a\r\n{}\r\n\r\n", line);
+            assert_eq!(
+                parse(&raw).err(),
+                Some(ParseError::BadHeader(line.into())),
+                "{:?}",
+                line
+            );
+        }
+    }
+    #[test]
+    fn test_connection_semantics() {
+        let parse = |raw: &str| HttpRequest::try_from(raw.as_bytes()).unwrap();
+        let req = parse("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(req.version, Version::V1_1);
+        assert!(req.keep_alive() && req.accepts_chunked());
+        assert!(!parse("GET / HTTP/1.1\r\nConnection: close\r\n\r\n").keep_alive());
+        let req = parse("GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(req.version, Version::V1_0);
+        assert!(!req.keep_alive() && !req.accepts_chunked());
+        assert!(parse("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").keep_alive());
+        assert!(!parse("GET / HTTP/1.1\r\nConnection: Upgrade, Close\r\n\r\n").keep_alive());
+        assert!(parse("GET / HTTP/1.1\r\nConnection: x-close\r\n\r\n").keep_alive());
+        assert!(!parse("GET / HTTP/1.0\r\nConnection: not-keep-alive\r\n\r\n").keep_alive());
+    }
+    #[test]
+    fn test_body_uses_content_length() {
+        let raw = "POST /orders HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello, extra";
+        let req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        assert_eq!(req.body_text(), Some("hello"));
+        let req = HttpRequest::try_from("POST / HTTP/1.1\n\nbody".as_bytes()).unwrap();
+        assert_eq!(req.body_text(), Some("body"));
+        // body 还没收全
+        assert_eq!(
+            HttpRequest::try_from(&raw.as_bytes()[..raw.len() - 10]).err(),
+            Some(ParseError::Incomplete)
+        );
+    }
+    #[test]
+    fn test_repeated_headers() {
+        let raw = "POST / HTTP/1.1\r\ncontent-length: 2\r\nAccept: a\r\naccept: b\r\n\r\nhi";
+        let req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        assert_eq!(req.headers.get("Content-Length"), Some("2"));
+        assert_eq!(req.header("CONTENT-LENGTH"), Some("2"));
+        assert_eq!(req.headers.get_all("Accept").count(), 2);
+        // 相同的 Content-Length 重复出现没问题，不同时拒绝
+        let raw = "POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nhi";
+        assert_eq!(
+            HttpRequest::try_from(raw.as_bytes()).unwrap().body_text(),
+            Some("hi")
+        );
+        let raw = "POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi!";
+        assert_eq!(
+            HttpRequest::try_from(raw.as_bytes()).err(),
+            Some(ParseError::BadHeader("Content-Length: 3".into()))
+        );
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let raw = "POST /orders?id=1 HTTP/1.0\r\nHost: a\r\nContent-Length: 2\r\n\r\nhi";
+        let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        req.remote_addr = Some("10.0.0.1:5000".parse().unwrap());
+        req.extensions.insert(7u32);
+        let json = serde_json::to_string(&req).unwrap();
+        let back: HttpRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.method, Method::Post);
+        assert_eq!(back.version, Version::V1_0);
+        assert_eq!(back.resource, req.resource);
+        assert_eq!(back.headers, req.headers);
+        assert_eq!(back.msg_body, b"hi");
+        assert!(json.contains(r#""msg_body":"hi""#));
+        req.msg_body = vec![0xa1, 0x00];
+        let json = serde_json::to_string(&req).unwrap();
+        let back: HttpRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.msg_body, [0xa1, 0x00]);
+        assert_eq!(back.remote_addr, req.remote_addr);
+        // extensions 不参与序列化
+        assert!(back.extensions.is_empty());
+    }
+    #[test]
+    fn test_chunked_body() {
+        let raw = "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   4\r\nWiki\r\n5\r\npedia\r\n0\r\nX-Sum: 9\r\n\r\n";
+        let req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        assert_eq!(req.body_text(), Some("Wikipedia"));
+        assert_eq!(&req.trailers["x-sum"], "9");
+        assert_eq!(message_len(raw.as_bytes()), Ok(Some(raw.len())));
+        assert_eq!(message_len(&raw.as_bytes()[..raw.len() - 2]), Ok(None));
+        let parse = |raw: &str| HttpRequest::try_from(raw.as_bytes()).err();
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n"),
+            Some(ParseError::ConflictingLength)
+        );
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n"),
+            Some(ParseError::LengthRequired)
+        );
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            Some(ParseError::BadChunk(ChunkError::InvalidSize("zz".into())))
+        );
+    }
+    #[test]
+    fn test_message_len() {
+        assert_eq!(message_len(b"POST / HTTP/1.1\r\nContent-Le"), Ok(None));
+        let head = "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n";
+        assert_eq!(message_len(head.as_bytes()), Ok(Some(head.len() + 5)));
+        assert_eq!(message_len(b"GET / HTTP/1.1\r\n\r\n"), Ok(Some(18)));
+        assert_eq!(
+            message_len(b"POST / HTTP/1.1\r\nContent-Length: 9999999999\r\n\r\n"),
+            Err(ParseError::BodyTooLarge(MAX_BODY_SIZE))
+        );
+        assert_eq!(
+            message_len(&vec![b'a'; MAX_HEAD_SIZE + 1]),
+            Err(ParseError::HeadTooLarge(MAX_HEAD_SIZE))
+        );
+    }
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            request_line: 20,
+            headers: 2,
+            head: 100,
+            body: 10,
+        };
+        let parse = |raw: &str| HttpRequest::parse(raw.as_bytes(), &limits).err();
+        let len = |raw: &str| message_len_with(raw.as_bytes(), &limits);
+        assert_eq!(parse("GET /ok HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n"), None);
+        // 请求行还没收完就能判断出太长
+        let long = format!("GET /{} HTTP/1.1", "a".repeat(20));
+        assert_eq!(len(&long[..25]), Err(ParseError::RequestLineTooLong(20)));
+        assert_eq!(
+            parse(&format!("{}\r\n\r\n", long)),
+            Some(ParseError::RequestLineTooLong(20))
+        );
+        // 逐字节收到头部个数刚好等于上限的请求，任何时候都不能提前判断为太多
+        let exact = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n";
+        for end in 1..exact.len() {
+            assert_eq!(len(&exact[..end]), Ok(None), "{:?}", &exact[..end]);
+        }
+        assert_eq!(len(exact), Ok(Some(exact.len())));
+        let many = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        // 第三个头部收完就可以停止读取
+        assert_eq!(len(&many[..34]), Err(ParseError::TooManyHeaders(2)));
+        assert_eq!(len(&many[..33]), Ok(None));
+        assert_eq!(len(many), Err(ParseError::TooManyHeaders(2)));
+        assert_eq!(parse(many), Some(ParseError::TooManyHeaders(2)));
+        let big = format!("GET / HTTP/1.1\r\nA: {}", "x".repeat(100));
+        assert_eq!(len(&big), Err(ParseError::HeadTooLarge(100)));
+        let post = "POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\n";
+        assert_eq!(len(post), Err(ParseError::BodyTooLarge(10)));
+        assert_eq!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nb\r\nhello world\r\n0\r\n\r\n"),
+            Some(ParseError::BodyTooLarge(10))
+        );
+        // 默认的上限
+        let headers: String = (0..=MAX_HEADERS)
+            .map(|i| format!("X-{}: 1\r\n", i))
+            .collect();
+        assert_eq!(
+            HttpRequest::try_from(format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes()).err(),
+            Some(ParseError::TooManyHeaders(MAX_HEADERS))
+        );
+    }
+    #[test]
+    fn test_query_params() {
+        let req = HttpRequest::try_from(
+            "GET /api/shipping/orders?sort=id&sort=date HTTP/1.1\r\n\r\n".as_bytes(),
+        )
+        .unwrap();
+        let q = req.query_params();
+        assert_eq!(q.get_all("sort"), vec!["id", "date"]);
+        let req = HttpRequest::try_from("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+        assert!(req.query_params().is_empty());
+        assert_eq!(req.path(), "/");
+    }
+    #[test]
+    fn test_query() {
+        let req = HttpRequest::try_from(
+            "GET /search?q=rust+http&tag=a%26b&tag=c&empty HTTP/1.1\r\n\r\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(req.path(), "/search");
+        let q = req.query();
+        assert_eq!(q["q"], "rust http");
+        assert_eq!(q["tag"], "a&b");
+        assert_eq!(q["empty"], "");
+        assert_eq!(q.len(), 3);
+    }
+    #[test]
+    fn test_cookies() {
+        let req = HttpRequest::try_from(
+            "GET / HTTP/1.1\r\nCookie: session=abc; theme=dark\r\n\r\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(req.cookies().len(), 2);
+        assert_eq!(req.cookie("theme").as_deref(), Some("dark"));
+        assert_eq!(req.cookie("missing"), None);
     }
 }
 // Into 是 Rust 标准库中的一个 trait。它定义在 std::convert::Into 中。它是 From trait 的对偶（dual）
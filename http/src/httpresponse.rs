@@ -1,133 +1,543 @@
+use crate::chunked::ChunkedWriter;
+use crate::clock;
+use crate::cookie::Cookie;
+use crate::etag;
+use crate::headermap::HeaderMap;
+use crate::headers::{self, names, HeaderError};
+use crate::mime;
+use crate::range::ByteRange;
+use crate::status::StatusCode;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{Result, Write};
+use std::fs::{self, File};
+use std::io::{self, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 // 任何引用类型都需要生命周期标注。
 // 拥有所有权的类型（如 String, Vec 等）不需要生命周期标注。
 // 结构体中有引用，整个结构体就需要生命周期参数。
 // impl 块和方法中使用的生命周期要与结构体定义一致。
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // 当结构体中的 字段是引用类型 需要添加生命周期
 // 对于拥有所有权的类型（如 String），不需要生命周期标注
 pub struct HttpResponse<'a> {
     // 不需要修改所以用了 引用
     version: &'a str,
-    status_code: &'a str,
-    status_text: &'a str,
-    headers: Option<HashMap<&'a str, &'a str>>,
-    // body 是 Option<String>，String 拥有所有权，不需要生命周期标注
-    body: Option<String>,
+    // 原因短语由状态码决定，见 StatusCode::reason
+    status: StatusCode,
+    // 大部分值是借用的字面量，重定向等需要清洗的值则持有自己的 String
+    #[cfg_attr(feature = "serde", serde(default))]
+    headers: HeaderMap<'a>,
+    // Set-Cookie 可以出现多次，不能放进 headers，每个 cookie 单独一行
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    cookies: Vec<String>,
+    // body 是原始字节，图片、字体等非 UTF-8 内容也能原样发送
+    // Vec<u8> 拥有所有权，不需要生命周期标注
+    body: Option<Vec<u8>>,
+    // 静态文件等不读进内存的 body，发送时才打开，见 HttpResponse::file
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    // 装箱，不让每个响应都变大
+    file: Option<Box<FileBody>>,
+}
+
+// 文件 body 每次读写的块大小，大文件也只占这么多内存
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct FileBody {
+    path: PathBuf,
+    // 从文件的哪个字节开始发，Range 请求时不为 0
+    #[cfg_attr(feature = "serde", serde(default))]
+    offset: u64,
+    // 普通文件的长度来自 metadata；管道、设备等长度未知，用 chunked 发送
+    len: Option<u64>,
 }
 // 当为带有生命周期参数的结构体实现方法时，需要在 impl 后声明生命周期。
 impl<'a> Default for HttpResponse<'a> {
     fn default() -> Self {
         Self {
-            version: "HTTP/1.1".into(),
-            status_code: "200".into(),
-            status_text: "OK".into(),
-            headers: None,
+            version: "HTTP/1.1",
+            status: StatusCode::Ok,
+            headers: HeaderMap::new(),
+            cookies: Vec::new(),
             body: None,
+            file: None,
         }
     }
 }
 // 为特定类型实现from
+// 完整的响应报文，Content-Length 是 body 的字节数
+// 文件 body 在这里读进来；文件读不了（被删除、变短……）时已经生成的部分作废，换成 500
+impl<'a> From<HttpResponse<'a>> for Vec<u8> {
+    fn from(res: HttpResponse) -> Vec<u8> {
+        let mut out = Vec::new();
+        if res.send_response(&mut out).is_err() {
+            out.clear();
+            let _ = HttpResponse::new("500", None, Some(String::new())).send_response(&mut out);
+        }
+        out
+    }
+}
+// 方便测试和日志查看，body 不是 UTF-8 时有损转换
 impl<'a> From<HttpResponse<'a>> for String {
     fn from(res: HttpResponse) -> String {
-        let res1 = res.clone();
-        format!(
-            "{} {} {}\r\n{}Content-Length: {}\r\n\r\n{}",
-            &res1.version(),
-            &res1.status_code(),
-            &res1.status_text(),
-            &res1.headers(),
-            &res.body.unwrap().len(),
-            &res1.body()
-        )
+        let bytes: Vec<u8> = res.into();
+        String::from_utf8_lossy(&bytes).into_owned()
     }
 }
 // 当为带有生命周期参数的结构体实现方法时，需要在 impl 后声明生命周期。
 // 如果方法参数或返回值涉及结构体的生命周期，需要使用相同的生命周期标注
 impl<'a> HttpResponse<'a> {
+    // 逐步设置状态码、头部和 body，不会自动添加 Content-Type
+    pub fn builder() -> ResponseBuilder<'a> {
+        ResponseBuilder {
+            response: HttpResponse::default(),
+            error: None,
+        }
+    }
     pub fn new(
         status_code: &'a str,
         headers: Option<HashMap<&'a str, &'a str>>,
         body: Option<String>,
     ) -> HttpResponse<'a> {
         // 初始化变量
-        let mut response: HttpResponse<'a> = HttpResponse::default();
-        // 状态码
-        if status_code != "200" {
-            // 直接赋值 status_code 是可以的，因为两者都是 &'a str 类型 response.status_code = status_code;
-            // 它提供了更好的灵活性。如果将来 status_code 的类型改变（比如改为 String），.into() 仍然可以工作。
-            // 它使代码更加一致，特别是当你在其他地方也使用 .into() 时
-            response.status_code = status_code.into();
-        }
+        // 状态码写错（不是三位数字）时当作服务器内部错误，而不是发出格式错误的状态行
+        let mut response: HttpResponse<'a> = HttpResponse {
+            status: StatusCode::parse(status_code).unwrap_or(StatusCode::InternalServerError),
+            ..HttpResponse::default()
+        };
         // header
-        response.headers = match &headers {
-            // 有值就返回值
-            // 这里使用 _h 是一种常见的 Rust 模式匹配写法
-            // 这个模式匹配 Some 变体，但我们不需要使用其中的值
-            // 它只是检查 headers 是否是 Some，而不关心 Some 中具体包含什么
-            Some(_h) => headers,
+        response.headers = match headers {
+            // 有值就返回值，但丢弃名字或值不合法的头部，防止 CRLF 注入
+            // 需要知道哪个头部不合法时用 with_header
+            Some(h) => h
+                .into_iter()
+                .filter(|(k, v)| headers::validate(k, v).is_ok())
+                .collect(),
             // 没值 就创建一个
-            None => {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            }
-        };
-        // 返回status_text 根据状态码 设置
-        response.status_text = match response.status_code {
-            "200" => "OK".into(),
-            "400" => "Bad Request".into(),
-            "404" => "Not Found".into(),
-            "500" => "Internal Server Error".into(),
-            _ => "Not Found".into(),
+            None => [(names::CONTENT_TYPE, "text/html")].into_iter().collect(),
         };
         // 返回body
-        response.body = body;
+        response.body = body.map(String::into_bytes);
+        response
+    }
+    // 校验后添加（或覆盖）一个头部，名字或值不合法时返回错误
+    pub fn with_header(
+        mut self,
+        name: &'a str,
+        value: impl Into<Cow<'a, str>>,
+    ) -> std::result::Result<Self, HeaderError> {
+        self.set_header(name, value)?;
+        Ok(self)
+    }
+    // 同 with_header，供只拿到 &mut 的中间件使用
+    pub fn set_header(
+        &mut self,
+        name: &'a str,
+        value: impl Into<Cow<'a, str>>,
+    ) -> std::result::Result<(), HeaderError> {
+        let value = value.into();
+        headers::validate(name, &value)?;
+        self.headers.insert(name, value);
+        Ok(())
+    }
+    // 同 set_header，但保留已有的同名头部，例如多个 Link 或 Vary
+    // 名字也可以是运行时才知道的，例如代理转发上游的响应头
+    pub fn append_header(
+        &mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+    ) -> std::result::Result<(), HeaderError> {
+        let name = name.into();
+        let value = value.into();
+        headers::validate(&name, &value)?;
+        self.headers.append(name, value);
+        Ok(())
+    }
+    // 追加一个 Set-Cookie 头部，名字或值不合法时返回错误
+    pub fn add_cookie(&mut self, cookie: &Cookie) -> std::result::Result<(), HeaderError> {
+        cookie.validate()?;
+        self.cookies.push(cookie.to_string());
+        Ok(())
+    }
+    pub fn with_cookie(mut self, cookie: &Cookie) -> std::result::Result<Self, HeaderError> {
+        self.add_cookie(cookie)?;
+        Ok(self)
+    }
+    // 已经添加的 Set-Cookie 的值，按添加顺序
+    pub fn set_cookies(&self) -> impl Iterator<Item = &str> {
+        self.cookies.iter().map(String::as_str)
+    }
+    // 来自请求的数据（路径、查询参数）写进头部前必须先清洗，
+    // 否则 %0d%0a 解码后的 CRLF 可以伪造头部甚至拆分出第二个响应
+    pub fn with_header_sanitized(mut self, name: &'a str, value: &str) -> Self {
+        let value = headers::sanitize_value(value).into_owned();
+        self.headers.insert(name, value);
+        self
+    }
+    // 下载：浏览器弹出保存对话框，默认文件名是 filename，Content-Type 按扩展名推断
+    pub fn attachment(filename: &str, body: impl Into<Vec<u8>>) -> HttpResponse<'a> {
+        let mut headers = HashMap::new();
+        headers.insert(names::CONTENT_TYPE, mime::from_path(filename));
+        HttpResponse::new("200", Some(headers), None)
+            .with_bytes(body.into())
+            .with_header(
+                names::CONTENT_DISPOSITION,
+                headers::content_disposition("attachment", filename),
+            )
+            .expect("content disposition is a valid header value")
+    }
+    // 200，body 是 path 指向的文件，发送时按 FILE_CHUNK_SIZE 分块复制到连接上，不整个读进内存
+    // Content-Type 按扩展名推断，Last-Modified 取文件的修改时间，ETag 由修改时间和长度生成
+    pub fn file(path: impl AsRef<Path>) -> io::Result<HttpResponse<'a>> {
+        let path = path.as_ref();
+        let meta = fs::metadata(path)?;
+        if meta.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+        let mut response = HttpResponse::default();
+        let file_name = path.to_string_lossy();
         response
+            .headers
+            .insert(names::CONTENT_TYPE, mime::from_path(&file_name));
+        if let Ok(modified) = meta.modified() {
+            response
+                .headers
+                .insert(names::LAST_MODIFIED, clock::http_date(modified));
+            if meta.is_file() {
+                response
+                    .headers
+                    .insert(names::ETAG, etag::from_metadata(meta.len(), modified));
+            }
+        }
+        response.file = Some(Box::new(FileBody {
+            path: path.to_path_buf(),
+            offset: 0,
+            len: meta.is_file().then_some(meta.len()),
+        }));
+        Ok(response)
+    }
+    // 重定向，status_code 一般是 301 / 302 / 303 / 307 / 308
+    pub fn redirect(status_code: &'a str, location: &str) -> HttpResponse<'a> {
+        let response = HttpResponse::new(status_code, None, Some(String::new()));
+        response.with_header_sanitized(names::LOCATION, location)
+    }
+    // 供中间件检查和改写响应
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+    // 头部名大小写不敏感
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+    pub fn headers(&self) -> &HeaderMap<'a> {
+        &self.headers
+    }
+    // 文件 body 对应的文件，中间件需要内容时自己读取
+    pub fn file_path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|f| f.path.as_path())
+    }
+    // body 的字节数，长度未知的文件 body 返回 None
+    pub fn body_len(&self) -> Option<u64> {
+        match &self.file {
+            Some(file) => file.len,
+            None => Some(self.body().len() as u64),
+        }
+    }
+    // body 不是 UTF-8 文本（图片等）或者是文件 body 时返回 None
+    pub fn body_text(&self) -> Option<&str> {
+        std::str::from_utf8(self.body.as_deref()?).ok()
+    }
+    pub fn body_bytes(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+    // 206，只发送 body 里 range 这一段，range 要先用 range::resolve 按 body_len 检查过
+    // 文件 body 发送时从 range.start 开始读，不会读前面的部分
+    pub fn with_range(mut self, range: ByteRange) -> Self {
+        let Some(total) = self.body_len() else {
+            return self;
+        };
+        self.status = StatusCode::PartialContent;
+        self.headers
+            .insert(names::CONTENT_RANGE, range.content_range(total));
+        match &mut self.file {
+            Some(file) => {
+                file.offset += range.start;
+                file.len = Some(range.len());
+            }
+            None => {
+                let body = self.body.take().unwrap_or_default();
+                self.body = Some(body[range.start as usize..=range.end as usize].to_vec());
+            }
+        }
+        self
+    }
+    // 304，客户端缓存的版本还是最新的：去掉 body，保留 ETag、Last-Modified、Cache-Control 等头部
+    pub fn not_modified(mut self) -> Self {
+        self.status = StatusCode::NotModified;
+        self.body = None;
+        self.file = None;
+        self.headers.remove(names::CONTENT_RANGE);
+        self
+    }
+    // 内存里的 body 还没有 ETag 时按内容生成一个
+    pub fn with_etag(mut self) -> Self {
+        if self.file.is_none() && !self.headers.contains_key(names::ETAG) {
+            let tag = etag::from_bytes(self.body());
+            self.headers.insert(names::ETAG, tag);
+        }
+        self
+    }
+    // 替换 body，原来的文件 body 不再发送
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = Some(body.into_bytes());
+        self.file = None;
+        self
+    }
+    // 二进制 body，Content-Type 由调用方设置
+    pub fn with_bytes(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self.file = None;
+        self
+    }
+    // 103 Early Hints（RFC 8297），每个链接一行 Link，例如 </app.css>; rel=preload; as=style
+    pub fn early_hints<S: AsRef<str>>(
+        links: &[S],
+    ) -> std::result::Result<HttpResponse<'a>, HeaderError> {
+        let mut response = HttpResponse::default().with_status(StatusCode::EarlyHints);
+        for link in links {
+            response.append_header(names::LINK, link.as_ref().to_string())?;
+        }
+        Ok(response)
+    }
+    // 1xx 临时响应只有状态行和头部，同一个请求后面还会有最终响应
+    pub fn send_interim(&self, write_stream: &mut impl Write) -> Result<()> {
+        if !self.status.is_informational() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not an interim status", self.status),
+            ));
+        }
+        write!(write_stream, "{}\r\n", self.head())
     }
     pub fn send_response(&self, write_stream: &mut impl Write) -> Result<()> {
-        // clone() 是 Rust 中用于创建对象深拷贝的方法。创建一个对象的完整副本，包括所有拥有的数据,新副本与原对象完全独立，修改一个不会影响另一个,对于复杂的数据结构，可能会涉及大量的内存分配和复制。
-        // 实现了 Clone trait 的类型才能使用 clone()
-        let res = self.clone();
-        let response_string: String = String::from(res);
+        self.send_with(write_stream, true)
+    }
+    // chunked 为 false 时用于 HTTP/1.0 客户端，长度未知的文件 body 不带长度写出，由关闭连接表示结束
+    pub fn send_with(&self, write_stream: &mut impl Write, chunked: bool) -> Result<()> {
+        if let Some(file) = &self.file {
+            return self.send_file(write_stream, file, chunked);
+        }
+        // 304 没有 body，Content-Length 会被缓存当成资源的长度，不发
+        if self.status == StatusCode::NotModified {
+            return write!(write_stream, "{}\r\n", self.head());
+        }
+        let body = self.body();
         // write! 是 Rust 标准库提供的一个宏，用于格式化并写入数据到一个实现了 std::io::Write trait 的对象中
         // 语法 write!(destination, "formatted string {}", value)
-        let _ = write!(write_stream, "{}", response_string);
+        write!(
+            write_stream,
+            "{}Content-Length: {}\r\n\r\n",
+            self.head(),
+            body.len()
+        )?;
+        // body 按原始字节写出，不经过 String
+        write_stream.write_all(body)
+    }
+    // 只发送状态行和头部，回复 HEAD 请求用；Content-Length 和 GET 时一样，文件不会被打开
+    pub fn send_head(&self, write_stream: &mut impl Write) -> Result<()> {
+        if self.status == StatusCode::NotModified {
+            return write!(write_stream, "{}\r\n", self.head());
+        }
+        match self.body_len() {
+            Some(len) => write!(
+                write_stream,
+                "{}Content-Length: {}\r\n\r\n",
+                self.head(),
+                len
+            ),
+            None => write!(
+                write_stream,
+                "{}{}: chunked\r\n\r\n",
+                self.head(),
+                names::TRANSFER_ENCODING
+            ),
+        }
+    }
+    // 长度已知时先写 Content-Length，再按块复制；文件在打开之后变短时返回 UnexpectedEof，
+    // 已经发出的长度对不上，连接不能再用
+    fn send_file(
+        &self,
+        write_stream: &mut impl Write,
+        file: &FileBody,
+        chunked: bool,
+    ) -> Result<()> {
+        let mut body = File::open(&file.path)?;
+        if file.offset > 0 {
+            body.seek(SeekFrom::Start(file.offset))?;
+        }
+        let Some(len) = file.len else {
+            return self.send_streaming(write_stream, &mut body, chunked);
+        };
+        write!(
+            write_stream,
+            "{}Content-Length: {}\r\n\r\n",
+            self.head(),
+            len
+        )?;
+        let copied = copy_chunks(&mut body.take(len), write_stream)?;
+        if copied < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} is shorter than {} bytes", file.path.display(), len),
+            ));
+        }
         Ok(())
     }
+    // 长度未知的 body（文件、生成器……）边读边发，不经过 String
+    // chunked 为 false 时用于 HTTP/1.0 客户端（见 HttpRequest::accepts_chunked）：
+    // 不带长度直接写出，由关闭连接表示结束
+    pub fn send_streaming(
+        &self,
+        write_stream: &mut impl Write,
+        body: &mut impl Read,
+        chunked: bool,
+    ) -> Result<()> {
+        if chunked {
+            return self.send_chunked_with(write_stream, |w| copy_chunks(body, w).map(|_| ()));
+        }
+        write!(
+            write_stream,
+            "{}{}: close\r\n\r\n",
+            self.head(),
+            names::CONNECTION
+        )?;
+        copy_chunks(body, write_stream)?;
+        write_stream.flush()
+    }
+    // 由闭包逐块生成 body，每次写入成为一个 chunk
+    pub fn send_chunked_with<W: Write>(
+        &self,
+        write_stream: &mut W,
+        generate: impl FnOnce(&mut ChunkedWriter<&mut W>) -> Result<()>,
+    ) -> Result<()> {
+        write!(
+            write_stream,
+            "{}{}: chunked\r\n\r\n",
+            self.head(),
+            names::TRANSFER_ENCODING
+        )?;
+        let mut chunks = ChunkedWriter::new(write_stream);
+        generate(&mut chunks)?;
+        chunks.finish().map(|_| ())
+    }
+    // 状态行和头部，不含结尾的空行
+    fn head(&self) -> String {
+        format!(
+            "{} {}\r\n{}",
+            self.version(),
+            self.status,
+            self.header_lines()
+        )
+    }
     // getter
     fn version(&self) -> &str {
         // 方法返回一个对 self.status_text 的引用,不转移所有权，只是借用数据
         // 适用于 status_text 字段本身就是 &str 类型的情况,生命周期与 &self 相关联，意味着返回的引用不能比 self 活得更久
-        &self.version
-    }
-    fn status_code(&self) -> &str {
-        &self.status_code
+        self.version
     }
-    fn status_text(&self) -> &str {
-        &self.status_text
-    }
-    fn headers(&self) -> String {
+    fn header_lines(&self) -> String {
         // unwrap() 是 Rust 中常用但需谨慎使用的方法。它主要用于处理 Option 和 Result 类型
         // 有值取值 None 直接panic
         // unwrap_or(default): 提供一个默认值，在 None 或 Err 时返回。
         // unwrap_or_else(f): 提供一个闭包，在 None 或 Err 时调用。
         // expect("message"): 类似 unwrap()，但可以指定 panic 时的错误消息。
-        let map: HashMap<&str, &str> = self.headers.clone().unwrap();
         let mut header_string: String = "".into();
-        for (k, v) in map.iter() {
+        for (k, v) in self.headers.iter() {
+            // 文本类型总是带上 charset
+            let v = if k.eq_ignore_ascii_case(names::CONTENT_TYPE) {
+                mime::with_charset(v)
+            } else {
+                Cow::Borrowed(v)
+            };
             header_string = format!("{}{}:{}\r\n", header_string, k, v);
         }
+        for cookie in &self.cookies {
+            header_string = format!("{}{}:{}\r\n", header_string, names::SET_COOKIE, cookie);
+        }
         header_string
     }
-    fn body(&self) -> String {
-        match &self.body {
-            Some(body) => body.into(),
-            None => "".into(),
+    fn body(&self) -> &[u8] {
+        self.body.as_deref().unwrap_or_default()
+    }
+}
+// 按 FILE_CHUNK_SIZE 读一块写一块，chunked 编码下每块就是一个 chunk
+fn copy_chunks(from: &mut impl Read, to: &mut impl Write) -> Result<u64> {
+    let mut buf = vec![0; FILE_CHUNK_SIZE];
+    let mut total = 0;
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        to.write_all(&buf[..n])?;
+        total += n as u64;
+    }
+}
+// HttpResponse::builder().status(StatusCode::NotFound).header("X-Foo", "bar").body("...").build()
+// 头部不合法时记下第一个错误，由 build 返回
+#[derive(Debug)]
+pub struct ResponseBuilder<'a> {
+    response: HttpResponse<'a>,
+    error: Option<HeaderError>,
+}
+
+impl<'a> ResponseBuilder<'a> {
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.response.status = status;
+        self
+    }
+    // 同名头部（不区分大小写）后设置的覆盖前面的
+    pub fn header(mut self, name: &'a str, value: impl Into<Cow<'a, str>>) -> Self {
+        let value = value.into();
+        match headers::validate(name, &value) {
+            Ok(()) => {
+                self.response.headers.insert(name, value);
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.response.body = Some(body.into());
+        self
+    }
+    pub fn build(self) -> std::result::Result<HttpResponse<'a>, HeaderError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.response),
         }
     }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,14 +547,11 @@ mod tests {
         let response_actual = HttpResponse::new("200", None, Some("xxxx".into()));
         let response_expected = HttpResponse {
             version: "HTTP/1.1",
-            status_code: "200",
-            status_text: "OK",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            status: StatusCode::Ok,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            cookies: Vec::new(),
+            body: Some(b"xxxx".to_vec()),
+            file: None,
         };
         assert_eq!(response_actual, response_expected);
     }
@@ -153,34 +560,281 @@ mod tests {
         let response_actual = HttpResponse::new("404", None, Some("xxxx".into()));
         let response_expected = HttpResponse {
             version: "HTTP/1.1",
-            status_code: "404",
-            status_text: "Not Found",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            status: StatusCode::NotFound,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            cookies: Vec::new(),
+            body: Some(b"xxxx".to_vec()),
+            file: None,
         };
         assert_eq!(response_actual, response_expected);
     }
 
+    #[test]
+    fn test_header_validation() {
+        let response = HttpResponse::new("200", None, None)
+            .with_header("X-Request-Id", "abc")
+            .unwrap();
+        assert_eq!(response.headers.get("x-request-id"), Some("abc"));
+        assert_eq!(
+            response.with_header("X-Evil", "a\r\nSet-Cookie: x=1"),
+            Err(HeaderError::InvalidValue("a\r\nSet-Cookie: x=1".into()))
+        );
+        let mut injected = HashMap::new();
+        injected.insert("Location", "/\r\nSet-Cookie: x=1");
+        let response = HttpResponse::new("200", Some(injected), None);
+        assert!(response.headers.is_empty());
+    }
+    #[test]
+    fn test_redirect_cannot_split_response() {
+        // /%0d%0aSet-Cookie:%20x=1 解码后的样子
+        let decoded = "/next\r\nSet-Cookie: x=1\r\n\r\n<script>";
+        let http_string: String = HttpResponse::redirect("302", decoded).into();
+        let head = http_string.split("\r\n\r\n").next().unwrap();
+        assert!(head.starts_with("HTTP/1.1 302 Found\r\n"));
+        assert!(head.contains("Location:/next%0D%0ASet-Cookie: x=1%0D%0A%0D%0A<script>"));
+        assert!(!head.contains("\r\nSet-Cookie"));
+        // 头部之后只有空 body
+        assert!(http_string.ends_with("Content-Length: 0\r\n\r\n"));
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let response = HttpResponse::redirect("302", "/next\r\nx");
+        let json = serde_json::to_string(&response).unwrap();
+        let back: HttpResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, response);
+    }
+    #[test]
+    fn test_send_streaming() {
+        let response = HttpResponse::new("200", None, None);
+        let mut out = Vec::new();
+        let mut body: &[u8] = b"hello";
+        response.send_streaming(&mut out, &mut body, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type:text/html; charset=utf-8\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nhello\r\n0\r\n\r\n"
+        );
+        let mut out = Vec::new();
+        let mut body: &[u8] = b"hello";
+        response.send_streaming(&mut out, &mut body, false).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Connection: close\r\n\r\nhello"));
+        let mut out = Vec::new();
+        response
+            .send_chunked_with(&mut out, |w| {
+                for i in 0..3 {
+                    write!(w, "{}", i)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("1\r\n0\r\n1\r\n1\r\n1\r\n2\r\n0\r\n\r\n"));
+    }
+    #[test]
+    fn test_status_line() {
+        for (code, line) in [
+            ("201", "HTTP/1.1 201 Created\r\n"),
+            ("418", "HTTP/1.1 418 I'm a teapot\r\n"),
+            ("599", "HTTP/1.1 599 \r\n"),
+            // 以前不认识的状态码都会变成 Not Found
+            ("502", "HTTP/1.1 502 Bad Gateway\r\n"),
+            ("2oo", "HTTP/1.1 500 Internal Server Error\r\n"),
+        ] {
+            let text: String = HttpResponse::new(code, None, None).into();
+            assert!(text.starts_with(line), "{}", text);
+        }
+        let response = HttpResponse::new("200", None, None).with_status(StatusCode::Accepted);
+        assert!(response.status().is_success());
+        assert_eq!(response.status().as_u16(), 202);
+    }
+    #[test]
+    fn test_early_hints() {
+        let hints = HttpResponse::early_hints(&[
+            "</app.css>; rel=preload; as=style",
+            "</app.js>; rel=preload; as=script",
+        ])
+        .unwrap();
+        let mut out = Vec::new();
+        hints.send_interim(&mut out).unwrap();
+        // 没有 Content-Type 和 Content-Length，空行之后紧接着就是最终响应
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 103 Early Hints\r\nLink:</app.css>; rel=preload; as=style\r\n\
+             Link:</app.js>; rel=preload; as=script\r\n\r\n"
+        );
+        assert!(HttpResponse::early_hints(&["</a>\r\nX-Evil: 1"]).is_err());
+        let mut out = Vec::new();
+        assert!(HttpResponse::new("200", None, None)
+            .send_interim(&mut out)
+            .is_err());
+        assert!(out.is_empty());
+    }
+    #[test]
+    fn test_builder() {
+        let response = HttpResponse::builder()
+            .status(StatusCode::NotFound)
+            .header("X-Foo", "bar")
+            .header(names::CONTENT_TYPE, "text/plain")
+            .body(b"missing")
+            .build()
+            .unwrap();
+        let text: String = response.into();
+        assert!(text.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(text.contains("X-Foo:bar\r\n"));
+        assert!(text.ends_with("Content-Length: 7\r\n\r\nmissing"));
+        // 默认 200，没有 body 也没有 Content-Type
+        let empty: String = HttpResponse::builder().build().unwrap().into();
+        assert_eq!(empty, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(
+            HttpResponse::builder()
+                .header("X-Evil", "a\r\nb")
+                .header("Bad Name", "x")
+                .build(),
+            Err(HeaderError::InvalidValue("a\r\nb".into()))
+        );
+    }
+    #[test]
+    fn test_binary_body() {
+        // PNG 文件头，不是合法的 UTF-8
+        let png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff];
+        let response = HttpResponse::new("200", None, None).with_bytes(png.clone());
+        assert_eq!(response.body_text(), None);
+        assert_eq!(response.body_bytes(), Some(png.as_slice()));
+        let mut out = Vec::new();
+        response.send_response(&mut out).unwrap();
+        assert!(out.ends_with(&png));
+        let head = String::from_utf8_lossy(&out[..out.len() - png.len()]).into_owned();
+        assert!(head.ends_with("Content-Length: 9\r\n\r\n"));
+        // 多字节字符按字节计算长度
+        let text: Vec<u8> = HttpResponse::new("200", None, Some("你好".into())).into();
+        assert!(text.ends_with("Content-Length: 6\r\n\r\n你好".as_bytes()));
+        // 没有 body 时长度为 0
+        let empty: String = HttpResponse::new("204", None, None).into();
+        assert!(empty.ends_with("Content-Length: 0\r\n\r\n"));
+    }
+    #[test]
+    fn test_file_body() {
+        let dir = std::env::temp_dir().join(format!("http-file-body-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.bin");
+        // 比一块大，最后一块不满
+        let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        fs::write(&path, &data).unwrap();
+        let response = HttpResponse::file(&path).unwrap();
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("application/octet-stream")
+        );
+        assert!(response.header("Last-Modified").unwrap().ends_with(" GMT"));
+        let etag = format!("-{:x}\"", data.len());
+        assert!(response.header("ETag").unwrap().ends_with(&etag));
+        assert_eq!(response.body_len(), Some(data.len() as u64));
+        assert_eq!(response.body_bytes(), None);
+        assert_eq!(response.file_path(), Some(path.as_path()));
+        let mut out = Vec::new();
+        response.send_response(&mut out).unwrap();
+        let head = format!("Content-Length: {}\r\n\r\n", data.len());
+        let at = out
+            .windows(head.len())
+            .position(|w| w == head.as_bytes())
+            .unwrap();
+        assert_eq!(&out[at + head.len()..], &data[..]);
+        // HEAD 不打开文件
+        let mut out = Vec::new();
+        response.send_head(&mut out).unwrap();
+        assert!(out.ends_with(head.as_bytes()));
+        // 文件在发送前变短
+        fs::write(&path, b"short").unwrap();
+        let mut out = Vec::new();
+        let err = response.send_response(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let out: Vec<u8> = response.clone().into();
+        assert!(out.starts_with(b"HTTP/1.1 500"));
+        assert!(out.ends_with(b"Content-Length: 0\r\n\r\n"));
+        // 内存里的 body 替换文件 body
+        let replaced = response.with_body("x".into());
+        assert_eq!(replaced.file_path(), None);
+        assert_eq!(replaced.body_len(), Some(1));
+        assert!(HttpResponse::file(&dir).is_err());
+        assert!(HttpResponse::file(dir.join("missing")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_not_modified() {
+        let response = HttpResponse::new("200", None, Some("hello".into())).with_etag();
+        assert_eq!(response.header("ETag"), Some("\"a430d84680aabd0b\""));
+        // 已经有 ETag 时不覆盖
+        let response = response.with_body("changed".into()).with_etag();
+        assert_eq!(response.header("ETag"), Some("\"a430d84680aabd0b\""));
+        let response = response.not_modified();
+        let text: String = response.clone().into();
+        assert_eq!(
+            text,
+            "HTTP/1.1 304 Not Modified\r\nContent-Type:text/html; charset=utf-8\r\nETag:\"a430d84680aabd0b\"\r\n\r\n"
+        );
+        let mut out = Vec::new();
+        response.send_head(&mut out).unwrap();
+        assert_eq!(out, text.as_bytes());
+    }
+    #[test]
+    fn test_with_range() {
+        let range = ByteRange { start: 2, end: 4 };
+        let text: String = HttpResponse::new("200", None, Some("abcdefg".into()))
+            .with_range(range)
+            .into();
+        assert!(text.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(text.contains("Content-Range:bytes 2-4/7\r\n"));
+        assert!(text.ends_with("Content-Length: 3\r\n\r\ncde"));
+        let path = std::env::temp_dir().join(format!("http-range-{}.txt", std::process::id()));
+        fs::write(&path, "abcdefg").unwrap();
+        let text: String = HttpResponse::file(&path).unwrap().with_range(range).into();
+        assert!(text.contains("Content-Range:bytes 2-4/7\r\n"));
+        assert!(text.ends_with("Content-Length: 3\r\n\r\ncde"));
+        fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn test_attachment() {
+        let response = HttpResponse::attachment("数据.csv", "a,b\n");
+        assert_eq!(response.header("Content-Type"), Some("text/csv"));
+        assert_eq!(
+            response.header("Content-Disposition"),
+            Some("attachment; filename=\"__.csv\"; filename*=UTF-8''%E6%95%B0%E6%8D%AE.csv")
+        );
+        assert_eq!(response.body_bytes(), Some(&b"a,b\n"[..]));
+    }
+    #[test]
+    fn test_multiple_set_cookie_headers() {
+        let mut response = HttpResponse::new("200", None, None)
+            .with_cookie(&Cookie::new("a", "1").http_only(true))
+            .unwrap();
+        response
+            .add_cookie(&Cookie::new("b", "2").path("/"))
+            .unwrap();
+        assert!(response.add_cookie(&Cookie::new("c", "x\r\ny")).is_err());
+        let text: String = response.clone().into();
+        assert!(text.contains("\r\nSet-Cookie:a=1; HttpOnly\r\nSet-Cookie:b=2; Path=/\r\n"));
+        assert_eq!(
+            response.set_cookies().collect::<Vec<_>>(),
+            vec!["a=1; HttpOnly", "b=2; Path=/"]
+        );
+    }
     #[test]
     fn test_http_response_creation() {
         let response_expected = HttpResponse {
             version: "HTTP/1.1",
-            status_code: "404",
-            status_text: "Not Found",
-            headers: {
-                let mut h = HashMap::new();
-                h.insert("Content-Type", "text/html");
-                Some(h)
-            },
-            body: Some("xxxx".into()),
+            status: StatusCode::NotFound,
+            headers: [("Content-Type", "text/html")].into_iter().collect(),
+            cookies: Vec::new(),
+            body: Some(b"xxxx".to_vec()),
+            file: None,
         };
         let http_string: String = response_expected.into();
         let actual_string =
-            "HTTP/1.1 404 Not Found\r\nContent-Type:text/html\r\nContent-Length: 4\r\n\r\nxxxx"
+            "HTTP/1.1 404 Not Found\r\nContent-Type:text/html; charset=utf-8\r\nContent-Length: 4\r\n\r\nxxxx"
                 .to_string();
         assert_eq!(http_string, actual_string);
     }
@@ -0,0 +1,173 @@
+// JSON 请求体和响应体：req.json::<T>() 反序列化，HttpResponse::json(&value) 序列化
+// 只接受 Content-Type 为 application/json（或 application/xxx+json）的请求体，
+// 表单之类的请求不会被当成 JSON 误解析
+use crate::codec::{Codec, CodecError, Visit};
+use crate::headers::names;
+use crate::httprequest::HttpRequest;
+use crate::httpresponse::HttpResponse;
+use crate::status::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+pub const CONTENT_TYPE: &str = "application/json";
+
+#[derive(Debug)]
+pub enum JsonError {
+    // Content-Type 不是 JSON，值为请求里的 Content-Type（没有时为空）
+    UnsupportedMediaType(String),
+    // 不是合法的 JSON，或者和目标类型对不上
+    Invalid(serde_json::Error),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::UnsupportedMediaType(t) if t.is_empty() => {
+                write!(f, "expected Content-Type {}", CONTENT_TYPE)
+            }
+            JsonError::UnsupportedMediaType(t) => {
+                write!(f, "expected Content-Type {}, got {:?}", CONTENT_TYPE, t)
+            }
+            JsonError::Invalid(e) => write!(f, "invalid JSON body: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl JsonError {
+    // 415 / 400，body 是 {"error": "..."}，处理器可以直接返回
+    pub fn into_response(self) -> HttpResponse<'static> {
+        let status = match self {
+            JsonError::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            JsonError::Invalid(_) => StatusCode::BadRequest,
+        };
+        HttpResponse::json(&serde_json::json!({ "error": self.to_string() })).with_status(status)
+    }
+}
+
+// application/json; charset=utf-8、application/problem+json 都算
+pub fn is_json(content_type: &str) -> bool {
+    let media = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    media == CONTENT_TYPE
+        || media.len() > "application/+json".len()
+            && media.starts_with("application/")
+            && media.ends_with("+json")
+}
+
+// 注册在 codec::Codecs 里的 JSON 编解码器，+json 的类型也由它解码
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn media_type(&self) -> &'static str {
+        CONTENT_TYPE
+    }
+    fn decodes(&self, media: &str) -> bool {
+        is_json(media)
+    }
+    fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError> {
+        let mut de = serde_json::Deserializer::from_slice(body);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))
+            .map_err(|e| CodecError::Invalid(e.to_string()))?;
+        // 值后面还有别的内容
+        de.end().map_err(|e| CodecError::Invalid(e.to_string()))
+    }
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(value).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+}
+
+impl HttpRequest {
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, JsonError> {
+        let content_type = self.header(names::CONTENT_TYPE).unwrap_or_default();
+        if !is_json(content_type) {
+            return Err(JsonError::UnsupportedMediaType(content_type.to_string()));
+        }
+        serde_json::from_slice(&self.msg_body).map_err(JsonError::Invalid)
+    }
+}
+
+impl<'a> HttpResponse<'a> {
+    // 200 + application/json；需要别的状态码时再调用 with_status
+    // 序列化失败（例如 map 的键不是字符串）是程序错误，返回 500
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> HttpResponse<'a> {
+        let mut headers = HashMap::new();
+        headers.insert(names::CONTENT_TYPE, CONTENT_TYPE);
+        match serde_json::to_vec(value) {
+            Ok(body) => HttpResponse::new("200", Some(headers), None).with_bytes(body),
+            Err(e) => HttpResponse::new("500", None, Some(format!("cannot serialize JSON: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        status: String,
+    }
+
+    fn request(content_type: &str, body: &str) -> HttpRequest {
+        let raw = format!(
+            "POST /orders HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        );
+        HttpRequest::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_request_json() {
+        let req = request(
+            "application/json; charset=utf-8",
+            r#"{"id":7,"status":"shipped"}"#,
+        );
+        assert_eq!(
+            req.json::<Order>().unwrap(),
+            Order {
+                id: 7,
+                status: "shipped".into()
+            }
+        );
+        let err = request("text/plain", "{}").json::<Order>().unwrap_err();
+        assert!(matches!(err, JsonError::UnsupportedMediaType(ref t) if t == "text/plain"));
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::UnsupportedMediaType
+        );
+        let err = request("application/json", r#"{"id":"x"}"#)
+            .json::<Order>()
+            .unwrap_err();
+        assert!(matches!(err, JsonError::Invalid(_)));
+        assert_eq!(err.into_response().status(), StatusCode::BadRequest);
+        assert!(is_json("Application/Merge-Patch+JSON"));
+        assert!(!is_json("application/jsonx"));
+    }
+
+    #[test]
+    fn test_response_json() {
+        let order = Order {
+            id: 1,
+            status: "新".into(),
+        };
+        let resp = HttpResponse::json(&order);
+        assert_eq!(resp.status(), StatusCode::Ok);
+        assert_eq!(resp.header("Content-Type"), Some("application/json"));
+        assert_eq!(resp.body_text(), Some(r#"{"id":1,"status":"新"}"#));
+        let bytes: Vec<u8> = resp.into();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains("Content-Length: 23\r\n"));
+    }
+}
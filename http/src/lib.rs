@@ -1,2 +1,25 @@
+pub mod chunked;
+pub mod clock;
+#[cfg(feature = "codec")]
+pub mod codec;
+pub mod connpool;
+pub mod cookie;
+pub mod etag;
+pub mod extensions;
+pub mod headermap;
+pub mod headers;
+pub mod html;
+pub mod httpclient;
 pub mod httprequest;
 pub mod httpresponse;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod links;
+pub mod mime;
+pub mod proxy;
+pub mod query;
+pub mod random;
+pub mod range;
+pub mod scan;
+pub mod socks;
+pub mod status;
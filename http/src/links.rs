@@ -0,0 +1,141 @@
+// RFC 8288 Web Linking：分页接口在 Link 头部里给出首页、前后页和末页的地址
+//
+//     let links = Links::paginate(2, 5, |p| format!("/orders?page={}", p));
+//     resp.set_header(names::LINK, links.to_string())
+//     // Link: </orders?page=1>; rel="first", </orders?page=1>; rel="prev", ...
+//
+// 开启 serde 时同样的链接可以放进 body 的 _links 对象（HAL 的写法），客户端不用解析头部：
+//     {"_links": {"first": {"href": "/orders?page=1"}, "next": {"href": "/orders?page=3"}}}
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Links {
+    // (rel, href)，按添加顺序
+    entries: Vec<(String, String)>,
+}
+
+impl Links {
+    pub fn new() -> Self {
+        Links::default()
+    }
+    // href 是已经编码好的 URI 引用，不能含有空格、> 等字符；同一个 rel 再次添加时替换
+    pub fn link(mut self, rel: &str, href: impl Into<String>) -> Self {
+        let href = href.into();
+        match self.entries.iter_mut().find(|(r, _)| r == rel) {
+            Some(entry) => entry.1 = href,
+            None => self.entries.push((rel.to_string(), href)),
+        }
+        self
+    }
+    pub fn first(self, href: impl Into<String>) -> Self {
+        self.link("first", href)
+    }
+    pub fn prev(self, href: impl Into<String>) -> Self {
+        self.link("prev", href)
+    }
+    pub fn next(self, href: impl Into<String>) -> Self {
+        self.link("next", href)
+    }
+    pub fn last(self, href: impl Into<String>) -> Self {
+        self.link("last", href)
+    }
+    // 第 page 页（从 1 开始），共 pages 页，href 由 url(页码) 生成
+    // 第一页没有 prev，最后一页没有 next；超出末页时 prev 指向末页
+    pub fn paginate(page: usize, pages: usize, url: impl Fn(usize) -> String) -> Self {
+        if pages == 0 {
+            return Links::new();
+        }
+        let mut links = Links::new().first(url(1));
+        if page > 1 {
+            links = links.prev(url((page - 1).min(pages)));
+        }
+        if page < pages {
+            links = links.next(url(page + 1));
+        }
+        links.last(url(pages))
+    }
+    pub fn get(&self, rel: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(r, _)| r == rel)
+            .map(|(_, href)| href.as_str())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(r, h)| (r.as_str(), h.as_str()))
+    }
+}
+
+// Link 头部的值：<href>; rel="next", <href>; rel="last"
+impl fmt::Display for Links {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (rel, href)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "<{}>; rel=\"{}\"", href, rel)?;
+        }
+        Ok(())
+    }
+}
+
+// {"next": {"href": "..."}, ...}
+#[cfg(feature = "serde")]
+impl serde::Serialize for Links {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        #[derive(serde::Serialize)]
+        struct Href<'a> {
+            href: &'a str,
+        }
+
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (rel, href) in &self.entries {
+            map.serialize_entry(rel, &Href { href })?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        let url = |p: usize| format!("/orders?page={}", p);
+        assert_eq!(
+            Links::paginate(2, 3, url).to_string(),
+            "</orders?page=1>; rel=\"first\", </orders?page=1>; rel=\"prev\", \
+             </orders?page=3>; rel=\"next\", </orders?page=3>; rel=\"last\""
+        );
+        let first = Links::paginate(1, 3, url);
+        assert_eq!(first.get("prev"), None);
+        assert_eq!(first.get("next"), Some("/orders?page=2"));
+        let last = Links::paginate(3, 3, url);
+        assert_eq!(last.get("next"), None);
+        assert_eq!(last.get("prev"), Some("/orders?page=2"));
+        // 超出末页
+        assert_eq!(
+            Links::paginate(9, 3, url).get("prev"),
+            Some("/orders?page=3")
+        );
+        assert!(Links::paginate(1, 0, url).is_empty());
+        // 同一个 rel 只保留最后一次
+        let links = Links::new().link("self", "/a").link("self", "/b");
+        assert_eq!(links.iter().collect::<Vec<_>>(), vec![("self", "/b")]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let links = Links::new().next("/orders?page=2").last("/orders?page=4");
+        assert_eq!(
+            serde_json::to_string(&links).unwrap(),
+            r#"{"next":{"href":"/orders?page=2"},"last":{"href":"/orders?page=4"}}"#
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// 按文件扩展名查 Content-Type，静态文件服务用
+// 扩展名不区分大小写；没有扩展名的当作 HTML 页面（/about 这样的干净 URL），
+// 认不出的扩展名返回 application/octet-stream，浏览器会下载而不是当成页面渲染
+use std::borrow::Cow;
+use std::path::Path;
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+// 按扩展名字母序排列，方便查找和补充
+const TYPES: &[(&str, &str)] = &[
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/x-icon"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("md", "text/markdown"),
+    ("mjs", "text/javascript"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "application/xml"),
+    ("zip", "application/zip"),
+];
+
+// 扩展名 -> Content-Type，扩展名不带点
+pub fn from_extension(ext: &str) -> Option<&'static str> {
+    let ext = ext.to_ascii_lowercase();
+    TYPES
+        .binary_search_by(|(e, _)| (*e).cmp(ext.as_str()))
+        .ok()
+        .map(|i| TYPES[i].1)
+}
+
+pub fn from_path(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        None => "text/html",
+        Some(ext) => from_extension(ext).unwrap_or(OCTET_STREAM),
+    }
+}
+
+// text/* 没有写明字符集时浏览器会去猜，UTF-7 之类的猜测可以绕过 XSS 过滤
+// 这里的文本都按 UTF-8 编码，统一加上 charset=utf-8；已经写了 charset 的不动
+pub fn with_charset(content_type: &str) -> Cow<'_, str> {
+    let mut parts = content_type.split(';');
+    let media = parts.next().unwrap_or_default().trim();
+    let is_text = media.len() > 5 && media[..5].eq_ignore_ascii_case("text/");
+    let has_charset = parts.any(|p| {
+        p.trim()
+            .split('=')
+            .next()
+            .is_some_and(|k| k.trim().eq_ignore_ascii_case("charset"))
+    });
+    if is_text && !has_charset {
+        Cow::Owned(format!("{}; charset=utf-8", content_type))
+    } else {
+        Cow::Borrowed(content_type)
+    }
+}
+
+// 按文件开头的特征字节认出的类型，认不出返回 None
+// 只列出有固定特征的二进制格式，文本格式没有可靠的特征
+const MAGIC: &[(&[u8], usize, &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", 0, "image/png"),
+    (b"\xff\xd8\xff", 0, "image/jpeg"),
+    (b"GIF87a", 0, "image/gif"),
+    (b"GIF89a", 0, "image/gif"),
+    (b"WEBP", 8, "image/webp"),
+    (b"BM", 0, "image/bmp"),
+    (b"\x00\x00\x01\x00", 0, "image/x-icon"),
+    (b"ftypavif", 4, "image/avif"),
+    (b"%PDF-", 0, "application/pdf"),
+    (b"PK\x03\x04", 0, "application/zip"),
+    (b"\x1f\x8b", 0, "application/gzip"),
+    (b"ustar", 257, "application/x-tar"),
+    (b"\x00asm", 0, "application/wasm"),
+    (b"wOFF", 0, "font/woff"),
+    (b"wOF2", 0, "font/woff2"),
+    (b"OTTO", 0, "font/otf"),
+    (b"\x00\x01\x00\x00", 0, "font/ttf"),
+    (b"ID3", 0, "audio/mpeg"),
+    (b"WAVE", 8, "audio/wav"),
+    (b"OggS", 0, "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", 0, "video/webm"),
+    (b"ftyp", 4, "video/mp4"),
+];
+
+// 认出 MAGIC 里的类型最多需要文件开头的这么多字节（tar 的特征在 257）
+pub const SNIFF_LEN: usize = 512;
+
+fn sniff_magic(bytes: &[u8]) -> Option<&'static (&'static [u8], usize, &'static str)> {
+    MAGIC
+        .iter()
+        .find(|(magic, at, _)| bytes.get(*at..*at + magic.len()) == Some(*magic))
+}
+
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    sniff_magic(bytes).map(|(_, _, t)| *t)
+}
+
+// 文件内容和扩展名对不上：声称是有特征的二进制格式但特征不符（例如写着 .png 的 HTML），
+// 或者声称是文本 / 没有特征的格式，开头却是二进制格式的特征
+// 后一种只认带不可打印字节的特征，"BM"、"ID3" 开头的普通文本不算
+// 同一家族的格式（ogg 音频和视频、mp4 容器）互相不算矛盾
+pub fn contradicts(content_type: &str, bytes: &[u8]) -> bool {
+    let claimed = content_type.split(';').next().unwrap_or_default().trim();
+    fn family(t: &str) -> &str {
+        match t {
+            "audio/ogg" | "video/ogg" => "ogg",
+            "video/mp4" | "image/avif" => "isobmff",
+            _ => t,
+        }
+    }
+    let has_magic = MAGIC.iter().any(|(_, _, t)| *t == claimed);
+    match sniff_magic(bytes) {
+        Some((_, _, actual)) if has_magic => family(actual) != family(claimed),
+        Some((magic, _, _)) => magic.iter().any(|b| !(0x20..0x7f).contains(b)),
+        None => has_magic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert!(TYPES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(from_path("app.css"), "text/css");
+        assert_eq!(from_path("img/logo.SVG"), "image/svg+xml");
+        assert_eq!(from_path("fonts/inter.woff2"), "font/woff2");
+        assert_eq!(from_path("pkg/app_bg.wasm"), "application/wasm");
+        assert_eq!(from_path("about"), "text/html");
+        assert_eq!(from_path("backup.bin"), OCTET_STREAM);
+    }
+
+    #[test]
+    fn test_with_charset() {
+        assert_eq!(with_charset("text/html"), "text/html; charset=utf-8");
+        assert_eq!(with_charset("Text/CSS"), "Text/CSS; charset=utf-8");
+        assert_eq!(
+            with_charset("text/plain; charset=iso-8859-1"),
+            "text/plain; charset=iso-8859-1"
+        );
+        assert!(matches!(with_charset("image/png"), Cow::Borrowed(_)));
+        assert!(matches!(with_charset("application/json"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"\x00\x00\x00\x1cftypisom"), Some("video/mp4"));
+        assert_eq!(sniff(b"<html>"), None);
+        // 写着 .png 的 HTML
+        assert!(contradicts("image/png", b"<html><script>alert(1)</script>"));
+        // 写着 .txt 的 zip
+        assert!(contradicts("text/plain", b"PK\x03\x04rest"));
+        assert!(!contradicts("image/png", b"\x89PNG\r\n\x1a\nrest"));
+        assert!(!contradicts("text/css", b"body { color: red }"));
+        assert!(!contradicts("video/ogg", b"OggS\x00"));
+        assert!(!contradicts("image/svg+xml", b"<svg></svg>"));
+        assert!(!contradicts("text/plain", b"BMW parts list"));
+    }
+}
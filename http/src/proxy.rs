@@ -0,0 +1,306 @@
+use crate::httprequest::HttpRequest;
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidCidr(pub String);
+
+impl fmt::Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CIDR {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCidr {}
+
+// 10.0.0.0/8、::1/128，不写前缀长度时表示单个地址
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Result<Cidr, InvalidCidr> {
+        let err = || InvalidCidr(s.to_string());
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| err())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().map_err(|_| err())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(err());
+        }
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 映射的 IPv6 地址（::ffff:10.0.0.1）按 IPv4 比较
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// 反向代理转发时附带的原始请求信息
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ForwardedInfo {
+    pub client_ip: Option<IpAddr>,
+    pub proto: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+// 只有直接连上来的对端在列表里时，才相信 Forwarded / X-Forwarded-* 头，
+// 否则任何客户端都能伪造自己的 IP 和协议
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    cidrs: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    pub fn new(cidrs: Vec<Cidr>) -> Self {
+        TrustedProxies { cidrs }
+    }
+    pub fn parse<S: AsRef<str>>(list: &[S]) -> Result<Self, InvalidCidr> {
+        let cidrs = list
+            .iter()
+            .map(|s| Cidr::parse(s.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(TrustedProxies { cidrs })
+    }
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.cidrs.iter().any(|c| c.contains(ip))
+    }
+
+    // 对端可信时解析转发头并写入 req.forwarded
+    pub fn apply(&self, req: &mut HttpRequest) {
+        let peer = match req.remote_addr {
+            Some(addr) if self.is_trusted(addr.ip()) => addr.ip(),
+            _ => return,
+        };
+        let info = match req.header("Forwarded") {
+            Some(v) => self.parse_forwarded(v, peer),
+            None => self.parse_x_forwarded(req, peer),
+        };
+        req.forwarded = Some(info);
+    }
+
+    // 从右往左跳过可信代理，第一个不可信的地址才是真实客户端
+    fn client_ip<'a>(
+        &self,
+        hops: impl DoubleEndedIterator<Item = &'a str>,
+        peer: IpAddr,
+    ) -> IpAddr {
+        let mut client = peer;
+        for hop in hops.rev() {
+            match parse_node(hop) {
+                Some(ip) => {
+                    client = ip;
+                    if !self.is_trusted(ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        client
+    }
+
+    // RFC 7239：Forwarded: for=192.0.2.60;proto=https;host=example.com, for=10.0.0.2
+    fn parse_forwarded(&self, value: &str, peer: IpAddr) -> ForwardedInfo {
+        let elements: Vec<Vec<(String, String)>> = value
+            .split(',')
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(k, v)| {
+                        (
+                            k.trim().to_lowercase(),
+                            v.trim().trim_matches('"').to_string(),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        let fors: Vec<&str> = elements
+            .iter()
+            .filter_map(|e| e.iter().find(|(k, _)| k == "for").map(|(_, v)| v.as_str()))
+            .collect();
+        // proto / host 取最后一个（离我们最近的代理写入的）元素
+        let last = elements.last();
+        let find = |key: &str| {
+            last.and_then(|e| e.iter().find(|(k, _)| k == key))
+                .map(|(_, v)| v.clone())
+        };
+        let (host, port) = match find("host") {
+            Some(h) => split_host_port(&h),
+            None => (None, None),
+        };
+        ForwardedInfo {
+            client_ip: Some(self.client_ip(fors.into_iter(), peer)),
+            proto: find("proto").map(|p| p.to_lowercase()),
+            host,
+            port,
+        }
+    }
+
+    fn parse_x_forwarded(&self, req: &HttpRequest, peer: IpAddr) -> ForwardedInfo {
+        let last_value = |name: &str| {
+            req.header(name)
+                .and_then(|v| v.rsplit(',').next())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let (host, host_port) = match last_value("X-Forwarded-Host") {
+            Some(h) => split_host_port(&h),
+            None => (None, None),
+        };
+        let client_ip = match req.header("X-Forwarded-For") {
+            Some(v) => self.client_ip(v.split(','), peer),
+            None => peer,
+        };
+        ForwardedInfo {
+            client_ip: Some(client_ip),
+            proto: last_value("X-Forwarded-Proto").map(|p| p.to_lowercase()),
+            host,
+            port: last_value("X-Forwarded-Port")
+                .and_then(|p| p.parse().ok())
+                .or(host_port),
+        }
+    }
+}
+
+// for= 的值可能是 "192.0.2.43:47011"、"[2001:db8::1]:4711" 或 unknown
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
+
+// example.com:8080 -> (example.com, 8080)，[::1]:8080 同理
+pub fn split_host_port(s: &str) -> (Option<String>, Option<u16>) {
+    let s = s.trim();
+    if s.is_empty() {
+        return (None, None);
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            let port = after.strip_prefix(':').and_then(|p| p.parse().ok());
+            return (Some(format!("[{}]", host)), port);
+        }
+    }
+    match s.rsplit_once(':') {
+        Some((h, p)) if !h.contains(':') => (Some(h.to_string()), p.parse().ok()),
+        _ => (Some(s.to_string()), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut raw = String::from("GET /orders HTTP/1.1\r\nHost: internal:3000\r\n");
+        for (k, v) in headers {
+            raw.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        raw.push_str("\r\n");
+        let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        req.remote_addr = Some(peer.parse::<SocketAddr>().unwrap());
+        req
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(Cidr::parse("::1").unwrap().contains("::1".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("nope").is_err());
+    }
+    #[test]
+    fn test_forwarded_header_from_trusted_proxy() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+        let mut req = request(
+            "10.0.0.5:5000",
+            &[(
+                "Forwarded",
+                "for=1.2.3.4;proto=http, for=198.51.100.7;proto=https;host=shop.example.com",
+            )],
+        );
+        proxies.apply(&mut req);
+        assert_eq!(req.effective_scheme(), "https");
+        assert_eq!(req.effective_host().as_deref(), Some("shop.example.com"));
+        assert_eq!(req.effective_port(), 443);
+        assert_eq!(req.effective_origin(), "https://shop.example.com");
+        assert_eq!(req.client_ip(), Some("198.51.100.7".parse().unwrap()));
+        assert_eq!(
+            parse_node("\"[2001:db8::1]:4711\""),
+            "2001:db8::1".parse().ok()
+        );
+        assert_eq!(parse_node("198.51.100.7:4711"), "198.51.100.7".parse().ok());
+        assert_eq!(parse_node("unknown"), None);
+    }
+    #[test]
+    fn test_x_forwarded_headers() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+        let mut req = request(
+            "10.0.0.5:5000",
+            &[
+                ("X-Forwarded-For", "203.0.113.9, 10.0.0.7"),
+                ("X-Forwarded-Proto", "https"),
+                ("X-Forwarded-Host", "api.example.com"),
+                ("X-Forwarded-Port", "8443"),
+            ],
+        );
+        proxies.apply(&mut req);
+        assert_eq!(req.effective_origin(), "https://api.example.com:8443");
+        assert_eq!(req.client_ip(), Some("203.0.113.9".parse().unwrap()));
+    }
+    #[test]
+    fn test_untrusted_peer_is_ignored() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+        let mut req = request(
+            "203.0.113.1:5000",
+            &[
+                ("X-Forwarded-Proto", "https"),
+                ("X-Forwarded-For", "1.1.1.1"),
+            ],
+        );
+        proxies.apply(&mut req);
+        assert_eq!(req.forwarded, None);
+        assert_eq!(req.effective_scheme(), "http");
+        assert_eq!(req.client_ip(), Some("203.0.113.1".parse().unwrap()));
+    }
+}
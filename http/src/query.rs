@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// 同名参数出现多次时的处理方式（HTTP 参数污染）
+// a=1&a=2：FirstWins 取 1，LastWins 取 2，Reject 直接报错
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    FirstWins,
+    LastWins,
+    // 安全敏感的路由（支付、权限）使用，避免前后端取到不同的值
+    Reject,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DuplicateKey(pub String);
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "duplicate query parameter {:?}", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateKey {}
+
+// 按出现顺序保存所有键值对，解码后的结果
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryParams {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryParams {
+    // 解析 a=1&b=hello+world&c=%E4%BD%A0，不含开头的 ?
+    pub fn parse(query: &str) -> QueryParams {
+        let pairs = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (k, v) = p.split_once('=').unwrap_or((p, ""));
+                (percent_decode(k, true), percent_decode(v, true))
+            })
+            .collect();
+        QueryParams { pairs }
+    }
+
+    pub fn first(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn last(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    // 多值 API：?tag=a&tag=b
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+    pub fn get(&self, key: &str, policy: DuplicatePolicy) -> Result<Option<&str>, DuplicateKey> {
+        match policy {
+            DuplicatePolicy::FirstWins => Ok(self.first(key)),
+            DuplicatePolicy::LastWins => Ok(self.last(key)),
+            DuplicatePolicy::Reject => match self.get_all(key).as_slice() {
+                [] => Ok(None),
+                [v] => Ok(Some(v)),
+                _ => Err(DuplicateKey(key.to_string())),
+            },
+        }
+    }
+    pub fn pairs(&self) -> &[(String, String)] {
+        &self.pairs
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // 按策略折叠成单值 map
+    pub fn to_map(&self, policy: DuplicatePolicy) -> Result<HashMap<String, String>, DuplicateKey> {
+        let mut map = HashMap::new();
+        for (k, v) in &self.pairs {
+            match policy {
+                DuplicatePolicy::FirstWins => {
+                    map.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+                DuplicatePolicy::LastWins => {
+                    map.insert(k.clone(), v.clone());
+                }
+                DuplicatePolicy::Reject => {
+                    if map.insert(k.clone(), v.clone()).is_some() {
+                        return Err(DuplicateKey(k.clone()));
+                    }
+                }
+            }
+        }
+        Ok(map)
+    }
+}
+
+// %XX 解码，非法的转义原样保留；plus_as_space 只用于查询字符串
+pub fn percent_decode(s: &str, plus_as_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(h), Some(l)) => {
+                    out.push(h << 4 | l);
+                    i += 3;
+                    continue;
+                }
+                _ => out.push(b'%'),
+            },
+            b'+' if plus_as_space => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// 生成 URL 路径段：保留 RFC 3986 的 unreserved 字符，其余（含 / ? #）全部编码
+pub fn percent_encode_segment(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_and_decode() {
+        let q = QueryParams::parse("sort=id&name=hello+world&city=%E5%8C%97%E4%BA%AC&flag&bad=%zz");
+        assert_eq!(q.first("sort"), Some("id"));
+        assert_eq!(q.first("name"), Some("hello world"));
+        assert_eq!(q.first("city"), Some("北京"));
+        assert_eq!(q.first("flag"), Some(""));
+        assert_eq!(q.first("bad"), Some("%zz"));
+        assert_eq!(percent_decode("a+b%2B", false), "a+b+");
+        assert_eq!(percent_decode("%4", false), "%4");
+        assert_eq!(percent_encode_segment("a b/北"), "a%20b%2F%E5%8C%97");
+        assert_eq!(
+            percent_decode(&percent_encode_segment("x?y#z"), false),
+            "x?y#z"
+        );
+    }
+    #[test]
+    fn test_duplicate_policies() {
+        let q = QueryParams::parse("id=1&id=2&page=3");
+        assert_eq!(q.get("id", DuplicatePolicy::FirstWins), Ok(Some("1")));
+        assert_eq!(q.get("id", DuplicatePolicy::LastWins), Ok(Some("2")));
+        assert_eq!(
+            q.get("id", DuplicatePolicy::Reject),
+            Err(DuplicateKey("id".into()))
+        );
+        assert_eq!(q.get("page", DuplicatePolicy::Reject), Ok(Some("3")));
+        assert_eq!(q.get_all("id"), vec!["1", "2"]);
+        assert_eq!(q.to_map(DuplicatePolicy::LastWins).unwrap()["id"], "2");
+        assert!(q.to_map(DuplicatePolicy::Reject).is_err());
+    }
+}
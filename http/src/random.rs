@@ -0,0 +1,110 @@
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use std::sync::Mutex;
+
+// 请求 ID、session ID、CSRF token、WebSocket key 等随机值都从这里取，
+// 安全审查只需要看这一个地方；测试里换成 SeededRandom 得到确定的输出
+pub trait RandomSource: Send + Sync {
+    fn fill_bytes(&self, buf: &mut [u8]);
+
+    fn next_u64(&self) -> u64 {
+        let mut buf = [0; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    // [0, 1) 之间均匀分布，取高 53 位作为 f64 的尾数
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// 默认实现：操作系统提供的密码学安全随机数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        // 系统随机源不可用时没有安全的退路，直接 panic
+        getrandom::fill(buf).expect("OS random source unavailable");
+    }
+}
+
+// 仅用于测试：splitmix64，同一个种子总是得到相同的序列
+#[derive(Debug)]
+pub struct SeededRandom {
+    state: Mutex<u64>,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            state: Mutex::new(seed),
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        let mut state = self.state.lock().unwrap();
+        for chunk in buf.chunks_mut(8) {
+            *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = *state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+            chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+fn random_bytes(rng: &dyn RandomSource, n: usize) -> Vec<u8> {
+    let mut buf = vec![0; n];
+    rng.fill_bytes(&mut buf);
+    buf
+}
+
+// 16 字节十六进制，用于日志关联的请求 ID
+pub fn request_id(rng: &dyn RandomSource) -> String {
+    random_bytes(rng, 16)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// 32 字节 base64url，可直接放进 cookie
+pub fn session_id(rng: &dyn RandomSource) -> String {
+    URL_SAFE_NO_PAD.encode(random_bytes(rng, 32))
+}
+
+pub fn csrf_token(rng: &dyn RandomSource) -> String {
+    URL_SAFE_NO_PAD.encode(random_bytes(rng, 32))
+}
+
+// RFC 6455：Sec-WebSocket-Key 是 16 字节随机数的标准 base64
+pub fn websocket_key(rng: &dyn RandomSource) -> String {
+    STANDARD.encode(random_bytes(rng, 16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_seeded_random_is_deterministic() {
+        let a = SeededRandom::new(42);
+        let b = SeededRandom::new(42);
+        assert_eq!(request_id(&a), request_id(&b));
+        assert_eq!(session_id(&a), session_id(&b));
+        assert_ne!(request_id(&a), request_id(&SeededRandom::new(7)));
+    }
+    #[test]
+    fn test_token_formats() {
+        let rng = OsRandom;
+        assert_eq!(request_id(&rng).len(), 32);
+        assert_eq!(session_id(&rng).len(), 43);
+        assert_eq!(websocket_key(&rng).len(), 24);
+        assert_ne!(csrf_token(&rng), csrf_token(&rng));
+        let f = SeededRandom::new(1).next_f64();
+        assert!((0.0..1.0).contains(&f));
+    }
+}
@@ -0,0 +1,123 @@
+// Range 请求（RFC 7233）：播放器拖动进度条、下载工具断点续传时只要文件的一段
+//
+//     Range: bytes=0-499     前 500 个字节
+//     Range: bytes=500-      从第 500 个字节到结尾
+//     Range: bytes=-500      最后 500 个字节
+//
+// 只支持单个范围；多个范围（需要 multipart/byteranges）和语法不对的 Range 按规范忽略，返回整个 body
+// 闭区间 [start, end]，已经按 body 长度截好
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+    // resolve 给出的区间至少有一个字节
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+    // Content-Range 的值，例如 bytes 0-499/1234
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    // 没有 Range 或者忽略它，返回 200 和整个 body
+    Full,
+    // 返回 206 和这一段
+    Partial(ByteRange),
+    // 起点超出 body 的长度，返回 416
+    Unsatisfiable,
+}
+
+// 416 响应的 Content-Range，告诉客户端实际长度
+pub fn unsatisfiable_range(total: u64) -> String {
+    format!("bytes */{}", total)
+}
+
+// header 是 Range 头部的值，len 是整个 body 的字节数
+pub fn resolve(header: Option<&str>, len: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let number = |s: &str| {
+        let s = s.trim();
+        // u64::from_str 接受 +5，规范里只有数字
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse::<u64>().ok()
+    };
+    let range = match (number(first), number(last)) {
+        // bytes=-500：最后 500 个字节，body 不够长时就是整个 body
+        (None, Some(suffix)) if first.trim().is_empty() => {
+            if suffix == 0 || len == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            }
+        }
+        (Some(start), None) if last.trim().is_empty() => ByteRange {
+            start,
+            end: len.saturating_sub(1),
+        },
+        (Some(start), Some(end)) if start <= end => ByteRange {
+            start,
+            end: end.min(len.saturating_sub(1)),
+        },
+        _ => return RangeRequest::Full,
+    };
+    if range.start >= len {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+        for (header, expected) in [
+            ("bytes=0-499", partial(0, 499)),
+            ("bytes=500-", partial(500, 999)),
+            ("bytes=-300", partial(700, 999)),
+            // 终点超出长度时截到结尾
+            ("bytes=900-5000", partial(900, 999)),
+            ("bytes=-5000", partial(0, 999)),
+            (" bytes= 10 - 20 ", partial(10, 20)),
+            ("bytes=1000-", RangeRequest::Unsatisfiable),
+            ("bytes=-0", RangeRequest::Unsatisfiable),
+            // 忽略的 Range
+            ("bytes=5-1", RangeRequest::Full),
+            ("bytes=0-1,5-9", RangeRequest::Full),
+            ("bytes=+1-2", RangeRequest::Full),
+            ("items=0-1", RangeRequest::Full),
+            ("bytes=abc", RangeRequest::Full),
+        ] {
+            assert_eq!(resolve(Some(header), 1000), expected, "{}", header);
+        }
+        assert_eq!(resolve(None, 1000), RangeRequest::Full);
+        assert_eq!(resolve(Some("bytes=0-0"), 0), RangeRequest::Unsatisfiable);
+        let range = ByteRange { start: 0, end: 499 };
+        assert_eq!(range.len(), 500);
+        assert_eq!(range.content_range(1234), "bytes 0-499/1234");
+        assert_eq!(unsatisfiable_range(1234), "bytes */1234");
+    }
+}
@@ -0,0 +1,168 @@
+// 解析请求头时查找分隔符：头部结束的空行、每行末尾的 \n、名字和值之间的冒号
+// 打开 simd feature 后用 memchr，运行时检测 CPU 支持的指令集：x86_64 上有 AVX2 用 AVX2，
+// 否则用 SSE2，aarch64 上用 NEON，其他平台按机器字一次比较 8 个字节（SWAR）；
+// 不打开时逐字节比较，不增加依赖
+// 头部越大差别越明显，几 KB 的 Cookie 很常见；基准测试：cargo bench -p http --features simd --bench header_scan
+
+// needle 第一次出现的位置
+#[cfg(feature = "simd")]
+pub fn find(needle: u8, haystack: &[u8]) -> Option<usize> {
+    memchr::memchr(needle, haystack)
+}
+
+#[cfg(not(feature = "simd"))]
+pub fn find(needle: u8, haystack: &[u8]) -> Option<usize> {
+    scalar::find(needle, haystack)
+}
+
+// needle 出现的次数
+#[cfg(feature = "simd")]
+pub fn count(needle: u8, haystack: &[u8]) -> usize {
+    memchr::memchr_iter(needle, haystack).count()
+}
+
+#[cfg(not(feature = "simd"))]
+pub fn count(needle: u8, haystack: &[u8]) -> usize {
+    scalar::count(needle, haystack)
+}
+
+// 实际使用的实现，基准测试输出里显示
+pub fn backend() -> &'static str {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if std::arch::is_x86_feature_detected!("avx2") {
+            "avx2"
+        } else {
+            "sse2"
+        }
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    {
+        "neon"
+    }
+    #[cfg(all(
+        feature = "simd",
+        not(any(target_arch = "x86_64", target_arch = "aarch64"))
+    ))]
+    {
+        "swar"
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        "scalar"
+    }
+}
+
+// 第一个空行的位置和长度：\r\n\r\n 是 4 个字节，只用 \n 换行的客户端是 \n\n，2 个字节
+// 只跳到每个 \n 上检查后面的字节，不用在每个位置比较一个窗口
+pub fn head_end(raw: &[u8]) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(i) = find(b'\n', &raw[from..]).map(|i| from + i) {
+        let rest = &raw[i + 1..];
+        if i > 0 && raw[i - 1] == b'\r' && rest.starts_with(b"\r\n") {
+            return Some((i - 1, 4));
+        }
+        if rest.starts_with(b"\n") {
+            return Some((i, 2));
+        }
+        from = i + 1;
+    }
+    None
+}
+
+// 和 str::lines 一样按 \n 分行并去掉 \n 前面的 \r，最后一行没有 \n 时也返回
+pub fn lines(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = Some(text);
+    std::iter::from_fn(move || {
+        let text = rest.filter(|t| !t.is_empty())?;
+        match find(b'\n', text.as_bytes()) {
+            // \n 是 ASCII，前后都是字符边界
+            Some(i) => {
+                rest = Some(&text[i + 1..]);
+                let line = &text[..i];
+                Some(line.strip_suffix('\r').unwrap_or(line))
+            }
+            None => {
+                rest = None;
+                Some(text)
+            }
+        }
+    })
+}
+
+// 按第一个 sep 分成两半，sep 必须是 ASCII
+pub fn split_once(text: &str, sep: u8) -> Option<(&str, &str)> {
+    let i = find(sep, text.as_bytes())?;
+    Some((&text[..i], &text[i + 1..]))
+}
+
+// 逐字节比较的版本，不打开 simd 时使用，基准测试用它做对照
+pub mod scalar {
+    pub fn find(needle: u8, haystack: &[u8]) -> Option<usize> {
+        haystack.iter().position(|b| *b == needle)
+    }
+
+    pub fn count(needle: u8, haystack: &[u8]) -> usize {
+        haystack.iter().filter(|b| **b == needle).count()
+    }
+
+    // 原来的写法：在每个位置比较一个窗口
+    pub fn head_end(raw: &[u8]) -> Option<(usize, usize)> {
+        let crlf = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| (i, 4));
+        let lf = raw.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+        match (crlf, lf) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head_end() {
+        let cases: [&[u8]; 9] = [
+            b"GET / HTTP/1.1\r\nHost: a\r\n\r\nbody",
+            b"GET / HTTP/1.1\nHost: a\n\nbody",
+            // 混用的换行按先出现的空行算
+            b"GET / HTTP/1.1\nHost: a\r\n\nbody\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a\n\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a\r\n",
+            b"\r\n\r\n",
+            b"\n\n",
+            b"\n",
+            b"",
+        ];
+        for raw in cases {
+            assert_eq!(head_end(raw), scalar::head_end(raw), "{:?}", raw);
+        }
+        assert_eq!(head_end(cases[0]), Some((23, 4)));
+        assert_eq!(head_end(cases[2]), Some((23, 2)));
+        // 超过一个 SIMD 寄存器的长度
+        let mut long = b"GET / HTTP/1.1\r\nCookie: ".to_vec();
+        long.extend(std::iter::repeat_n(b'a', 1000));
+        long.extend(b"\r\n\r\n");
+        assert_eq!(head_end(&long), Some((long.len() - 4, 4)));
+        assert_eq!(count(b'\n', &long), 3);
+        assert_eq!(find(b':', &long), scalar::find(b':', &long));
+    }
+
+    #[test]
+    fn test_lines() {
+        for text in ["a\r\nb\nc", "a\n\nb\n", "", "\n", "x\r", "a:b\r\n"] {
+            assert_eq!(
+                lines(text).collect::<Vec<_>>(),
+                text.lines().collect::<Vec<_>>(),
+                "{:?}",
+                text
+            );
+        }
+        assert_eq!(split_once("Host: a:3000", b':'), Some(("Host", " a:3000")));
+        assert_eq!(split_once("no colon", b':'), None);
+    }
+}
@@ -0,0 +1,308 @@
+// SOCKS5 代理（RFC 1928），只实现 CONNECT，认证支持无认证和用户名/密码（RFC 1929）
+// 目标主机名原样交给代理解析，通过 Tor 之类的隧道访问时本地不会泄露 DNS 查询
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::time::Duration;
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USER_PASS: u8 = 0x02;
+const NO_ACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Debug)]
+pub enum SocksError {
+    Io(io::Error),
+    // 代理回复的不是 SOCKS5
+    BadReply(String),
+    // 代理不接受我们提供的认证方式
+    NoAcceptableMethod,
+    AuthFailed,
+    // CONNECT 失败，携带代理返回的错误码
+    ConnectFailed(u8),
+    // 主机名、用户名或密码超过 255 字节
+    TooLong(&'static str),
+}
+
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocksError::Io(e) => write!(f, "socks5 io error: {}", e),
+            SocksError::BadReply(m) => write!(f, "socks5 bad reply: {}", m),
+            SocksError::NoAcceptableMethod => {
+                write!(f, "socks5 proxy accepts none of the offered auth methods")
+            }
+            SocksError::AuthFailed => write!(f, "socks5 authentication failed"),
+            SocksError::ConnectFailed(code) => {
+                write!(f, "socks5 connect failed: {}", reply_text(*code))
+            }
+            SocksError::TooLong(what) => write!(f, "socks5 {} longer than 255 bytes", what),
+        }
+    }
+}
+
+impl std::error::Error for SocksError {}
+
+impl From<io::Error> for SocksError {
+    fn from(e: io::Error) -> SocksError {
+        SocksError::Io(e)
+    }
+}
+
+// RFC 1928 第 6 节的错误码
+fn reply_text(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Socks5Proxy {
+    // 代理地址 host:port
+    pub addr: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Socks5Proxy {
+    pub fn new(addr: &str) -> Self {
+        Socks5Proxy {
+            addr: addr.to_string(),
+            username: None,
+            password: None,
+        }
+    }
+    // 链式配置，消耗 self 再返回
+    pub fn auth(mut self, username: &str, password: &str) -> Self {
+        self.username = Some(username.to_string());
+        self.password = Some(password.to_string());
+        self
+    }
+    // [socks5://][user:pass@]host:port，方便从环境变量读取
+    pub fn parse(s: &str) -> Option<Socks5Proxy> {
+        let s = s.strip_prefix("socks5://").unwrap_or(s);
+        let (auth, addr) = match s.rsplit_once('@') {
+            Some((auth, addr)) => (Some(auth), addr),
+            None => (None, s),
+        };
+        let (_, port) = addr.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        let proxy = Socks5Proxy::new(addr);
+        match auth {
+            Some(auth) => {
+                let (user, pass) = auth.split_once(':')?;
+                Some(proxy.auth(user, pass))
+            }
+            None => Some(proxy),
+        }
+    }
+
+    // 连上代理并让它 CONNECT 到 host:port，返回的连接之后就像直连目标一样使用
+    pub fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, SocksError> {
+        let mut stream = TcpStream::connect(self.addr.as_str())?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let auth = match (&self.username, &self.password) {
+            (Some(u), Some(p)) => Some((u.as_str(), p.as_str())),
+            _ => None,
+        };
+        handshake(&mut stream, host, port, auth)?;
+        Ok(stream)
+    }
+}
+
+// 在已经连上代理的流上完成协商、认证和 CONNECT
+pub fn handshake(
+    stream: &mut (impl Read + Write),
+    host: &str,
+    port: u16,
+    auth: Option<(&str, &str)>,
+) -> Result<(), SocksError> {
+    // 问候：版本 + 支持的认证方式
+    let greeting: &[u8] = match auth {
+        Some(_) => &[VERSION, 2, NO_AUTH, USER_PASS],
+        None => &[VERSION, 1, NO_AUTH],
+    };
+    stream.write_all(greeting)?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != VERSION {
+        return Err(SocksError::BadReply(format!("version {}", choice[0])));
+    }
+    match (choice[1], auth) {
+        (NO_AUTH, _) => {}
+        (USER_PASS, Some((user, pass))) => authenticate(stream, user, pass)?,
+        (NO_ACCEPTABLE, _) => return Err(SocksError::NoAcceptableMethod),
+        (method, _) => return Err(SocksError::BadReply(format!("auth method {}", method))),
+    }
+
+    let mut request = vec![VERSION, CMD_CONNECT, 0x00];
+    // 方括号包着的是 IPv6 地址
+    match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| SocksError::TooLong("host name"))?;
+            request.push(ATYP_DOMAIN);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    // 回复：VER REP RSV ATYP BND.ADDR BND.PORT，绑定地址对 CONNECT 没用，读掉即可
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(SocksError::BadReply(format!("version {}", reply[0])));
+    }
+    if reply[1] != 0x00 {
+        return Err(SocksError::ConnectFailed(reply[1]));
+    }
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        t => return Err(SocksError::BadReply(format!("address type {}", t))),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+// RFC 1929：VER(1) ULEN UNAME PLEN PASSWD，回复 VER STATUS，STATUS 为 0 表示成功
+fn authenticate(
+    stream: &mut (impl Read + Write),
+    user: &str,
+    pass: &str,
+) -> Result<(), SocksError> {
+    let ulen = u8::try_from(user.len()).map_err(|_| SocksError::TooLong("username"))?;
+    let plen = u8::try_from(pass.len()).map_err(|_| SocksError::TooLong("password"))?;
+    let mut msg = vec![0x01, ulen];
+    msg.extend_from_slice(user.as_bytes());
+    msg.push(plen);
+    msg.extend_from_slice(pass.as_bytes());
+    stream.write_all(&msg)?;
+    let mut status = [0; 2];
+    stream.read_exact(&mut status)?;
+    if status[1] != 0x00 {
+        return Err(SocksError::AuthFailed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // 预先写好代理的回复，记录客户端发出的字节
+    struct Scripted {
+        replies: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Scripted {
+        fn new(replies: &[u8]) -> Self {
+            Scripted {
+                replies: Cursor::new(replies.to_vec()),
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_connect_with_domain() {
+        let mut s = Scripted::new(&[5, 0, 5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90]);
+        handshake(&mut s, "example.com", 80, None).unwrap();
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0, 80]);
+        assert_eq!(s.sent, expected);
+    }
+
+    #[test]
+    fn test_connect_with_auth_and_ip() {
+        let mut s = Scripted::new(&[5, 2, 1, 0, 5, 0, 0, 3, 4, b'h', b'o', b's', b't', 0, 1]);
+        handshake(&mut s, "10.0.0.1", 443, Some(("bob", "pw"))).unwrap();
+        assert_eq!(
+            s.sent,
+            vec![
+                5, 2, 0, 2, 1, 3, b'b', b'o', b'b', 2, b'p', b'w', 5, 1, 0, 1, 10, 0, 0, 1, 1, 187
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failures() {
+        let mut s = Scripted::new(&[5, 0xff]);
+        assert!(matches!(
+            handshake(&mut s, "a", 1, None),
+            Err(SocksError::NoAcceptableMethod)
+        ));
+        let mut s = Scripted::new(&[5, 2, 1, 1]);
+        assert!(matches!(
+            handshake(&mut s, "a", 1, Some(("u", "p"))),
+            Err(SocksError::AuthFailed)
+        ));
+        let mut s = Scripted::new(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        let err = handshake(&mut s, "a", 1, None).unwrap_err();
+        assert_eq!(err.to_string(), "socks5 connect failed: connection refused");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Socks5Proxy::parse("socks5://u:p@127.0.0.1:9050"),
+            Some(Socks5Proxy::new("127.0.0.1:9050").auth("u", "p"))
+        );
+        assert_eq!(
+            Socks5Proxy::parse("proxy:1080"),
+            Some(Socks5Proxy::new("proxy:1080"))
+        );
+        assert_eq!(Socks5Proxy::parse("proxy"), None);
+    }
+}
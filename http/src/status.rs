@@ -0,0 +1,173 @@
+// 响应状态码，避免手写 "200" / "OK" 这样的字符串时写错
+// 原因短语按 RFC 9110，不认识的状态码用 Other 保存，原因短语为空
+use std::fmt;
+
+// 每一行生成一个变体，以及 as_u16 / reason / From<u16> 里对应的分支
+macro_rules! status_codes {
+    ($($variant:ident = $code:literal, $reason:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "serde", serde(into = "u16", from = "u16"))]
+        pub enum StatusCode {
+            $($variant,)*
+            Other(u16),
+        }
+
+        impl StatusCode {
+            pub fn as_u16(self) -> u16 {
+                match self {
+                    $(StatusCode::$variant => $code,)*
+                    StatusCode::Other(code) => code,
+                }
+            }
+
+            pub fn reason(self) -> &'static str {
+                match self {
+                    $(StatusCode::$variant => $reason,)*
+                    StatusCode::Other(_) => "",
+                }
+            }
+        }
+
+        impl From<u16> for StatusCode {
+            fn from(code: u16) -> Self {
+                match code {
+                    $($code => StatusCode::$variant,)*
+                    _ => StatusCode::Other(code),
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+    Ok = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    ImATeapot = 418, "I'm a teapot";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+impl StatusCode {
+    // "404"、"200" 这样的字符串，不是三位数字时返回 None
+    pub fn parse(code: &str) -> Option<StatusCode> {
+        if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        code.parse::<u16>().ok().map(StatusCode::from)
+    }
+    pub fn is_informational(self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+    pub fn is_redirection(self) -> bool {
+        (300..400).contains(&self.as_u16())
+    }
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+    pub fn is_server_error(self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.as_u16()
+    }
+}
+
+// 状态行的写法：404 Not Found
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.as_u16(), self.reason())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(StatusCode::from(404), StatusCode::NotFound);
+        assert_eq!(StatusCode::NotFound.as_u16(), 404);
+        assert_eq!(StatusCode::from(418).reason(), "I'm a teapot");
+        assert_eq!(StatusCode::from(299), StatusCode::Other(299));
+        assert_eq!(StatusCode::Other(299).to_string(), "299 ");
+        assert_eq!(
+            StatusCode::HttpVersionNotSupported.to_string(),
+            "505 HTTP Version Not Supported"
+        );
+        assert!(StatusCode::NoContent.is_success());
+        assert!(StatusCode::TooManyRequests.is_client_error());
+        assert!(!StatusCode::TooManyRequests.is_server_error());
+        assert!(StatusCode::Found.is_redirection());
+        assert!(StatusCode::EarlyHints.is_informational());
+        assert_eq!(
+            StatusCode::parse("503"),
+            Some(StatusCode::ServiceUnavailable)
+        );
+        assert_eq!(StatusCode::parse("2oo"), None);
+        assert_eq!(StatusCode::parse("+20"), None);
+    }
+}
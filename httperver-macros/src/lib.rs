@@ -0,0 +1,234 @@
+// httperver 的路由宏
+//
+//     #[route(GET, "/api/orders/:id")]
+//     fn order(req: &HttpRequest, id: u32) -> HttpResponse<'static> { ... }
+//
+//     let router = register_routes!(Router::new(""), order, admin::stats);
+//
+//     #[route(GET, "/api/orders/:id", title = "查询订单", response = r#"{"order_id": 1}"#)]
+//     路径之后可选 title / description / request / response，显示在 GET /docs 页面上
+//
+// #[route] 保留原函数，另外生成 __route_<函数名>() 返回 RouteDef；
+// 参数里的 &HttpRequest 直接传入，State<T> 取 Router::state 注册的共享状态（没有注册时返回 500），
+// Body<T> 按 Content-Type 解码请求体（失败时返回 415 / 400），
+// 其余参数按名字取对应的 :name 路径段并用 FromStr 解析，解析失败时返回 400，不会调用函数
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, FnArg, Ident, ItemFn, LitStr, Pat, Path, Token, Type};
+
+const METHODS: &[&str] = &[
+    "GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS", "TRACE", "CONNECT",
+];
+
+// 路径之后可以跟说明文档，见 httperver::docs::RouteDoc
+const DOC_KEYS: &[&str] = &["title", "description", "request", "response"];
+
+struct RouteArgs {
+    method: Ident,
+    path: LitStr,
+    doc: Vec<(Ident, LitStr)>,
+}
+
+impl Parse for RouteArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let method: Ident = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let path: LitStr = input.parse()?;
+        let mut doc: Vec<(Ident, LitStr)> = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            if !DOC_KEYS.contains(&key.to_string().as_str()) {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!(
+                        "unknown route option, expected one of {}",
+                        DOC_KEYS.join(", ")
+                    ),
+                ));
+            }
+            if doc.iter().any(|(k, _)| *k == key) {
+                return Err(syn::Error::new(key.span(), "duplicate route option"));
+            }
+            input.parse::<Token![=]>()?;
+            doc.push((key, input.parse()?));
+        }
+        Ok(RouteArgs { method, path, doc })
+    }
+}
+
+#[proc_macro_attribute]
+pub fn route(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as RouteArgs);
+    let func = parse_macro_input!(item as ItemFn);
+    match expand_route(args, func) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_route(args: RouteArgs, func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let method = args.method.to_string();
+    if !METHODS.contains(&method.as_str()) {
+        return Err(syn::Error::new(
+            args.method.span(),
+            format!(
+                "unknown HTTP method, expected one of {}",
+                METHODS.join(", ")
+            ),
+        ));
+    }
+    let path = args.path.value();
+    if !path.starts_with('/') {
+        return Err(syn::Error::new(
+            args.path.span(),
+            "route path must start with '/'",
+        ));
+    }
+    let params: Vec<&str> = path
+        .split('/')
+        .filter_map(|seg| seg.strip_prefix(':').or_else(|| seg.strip_prefix('*')))
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    // 每个函数参数对应的取值表达式
+    let mut extract = Vec::new();
+    let mut call_args = Vec::new();
+    for (i, arg) in func.sig.inputs.iter().enumerate() {
+        let FnArg::Typed(arg) = arg else {
+            return Err(syn::Error::new_spanned(
+                arg,
+                "route handlers cannot take self",
+            ));
+        };
+        // 引用类型的参数就是请求本身
+        if let Type::Reference(_) = &*arg.ty {
+            call_args.push(quote!(req));
+            continue;
+        }
+        // State<T>：按类型取共享状态
+        if let Type::Path(ty) = &*arg.ty {
+            if ty.path.segments.last().is_some_and(|s| s.ident == "State") {
+                let var = Ident::new(&format!("__arg{}", i), Span::call_site());
+                let name = quote!(#ty).to_string();
+                extract.push(quote! {
+                    let #var: #ty = match ::httperver::state::State::from_request(req) {
+                        ::std::option::Option::Some(v) => v,
+                        ::std::option::Option::None => return ::httperver::state::missing(#name),
+                    };
+                });
+                call_args.push(quote!(#var));
+                continue;
+            }
+            // Body<T>：按 Content-Type 解码请求体
+            if ty.path.segments.last().is_some_and(|s| s.ident == "Body") {
+                let var = Ident::new(&format!("__arg{}", i), Span::call_site());
+                extract.push(quote! {
+                    let #var: #ty = match ::httperver::body::Body::from_request(req) {
+                        ::std::result::Result::Ok(v) => v,
+                        ::std::result::Result::Err(resp) => return resp,
+                    };
+                });
+                call_args.push(quote!(#var));
+                continue;
+            }
+        }
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "route parameters must be plain identifiers",
+            ));
+        };
+        let name = pat.ident.to_string();
+        if !params.contains(&name.as_str()) {
+            return Err(syn::Error::new_spanned(
+                &pat.ident,
+                format!("no :{} segment in route path {:?}", name, path),
+            ));
+        }
+        let var = Ident::new(&format!("__arg{}", i), Span::call_site());
+        let ty = &arg.ty;
+        extract.push(quote! {
+            let #var: #ty = match params.parse(#name) {
+                ::std::result::Result::Ok(v) => v,
+                ::std::result::Result::Err(resp) => return resp,
+            };
+        });
+        call_args.push(quote!(#var));
+    }
+
+    let ident = &func.sig.ident;
+    let vis = &func.vis;
+    let def = format_ident!("__route_{}", ident);
+    let name = ident.to_string();
+    let doc_value = |key: &str| args.doc.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    let title = doc_value("title").map(|v| v.value()).unwrap_or_default();
+    let description = doc_value("description")
+        .map(|v| v.value())
+        .unwrap_or_default();
+    let example = |key: &str| match doc_value(key) {
+        Some(v) => quote!(::std::option::Option::Some(#v)),
+        None => quote!(::std::option::Option::None),
+    };
+    let (request, response) = (example("request"), example("response"));
+    Ok(quote! {
+        #func
+
+        #[doc(hidden)]
+        #vis fn #def() -> ::httperver::router::RouteDef {
+            ::httperver::router::RouteDef {
+                name: #name,
+                method: #method,
+                path: #path,
+                // 不捕获变量的闭包会转换成 fn 指针，参数类型由 RouteFn 推断
+                handler: |req, params| {
+                    let _ = (&req, &params);
+                    #(#extract)*
+                    #ident(#(#call_args),*)
+                },
+                doc: ::httperver::docs::RouteDoc {
+                    title: #title,
+                    description: #description,
+                    request_example: #request,
+                    response_example: #response,
+                },
+            }
+        }
+    })
+}
+
+struct RegisterArgs {
+    router: syn::Expr,
+    handlers: Punctuated<Path, Token![,]>,
+}
+
+impl Parse for RegisterArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let router = input.parse()?;
+        let handlers = if input.is_empty() {
+            Punctuated::new()
+        } else {
+            input.parse::<Token![,]>()?;
+            Punctuated::parse_terminated(input)?
+        };
+        Ok(RegisterArgs { router, handlers })
+    }
+}
+
+// register_routes!(router, a, b::c) 展开为 router.register(__route_a()).register(b::__route_c())
+#[proc_macro]
+pub fn register_routes(input: TokenStream) -> TokenStream {
+    let RegisterArgs { router, handlers } = parse_macro_input!(input as RegisterArgs);
+    let defs = handlers.into_iter().map(|mut path| {
+        let last = path.segments.last_mut().expect("path has a segment");
+        last.ident = format_ident!("__route_{}", last.ident);
+        quote!(.register(#path()))
+    });
+    quote!((#router)#(#defs)*).into()
+}
@@ -0,0 +1,75 @@
+// 比较同一个健康检查响应走普通路由和走 Router::fast 的耗时和内存分配次数
+// 用法：cargo bench -p httperver --bench fast_path
+// 请求事先解析好，只统计 Router::route 本身
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use httperver::fastpath::FastResponse;
+use httperver::router::Router;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// 统计 alloc 调用次数的分配器
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const REQUESTS: usize = 200_000;
+
+// 返回每个请求的平均纳秒数和平均分配次数
+fn run(router: &Router) -> (f64, f64) {
+    let raw = b"GET /health HTTP/1.1\r\nHost: localhost\r\nUser-Agent: probe\r\n\r\n";
+    let requests: Vec<HttpRequest> = (0..REQUESTS)
+        .map(|_| HttpRequest::try_from(&raw[..]).unwrap())
+        .collect();
+    let mut out = Vec::with_capacity(4096);
+    let mut allocations = 0;
+    let started = Instant::now();
+    for req in requests {
+        out.clear();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        router.route(req, &mut out);
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+        black_box(&out);
+    }
+    let elapsed = started.elapsed();
+    (
+        elapsed.as_nanos() as f64 / REQUESTS as f64,
+        allocations as f64 / REQUESTS as f64,
+    )
+}
+
+fn main() {
+    let normal = Router::new("").get("/health", |_req| {
+        HttpResponse::new("200", None, Some("ok".into()))
+    });
+    let fast = Router::new("").fast("/health", FastResponse::text("ok"));
+    // 先各跑一遍预热
+    run(&normal);
+    run(&fast);
+    let (normal_ns, normal_allocs) = run(&normal);
+    let (fast_ns, fast_allocs) = run(&fast);
+    println!(
+        "router       {:>8.1} ns/request  {:>5.1} allocations/request",
+        normal_ns, normal_allocs
+    );
+    println!(
+        "fast path    {:>8.1} ns/request  {:>5.1} allocations/request",
+        fast_ns, fast_allocs
+    );
+    println!("speedup      {:>8.1}x", normal_ns / fast_ns);
+}
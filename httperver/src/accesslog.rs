@@ -0,0 +1,369 @@
+// 访问日志：每个请求一行，记录客户端地址、方法、路径、状态码、响应大小和耗时
+// 作为路由最外层的中间件，所有处理器（包括挂载的子应用和 404）都会经过
+// 默认是 Common Log Format，方便交给现成的日志分析工具；也可以输出 JSON，一行一个对象
+// 写到 stdout，或者写文件并按大小轮转
+// 耗时用单调时钟从 before 量到 after；经过线程池服务器时还会带上请求的排队时间
+use crate::metrics::QueueTime;
+use crate::middleware::Middleware;
+use http::clock::{clf_date, Clock};
+use http::httprequest::{HttpRequest, Method, Version};
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_MAX_SIZE_MB: u64 = 100;
+pub const DEFAULT_KEEP: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Clf,
+    Json,
+}
+
+// [request_log]
+// format = "clf"
+// file = "/var/log/httperver/access.log"
+// max_size_mb = 100
+// keep = 5
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestLogConfig {
+    // 不设置表示不记录
+    pub format: Option<LogFormat>,
+    // 不设置时写 stdout
+    pub file: Option<String>,
+    // 文件超过这个大小后轮转成 access.log.1，旧的依次后移
+    pub max_size_mb: u64,
+    // 保留多少个轮转出来的旧文件，0 表示直接清空重写
+    pub keep: usize,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        RequestLogConfig {
+            format: None,
+            file: None,
+            max_size_mb: DEFAULT_MAX_SIZE_MB,
+            keep: DEFAULT_KEEP,
+        }
+    }
+}
+
+impl RequestLogConfig {
+    pub fn enabled(&self) -> bool {
+        self.format.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if !self.enabled() {
+            if self.file.is_some() {
+                problems.push("request_log: file requires request_log.format".to_string());
+            }
+            return;
+        }
+        if self.max_size_mb == 0 {
+            problems.push("request_log: max_size_mb must be positive".to_string());
+        }
+    }
+
+    // 打开日志目标，配置了文件时文件所在目录必须存在
+    pub fn open(&self) -> io::Result<Box<dyn Write + Send>> {
+        match &self.file {
+            Some(path) => Ok(Box::new(RotatingFile::open(
+                path,
+                self.max_size_mb * 1024 * 1024,
+                self.keep,
+            )?)),
+            None => Ok(Box::new(io::stdout())),
+        }
+    }
+}
+
+// 按大小轮转的日志文件：access.log -> access.log.1 -> access.log.2 ...
+// 只在一行写完之后检查大小，一行日志不会被拆到两个文件里
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: impl AsRef<Path>, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            keep,
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        if self.written >= self.max_bytes && buf[..n].ends_with(b"\n") {
+            self.rotate()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// before 里记下的开始时间和原始请求行；路由会改写路径（base_path、挂载前缀），
+// 日志里要的是客户端实际请求的路径
+struct Started {
+    // 日志里的时间戳
+    at: SystemTime,
+    // 计算耗时，不受系统时间调整影响
+    instant: Instant,
+    method: &'static str,
+    target: String,
+}
+
+pub struct AccessLog {
+    format: LogFormat,
+    clock: Arc<dyn Clock>,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat, out: Box<dyn Write + Send>, clock: Arc<dyn Clock>) -> Self {
+        AccessLog {
+            format,
+            clock,
+            out: Mutex::new(out),
+        }
+    }
+
+    fn line(&self, req: &HttpRequest, resp: &HttpResponse<'static>, latency: Duration) -> String {
+        let (at, method, target) = match req.extensions.get::<Started>() {
+            Some(s) => (s.at, s.method, s.target.as_str()),
+            None => (self.clock.now(), req.method.as_str(), req.path()),
+        };
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let queue_ms = req
+            .extensions
+            .get::<QueueTime>()
+            .map(|q| q.0.as_secs_f64() * 1000.0);
+        let remote = req
+            .client_ip()
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        // HEAD 只发头部，没有 body
+        let size = match req.method {
+            Method::Head => 0,
+            _ => resp.body_len().unwrap_or(0),
+        };
+        let status = resp.status().as_u16();
+        match self.format {
+            // 127.0.0.1 - - [06/Nov/1994:08:49:37 +0000] "GET /index.html HTTP/1.1" 200 2326 0.412 0.050
+            // 末尾是 CLF 之外附加的处理耗时和排队时间（毫秒），没有排队时间时是 -
+            LogFormat::Clf => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} {:.3} {}",
+                remote,
+                clf_date(at),
+                method,
+                target,
+                version_str(&req.version),
+                status,
+                if size == 0 {
+                    "-".to_string()
+                } else {
+                    size.to_string()
+                },
+                latency_ms,
+                queue_ms.map_or("-".to_string(), |ms| format!("{:.3}", ms))
+            ),
+            LogFormat::Json => serde_json::json!({
+                "time": at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                "remote_addr": remote,
+                "method": method,
+                "path": target,
+                "version": version_str(&req.version),
+                "status": status,
+                "size": size,
+                "latency_ms": latency_ms,
+                "queue_ms": queue_ms,
+            })
+            .to_string(),
+        }
+    }
+}
+
+impl Middleware for AccessLog {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let http::httprequest::Resource::Path(target) = &req.resource;
+        let started = Started {
+            at: self.clock.now(),
+            instant: Instant::now(),
+            method: req.method.as_str(),
+            target: target.clone(),
+        };
+        req.extensions.insert(started);
+        None
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+        let latency = req
+            .extensions
+            .get::<Started>()
+            .map_or(Duration::ZERO, |s| s.instant.elapsed());
+        let mut line = self.line(req, resp, latency);
+        line.push('\n');
+        let mut out = self.out.lock().unwrap();
+        // 日志写失败不影响响应
+        let _ = out.write_all(line.as_bytes());
+        let _ = out.flush();
+    }
+}
+
+fn version_str(version: &Version) -> &'static str {
+    match version {
+        Version::V1_0 => "HTTP/1.0",
+        Version::V1_1 => "HTTP/1.1",
+        Version::V2_0 => "HTTP/2.0",
+        Version::Uninitialized => "-",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use std::time::Duration;
+
+    // 测试里把日志写进共享的缓冲区
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 返回 (after 实际写出的内容, 耗时固定为 12ms 时的日志行)
+    fn log_one(format: LogFormat, raw: &str, queue: Option<Duration>) -> (String, String) {
+        let clock = MockClock::from_unix_secs(784_111_777);
+        let buf = Buffer::default();
+        let log = AccessLog::new(format, Box::new(buf.clone()), Arc::new(clock.clone()));
+        let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        req.remote_addr = Some("10.0.0.7:5555".parse().unwrap());
+        if let Some(queue) = queue {
+            req.extensions.insert(QueueTime(queue));
+        }
+        log.before(&mut req);
+        // 路由改写了路径，日志仍然记录原始路径
+        req.resource = http::httprequest::Resource::Path("/rewritten".into());
+        // 系统时间被往后调了一小时，耗时不受影响
+        clock.advance(Duration::from_secs(3600));
+        let mut resp = HttpResponse::new("404", None, Some("nope".into()));
+        log.after(&req, &mut resp);
+        let written = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        (written, log.line(&req, &resp, Duration::from_millis(12)))
+    }
+
+    #[test]
+    fn test_clf_line() {
+        let raw = "GET /app/x?y=1 HTTP/1.1\r\n\r\n";
+        let (written, line) = log_one(LogFormat::Clf, raw, Some(Duration::from_micros(1500)));
+        assert_eq!(
+            line,
+            "10.0.0.7 - - [06/Nov/1994:08:49:37 +0000] \"GET /app/x?y=1 HTTP/1.1\" 404 4 12.000 1.500"
+        );
+        let prefix = "10.0.0.7 - - [06/Nov/1994:08:49:37 +0000] \"GET /app/x?y=1 HTTP/1.1\" 404 4 ";
+        assert!(written.starts_with(prefix), "{}", written);
+        let latency: f64 = written[prefix.len()..]
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(latency < 1000.0, "{}", written);
+        assert!(written.ends_with(" 1.500\n"));
+        // 没有经过线程池服务器时没有排队时间
+        let (_, line) = log_one(LogFormat::Clf, raw, None);
+        assert!(line.ends_with(" 404 4 12.000 -"), "{}", line);
+    }
+
+    #[test]
+    fn test_json_line() {
+        let (written, line) = log_one(LogFormat::Json, "HEAD /a HTTP/1.0\r\n\r\n", None);
+        assert!(written.ends_with("}\n"));
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["method"], "HEAD");
+        assert_eq!(v["path"], "/a");
+        assert_eq!(v["status"], 404);
+        assert_eq!(v["size"], 0);
+        assert_eq!(v["latency_ms"], 12.0);
+        assert_eq!(v["queue_ms"], serde_json::Value::Null);
+        assert_eq!(v["time"], 784_111_777);
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = crate::testdir::temp_path("accesslog");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for line in ["first line\n", "second line\n", "third line\n", "4\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "4\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("access.log.1")).unwrap(),
+            "third line\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("access.log.2")).unwrap(),
+            "second line\n"
+        );
+        assert!(!dir.join("access.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+// 静态资源指纹：启动时扫描静态目录，为每个文件计算内容哈希，
+// app.css -> app.9f3a1b2c.css。文件内容变了 URL 就变，所以带哈希的地址可以永久缓存
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// 带哈希的资源统一放在这个前缀下
+pub const ASSET_PREFIX: &str = "/static";
+// 一年，配合 immutable 浏览器不会再来验证
+pub const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, Default, Clone)]
+pub struct AssetManifest {
+    // 原文件名 -> 带哈希的文件名
+    hashed: HashMap<String, String>,
+    // 带哈希的文件名 -> 原文件名，处理请求时用
+    originals: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    // 递归扫描 root，文件名用相对路径，例如 css/app.css
+    pub fn build(root: &str) -> io::Result<Self> {
+        let mut manifest = AssetManifest::default();
+        manifest.scan(Path::new(root), "")?;
+        Ok(manifest)
+    }
+
+    fn scan(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.scan(&entry.path(), &format!("{}/", name))?;
+            } else {
+                let contents = fs::read(entry.path())?;
+                self.insert(&name, &contents);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, name: &str, contents: &[u8]) {
+        let hash = format!("{:016x}", fnv1a(contents));
+        let hashed = hashed_name(name, &hash[..8]);
+        self.originals.insert(hashed.clone(), name.to_string());
+        self.hashed.insert(name.to_string(), hashed);
+    }
+
+    // 模板里使用：asset("app.css") -> /static/app.9f3a1b2c.css
+    // 不在清单里的文件原样返回，不至于让页面引用断掉
+    pub fn asset(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        match self.hashed.get(name) {
+            Some(hashed) => format!("{}/{}", ASSET_PREFIX, hashed),
+            None => format!("/{}", name),
+        }
+    }
+
+    // 带哈希的文件名对应的原文件，旧版本的哈希返回 None
+    pub fn original(&self, hashed: &str) -> Option<&str> {
+        self.originals.get(hashed).map(|s| s.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashed.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.hashed.is_empty()
+    }
+}
+
+// 每个静态目录一份清单：配置了蓝绿内容目录时，文件来自哪个目录就用哪个目录算出的哈希
+// 第一次用到某个目录时扫描，之后复用；目录切换后旧目录的清单留着，切回来不用重新扫描
+#[derive(Debug, Default)]
+pub struct AssetManifests {
+    // 目录 -> 清单
+    built: RwLock<HashMap<String, Arc<AssetManifest>>>,
+}
+
+impl AssetManifests {
+    pub fn new() -> Self {
+        AssetManifests::default()
+    }
+
+    pub fn for_root(&self, root: &str) -> io::Result<Arc<AssetManifest>> {
+        let built = self.built.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(manifest) = built.get(root) {
+            return Ok(manifest.clone());
+        }
+        drop(built);
+        let manifest = Arc::new(AssetManifest::build(root)?);
+        let mut built = self.built.write().unwrap_or_else(|e| e.into_inner());
+        Ok(built.entry(root.to_string()).or_insert(manifest).clone())
+    }
+}
+
+// 哈希插在最后一个扩展名前面：css/app.css -> css/app.<hash>.css，没有扩展名时加在末尾
+fn hashed_name(name: &str, hash: &str) -> String {
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((d, f)) => (format!("{}/", d), f),
+        None => (String::new(), name),
+    };
+    match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}{}.{}.{}", dir, stem, hash, ext),
+        _ => format!("{}{}.{}", dir, file, hash),
+    }
+}
+
+// FNV-1a 64 位，结果跨版本、跨平台稳定，足够用来做缓存指纹
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_asset_manifest() {
+        let mut manifest = AssetManifest::default();
+        manifest.insert("app.css", b"body {}");
+        manifest.insert("js/main.js", b"alert(1)");
+        let url = manifest.asset("app.css");
+        assert!(url.starts_with("/static/app."));
+        assert!(url.ends_with(".css"));
+        let hashed = url.trim_start_matches("/static/");
+        assert_eq!(manifest.original(hashed), Some("app.css"));
+        assert!(manifest
+            .asset("/js/main.js")
+            .starts_with("/static/js/main."));
+        assert_eq!(manifest.asset("missing.png"), "/missing.png");
+        // 内容不同哈希不同
+        let mut other = AssetManifest::default();
+        other.insert("app.css", b"body { color: red }");
+        assert_ne!(other.asset("app.css"), url);
+        assert_eq!(hashed_name("LICENSE", "abcd1234"), "LICENSE.abcd1234");
+        assert_eq!(hashed_name(".env", "abcd1234"), ".env.abcd1234");
+    }
+
+    #[test]
+    fn test_manifest_per_root() {
+        let dir = crate::testdir::temp_path("manifests");
+        for (name, css) in [("blue", "body {}"), ("green", "body { color: red }")] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(dir.join(name).join("app.css"), css).unwrap();
+        }
+        let root = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let manifests = AssetManifests::new();
+        let blue = manifests.for_root(&root("blue")).unwrap();
+        let green = manifests.for_root(&root("green")).unwrap();
+        assert_ne!(blue.asset("app.css"), green.asset("app.css"));
+        // 同一个目录只扫描一次
+        assert!(Arc::ptr_eq(
+            &blue,
+            &manifests.for_root(&root("blue")).unwrap()
+        ));
+        assert!(manifests.for_root(&root("missing")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
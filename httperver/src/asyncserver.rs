@@ -0,0 +1,585 @@
+// tokio 上的服务器：每个连接是一个异步任务而不是占住一个工作线程，
+// 上千个空闲的长连接只占内存
+// 同步的 Router 放在 spawn_blocking 里执行，静态文件之类的阻塞 IO 不会卡住运行时；
+// 用 get_async / on_async 注册的异步处理器直接在运行时上执行，可以 await 其他异步调用
+// 读写超时和线程池版本一样；限速、租户、录制、优先级队列等功能仍然只在线程池版本的 Server 上
+use crate::docs::RouteDoc;
+use crate::neterror::{self, ErrorClass};
+use crate::priority::Priority;
+use crate::router::{RouteInfo, Router};
+use crate::server::{
+    self, ConnectionHeader, KeepAlive, ReadError, Timeouts, DEFAULT_DRAIN_TIMEOUT_SECS,
+};
+use crate::shutdown::{self, ShutdownHandle};
+use http::headers::names;
+use http::httprequest::{self, HttpRequest, Limits, Method, ParseError};
+use http::httpresponse::{HttpResponse, FILE_CHUNK_SIZE};
+use http::proxy::TrustedProxies;
+use std::future::Future;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+type AsyncHandler = Arc<dyn Fn(HttpRequest) -> BoxFuture<HttpResponse<'static>> + Send + Sync>;
+
+struct AsyncRoute {
+    info: RouteInfo,
+    handler: AsyncHandler,
+}
+
+// 连接任务还没写出的响应块最多攒几个，超过时生成响应的一方等待
+const RESPONSE_CHUNKS: usize = 4;
+// 多久检查一次退出标志
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// accept 遇到暂时性错误后稍等再试
+const TRANSIENT_PAUSE: Duration = Duration::from_millis(10);
+
+pub struct AsyncServer {
+    addr: String,
+    router: Arc<Router>,
+    // 先于 router 匹配
+    routes: Vec<AsyncRoute>,
+    keep_alive: KeepAlive,
+    timeouts: Timeouts,
+    limits: Limits,
+    trusted_proxies: TrustedProxies,
+    // run_blocking 创建的运行时的线程数
+    workers: usize,
+    shutdown: ShutdownHandle,
+    handle_signals: bool,
+    // 停止接受连接后，最多等这么久让正在处理的连接结束
+    drain_timeout: Duration,
+}
+
+impl AsyncServer {
+    pub fn new(addr: impl Into<String>) -> Self {
+        AsyncServer {
+            addr: addr.into(),
+            router: Arc::new(Router::default()),
+            routes: Vec::new(),
+            keep_alive: KeepAlive::default(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            trusted_proxies: TrustedProxies::default(),
+            workers: server::DEFAULT_WORKERS,
+            shutdown: ShutdownHandle::new(),
+            handle_signals: false,
+            drain_timeout: Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECS),
+        }
+    }
+    pub fn router(mut self, router: Router) -> Self {
+        self.router = Arc::new(router);
+        self
+    }
+    // server.get_async("/api/quote/:id", |req| async move { ... })
+    // 路径参数同样通过 req.params() 读取；匹配的是完整路径，不去掉 router 的 base_path
+    pub fn get_async<F, Fut>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse<'static>> + Send + 'static,
+    {
+        self.on_async("GET", path, handler)
+    }
+    pub fn post_async<F, Fut>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse<'static>> + Send + 'static,
+    {
+        self.on_async("POST", path, handler)
+    }
+    // method 为 "*" 时匹配任意方法
+    pub fn on_async<F, Fut>(mut self, method: &'static str, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(HttpRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HttpResponse<'static>> + Send + 'static,
+    {
+        let info = RouteInfo {
+            name: None,
+            method,
+            path,
+            handler: "async closure",
+            priority: Priority::Normal,
+            doc: RouteDoc::default(),
+        };
+        let handler: AsyncHandler = Arc::new(move |req| Box::pin(handler(req)));
+        self.routes.push(AsyncRoute { info, handler });
+        self
+    }
+    // max_requests 为 1 时每个连接只处理一个请求
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+    // 读头部、读整个请求、每次读写的超时，含义和线程池版本的 Server::timeouts 一样
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+    // 请求行、头部和 body 的大小上限，超出时回 414 / 431 / 413
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    // 来自这些地址的请求才会解析 Forwarded / X-Forwarded-* 头
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+    // 收到 SIGINT / SIGTERM 时优雅退出
+    pub fn handle_signals(mut self, enabled: bool) -> Self {
+        self.handle_signals = enabled;
+        self
+    }
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+    // 在其他线程或任务里调用 shutdown() 让 serve() 返回
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+    fn stop_requested(&self) -> bool {
+        self.shutdown.is_requested() || (self.handle_signals && shutdown::signalled())
+    }
+
+    pub async fn bind(&self) -> io::Result<TcpListener> {
+        TcpListener::bind(&self.addr).await
+    }
+
+    pub async fn run(self) -> io::Result<()> {
+        let listener = self.bind().await?;
+        println!("Running on {} (async)", listener.local_addr()?);
+        self.serve(listener).await
+    }
+
+    // 不想自己搭 tokio 运行时的程序用这个，阻塞到服务器退出
+    pub fn run_blocking(self) -> io::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.workers)
+            .enable_all()
+            .build()?
+            .block_on(self.run())
+    }
+
+    // 测试里先 bind 到 127.0.0.1:0 拿到端口，再交给 serve
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        if self.handle_signals {
+            shutdown::install_signal_handler();
+        }
+        let server = Arc::new(self);
+        let mut conns = JoinSet::new();
+        loop {
+            if server.stop_requested() {
+                println!("Shutting down, finishing in-flight requests");
+                break;
+            }
+            // 顺便回收已经结束的连接任务
+            while conns.try_join_next().is_some() {}
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(conn) => conn,
+                    Err(e) => match neterror::classify(&e) {
+                        ErrorClass::Fatal => {
+                            eprintln!("Listener failed, stop accepting: {}", e);
+                            break;
+                        }
+                        ErrorClass::Connection => continue,
+                        _ => {
+                            tokio::time::sleep(TRANSIENT_PAUSE).await;
+                            continue;
+                        }
+                    },
+                },
+                _ = tokio::time::sleep(POLL_INTERVAL) => continue,
+            };
+            let server = server.clone();
+            // 连接任务结束时丢弃，打开的连接数减一
+            let open = server.router.prometheus_metrics().map(|m| m.connection());
+            conns.spawn(async move {
+                server.connection(stream, peer).await;
+                drop(open);
+            });
+        }
+        drop(listener);
+        // 正在处理的请求做完再返回，超时后剩下的连接直接断开
+        let drained = tokio::time::timeout(server.drain_timeout, async {
+            while conns.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            eprintln!("Drain timeout, dropping {} connections", conns.len());
+            conns.abort_all();
+        }
+        println!("Server stopped");
+        Ok(())
+    }
+
+    // 一个连接上的所有请求，逐个读取、处理、写回
+    async fn connection(&self, mut stream: TcpStream, peer: SocketAddr) {
+        let mut pending = Vec::new();
+        let mut served = 0;
+        loop {
+            // 新连接上等第一个字节受 read 限制，长连接上两个请求之间受空闲时间限制
+            let first = if served == 0 {
+                self.timeouts.read
+            } else {
+                self.keep_alive.idle_timeout
+            };
+            let read = read_request(
+                &mut stream,
+                std::mem::take(&mut pending),
+                first,
+                &self.timeouts,
+                &self.limits,
+            );
+            let mut buffer = match read.await {
+                Ok(buffer) => buffer,
+                // 长连接空闲超时，正常关闭
+                Err(ReadError::Timeout { started: false }) if served > 0 => return,
+                Err(ReadError::Timeout { .. }) => {
+                    let out: Vec<u8> = server::request_timeout().into();
+                    let _ = write_with_timeout(&mut stream, &out, self.timeouts.write).await;
+                    return;
+                }
+                Err(ReadError::Io(e)) => {
+                    neterror::log_connection_error(&format!("read from {}", peer), &e);
+                    return;
+                }
+            };
+            // 客户端可能不等响应就发来下一个请求，多读到的部分留给下一轮
+            if let Ok(Some(len)) = httprequest::message_len_with(&buffer, &self.limits) {
+                if len < buffer.len() {
+                    pending = buffer.split_off(len);
+                }
+            }
+            let mut req = match HttpRequest::parse(&buffer, &self.limits) {
+                Ok(req) => req,
+                Err(ParseError::Empty) => return,
+                Err(e) => {
+                    let out: Vec<u8> = server::parse_error_response(e).into();
+                    let _ = write_with_timeout(&mut stream, &out, self.timeouts.write).await;
+                    return;
+                }
+            };
+            req.remote_addr = Some(peer);
+            self.trusted_proxies.apply(&mut req);
+            // 达到单连接请求上限或服务器正在退出时，这是连接上的最后一个请求
+            served += 1;
+            let keep_alive =
+                req.keep_alive() && served < self.keep_alive.max_requests && !self.stop_requested();
+            // 响应在阻塞线程上生成，按块经 channel 交给这里写出，文件 body 不会整个读进内存
+            let (tx, mut rx) = mpsc::channel(RESPONSE_CHUNKS);
+            let responding = self.respond(req, keep_alive, tx);
+            let mut sent = false;
+            while let Some(chunk) = rx.recv().await {
+                sent = true;
+                // 写失败时 rx 随连接一起丢弃，生成响应的一方写入出错后停止
+                if let Err(e) = write_with_timeout(&mut stream, &chunk, self.timeouts.write).await {
+                    neterror::log_connection_error("write response", &e);
+                    return;
+                }
+            }
+            let reusable = match responding.await {
+                Ok(Ok(reusable)) => reusable,
+                // 处理器 panic 只影响这一个请求；已经写出一部分时只能断开连接
+                _ => {
+                    eprintln!("Request handler panicked");
+                    if !sent {
+                        let out: Vec<u8> = HttpResponse::new("500", None, Some(String::new()))
+                            .with_header(names::CONNECTION, "close")
+                            .expect("valid header")
+                            .into();
+                        let _ = write_with_timeout(&mut stream, &out, self.timeouts.write).await;
+                    }
+                    return;
+                }
+            };
+            if !reusable {
+                return;
+            }
+        }
+    }
+
+    // 匹配的异步路由直接 await，其他请求交给同步的 Router
+    // 两种响应都在阻塞线程上写进 tx，补上 Connection 头；返回连接能否复用
+    fn respond(
+        &self,
+        mut req: HttpRequest,
+        keep_alive: bool,
+        tx: mpsc::Sender<Vec<u8>>,
+    ) -> JoinHandle<Result<bool, JoinError>> {
+        let head = req.method == Method::Head;
+        let method = if head { "GET" } else { req.method.as_str() };
+        let matched = self.routes.iter().find_map(|r| {
+            let params = r.info.params(method, req.path())?;
+            Some((params, r.handler.clone()))
+        });
+        let router = self.router.clone();
+        // 单独的任务，连接任务同时从 rx 取出响应块写出
+        tokio::spawn(async move {
+            let routed = match matched {
+                Some((params, handler)) => {
+                    req.extensions.insert(params);
+                    Routed::Async(handler(req).await)
+                }
+                None => Routed::Sync(req),
+            };
+            tokio::task::spawn_blocking(move || {
+                let mut out = ChannelWriter::new(tx);
+                let mut header = ConnectionHeader::new(&mut out, keep_alive);
+                match routed {
+                    Routed::Async(resp) if head => {
+                        let _ = resp.send_head(&mut header);
+                    }
+                    Routed::Async(resp) => {
+                        let _ = resp.send_response(&mut header);
+                    }
+                    Routed::Sync(req) => router.route(req, &mut header),
+                }
+                header.finish().unwrap_or(false)
+            })
+            .await
+        })
+    }
+}
+
+// 异步处理器已经给出的响应，或者还要交给同步 Router 的请求
+enum Routed {
+    Async(HttpResponse<'static>),
+    Sync(HttpRequest),
+}
+
+// 同步代码写出的响应攒够一块就发给连接任务；连接任务已经放弃时写入返回 BrokenPipe
+struct ChannelWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(tx: mpsc::Sender<Vec<u8>>) -> Self {
+        ChannelWriter {
+            tx,
+            buf: Vec::with_capacity(FILE_CHUNK_SIZE),
+        }
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= FILE_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(FILE_CHUNK_SIZE));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+// 和线程池版本的 read_request 一样：先读到头部结束，再按 Content-Length 读完 body，
+// 头部和整个请求各有期限，每次读最多等 timeouts.read；first 是等第一个字节的时间
+async fn read_request(
+    stream: &mut (impl AsyncRead + Unpin),
+    mut buffer: Vec<u8>,
+    first: Duration,
+    timeouts: &Timeouts,
+    limits: &Limits,
+) -> Result<Vec<u8>, ReadError> {
+    let mut chunk = [0; 1024];
+    let mut started = None;
+    loop {
+        let head_done = match httprequest::message_len_with(&buffer, limits) {
+            Ok(None) => false,
+            Ok(Some(len)) if buffer.len() < len => true,
+            _ => return Ok(buffer),
+        };
+        let wait = if buffer.is_empty() {
+            first
+        } else {
+            let left = timeouts.left(*started.get_or_insert_with(Instant::now), head_done);
+            if left.is_zero() {
+                return Err(ReadError::Timeout { started: true });
+            }
+            left.min(timeouts.read)
+        };
+        let n = match tokio::time::timeout(wait, stream.read(&mut chunk)).await {
+            Err(_) => {
+                return Err(ReadError::Timeout {
+                    started: !buffer.is_empty(),
+                })
+            }
+            Ok(Ok(0)) => return Ok(buffer),
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Ok(Err(e)) => return Err(ReadError::Io(e)),
+        };
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+// 每次写最多等 timeout，对端一直不收数据时放弃这个连接
+async fn write_with_timeout(
+    stream: &mut (impl AsyncWrite + Unpin),
+    mut out: &[u8],
+    timeout: Duration,
+) -> io::Result<()> {
+    while !out.is_empty() {
+        let n = tokio::time::timeout(timeout, stream.write(out))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        out = &out[n..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::RequestParams;
+
+    #[tokio::test]
+    async fn test_async_and_sync_routes_share_a_connection() {
+        let router = Router::new("").get("/hello", |_req| {
+            HttpResponse::new("200", None, Some("hello".into()))
+        });
+        let server = AsyncServer::new("127.0.0.1:0")
+            .router(router)
+            .get_async("/quote/:id", |req| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let id = req.params().get("id").unwrap_or("").to_string();
+                HttpResponse::new("200", None, Some(format!("quote {}", id)))
+            })
+            .drain_timeout(Duration::from_secs(1));
+        let handle = server.shutdown_handle();
+        let listener = server.bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = tokio::spawn(server.serve(listener));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(
+                b"GET /quote/7 HTTP/1.1\r\n\r\nGET /hello HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut out = String::new();
+        client.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out.matches("HTTP/1.1 200").count(), 2);
+        assert!(out.contains("Connection: keep-alive\r\n\r\nquote 7"));
+        assert!(out.ends_with("Connection: close\r\n\r\nhello"));
+
+        handle.shutdown();
+        running.await.unwrap().unwrap();
+    }
+    #[tokio::test]
+    async fn test_file_body_streamed() {
+        let dir = crate::testdir::temp_path("async-file");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("big.bin");
+        // 好几块，最后一块不满
+        let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 3 + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&path, &data).unwrap();
+        let file = path.clone();
+        let router = Router::new("").get("/big", move |_req| HttpResponse::file(&file).unwrap());
+        let server = AsyncServer::new("127.0.0.1:0")
+            .router(router)
+            .get_async("/quote", |_req| async move {
+                HttpResponse::new("200", None, Some("quote".into()))
+            })
+            .drain_timeout(Duration::from_secs(1));
+        let handle = server.shutdown_handle();
+        let listener = server.bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = tokio::spawn(server.serve(listener));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(
+                b"HEAD /quote HTTP/1.1\r\n\r\nGET /big HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut out = Vec::new();
+        client.read_to_end(&mut out).await.unwrap();
+        let text = String::from_utf8_lossy(&out);
+        // HEAD 只有头部，紧接着是第二个响应
+        assert!(text.starts_with("HTTP/1.1 200"));
+        assert!(text.contains("Content-Length: 5\r\nConnection: keep-alive\r\n\r\nHTTP/1.1 200"));
+        let head = format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            data.len()
+        );
+        assert!(text.contains(&head));
+        assert!(out.ends_with(&data));
+
+        handle.shutdown();
+        running.await.unwrap().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_body_times_out() {
+        let server = AsyncServer::new("127.0.0.1:0")
+            .timeouts(Timeouts {
+                header: Duration::from_millis(300),
+                read: Duration::from_millis(200),
+                request: Duration::from_millis(500),
+                write: Duration::from_millis(200),
+            })
+            .drain_timeout(Duration::from_secs(1));
+        let handle = server.shutdown_handle();
+        let listener = server.bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let running = tokio::spawn(server.serve(listener));
+
+        // body 每 50ms 发一个字节，每次都在 read 之内，整个请求超过 request
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n")
+            .await
+            .unwrap();
+        let started = Instant::now();
+        let (mut reader, mut writer) = client.into_split();
+        let sender = tokio::spawn(async move {
+            for _ in 0..40 {
+                if writer.write_all(b"X").await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+        let mut out = String::new();
+        reader.read_to_string(&mut out).await.unwrap();
+        assert!(out.starts_with("HTTP/1.1 408"), "{}", out);
+        assert!(started.elapsed() < Duration::from_millis(1500));
+        sender.abort();
+
+        handle.shutdown();
+        running.await.unwrap().unwrap();
+    }
+}
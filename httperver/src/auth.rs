@@ -0,0 +1,296 @@
+// 认证：解析 Authorization 头部，支持 Basic（用户名和密码）和 Bearer（token）两种方式，
+// 校验交给使用方提供的回调；通过之后身份放进 req.extensions，处理器用 req.identity() 取出
+//
+//     let auth = Auth::new("shop")
+//         .basic(|user, password| users.check(user, password))
+//         .bearer(|token| tokens.owner(token));
+//     let router = Router::new("").mount("/admin", admin.middleware(auth));
+//
+// 没有凭据或者校验失败时回 401，每种启用的方式一个 WWW-Authenticate 头部
+// 用 .jwt::<ApiClaims>(jwt) 代替 .bearer 时 token 按 JWT 校验，claims 用 req.claims() 取出，见 jwt.rs
+// 比较密码和 token 时应该用与长度无关的常量时间比较，这由回调负责
+use crate::jwt::Jwt;
+use crate::middleware::Middleware;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use http::extensions::Extensions;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::de::DeserializeOwned;
+
+type BasicCheck = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+// 校验通过时返回主体，可以顺便往 req.extensions 里放数据（JWT 的 claims）
+type BearerCheck = Box<dyn Fn(&str, &mut Extensions) -> Option<String> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Basic,
+    Bearer,
+}
+
+// 通过认证的调用方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    // Basic 是用户名，Bearer 是回调返回的主体（用户 ID、服务名等）
+    pub name: String,
+    pub scheme: Scheme,
+}
+
+pub struct Auth {
+    realm: String,
+    basic: Option<BasicCheck>,
+    bearer: Option<BearerCheck>,
+    // 只检查这些路径前缀下的请求，为空时检查所有请求
+    paths: Vec<String>,
+}
+
+// Authorization 头部的解析结果
+enum Credentials<'a> {
+    Basic(String, String),
+    Bearer(&'a str),
+}
+
+// 不认识的方式和格式不对的凭据都返回 None，按没有凭据处理
+fn parse_credentials(header: &str) -> Option<Credentials<'_>> {
+    let (scheme, value) = header.trim().split_once(' ')?;
+    let value = value.trim();
+    if scheme.eq_ignore_ascii_case("Basic") {
+        let decoded = STANDARD.decode(value).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        // 用户名里不能有冒号，密码里可以有
+        let (user, password) = decoded.split_once(':')?;
+        Some(Credentials::Basic(user.to_string(), password.to_string()))
+    } else if scheme.eq_ignore_ascii_case("Bearer") && !value.is_empty() {
+        Some(Credentials::Bearer(value))
+    } else {
+        None
+    }
+}
+
+impl Auth {
+    // realm 出现在 WWW-Authenticate 里，浏览器弹出的登录框会显示它
+    pub fn new(realm: impl Into<String>) -> Self {
+        Auth {
+            realm: realm.into().replace(['"', '\\'], ""),
+            basic: None,
+            bearer: None,
+            paths: Vec::new(),
+        }
+    }
+    // 启用 Basic，回调判断用户名和密码是否正确
+    pub fn basic(mut self, check: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+        self.basic = Some(Box::new(check));
+        self
+    }
+    // 启用 Bearer，回调对有效的 token 返回它代表的主体
+    pub fn bearer(
+        mut self,
+        check: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.bearer = Some(Box::new(move |token, _| check(token)));
+        self
+    }
+    // 启用 Bearer，token 按 JWT 校验，主体是 sub；claims 转成 T 放进 req.extensions
+    // 和 bearer 二选一，后设置的生效
+    pub fn jwt<T: DeserializeOwned + Send + Sync + 'static>(mut self, jwt: Jwt) -> Self {
+        self.bearer = Some(Box::new(move |token, extensions| {
+            let claims = jwt.verify_value(token).ok()?;
+            let name = claims
+                .get("sub")
+                .and_then(|s| s.as_str())
+                .unwrap_or_default();
+            let name = name.to_string();
+            extensions.insert(serde_json::from_value::<T>(claims).ok()?);
+            Some(name)
+        }));
+        self
+    }
+    // 只保护 prefix 下的路径，可以调用多次；不调用时保护所有路径
+    pub fn only(mut self, prefix: &str) -> Self {
+        self.paths.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn protects(&self, path: &str) -> bool {
+        self.paths.is_empty()
+            || self
+                .paths
+                .iter()
+                .any(|p| match path.strip_prefix(p.as_str()) {
+                    Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                    None => false,
+                })
+    }
+
+    fn authenticate(&self, header: &str, extensions: &mut Extensions) -> Option<Identity> {
+        match parse_credentials(header)? {
+            Credentials::Basic(user, password) => {
+                let check = self.basic.as_ref()?;
+                check(&user, &password).then_some(Identity {
+                    name: user,
+                    scheme: Scheme::Basic,
+                })
+            }
+            Credentials::Bearer(token) => {
+                let check = self.bearer.as_ref()?;
+                Some(Identity {
+                    name: check(token, extensions)?,
+                    scheme: Scheme::Bearer,
+                })
+            }
+        }
+    }
+
+    // 带了 Bearer token 但是无效时按 RFC 6750 加上 error="invalid_token"
+    fn unauthorized(&self, header: Option<&str>) -> HttpResponse<'static> {
+        let invalid_token = header
+            .and_then(parse_credentials)
+            .is_some_and(|c| matches!(c, Credentials::Bearer(_)));
+        let mut resp = HttpResponse::new("401", None, Some("Unauthorized".into()));
+        if self.basic.is_some() {
+            let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm);
+            let _ = resp.append_header(names::WWW_AUTHENTICATE, challenge);
+        }
+        if self.bearer.is_some() {
+            let mut challenge = format!("Bearer realm=\"{}\"", self.realm);
+            if invalid_token {
+                challenge.push_str(", error=\"invalid_token\"");
+            }
+            let _ = resp.append_header(names::WWW_AUTHENTICATE, challenge);
+        }
+        resp
+    }
+}
+
+impl Middleware for Auth {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        if !self.protects(req.path()) {
+            return None;
+        }
+        let header = req.header(names::AUTHORIZATION).map(str::to_string);
+        let identity = header
+            .as_deref()
+            .and_then(|h| self.authenticate(h, &mut req.extensions));
+        match identity {
+            Some(identity) => {
+                req.extensions.insert(identity);
+                None
+            }
+            None => Some(self.unauthorized(header.as_deref())),
+        }
+    }
+}
+
+// 让处理器可以写 req.identity()，没有经过 Auth 的请求返回 None
+pub trait RequestIdentity {
+    fn identity(&self) -> Option<&Identity>;
+}
+
+impl RequestIdentity for HttpRequest {
+    fn identity(&self) -> Option<&Identity> {
+        self.extensions.get::<Identity>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::RequestClaims;
+    use crate::router::Router;
+    use http::clock::MockClock;
+    use std::sync::Arc;
+
+    fn router(auth: Auth) -> Router {
+        Router::new("").middleware(auth).get("/me", |req| {
+            let who = req.identity().map(|i| i.name.clone()).unwrap_or_default();
+            HttpResponse::new("200", None, Some(who))
+        })
+    }
+
+    fn send(router: &Router, authorization: Option<&str>) -> String {
+        let mut raw = "GET /me HTTP/1.1\r\n".to_string();
+        if let Some(value) = authorization {
+            raw.push_str(&format!("Authorization: {}\r\n", value));
+        }
+        raw.push_str("\r\n");
+        let mut out = Vec::new();
+        router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_auth() {
+        let router = router(
+            Auth::new("shop")
+                .basic(|user, password| user == "ann" && password == "p:w")
+                .bearer(|token| (token == "t0k3n").then(|| "svc-orders".to_string())),
+        );
+        let basic = |s: &str| format!("Basic {}", STANDARD.encode(s));
+        let out = send(&router, Some(&basic("ann:p:w")));
+        assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+        assert!(out.ends_with("\r\n\r\nann"));
+        let out = send(&router, Some("bearer t0k3n"));
+        assert!(out.ends_with("\r\n\r\nsvc-orders"));
+
+        let out = send(&router, None);
+        assert!(out.starts_with("HTTP/1.1 401"));
+        assert!(out.contains("WWW-Authenticate:Basic realm=\"shop\", charset=\"UTF-8\"\r\n"));
+        assert!(out.contains("WWW-Authenticate:Bearer realm=\"shop\"\r\n"));
+        for bad in [
+            basic("ann:wrong"),
+            basic("ann"),
+            "Basic not-base64!".to_string(),
+            "Digest x".to_string(),
+        ] {
+            assert!(
+                send(&router, Some(&bad)).starts_with("HTTP/1.1 401"),
+                "{}",
+                bad
+            );
+        }
+        let out = send(&router, Some("Bearer nope"));
+        assert!(out.starts_with("HTTP/1.1 401"));
+        assert!(out.contains("Bearer realm=\"shop\", error=\"invalid_token\"\r\n"));
+    }
+
+    #[test]
+    fn test_only_bearer() {
+        let router = router(Auth::new("api").bearer(|token| Some(token.to_string())));
+        let out = send(&router, Some(&format!("Basic {}", STANDARD.encode("a:b"))));
+        assert!(out.starts_with("HTTP/1.1 401"));
+        assert!(!out.contains("Basic realm"));
+        assert!(out.contains("WWW-Authenticate:Bearer realm=\"api\"\r\n"));
+    }
+
+    #[test]
+    fn test_jwt() {
+        #[derive(serde::Deserialize)]
+        struct ApiClaims {
+            scope: String,
+        }
+        let jwt = Jwt::hs256(b"k", Arc::new(MockClock::from_unix_secs(1_000)));
+        let router = Router::new("")
+            .middleware(Auth::new("api").jwt::<ApiClaims>(jwt.clone()).only("/me"))
+            .get("/me", |req| {
+                let who = &req.identity().unwrap().name;
+                let scope = &req.claims::<ApiClaims>().unwrap().scope;
+                HttpResponse::new("200", None, Some(format!("{} {}", who, scope)))
+            })
+            .get("/health", |_req| HttpResponse::new("200", None, None));
+        let token = jwt
+            .sign(&serde_json::json!({"sub": "ann", "scope": "orders", "exp": 2_000}))
+            .unwrap();
+        let out = send(&router, Some(&format!("Bearer {}", token)));
+        assert!(out.ends_with("\r\n\r\nann orders"), "{}", out);
+        // 缺字段转不成 ApiClaims 也算无效
+        let token = jwt.sign(&serde_json::json!({"sub": "ann", "exp": 2_000}));
+        let out = send(&router, Some(&format!("Bearer {}", token.unwrap())));
+        assert!(out.contains("error=\"invalid_token\""));
+        assert!(send(&router, None).starts_with("HTTP/1.1 401"));
+        let mut out = Vec::new();
+        let req = HttpRequest::try_from(&b"GET /health HTTP/1.1\r\n\r\n"[..]).unwrap();
+        router.route(req, &mut out);
+        assert!(out.starts_with(b"HTTP/1.1 200"));
+    }
+}
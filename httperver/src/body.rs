@@ -0,0 +1,127 @@
+// #[route] 处理器的参数类型，按 Content-Type 解码请求体
+//
+//     #[route(POST, "/api/orders")]
+//     fn create(order: Body<OrderInput>) -> HttpResponse<'static> { ... }
+//
+// 用哪些格式由 Router::codec 注册的编解码器决定，处理器不用关心；
+// 解码失败时直接返回 415 / 400，不会调用处理器
+use http::codec::CodecError;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::de::DeserializeOwned;
+use std::ops::{Deref, DerefMut};
+
+pub struct Body<T>(pub T);
+
+impl<T: DeserializeOwned> Body<T> {
+    pub fn from_request(req: &HttpRequest) -> Result<Self, HttpResponse<'static>> {
+        req.decode().map(Body).map_err(CodecError::into_response)
+    }
+}
+
+impl<T> Body<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Body<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Body<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route;
+    use crate::router::Router;
+    use http::codec::{erased_serde, Codec, Visit};
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        status: String,
+    }
+
+    #[route(POST, "/echo")]
+    fn echo(req: &HttpRequest, order: Body<Order>) -> HttpResponse<'static> {
+        HttpResponse::encode(req, &*order)
+    }
+
+    // 测试用的格式：JSON 外面包一层 < >
+    struct Angle;
+
+    impl Codec for Angle {
+        fn media_type(&self) -> &'static str {
+            "application/x-angle"
+        }
+        fn decode(&self, body: &[u8], visit: &mut Visit) -> Result<(), CodecError> {
+            let inner = body
+                .strip_prefix(b"<")
+                .and_then(|b| b.strip_suffix(b">"))
+                .ok_or_else(|| CodecError::Invalid("missing < >".into()))?;
+            let mut de = serde_json::Deserializer::from_slice(inner);
+            visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))
+                .map_err(|e| CodecError::Invalid(e.to_string()))
+        }
+        fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, CodecError> {
+            let json =
+                serde_json::to_string(value).map_err(|e| CodecError::Encode(e.to_string()))?;
+            Ok(format!("<{}>", json).into_bytes())
+        }
+    }
+
+    fn post(router: &Router, content_type: &str, accept: &str, body: &str) -> String {
+        let raw = format!(
+            "POST /echo HTTP/1.1\r\nContent-Type: {}\r\nAccept: {}\r\nContent-Length: {}\r\n\r\n{}",
+            content_type,
+            accept,
+            body.len(),
+            body
+        );
+        let mut out = Vec::new();
+        router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_body_extractor() {
+        let router = crate::register_routes!(Router::new(""), echo);
+        let out = post(
+            &router,
+            "application/x-www-form-urlencoded",
+            "application/json",
+            "id=3&status=new",
+        );
+        assert!(out.ends_with(r#"{"id":3,"status":"new"}"#), "{}", out);
+        let out = post(&router, "text/csv", "*/*", "3,new");
+        assert!(out.starts_with("HTTP/1.1 415"), "{}", out);
+        let out = post(&router, "application/json", "*/*", r#"{"id":"x"}"#);
+        assert!(out.starts_with("HTTP/1.1 400"), "{}", out);
+
+        // 注册新格式后处理器不用改
+        let router = crate::register_routes!(Router::new(""), echo).codec(Arc::new(Angle));
+        let out = post(
+            &router,
+            "application/x-angle",
+            "application/x-angle",
+            r#"<{"id":4,"status":"old"}>"#,
+        );
+        assert!(
+            out.contains("Content-Type:application/x-angle\r\n"),
+            "{}",
+            out
+        );
+        assert!(out.ends_with(r#"<{"id":4,"status":"old"}>"#), "{}", out);
+    }
+}
@@ -0,0 +1,230 @@
+// 按 User-Agent 给请求分类，已知爬虫可以单独限速或直接拒绝
+// 每个分类的请求数 / 拒绝数 / 限速数会被统计，本机通过 GET /_admin/bots 查看（由服务器的运行状态接口回复）
+use http::clock::Clock;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+
+// 没有匹配任何规则的请求归到这个分类
+pub const DEFAULT_CLASS: &str = "default";
+// 计数表超过这个大小时清理过期的窗口，防止大量不同 IP 把内存撑爆
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotAction {
+    Allow,
+    Throttle,
+    Block,
+}
+
+// [[bots]]
+// name = "scrapers"
+// patterns = ["python-requests", "scrapy"]
+// action = "throttle"
+// requests_per_minute = 30
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BotRule {
+    pub name: String,
+    // 不区分大小写的子串匹配，按配置顺序第一个命中的规则生效
+    pub patterns: Vec<String>,
+    pub action: BotAction,
+    // 只对 throttle 有效，按 分类 + 客户端 IP 计数
+    #[serde(default = "default_rate")]
+    pub requests_per_minute: u32,
+}
+
+fn default_rate() -> u32 {
+    60
+}
+
+impl BotRule {
+    pub fn validate(rules: &[BotRule], problems: &mut Vec<String>) {
+        for rule in rules {
+            if rule.name.is_empty() || rule.name == DEFAULT_CLASS {
+                problems.push(format!("bots: invalid rule name {:?}", rule.name));
+            }
+            if rule.patterns.iter().any(|p| p.is_empty()) || rule.patterns.is_empty() {
+                problems.push(format!("bots.{}: patterns must be non-empty", rule.name));
+            }
+            if rule.action == BotAction::Throttle && rule.requests_per_minute == 0 {
+                problems.push(format!(
+                    "bots.{}: requests_per_minute must be positive, use action = \"block\" instead",
+                    rule.name
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Block,
+    // 还要等多少秒才能再次请求
+    Throttle(u64),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ClassStats {
+    pub requests: u64,
+    pub blocked: u64,
+    pub throttled: u64,
+}
+
+pub struct BotGuard {
+    rules: Vec<BotRule>,
+    clock: Arc<dyn Clock>,
+    // (规则下标, 客户端 IP) -> (窗口开始的分钟数, 本窗口内的请求数)
+    buckets: Mutex<HashMap<(usize, IpAddr), (u64, u32)>>,
+    stats: Mutex<BTreeMap<String, ClassStats>>,
+}
+
+impl BotGuard {
+    pub fn new(rules: Vec<BotRule>, clock: Arc<dyn Clock>) -> Self {
+        let mut stats = BTreeMap::new();
+        stats.insert(DEFAULT_CLASS.to_string(), ClassStats::default());
+        for r in &rules {
+            stats.insert(r.name.clone(), ClassStats::default());
+        }
+        BotGuard {
+            rules,
+            clock,
+            buckets: Mutex::new(HashMap::new()),
+            stats: Mutex::new(stats),
+        }
+    }
+
+    // 没有 User-Agent 的请求按空字符串处理，只会落到 default 分类
+    fn classify(&self, user_agent: &str) -> Option<usize> {
+        let ua = user_agent.to_ascii_lowercase();
+        self.rules.iter().position(|r| {
+            r.patterns
+                .iter()
+                .any(|p| ua.contains(&p.to_ascii_lowercase()))
+        })
+    }
+
+    pub fn check(&self, req: &HttpRequest) -> Verdict {
+        let rule = self.classify(req.header("User-Agent").unwrap_or(""));
+        let class = rule
+            .map(|i| self.rules[i].name.as_str())
+            .unwrap_or(DEFAULT_CLASS);
+        let verdict = match rule.map(|i| (i, &self.rules[i])) {
+            None => Verdict::Allow,
+            Some((_, r)) if r.action == BotAction::Allow => Verdict::Allow,
+            Some((_, r)) if r.action == BotAction::Block => Verdict::Block,
+            Some((i, r)) => {
+                let ip = req.client_ip().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                self.throttle(i, ip, r.requests_per_minute)
+            }
+        };
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(class.to_string()).or_default();
+        entry.requests += 1;
+        match verdict {
+            Verdict::Block => entry.blocked += 1,
+            Verdict::Throttle(_) => entry.throttled += 1,
+            Verdict::Allow => {}
+        }
+        verdict
+    }
+
+    // 固定一分钟窗口计数
+    fn throttle(&self, rule: usize, ip: IpAddr, limit: u32) -> Verdict {
+        let now = self.clock.unix_secs();
+        let minute = now / 60;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, (start, _)| *start == minute);
+        }
+        let bucket = buckets.entry((rule, ip)).or_insert((minute, 0));
+        if bucket.0 != minute {
+            *bucket = (minute, 0);
+        }
+        if bucket.1 >= limit {
+            return Verdict::Throttle((minute + 1) * 60 - now);
+        }
+        bucket.1 += 1;
+        Verdict::Allow
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, ClassStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    // 需要服务器直接回复时返回响应：被拒绝返回 403，被限速返回 429；其余请求返回 None 继续路由
+    pub fn respond<'a>(&self, req: &HttpRequest) -> Option<HttpResponse<'a>> {
+        match self.check(req) {
+            Verdict::Allow => None,
+            Verdict::Block => Some(HttpResponse::new("403", None, Some("Forbidden".into()))),
+            Verdict::Throttle(secs) => Some(
+                HttpResponse::new("429", None, Some("Too Many Requests".into()))
+                    .with_header(names::RETRY_AFTER, secs.to_string())
+                    .expect("digits are a valid header value"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use std::time::Duration;
+
+    fn request(ua: &str) -> HttpRequest {
+        let mut req = HttpRequest::try_from(
+            format!("GET / HTTP/1.1\r\nUser-Agent: {}\r\n\r\n", ua).as_bytes(),
+        )
+        .unwrap();
+        req.remote_addr = Some("10.0.0.1:5000".parse().unwrap());
+        req
+    }
+
+    #[test]
+    fn test_bot_guard() {
+        let rules = vec![
+            BotRule {
+                name: "blocked".into(),
+                patterns: vec!["BadBot".into()],
+                action: BotAction::Block,
+                requests_per_minute: 60,
+            },
+            BotRule {
+                name: "scrapers".into(),
+                patterns: vec!["python-requests".into()],
+                action: BotAction::Throttle,
+                requests_per_minute: 2,
+            },
+        ];
+        let clock = MockClock::from_unix_secs(6_000);
+        let guard = BotGuard::new(rules, Arc::new(clock.clone()));
+        assert_eq!(guard.check(&request("Mozilla/5.0")), Verdict::Allow);
+        assert_eq!(guard.check(&request("badbot/1.0")), Verdict::Block);
+        let scraper = request("python-requests/2.31");
+        assert_eq!(guard.check(&scraper), Verdict::Allow);
+        assert_eq!(guard.check(&scraper), Verdict::Allow);
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(guard.check(&scraper), Verdict::Throttle(15));
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(guard.check(&scraper), Verdict::Allow);
+
+        let stats = guard.stats();
+        assert_eq!(stats["default"].requests, 1);
+        assert_eq!(stats["blocked"].blocked, 1);
+        assert_eq!(
+            stats["scrapers"],
+            ClassStats {
+                requests: 4,
+                blocked: 0,
+                throttled: 1
+            }
+        );
+    }
+}
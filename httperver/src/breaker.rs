@@ -0,0 +1,285 @@
+// 熔断器：上游挂了之后不再每个请求都等到超时，直接快速失败，过一会儿再放几个探测请求试试
+//
+//     关闭   正常放行，记录最近 window 次调用的结果；失败率达到 failure_rate 时打开
+//     打开   全部拒绝，open_secs 秒之后转成半开
+//     半开   最多同时放行 probes 个探测请求，全部成功就关闭，有一个失败就重新打开
+// 状态变了之后才回来的结果不计入新的状态，见 Call
+//
+//     [upstream.breaker]
+//     failure_rate = 0.5
+//     min_requests = 10
+//     open_secs = 30
+use http::clock::Clock;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakerConfig {
+    pub enabled: bool,
+    // 0 到 1，最近的调用里失败的比例达到这个值就打开
+    pub failure_rate: f64,
+    // 按最近多少次调用计算失败率
+    pub window: usize,
+    // 至少有这么多次调用才计算，刚启动时一两次失败不会打开
+    pub min_requests: usize,
+    pub open_secs: u64,
+    // 半开时的探测请求个数
+    pub probes: u32,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            enabled: true,
+            failure_rate: 0.5,
+            window: 20,
+            min_requests: 10,
+            open_secs: 30,
+            probes: 3,
+        }
+    }
+}
+
+impl BreakerConfig {
+    // prefix 是配置里的位置，例如 upstream.breaker
+    pub fn validate(&self, prefix: &str, problems: &mut Vec<String>) {
+        if !self.enabled {
+            return;
+        }
+        if !(self.failure_rate > 0.0 && self.failure_rate <= 1.0) {
+            problems.push(format!("{}.failure_rate must be in (0, 1]", prefix));
+        }
+        if self.min_requests == 0 || self.min_requests > self.window {
+            problems.push(format!(
+                "{}.min_requests must be between 1 and window ({})",
+                prefix, self.window
+            ));
+        }
+        if self.open_secs == 0 {
+            problems.push(format!("{}.open_secs must be positive", prefix));
+        }
+        if self.probes == 0 {
+            problems.push(format!("{}.probes must be positive", prefix));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    // 最近的调用结果，true 是成功
+    Closed(VecDeque<bool>),
+    Open { until: SystemTime },
+    HalfOpen { in_flight: u32, succeeded: u32 },
+}
+
+// try_acquire 放行的一次调用，记下放行时的状态；record 时状态已经变了的结果不计入，
+// 例如关闭时发出、打开之后才回来的慢请求不会被当成半开的探测结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use = "pass the call to CircuitBreaker::record when it finishes"]
+pub struct Call {
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    // 每次状态变化加一
+    generation: u64,
+}
+
+impl Inner {
+    fn transition(&mut self, state: State) {
+        self.state = state;
+        self.generation += 1;
+    }
+}
+
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig, clock: Arc<dyn Clock>) -> Self {
+        CircuitBreaker {
+            config,
+            clock,
+            inner: Mutex::new(Inner {
+                state: State::Closed(VecDeque::new()),
+                generation: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        match self.inner.lock().unwrap_or_else(|e| e.into_inner()).state {
+            State::Closed(_) => BreakerState::Closed,
+            State::Open { .. } => BreakerState::Open,
+            State::HalfOpen { .. } => BreakerState::HalfOpen,
+        }
+    }
+
+    // 放行时返回 Call，调用结束后必须交给 record；
+    // 拒绝时返回大约还要多久才会再放探测请求，可以用作 Retry-After
+    pub fn try_acquire(&self) -> Result<Call, Duration> {
+        if !self.config.enabled {
+            return Ok(Call { generation: 0 });
+        }
+        let now = self.clock.now();
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if let State::Open { until } = inner.state {
+            match until.duration_since(now) {
+                Ok(left) if !left.is_zero() => return Err(left),
+                _ => inner.transition(State::HalfOpen {
+                    in_flight: 0,
+                    succeeded: 0,
+                }),
+            }
+        }
+        let call = Call {
+            generation: inner.generation,
+        };
+        match &mut inner.state {
+            State::HalfOpen { in_flight, .. } if *in_flight >= self.config.probes => {
+                // 探测请求还没回来
+                Err(Duration::from_secs(1))
+            }
+            State::HalfOpen { in_flight, .. } => {
+                *in_flight += 1;
+                Ok(call)
+            }
+            _ => Ok(call),
+        }
+    }
+
+    pub fn record(&self, call: Call, success: bool) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        // 放行之后状态已经变了：打开之前发出的请求现在才回来，或者上一轮半开的探测请求
+        if call.generation != inner.generation {
+            return;
+        }
+        let open = State::Open {
+            until: self.clock.now() + Duration::from_secs(self.config.open_secs),
+        };
+        match &mut inner.state {
+            State::Closed(outcomes) => {
+                outcomes.push_back(success);
+                if outcomes.len() > self.config.window {
+                    outcomes.pop_front();
+                }
+                let failures = outcomes.iter().filter(|ok| !**ok).count();
+                if outcomes.len() >= self.config.min_requests
+                    && failures as f64 >= self.config.failure_rate * outcomes.len() as f64
+                {
+                    inner.transition(open);
+                }
+            }
+            State::HalfOpen { .. } if !success => inner.transition(open),
+            State::HalfOpen { succeeded, .. } => {
+                *succeeded += 1;
+                if *succeeded >= self.config.probes {
+                    inner.transition(State::Closed(VecDeque::new()));
+                }
+            }
+            // 打开期间不会放行，同一代里不会有结果
+            State::Open { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+
+    #[test]
+    fn test_breaker() {
+        let clock = Arc::new(MockClock::from_unix_secs(1_000));
+        let config = BreakerConfig {
+            window: 4,
+            min_requests: 4,
+            open_secs: 10,
+            probes: 2,
+            ..BreakerConfig::default()
+        };
+        let breaker = CircuitBreaker::new(config, clock.clone());
+        let call = |success| breaker.record(breaker.try_acquire().unwrap(), success);
+        // 打开之前发出的慢请求
+        let slow = breaker.try_acquire().unwrap();
+        // 没有达到 min_requests 不打开
+        for success in [false, false, false] {
+            call(success);
+        }
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        call(true);
+        // 3/4 失败
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(breaker.try_acquire(), Err(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(breaker.try_acquire(), Err(Duration::from_secs(6)));
+
+        // 半开：只放两个探测请求，有一个失败就重新打开
+        clock.advance(Duration::from_secs(6));
+        let first = breaker.try_acquire().unwrap();
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        let second = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_err());
+        // 关闭时发出的请求现在才回来，不算探测结果
+        breaker.record(slow, true);
+        breaker.record(first, true);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        breaker.record(second, false);
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        clock.advance(Duration::from_secs(10));
+        let probes = [
+            breaker.try_acquire().unwrap(),
+            breaker.try_acquire().unwrap(),
+        ];
+        breaker.record(probes[0], true);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        breaker.record(probes[1], true);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        // 上一轮的探测结果不计入关闭后的统计
+        breaker.record(second, false);
+        // 关闭之后重新统计
+        call(false);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        let disabled = CircuitBreaker::new(
+            BreakerConfig {
+                enabled: false,
+                ..BreakerConfig::default()
+            },
+            clock,
+        );
+        for _ in 0..50 {
+            disabled.record(disabled.try_acquire().unwrap(), false);
+        }
+        assert!(disabled.try_acquire().is_ok());
+
+        let mut problems = Vec::new();
+        BreakerConfig {
+            failure_rate: 0.0,
+            min_requests: 30,
+            ..BreakerConfig::default()
+        }
+        .validate("upstream.breaker", &mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+}
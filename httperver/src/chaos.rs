@@ -0,0 +1,213 @@
+// 故障注入：按比例给请求加延迟、返回 500、直接断开连接或截断响应，
+// 用来测试客户端的重试逻辑。默认关闭，需要在配置里打开
+// 作为中间件挂在路由上，断开和截断由 Router::route 在写出时执行
+use crate::middleware::Middleware;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use http::random::RandomSource;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    // 注入的延迟时长
+    pub latency_ms: u64,
+    // 以下都是 0~100 的百分比
+    pub latency_percent: f64,
+    pub error_percent: f64,
+    pub drop_percent: f64,
+    pub truncate_percent: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            enabled: false,
+            latency_ms: 1000,
+            latency_percent: 0.0,
+            error_percent: 0.0,
+            drop_percent: 0.0,
+            truncate_percent: 0.0,
+        }
+    }
+}
+
+// 断开和截断时放进 req.extensions，Router::route 据此少写或不写响应
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    // 返回 500
+    Error,
+    // 不写任何响应直接关闭连接
+    Drop,
+    // 只写出一半响应就关闭连接
+    Truncate,
+}
+
+impl ChaosConfig {
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        for (name, value) in [
+            ("latency_percent", self.latency_percent),
+            ("error_percent", self.error_percent),
+            ("drop_percent", self.drop_percent),
+            ("truncate_percent", self.truncate_percent),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                problems.push(format!(
+                    "chaos.{} must be between 0 and 100, got {}",
+                    name, value
+                ));
+            }
+        }
+    }
+
+    // 本次请求要注入的延迟
+    pub fn latency(&self, rng: &dyn RandomSource) -> Option<Duration> {
+        if self.enabled && hit(rng, self.latency_percent) {
+            Some(Duration::from_millis(self.latency_ms))
+        } else {
+            None
+        }
+    }
+
+    // 三种故障互斥，按 drop > error > truncate 的顺序判定
+    pub fn fault(&self, rng: &dyn RandomSource) -> Option<Fault> {
+        if !self.enabled {
+            None
+        } else if hit(rng, self.drop_percent) {
+            Some(Fault::Drop)
+        } else if hit(rng, self.error_percent) {
+            Some(Fault::Error)
+        } else if hit(rng, self.truncate_percent) {
+            Some(Fault::Truncate)
+        } else {
+            None
+        }
+    }
+}
+
+fn hit(rng: &dyn RandomSource, percent: f64) -> bool {
+    percent > 0.0 && rng.next_f64() * 100.0 < percent
+}
+
+// 故障注入中间件，随机数从外面传入，测试里换成固定的序列
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Arc<dyn RandomSource>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig, rng: Arc<dyn RandomSource>) -> Self {
+        Chaos { config, rng }
+    }
+}
+
+impl Middleware for Chaos {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        if let Some(delay) = self.config.latency(self.rng.as_ref()) {
+            std::thread::sleep(delay);
+        }
+        match self.config.fault(self.rng.as_ref())? {
+            Fault::Error => Some(HttpResponse::new(
+                "500",
+                None,
+                Some("chaos: injected failure".into()),
+            )),
+            fault => {
+                req.extensions.insert(fault);
+                None
+            }
+        }
+    }
+
+    // 截断的响应长度对不上，连接不能再复用
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+        if req.extensions.get::<Fault>() == Some(&Fault::Truncate) {
+            let _ = resp.set_header(names::CONNECTION, "close");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use http::random::SeededRandom;
+
+    // 每次都返回同一个值，next_f64 约等于 value
+    struct Fixed(f64);
+
+    impl RandomSource for Fixed {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            let bits = ((self.0 * (1u64 << 53) as f64) as u64) << 11;
+            for chunk in buf.chunks_mut(8) {
+                chunk.copy_from_slice(&bits.to_le_bytes()[..chunk.len()]);
+            }
+        }
+    }
+
+    fn route(config: ChaosConfig, rng: impl RandomSource + 'static) -> String {
+        let router = Router::new("")
+            .middleware(Chaos::new(config, Arc::new(rng)))
+            .get("/hello", |_| {
+                HttpResponse::new("200", None, Some("hello chaos".into()))
+            });
+        let req = HttpRequest::try_from(&b"GET /hello HTTP/1.1\r\nHost: a\r\n\r\n"[..]).unwrap();
+        let mut out = Vec::new();
+        router.route(req, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_faults() {
+        let config = |drop, error, truncate| ChaosConfig {
+            enabled: true,
+            drop_percent: drop,
+            error_percent: error,
+            truncate_percent: truncate,
+            ..ChaosConfig::default()
+        };
+        // 关闭时什么都不做
+        let off = ChaosConfig {
+            enabled: false,
+            ..config(100.0, 100.0, 100.0)
+        };
+        assert!(route(off, Fixed(0.0)).ends_with("hello chaos"));
+        assert_eq!(route(config(100.0, 0.0, 0.0), Fixed(0.0)), "");
+        let error = route(config(0.0, 100.0, 0.0), Fixed(0.0));
+        assert!(error.starts_with("HTTP/1.1 500"), "{}", error);
+        assert!(error.ends_with("chaos: injected failure"));
+        let full = route(config(0.0, 0.0, 0.0), Fixed(0.0));
+        let truncated = route(config(0.0, 0.0, 100.0), Fixed(0.0));
+        assert!(truncated.contains("Connection:close"), "{}", truncated);
+        assert!(!truncated.ends_with("hello chaos"));
+        assert!(truncated.len() < full.len());
+        // 50% 的比例，随机数 0.6 不命中、0.4 命中
+        assert!(route(config(50.0, 0.0, 0.0), Fixed(0.6)).ends_with("hello chaos"));
+        assert_eq!(route(config(50.0, 0.0, 0.0), Fixed(0.4)), "");
+    }
+
+    #[test]
+    fn test_rates() {
+        let config = ChaosConfig {
+            enabled: true,
+            error_percent: 30.0,
+            ..ChaosConfig::default()
+        };
+        let rng = SeededRandom::new(42);
+        let errors = (0..1000)
+            .filter(|_| config.fault(&rng) == Some(Fault::Error))
+            .count();
+        assert!((250..350).contains(&errors), "{}", errors);
+        let mut problems = Vec::new();
+        ChaosConfig {
+            drop_percent: 120.0,
+            ..config
+        }
+        .validate(&mut problems);
+        assert_eq!(problems.len(), 1);
+    }
+}
@@ -0,0 +1,994 @@
+use crate::accesslog::{AccessLog, LogFormat, RequestLogConfig};
+use crate::assets::AssetManifests;
+use crate::auth::Auth;
+use crate::bots::BotRule;
+use crate::chaos::{Chaos, ChaosConfig};
+use crate::connlimit::ConnLimitConfig;
+use crate::content::ContentRoots;
+use crate::deprecation::{DeprecationConfig, DeprecationNotices};
+use crate::disposition::DispositionConfig;
+use crate::geoip::GeoIpConfig;
+use crate::jwt::{self, Jwt, JwtConfig};
+use crate::latency::LatencyConfig;
+use crate::minify::Minifier;
+use crate::mirror::{Mirror, MirrorConfig};
+use crate::otel::OtelConfig;
+use crate::priority::Priority;
+use crate::prometheus::{Prometheus, PrometheusConfig};
+use crate::ratelimit::{MemoryBuckets, RateLimit, RateLimitConfig};
+use crate::router::Router;
+use crate::server::{
+    Timeouts, DEFAULT_DRAIN_TIMEOUT_SECS, DEFAULT_HEADER_TIMEOUT_SECS,
+    DEFAULT_KEEP_ALIVE_MAX_REQUESTS, DEFAULT_KEEP_ALIVE_TIMEOUT_SECS, DEFAULT_QUEUE_CAPACITY,
+    DEFAULT_READ_TIMEOUT_SECS, DEFAULT_REQUEST_TIMEOUT_SECS, DEFAULT_WORKERS,
+    DEFAULT_WRITE_TIMEOUT_SECS,
+};
+use crate::session::{MemoryStore, SessionConfig, SessionLayer};
+use crate::tenant::{Tenancy, TenancyConfig};
+use crate::throttle::BandwidthConfig;
+use crate::thumb::{self, Thumbnailer};
+use crate::timewindow::{TimeWindowRule, TimeWindows};
+use crate::tls::TlsConfig;
+use crate::uploads::{self, Uploads};
+use crate::upstream::{ReverseProxy, UpstreamConfig};
+use http::clock::SystemClock;
+use http::httprequest::{self, Limits};
+use http::proxy::Cidr;
+use http::random::OsRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 没有指定 --config 时，尝试读取当前目录下的这个文件
+pub const DEFAULT_CONFIG_FILE: &str = "httperver.toml";
+// 没有指定 --profile 时从这个环境变量取环境名
+pub const PROFILE_ENV: &str = "HTTPERVER_PROFILE";
+// HTTPERVER_WORKERS=8 覆盖 workers，嵌套字段用两个下划线：HTTPERVER_TENANCY__SOURCE=header
+pub const ENV_PREFIX: &str = "HTTPERVER_";
+// 同样以 HTTPERVER_ 开头但不是配置项的环境变量
+const RESERVED_ENV: &[&str] = &[PROFILE_ENV, "HTTPERVER_INHERIT_FD"];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(p, e) => write!(f, "cannot read config {}: {}", p.display(), e),
+            ConfigError::Parse(p, e) => write!(f, "cannot parse config {}: {}", p.display(), e),
+            ConfigError::Invalid(problems) => {
+                writeln!(f, "invalid config:")?;
+                for p in problems {
+                    writeln!(f, "  - {}", p)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// serde(default) 让配置文件里缺失的字段使用 Default 的值
+// deny_unknown_fields 拼错字段名时直接报错，而不是悄悄忽略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub addr: String,
+    // [tls] 同时在另一个地址上提供 HTTPS，默认关闭
+    pub tls: TlsConfig,
+    // 同时在本机 IPC 上提供服务：unix 上是 socket 文件路径，Windows 上是命名管道名
+    pub ipc: Option<String>,
+    // 应用挂载的路径前缀，例如 "/shop"，url_for 生成的地址会带上它
+    pub base_path: String,
+    pub public_path: String,
+    pub data_path: String,
+    // 仅 unix：fork 到后台运行
+    pub daemon: bool,
+    pub pid_file: Option<String>,
+    // 设置后 stdout / stderr 写入该文件，SIGHUP 时重新打开
+    pub log_file: Option<String>,
+    // 设置后把每个请求/响应对（脱敏后）录制到这个目录
+    pub record_dir: Option<String>,
+    // [chaos] 故障注入，默认关闭
+    pub chaos: ChaosConfig,
+    // 反向代理 / 负载均衡的地址段，例如 ["10.0.0.0/8", "::1"]
+    pub trusted_proxies: Vec<String>,
+    pub mounts: Vec<MountConfig>,
+    // [content_roots] blue = "/srv/blue"，设置后静态文件从 content_root 指定的目录读取，
+    // 可以通过 POST /_admin/content/<name> 切换
+    pub content_roots: BTreeMap<String, String>,
+    pub content_root: Option<String>,
+    // 为静态目录（配置了 content_roots 时是当前的内容目录）下的文件计算内容哈希，
+    // 通过 /static/app.<hash>.css 提供并永久缓存
+    pub asset_hashing: bool,
+    // [disposition] 静态文件按扩展名直接打开还是作为附件下载，默认不加 Content-Disposition
+    pub disposition: DispositionConfig,
+    // 响应带 X-Content-Type-Options: nosniff，禁止浏览器猜测内容类型
+    pub nosniff: bool,
+    // 拒绝发送开头的特征字节和扩展名矛盾的静态文件，返回 403
+    pub sniff_guard: bool,
+    // 返回前精简 HTML / CSS / JS，静态文件的结果按修改时间缓存
+    pub minify: bool,
+    // 开放 /thumb/<path>?w=&h= 缩略图，结果缓存在 thumb_cache_dir（默认系统临时目录）
+    pub thumbnails: bool,
+    pub thumb_cache_dir: Option<String>,
+    // 设置后开放 POST /uploads，上传的文件按内容去重保存在这个目录
+    pub uploads_dir: Option<String>,
+    // 可续传上传多少秒内没有传完就清理
+    pub upload_expiry_secs: u64,
+    // 排队等待处理的请求上限，满了之后优先丢弃低优先级的请求
+    pub queue_capacity: usize,
+    // 工作线程数，读取和处理请求都在工作线程上
+    pub workers: usize,
+    // 一个连接最多处理多少个请求，1 表示不复用连接
+    pub keep_alive_max_requests: usize,
+    // 长连接两个请求之间最多空闲多少秒
+    pub keep_alive_timeout_secs: u64,
+    // 收到请求的第一个字节之后，多少秒内必须收完头部，防止一个字节一个字节慢慢发的客户端占住线程
+    pub header_timeout_secs: u64,
+    // 每次读、写 socket 最多等多少秒，超时后回 408 或者直接关闭连接
+    pub read_timeout_secs: u64,
+    pub write_timeout_secs: u64,
+    // 收到请求的第一个字节之后，多少秒内必须收完整个请求（包括 body），超时回 408
+    pub request_timeout_secs: u64,
+    // 请求的大小上限：请求行、头部个数、请求行加头部的字节数、body 的字节数
+    // 超出时不再继续读取，分别回 414、431、431、413
+    pub max_request_line_bytes: usize,
+    pub max_headers: usize,
+    pub max_head_bytes: usize,
+    pub max_body_bytes: usize,
+    // 收到 SIGINT / SIGTERM 后最多等多少秒让排队的请求处理完，0 表示不等
+    pub drain_timeout_secs: u64,
+    // 没有配置 [request_log] 时按 Common Log Format 把访问日志写到 stdout
+    pub access_log: bool,
+    // [sessions] 基于 cookie 的服务端会话，默认关闭；会话保存在内存里
+    pub sessions: SessionConfig,
+    // [request_log] Common Log Format / JSON 访问日志，写 stdout 或轮转文件，默认关闭
+    pub request_log: RequestLogConfig,
+    // 用 tokio 处理连接，需要 async-server feature；只支持路由、长连接和优雅退出
+    pub async_io: bool,
+    // 按路由名覆盖默认优先级，例如 orders = "high"
+    pub route_priorities: BTreeMap<String, Priority>,
+    // [deprecated_routes.<路由名>] 废弃的路由，响应带 Deprecation / Sunset 头部并记录调用方
+    pub deprecated_routes: BTreeMap<String, DeprecationConfig>,
+    // 堆内存超过这个值（MB）后拒绝新连接、关闭已有的长连接，需要 memory-guard feature
+    pub memory_high_water_mb: Option<u64>,
+    // [conn_limit] 单个客户端 IP 的并发连接上限，默认不限
+    pub conn_limit: ConnLimitConfig,
+    // [[bots]] 按 User-Agent 分类限速或拒绝
+    pub bots: Vec<BotRule>,
+    // [geoip] 需要 geoip feature
+    pub geoip: GeoIpConfig,
+    // [bandwidth] 响应限速，默认不限
+    pub bandwidth: BandwidthConfig,
+    // [[time_windows]] 按时间段开放或关闭一组路由
+    pub time_windows: Vec<TimeWindowRule>,
+    // [tenancy] 多租户，默认关闭
+    pub tenancy: TenancyConfig,
+    // [latency] 按路由统计延迟分位数和 SLO 报警，默认关闭
+    pub latency: LatencyConfig,
+    // [otel] 把 span 和指标推给 OpenTelemetry collector，需要 otel feature
+    pub otel: OtelConfig,
+    // [mirror] 按比例把请求复制一份发给影子服务，默认关闭
+    pub mirror: MirrorConfig,
+    // [upstream] 把一个路径前缀下的请求转发给上游，可以按比例分流给金丝雀版本，默认关闭
+    pub upstream: UpstreamConfig,
+    // [rate_limit] 按客户端 IP（或者认证后的身份）的令牌桶限流，默认关闭
+    pub rate_limit: RateLimitConfig,
+    // [jwt] 设置 secret 后 paths 下的请求必须带 HS256 签名的 Bearer token，默认关闭
+    pub jwt: JwtConfig,
+    // [prometheus] 设置 path 后在这个路径输出 Prometheus 格式的请求数、耗时和连接数，默认关闭
+    pub prometheus: PrometheusConfig,
+    // 加载时发现的未知字段，和其他问题一起在 validate 里报告
+    #[serde(skip)]
+    unknown_keys: Vec<String>,
+}
+
+// [[mounts]] 把一个独立的静态站点挂到 prefix 下
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    pub prefix: String,
+    pub public_path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            addr: "localhost:3000".into(),
+            tls: TlsConfig::default(),
+            ipc: None,
+            base_path: String::new(),
+            public_path: format!("{}/public", env!("CARGO_MANIFEST_DIR")),
+            data_path: format!("{}/data", env!("CARGO_MANIFEST_DIR")),
+            daemon: false,
+            pid_file: None,
+            log_file: None,
+            record_dir: None,
+            chaos: ChaosConfig::default(),
+            trusted_proxies: Vec::new(),
+            mounts: Vec::new(),
+            content_roots: BTreeMap::new(),
+            content_root: None,
+            asset_hashing: false,
+            disposition: DispositionConfig::default(),
+            nosniff: true,
+            sniff_guard: false,
+            minify: false,
+            thumbnails: false,
+            thumb_cache_dir: None,
+            uploads_dir: None,
+            upload_expiry_secs: uploads::DEFAULT_EXPIRY_SECS,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            workers: DEFAULT_WORKERS,
+            keep_alive_max_requests: DEFAULT_KEEP_ALIVE_MAX_REQUESTS,
+            keep_alive_timeout_secs: DEFAULT_KEEP_ALIVE_TIMEOUT_SECS,
+            header_timeout_secs: DEFAULT_HEADER_TIMEOUT_SECS,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT_SECS,
+            write_timeout_secs: DEFAULT_WRITE_TIMEOUT_SECS,
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT_SECS,
+            max_request_line_bytes: httprequest::MAX_REQUEST_LINE,
+            max_headers: httprequest::MAX_HEADERS,
+            max_head_bytes: httprequest::MAX_HEAD_SIZE,
+            max_body_bytes: httprequest::MAX_BODY_SIZE,
+            drain_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+            access_log: false,
+            request_log: RequestLogConfig::default(),
+            sessions: SessionConfig::default(),
+            async_io: false,
+            route_priorities: BTreeMap::new(),
+            deprecated_routes: BTreeMap::new(),
+            memory_high_water_mb: None,
+            conn_limit: ConnLimitConfig::default(),
+            bots: Vec::new(),
+            geoip: GeoIpConfig::default(),
+            bandwidth: BandwidthConfig::default(),
+            time_windows: Vec::new(),
+            tenancy: TenancyConfig::default(),
+            latency: LatencyConfig::default(),
+            otel: OtelConfig::default(),
+            mirror: MirrorConfig::default(),
+            upstream: UpstreamConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            jwt: JwtConfig::default(),
+            prometheus: PrometheusConfig::default(),
+            unknown_keys: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // 分层加载，后面的覆盖前面的：
+    // 1. 默认值
+    // 2. 配置文件：指定了路径就必须存在；没指定时默认文件不存在也没关系
+    // 3. 环境配置：profile 为 prod 时读取同一目录下的 httperver.prod.toml，必须存在
+    // 4. HTTPERVER_ 开头的环境变量
+    // 5. 命令行参数，由 main.rs 在加载之后覆盖
+    // 表按字段逐层合并，数组和普通值整个替换
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Config, ConfigError> {
+        Config::load_from(path, profile, env::vars())
+    }
+
+    fn load_from(
+        path: Option<&Path>,
+        profile: Option<&str>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, ConfigError> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        let mut table = if required || path.exists() {
+            read_table(&path)?
+        } else {
+            toml::Table::new()
+        };
+        if let Some(profile) = profile {
+            let valid = !profile.is_empty()
+                && profile
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+            if !valid {
+                return Err(ConfigError::Invalid(vec![format!(
+                    "profile {:?} may only contain letters, digits, '-' and '_'",
+                    profile
+                )]));
+            }
+            merge(&mut table, read_table(&profile_path(&path, profile))?);
+        }
+        merge(&mut table, env_table(vars));
+        // deny_unknown_fields 遇到第一个未知字段就停下；这里逐个顶层字段单独检查，
+        // 未知字段记下来、删掉再试，拼错的字段和类型错误一次全部列出
+        let mut unknown_keys = Vec::new();
+        let mut errors = Vec::new();
+        let mut known = toml::Table::new();
+        for (key, value) in table {
+            let mut single = toml::Table::new();
+            single.insert(key.clone(), value);
+            loop {
+                let err = match toml::Value::Table(single.clone()).try_into::<Config>() {
+                    Ok(_) => break known.extend(single),
+                    Err(e) => e,
+                };
+                if let Some((name, expected)) = unknown_field(err.message()) {
+                    if let Some(at) = remove_unknown(&mut single, &name, &expected) {
+                        let mut problem = format!("unknown key {}", at);
+                        if let Some(s) = suggest(&name, &expected) {
+                            problem.push_str(&format!(", did you mean {:?}?", s));
+                        }
+                        unknown_keys.push(problem);
+                        continue;
+                    }
+                }
+                errors.push(format!("{}: {}", key, describe(err.message())));
+                break;
+            }
+        }
+        // 类型不对时没法继续检查取值，先报告到这里为止的问题
+        if !errors.is_empty() {
+            unknown_keys.extend(errors);
+            return Err(ConfigError::Invalid(unknown_keys));
+        }
+        let mut config: Config = toml::Value::Table(known)
+            .try_into()
+            .map_err(|e| ConfigError::Parse(path, e.to_string()))?;
+        config.unknown_keys = unknown_keys;
+        Ok(config)
+    }
+
+    // 一次性收集所有问题，而不是遇到第一个就退出
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = self.unknown_keys.clone();
+        if self.addr.to_socket_addrs().is_err() {
+            problems.push(format!("addr {:?} is not a valid host:port", self.addr));
+        }
+        if !self.base_path.is_empty() && !self.base_path.starts_with('/') {
+            problems.push(format!(
+                "base_path {:?} must start with '/'",
+                self.base_path
+            ));
+        }
+        if !Path::new(&self.public_path).is_dir() {
+            problems.push(format!(
+                "public_path {:?} is not a directory",
+                self.public_path
+            ));
+        }
+        if !Path::new(&self.data_path).is_dir() {
+            problems.push(format!("data_path {:?} is not a directory", self.data_path));
+        }
+        if self.daemon && cfg!(not(unix)) {
+            problems.push("daemon mode is only supported on unix".to_string());
+        }
+        if self.daemon && self.log_file.is_none() {
+            problems.push("daemon mode requires log_file, stdout is detached".to_string());
+        }
+        self.chaos.validate(&mut problems);
+        for (route, deprecation) in &self.deprecated_routes {
+            deprecation.validate(route, &mut problems);
+        }
+        BotRule::validate(&self.bots, &mut problems);
+        match self.memory_high_water_mb {
+            Some(0) => problems.push("memory_high_water_mb must be positive".to_string()),
+            Some(_) if cfg!(not(feature = "memory-guard")) => problems
+                .push("memory_high_water_mb requires building with --features memory-guard".into()),
+            _ => {}
+        }
+        if self.queue_capacity == 0 {
+            problems.push("queue_capacity must be positive".to_string());
+        }
+        if self.workers == 0 {
+            problems.push("workers must be positive".to_string());
+        }
+        if self.keep_alive_max_requests == 0 {
+            problems.push("keep_alive_max_requests must be positive".to_string());
+        }
+        if self.upload_expiry_secs == 0 {
+            problems.push("upload_expiry_secs must be positive".to_string());
+        }
+        if self.keep_alive_timeout_secs == 0 {
+            problems.push("keep_alive_timeout_secs must be positive".to_string());
+        }
+        for (name, secs) in [
+            ("header_timeout_secs", self.header_timeout_secs),
+            ("read_timeout_secs", self.read_timeout_secs),
+            ("write_timeout_secs", self.write_timeout_secs),
+            ("request_timeout_secs", self.request_timeout_secs),
+        ] {
+            if secs == 0 {
+                problems.push(format!("{} must be positive", name));
+            }
+        }
+        for (name, limit) in [
+            ("max_request_line_bytes", self.max_request_line_bytes),
+            ("max_headers", self.max_headers),
+            ("max_head_bytes", self.max_head_bytes),
+            ("max_body_bytes", self.max_body_bytes),
+        ] {
+            if limit == 0 {
+                problems.push(format!("{} must be positive", name));
+            }
+        }
+        // 请求行也算在头部里
+        if self.max_request_line_bytes > self.max_head_bytes {
+            problems.push("max_request_line_bytes must not exceed max_head_bytes".to_string());
+        }
+        if self.async_io {
+            self.validate_async_io(&mut problems);
+        }
+        self.tls.validate(&self.addr, &mut problems);
+        self.geoip.validate(&mut problems);
+        self.bandwidth.validate(&mut problems);
+        TimeWindowRule::validate(&self.time_windows, &mut problems);
+        self.tenancy.validate(&mut problems);
+        self.latency.validate(&mut problems);
+        self.request_log.validate(&mut problems);
+        self.sessions.validate(&mut problems);
+        self.disposition.validate(&mut problems);
+        self.conn_limit.validate(&mut problems);
+        self.otel.validate(&mut problems);
+        self.mirror.validate(&mut problems);
+        self.upstream.validate(&mut problems);
+        self.rate_limit.validate(&mut problems);
+        if self.rate_limit.by_identity && self.jwt.secret.is_none() {
+            problems.push("rate_limit.by_identity requires jwt.secret".to_string());
+        }
+        self.jwt.validate(&mut problems);
+        self.prometheus.validate(&mut problems);
+        for (i, m) in self.mounts.iter().enumerate() {
+            let prefix = m.prefix.trim_end_matches('/');
+            if self.mounts[..i]
+                .iter()
+                .any(|o| o.prefix.trim_end_matches('/') == prefix)
+            {
+                problems.push(format!(
+                    "mount prefix {:?} is used more than once",
+                    m.prefix
+                ));
+            }
+            if !m.prefix.starts_with('/') || m.prefix.trim_end_matches('/').is_empty() {
+                problems.push(format!(
+                    "mount prefix {:?} must be a non-root path",
+                    m.prefix
+                ));
+            }
+            if !Path::new(&m.public_path).is_dir() {
+                problems.push(format!(
+                    "mount {:?}: public_path {:?} is not a directory",
+                    m.prefix, m.public_path
+                ));
+            }
+        }
+        if !self.content_roots.is_empty() {
+            match &self.content_root {
+                Some(name) if !self.content_roots.contains_key(name) => {
+                    problems.push(format!("content_root {:?} is not in content_roots", name))
+                }
+                None => problems.push("content_roots requires content_root".to_string()),
+                _ => {}
+            }
+        }
+        for cidr in &self.trusted_proxies {
+            if let Err(e) = Cidr::parse(cidr) {
+                problems.push(format!("trusted_proxies: {}", e));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // 异步服务器只接了路由，其他服务器层面的功能配置了也不会生效，直接报错
+    fn validate_async_io(&self, problems: &mut Vec<String>) {
+        if cfg!(not(feature = "async-server")) {
+            problems.push("async_io requires building with --features async-server".to_string());
+        }
+        let unsupported = [
+            ("tls", self.tls.enabled()),
+            ("ipc", self.ipc.is_some()),
+            ("record_dir", self.record_dir.is_some()),
+            ("bots", !self.bots.is_empty()),
+            ("latency", self.latency.enabled),
+            ("otel", self.otel.enabled()),
+            ("memory_high_water_mb", self.memory_high_water_mb.is_some()),
+            ("conn_limit", self.conn_limit.enabled()),
+        ];
+        for (name, set) in unsupported {
+            if set {
+                problems.push(format!("{} is not supported with async_io", name));
+            }
+        }
+    }
+
+    // 两种服务器共用的读写超时
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            header: Duration::from_secs(self.header_timeout_secs),
+            read: Duration::from_secs(self.read_timeout_secs),
+            request: Duration::from_secs(self.request_timeout_secs),
+            write: Duration::from_secs(self.write_timeout_secs),
+        }
+    }
+
+    // 两种服务器共用的请求大小上限
+    pub fn limits(&self) -> Limits {
+        Limits {
+            request_line: self.max_request_line_bytes,
+            headers: self.max_headers,
+            head: self.max_head_bytes,
+            body: self.max_body_bytes,
+        }
+    }
+
+    // 密钥在这里才读取，环境变量没有设置时报错；处理器用 req.claims::<serde_json::Value>() 取 claims
+    fn jwt_auth(&self) -> Result<Option<Auth>, ConfigError> {
+        let Some(source) = &self.jwt.secret else {
+            return Ok(None);
+        };
+        let secret = source
+            .load()
+            .map_err(|e| ConfigError::Invalid(vec![format!("jwt.secret: {}", e)]))?;
+        if secret.expose().len() < jwt::MIN_SECRET_BYTES {
+            return Err(ConfigError::Invalid(vec![format!(
+                "jwt.secret must be at least {} bytes",
+                jwt::MIN_SECRET_BYTES
+            )]));
+        }
+        let mut jwt = Jwt::hs256(secret.expose().as_bytes(), Arc::new(SystemClock))
+            .leeway(Duration::from_secs(self.jwt.leeway_secs));
+        if let Some(issuer) = &self.jwt.issuer {
+            jwt = jwt.issuer(issuer);
+        }
+        if let Some(audience) = &self.jwt.audience {
+            jwt = jwt.audience(audience);
+        }
+        let auth = Auth::new(&self.jwt.realm).jwt::<serde_json::Value>(jwt);
+        Ok(Some(
+            self.jwt.paths.iter().fold(auth, |auth, p| auth.only(p)),
+        ))
+    }
+
+    // 根据配置构造路由，包括挂载的子应用
+    // 配置了 content_roots 时，当前目录不在其中会返回错误
+    pub fn router(&self) -> Result<Router, ConfigError> {
+        // 处理器可以通过 req.state::<Config>() 读到生效的配置
+        let mut router = Router::new(&self.base_path)
+            .state(Arc::new(self.clone()))
+            .nosniff(self.nosniff)
+            .sniff_guard(self.sniff_guard);
+        // 最先注册，是最外层的中间件，被其他中间件短路的请求也会记录
+        let format = self
+            .request_log
+            .format
+            .or(self.access_log.then_some(LogFormat::Clf));
+        if let Some(format) = format {
+            let out = self.request_log.open().map_err(|e| {
+                ConfigError::Invalid(vec![format!(
+                    "cannot open request_log.file {:?}: {}",
+                    self.request_log.file.as_deref().unwrap_or_default(),
+                    e
+                )])
+            })?;
+            router = router.middleware(AccessLog::new(format, out, Arc::new(SystemClock)));
+        }
+        // 在限流之前，被限流拒绝的 429 也要统计
+        if let Some(path) = &self.prometheus.path {
+            let metrics = Prometheus::new(self.prometheus.buckets.clone(), Arc::new(SystemClock));
+            router = router.prometheus(path, Arc::new(metrics));
+        }
+        // 在日志和指标之后，注入的延迟和 500 也会被记录
+        if self.chaos.enabled {
+            router = router.middleware(Chaos::new(self.chaos.clone(), Arc::new(OsRandom)));
+        }
+        // 按路径区分租户时会去掉路径里的租户部分，放在按路由名生效的中间件之前
+        if self.tenancy.enabled() {
+            let tenancy = Tenancy::new(self.tenancy.clone(), Arc::new(SystemClock));
+            router = router.tenancy(Arc::new(tenancy));
+        }
+        // 关闭时间段内的请求直接拒绝，不占用限流额度
+        if !self.time_windows.is_empty() {
+            let windows = TimeWindows::new(self.time_windows.clone(), Arc::new(SystemClock));
+            router = router.middleware(windows);
+        }
+        // 超出限额的请求不再往下走，不复制、不验证 token、不转发
+        // 按身份分桶时身份要先验证过，放到认证之后
+        let mut limiter = self.rate_limit.enabled().then(|| {
+            let limiter = RateLimit::new(
+                self.rate_limit.rate(),
+                Arc::new(MemoryBuckets::new()),
+                Arc::new(SystemClock),
+            );
+            if self.rate_limit.by_identity {
+                limiter.by_identity()
+            } else {
+                limiter
+            }
+        });
+        if !self.rate_limit.by_identity {
+            if let Some(limiter) = limiter.take() {
+                router = router.middleware(limiter);
+            }
+        }
+        // 在会话等中间件改写请求之前复制，影子服务收到的和线上一样
+        if self.mirror.enabled() {
+            let mirror = Mirror::new(&self.mirror, Arc::new(OsRandom))
+                .map_err(|e| ConfigError::Invalid(vec![format!("mirror: {}", e)]))?;
+            router = router.middleware(mirror);
+        }
+        // 在代理之前，转发给上游的请求也要先验证 token
+        if let Some(auth) = self.jwt_auth()? {
+            router = router.middleware(auth);
+        }
+        if let Some(limiter) = limiter {
+            router = router.middleware(limiter);
+        }
+        if self.upstream.enabled() {
+            let proxy =
+                ReverseProxy::new(&self.upstream, Arc::new(SystemClock), Arc::new(OsRandom))
+                    .map_err(|e| ConfigError::Invalid(vec![format!("upstream: {}", e)]))?;
+            router = router.upstream(Arc::new(proxy));
+        }
+        if self.sessions.enabled {
+            router = router.middleware(SessionLayer::new(
+                self.sessions.clone(),
+                Arc::new(MemoryStore::new()),
+                Arc::new(SystemClock),
+                Arc::new(OsRandom),
+            ));
+        }
+        if !self.deprecated_routes.is_empty() {
+            router = router.middleware(DeprecationNotices::new());
+        }
+        if !self.content_roots.is_empty() {
+            let current = self.content_root.clone().unwrap_or_default();
+            let content = ContentRoots::new(self.content_roots.clone(), &current)
+                .map_err(|e| ConfigError::Invalid(vec![e.to_string()]))?;
+            router = router.content_roots(Arc::new(content));
+        }
+        if self.disposition.enabled() {
+            router = router.disposition(Arc::new(self.disposition.clone()));
+        }
+        if self.minify {
+            router = router.minifier(Arc::new(Minifier::new()));
+        }
+        if self.thumbnails {
+            let dir = self
+                .thumb_cache_dir
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(thumb::default_cache_dir);
+            let thumbs = Thumbnailer::new(&dir).map_err(|e| {
+                ConfigError::Invalid(vec![format!(
+                    "cannot create thumb_cache_dir {:?}: {}",
+                    dir, e
+                )])
+            })?;
+            router = router.thumbnails(Arc::new(thumbs));
+        }
+        if let Some(dir) = &self.uploads_dir {
+            let uploads = Uploads::new(dir).map_err(|e| {
+                ConfigError::Invalid(vec![format!("cannot create uploads_dir {:?}: {}", dir, e)])
+            })?;
+            let uploads = uploads.expiry(Duration::from_secs(self.upload_expiry_secs));
+            router = router.uploads(Arc::new(uploads));
+        }
+        for (name, priority) in &self.route_priorities {
+            router = router
+                .priority(name, *priority)
+                .map_err(|e| ConfigError::Invalid(vec![format!("route_priorities: {}", e)]))?;
+        }
+        for (name, deprecation) in &self.deprecated_routes {
+            let deprecation = deprecation.build().ok_or_else(|| {
+                ConfigError::Invalid(vec![format!("deprecated_routes.{}: invalid date", name)])
+            })?;
+            router = router
+                .deprecate(name, deprecation)
+                .map_err(|e| ConfigError::Invalid(vec![format!("deprecated_routes: {}", e)]))?;
+        }
+        if self.asset_hashing {
+            // 启动时先扫描一遍所有可能提供文件的目录，目录读不了直接报错
+            let assets = AssetManifests::new();
+            let roots: Vec<&String> = if self.content_roots.is_empty() {
+                vec![&self.public_path]
+            } else {
+                self.content_roots.values().collect()
+            };
+            for root in roots {
+                assets.for_root(root).map_err(|e| {
+                    ConfigError::Invalid(vec![format!("cannot hash assets in {:?}: {}", root, e)])
+                })?;
+            }
+            router = router.asset_hashing(Arc::new(assets));
+        }
+        Ok(self.mounts.iter().fold(router, |router, m| {
+            let site = Router::new("")
+                .static_root(&m.public_path)
+                .nosniff(self.nosniff)
+                .sniff_guard(self.sniff_guard);
+            router.mount(&m.prefix, site)
+        }))
+    }
+
+    // 处理器通过 PUBLIC_PATH / DATA_PATH 环境变量找文件
+    // 合并后的最终配置，check-config --print-effective 输出
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config is representable in TOML")
+    }
+
+    pub fn apply_env(&self) {
+        env::set_var("PUBLIC_PATH", &self.public_path);
+        env::set_var("DATA_PATH", &self.data_path);
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, ConfigError> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+    toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))
+}
+
+// conf/httperver.toml + prod -> conf/httperver.prod.toml
+fn profile_path(base: &Path, profile: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("httperver");
+    base.with_file_name(format!("{}.{}.toml", stem, profile))
+}
+
+// overlay 覆盖 base：两边都是表时逐个字段合并，否则整个替换
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge(b, o),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// HTTPERVER_TENANCY__SOURCE=header -> [tenancy] source = "header"
+// 值按 TOML 解析（数字、布尔、数组），解析不了的当作字符串
+fn env_table(vars: impl IntoIterator<Item = (String, String)>) -> toml::Table {
+    let mut table = toml::Table::new();
+    for (name, raw) in vars {
+        if RESERVED_ENV.contains(&name.as_str()) {
+            continue;
+        }
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let value = toml::from_str::<toml::Table>(&format!("v = {}", raw))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or(toml::Value::String(raw));
+        let key = key.to_ascii_lowercase();
+        let mut parts: Vec<&str> = key.split("__").collect();
+        let last = parts.pop().unwrap_or_default();
+        let mut overlay = toml::Table::new();
+        overlay.insert(last.to_string(), value);
+        for part in parts.into_iter().rev() {
+            let mut outer = toml::Table::new();
+            outer.insert(part.to_string(), toml::Value::Table(overlay));
+            overlay = outer;
+        }
+        merge(&mut table, overlay);
+    }
+    table
+}
+
+// serde 的报错里用反引号括起名字："unknown field `wokers`, expected one of `addr`, ..."
+fn backticked(message: &str) -> Vec<String> {
+    message
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(str::to_string)
+        .collect()
+}
+
+// (未知字段, 允许的字段)
+fn unknown_field(message: &str) -> Option<(String, Vec<String>)> {
+    if !message.starts_with("unknown field") {
+        return None;
+    }
+    let mut names = backticked(message).into_iter();
+    Some((names.next()?, names.collect()))
+}
+
+// (拼错的枚举值, 允许的值)
+fn unknown_variant(message: &str) -> Option<(String, Vec<String>)> {
+    if !message.starts_with("unknown variant") {
+        return None;
+    }
+    let mut names = backticked(message).into_iter();
+    Some((names.next()?, names.collect()))
+}
+
+// 类型错误、枚举值拼错：serde 的报错换成一行，能猜出本意时附上建议
+fn describe(message: &str) -> String {
+    let mut line = message.trim().replace('\n', " ");
+    if let Some((value, expected)) = unknown_variant(message) {
+        if let Some(s) = suggest(&value, &expected) {
+            line.push_str(&format!(", did you mean {:?}?", s));
+        }
+    }
+    line
+}
+
+// 报错里没有位置，找出包含这个字段、其余字段最符合 expected 的表，删掉字段并返回完整路径
+fn remove_unknown(table: &mut toml::Table, key: &str, expected: &[String]) -> Option<String> {
+    fn visit<'t>(
+        table: &'t mut toml::Table,
+        path: String,
+        key: &str,
+        expected: &[String],
+        best: &mut Option<(usize, String, &'t mut toml::Table)>,
+    ) {
+        let score = table.keys().filter(|k| expected.contains(k)).count();
+        if table.contains_key(key) && best.as_ref().is_none_or(|b| score > b.0) {
+            *best = Some((score, path.clone(), &mut *table));
+            return;
+        }
+        for (name, value) in table.iter_mut() {
+            let path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", path, name)
+            };
+            match value {
+                toml::Value::Table(t) => visit(t, path, key, expected, best),
+                toml::Value::Array(items) => {
+                    for (i, item) in items.iter_mut().enumerate() {
+                        if let toml::Value::Table(t) = item {
+                            visit(t, format!("{}[{}]", path, i), key, expected, best);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut best = None;
+    visit(table, String::new(), key, expected, &mut best);
+    let (_, path, table) = best?;
+    table.remove(key);
+    Some(if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    })
+}
+
+// 编辑距离最近、且足够接近的候选
+fn suggest<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let limit = (name.len() / 3).max(2);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c.as_str())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = prev[j] + usize::from(ca != *cb);
+            row[j + 1] = replace.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::TenantSource;
+
+    fn load_str(name: &str, contents: &str) -> Result<Config, ConfigError> {
+        let path = crate::testdir::temp_path(name).with_extension("toml");
+        fs::write(&path, contents).unwrap();
+        let result = Config::load_from(Some(&path), None, Vec::new());
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn test_unknown_keys_reported_together() {
+        let config = load_str(
+            "unknown",
+            "wokers = 8\nqueue_capacity = 16\n[latency]\nenabld = true\n[tenncy]\nsource = \"header\"\n",
+        )
+        .unwrap();
+        // 其余字段照常加载
+        assert_eq!(config.queue_capacity, 16);
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("expected invalid config");
+        };
+        assert!(problems.contains(&"unknown key wokers, did you mean \"workers\"?".to_string()));
+        assert!(
+            problems.contains(&"unknown key latency.enabld, did you mean \"enabled\"?".to_string())
+        );
+        assert!(problems.contains(&"unknown key tenncy, did you mean \"tenancy\"?".to_string()));
+    }
+
+    #[test]
+    fn test_type_errors_with_suggestion() {
+        let err = load_str(
+            "types",
+            "workers = \"four\"\nroute_priorities = { orders = \"hgih\" }\n",
+        )
+        .unwrap_err();
+        let ConfigError::Invalid(problems) = err else {
+            panic!("expected invalid config");
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].ends_with("did you mean \"high\"?"));
+        assert!(problems[1].starts_with("workers: invalid type"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let names: Vec<String> = ["addr", "workers", "public_path"].map(String::from).into();
+        assert_eq!(suggest("pubic_path", &names), Some("public_path"));
+        assert_eq!(suggest("color", &names), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_layers() {
+        let dir = crate::testdir::temp_path("layers");
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("httperver.toml");
+        fs::write(
+            &base,
+            "workers = 2\nqueue_capacity = 16\n[tenancy]\nheader = \"X-Org\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("httperver.prod.toml"),
+            "workers = 8\n[tenancy]\nsource = \"header\"\n",
+        )
+        .unwrap();
+        let vars = [
+            ("HTTPERVER_WORKERS", "16"),
+            ("HTTPERVER_TENANCY__BASE_DOMAIN", "example.com"),
+            ("HTTPERVER_INHERIT_FD", "3"),
+            ("PATH", "/bin"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let config = Config::load_from(Some(&base), Some("prod"), vars).unwrap();
+        let missing = Config::load_from(Some(&base), Some("staging"), Vec::new());
+        fs::remove_dir_all(&dir).unwrap();
+        // 环境变量 > 环境配置 > 配置文件，表按字段合并
+        assert_eq!(config.workers, 16);
+        assert_eq!(config.queue_capacity, 16);
+        assert_eq!(config.tenancy.header, "X-Org");
+        assert_eq!(config.tenancy.source, Some(TenantSource::Header));
+        assert_eq!(config.tenancy.base_domain.as_deref(), Some("example.com"));
+        assert!(config.unknown_keys.is_empty());
+        assert!(matches!(missing, Err(ConfigError::Read(..))));
+        // 输出的最终配置可以重新加载
+        let reloaded: Config = toml::from_str(&config.to_toml()).unwrap();
+        assert_eq!(reloaded, config);
+    }
+}
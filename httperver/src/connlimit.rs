@@ -0,0 +1,191 @@
+// 单个客户端 IP 同时打开的连接数上限，和全局的队列容量分开计算
+// 一台机器开几百个连接就能占满队列和工作线程，超过上限的新连接直接拒绝：
+// 明文连接回复 429 后关闭，HTTPS 连接还没握手，直接关闭
+// 按 TCP 对端地址计数，不看 X-Forwarded-For；反向代理的地址要放进 allow
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use http::proxy::{Cidr, InvalidCidr};
+
+// [conn_limit]
+// max_per_ip = 20
+// allow = ["10.0.0.0/8", "127.0.0.1"]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnLimitConfig {
+    // 不设置表示不限制
+    pub max_per_ip: Option<usize>,
+    // 不受限制的地址，CIDR 或单个地址
+    pub allow: Vec<String>,
+}
+
+impl ConnLimitConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_per_ip.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if self.max_per_ip == Some(0) {
+            problems.push("conn_limit: max_per_ip must be positive".to_string());
+        }
+        if !self.enabled() && !self.allow.is_empty() {
+            problems.push("conn_limit: allow requires conn_limit.max_per_ip".to_string());
+        }
+        for cidr in &self.allow {
+            if let Err(e) = Cidr::parse(cidr) {
+                problems.push(format!("conn_limit.allow: {}", e));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnLimitStats {
+    pub max_per_ip: usize,
+    // 当前有连接的 IP 数
+    pub clients: usize,
+    pub rejected: usize,
+}
+
+struct Inner {
+    max: usize,
+    allow: Vec<Cidr>,
+    open: Mutex<HashMap<IpAddr, usize>>,
+    rejected: AtomicUsize,
+}
+
+#[derive(Clone)]
+pub struct ConnLimiter {
+    inner: Arc<Inner>,
+}
+
+// 跟着连接走，连接关闭（被丢弃）时归还名额
+// ip 为 None 表示在 allow 里，没有计数
+pub struct ConnPermit {
+    inner: Arc<Inner>,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnPermit {
+    fn drop(&mut self) {
+        let Some(ip) = self.ip else {
+            return;
+        };
+        let mut open = self.inner.open.lock().unwrap();
+        if let Some(n) = open.get_mut(&ip) {
+            *n -= 1;
+            if *n == 0 {
+                open.remove(&ip);
+            }
+        }
+    }
+}
+
+impl ConnLimiter {
+    pub fn new(max: usize, allow: Vec<Cidr>) -> Self {
+        ConnLimiter {
+            inner: Arc::new(Inner {
+                max,
+                allow,
+                open: Mutex::new(HashMap::new()),
+                rejected: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn from_config(config: &ConnLimitConfig) -> Result<Option<Self>, InvalidCidr> {
+        let Some(max) = config.max_per_ip else {
+            return Ok(None);
+        };
+        let allow = config
+            .allow
+            .iter()
+            .map(|s| Cidr::parse(s))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(ConnLimiter::new(max, allow)))
+    }
+
+    // 新连接进来时调用，超过上限返回 None 并计数
+    pub fn acquire(&self, ip: IpAddr) -> Option<ConnPermit> {
+        let permit = |ip| ConnPermit {
+            inner: self.inner.clone(),
+            ip,
+        };
+        if self.inner.allow.iter().any(|c| c.contains(ip)) {
+            return Some(permit(None));
+        }
+        let mut open = self.inner.open.lock().unwrap();
+        let n = open.entry(ip).or_insert(0);
+        if *n >= self.inner.max {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        *n += 1;
+        Some(permit(Some(ip)))
+    }
+
+    pub fn stats(&self) -> ConnLimitStats {
+        ConnLimitStats {
+            max_per_ip: self.inner.max,
+            clients: self.inner.open.lock().unwrap().len(),
+            rejected: self.inner.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conn_limit_per_ip() {
+        let limiter = ConnLimiter::new(2, vec![Cidr::parse("10.0.0.0/8").unwrap()]);
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        let first = limiter.acquire(a);
+        let second = limiter.acquire(a);
+        assert!(first.is_some() && second.is_some());
+        assert!(limiter.acquire(a).is_none());
+        // 其他 IP 不受影响
+        assert!(limiter.acquire(b).is_some());
+        // 关闭一个连接后名额归还
+        drop(first);
+        let third = limiter.acquire(a);
+        assert!(third.is_some());
+        // allow 里的地址不计数
+        let allowed: Vec<_> = (0..5)
+            .map(|_| limiter.acquire("10.1.2.3".parse().unwrap()))
+            .collect();
+        assert!(allowed.iter().all(Option::is_some));
+        assert_eq!(limiter.stats().clients, 1);
+        drop((second, third));
+        assert_eq!(
+            limiter.stats(),
+            ConnLimitStats {
+                max_per_ip: 2,
+                clients: 0,
+                rejected: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_conn_limit_config_validate() {
+        let mut problems = Vec::new();
+        ConnLimitConfig {
+            max_per_ip: Some(0),
+            allow: vec!["10.0.0.0/33".into()],
+        }
+        .validate(&mut problems);
+        assert_eq!(
+            problems,
+            vec![
+                "conn_limit: max_per_ip must be positive",
+                "conn_limit.allow: invalid CIDR \"10.0.0.0/33\"",
+            ]
+        );
+    }
+}
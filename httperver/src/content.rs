@@ -0,0 +1,114 @@
+// 蓝绿发布：静态文件可以有多个内容目录（blue / green），通过管理接口原子切换当前目录。
+// 每个请求开始时取一次目录快照，切换不会影响正在进行的请求（例如分段下载）
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::sync::RwLock;
+
+#[derive(Debug, PartialEq)]
+pub enum ContentError {
+    UnknownRoot(String),
+    Missing(String),
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentError::UnknownRoot(n) => write!(f, "unknown content root {:?}", n),
+            ContentError::Missing(p) => write!(f, "content root {:?} is not a directory", p),
+        }
+    }
+}
+
+impl std::error::Error for ContentError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContentState {
+    pub current: String,
+    // 上一个目录保留下来，方便回滚
+    pub previous: Option<String>,
+}
+
+pub struct ContentRoots {
+    // 名字 -> 目录
+    roots: BTreeMap<String, String>,
+    state: RwLock<ContentState>,
+}
+
+impl ContentRoots {
+    pub fn new(roots: BTreeMap<String, String>, current: &str) -> Result<Self, ContentError> {
+        if !roots.contains_key(current) {
+            return Err(ContentError::UnknownRoot(current.to_string()));
+        }
+        Ok(ContentRoots {
+            roots,
+            state: RwLock::new(ContentState {
+                current: current.to_string(),
+                previous: None,
+            }),
+        })
+    }
+
+    pub fn state(&self) -> ContentState {
+        self.state.read().unwrap().clone()
+    }
+    pub fn names(&self) -> Vec<&str> {
+        self.roots.keys().map(|k| k.as_str()).collect()
+    }
+
+    // 当前目录的真实路径；如果配置的是符号链接（current -> releases/v42），
+    // 这里解析一次，部署工具 ln -sfn 切换链接后下一个请求就会生效
+    pub fn current_path(&self) -> String {
+        let name = self.state.read().unwrap().current.clone();
+        let path = &self.roots[&name];
+        fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.clone())
+    }
+
+    // 切换前检查目录存在，避免切到一个空目录导致全站 404
+    pub fn switch(&self, name: &str) -> Result<ContentState, ContentError> {
+        let path = self
+            .roots
+            .get(name)
+            .ok_or_else(|| ContentError::UnknownRoot(name.to_string()))?;
+        if !fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false) {
+            return Err(ContentError::Missing(path.clone()));
+        }
+        let mut state = self.state.write().unwrap();
+        if state.current != name {
+            state.previous = Some(std::mem::replace(&mut state.current, name.to_string()));
+        }
+        Ok(state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_switch_content_root() {
+        let dir = crate::testdir::temp_path("content");
+        let mut roots = BTreeMap::new();
+        for name in ["blue", "green"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            roots.insert(
+                name.to_string(),
+                dir.join(name).to_string_lossy().into_owned(),
+            );
+        }
+        roots.insert("gone".to_string(), "/no/such/dir".to_string());
+        let content = ContentRoots::new(roots, "blue").unwrap();
+        let state = content.switch("green").unwrap();
+        assert_eq!(state.current, "green");
+        assert_eq!(state.previous.as_deref(), Some("blue"));
+        assert_eq!(
+            content.switch("red"),
+            Err(ContentError::UnknownRoot("red".into()))
+        );
+        assert!(content.switch("gone").is_err());
+        assert_eq!(content.state().current, "green");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+// 后台运行 / pid 文件 / 信号控制，只在 unix 上可用
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 信号处理函数里只能做异步信号安全的事情，所以只设置一个标志位
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// fork 一次，父进程退出，子进程 setsid 脱离终端
+pub fn daemonize() -> io::Result<()> {
+    // SAFETY: 此时还没有创建其他线程，fork 之后子进程可以安全继续执行
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // stdin 指向 /dev/null，避免读终端
+    let devnull = OpenOptions::new().read(true).open("/dev/null")?;
+    if unsafe { libc::dup2(devnull.as_raw_fd(), libc::STDIN_FILENO) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// 以追加方式打开日志文件，并把 stdout / stderr 重定向过去
+pub fn redirect_output(log_file: &str) -> io::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+    let fd = file.as_raw_fd();
+    // dup2 之后 file 被 drop 也没关系，1 和 2 已经指向同一个打开的文件
+    if unsafe { libc::dup2(fd, libc::STDOUT_FILENO) } == -1
+        || unsafe { libc::dup2(fd, libc::STDERR_FILENO) } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn write_pid_file(path: &str) -> io::Result<()> {
+    fs::write(path, format!("{}\n", std::process::id()))
+}
+
+pub fn read_pid_file(path: &str) -> io::Result<i32> {
+    let contents = fs::read_to_string(path)?;
+    contents.trim().parse::<i32>().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("pid file {} does not contain a pid", path),
+        )
+    })
+}
+
+// 收到 SIGHUP 后重新打开日志文件，配合 logrotate 使用
+pub fn install_reload_handler(log_file: Option<String>) {
+    // SAFETY: on_sighup 只做原子写入，是异步信号安全的
+    unsafe {
+        libc::signal(libc::SIGHUP, on_sighup as *const () as libc::sighandler_t);
+    }
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(500));
+        if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+            match &log_file {
+                Some(path) => match redirect_output(path) {
+                    Ok(()) => println!("reopened log file {}", path),
+                    Err(e) => eprintln!("cannot reopen log file {}: {}", path, e),
+                },
+                None => println!("received SIGHUP, nothing to reload"),
+            }
+        }
+    });
+}
+
+fn process_alive(pid: i32) -> bool {
+    // 信号 0 不会真的发送，只检查进程是否存在
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+pub fn send_signal(pid_file: &str, signal: libc::c_int) -> io::Result<i32> {
+    let pid = read_pid_file(pid_file)?;
+    if unsafe { libc::kill(pid, signal) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(pid)
+}
+
+// 发送 SIGTERM 并等待进程退出，然后删除 pid 文件
+pub fn stop(pid_file: &str, wait: Duration) -> io::Result<i32> {
+    let pid = send_signal(pid_file, libc::SIGTERM)?;
+    let start = Instant::now();
+    while process_alive(pid) {
+        if start.elapsed() > wait {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("process {} did not exit within {:?}", pid, wait),
+            ));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = fs::remove_file(pid_file);
+    Ok(pid)
+}
+
+pub fn reload(pid_file: &str) -> io::Result<i32> {
+    send_signal(pid_file, libc::SIGHUP)
+}
+
+// 新进程会重写 pid 文件
+pub fn upgrade(pid_file: &str) -> io::Result<i32> {
+    send_signal(pid_file, libc::SIGUSR2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn pid_path(name: &str) -> String {
+        let path = crate::testdir::temp_path(name).with_extension("pid");
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_pid_file() {
+        let path = pid_path("pidfile");
+        write_pid_file(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        assert_eq!(read_pid_file(&path).unwrap(), std::process::id() as i32);
+        fs::write(&path, "not a pid\n").unwrap();
+        let err = read_pid_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(&path));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            read_pid_file(&path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_stop_removes_pid_file() {
+        let path = pid_path("stop");
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        fs::write(&path, format!("{}\n", child.id())).unwrap();
+        // 子进程退出后要有人回收，否则僵尸进程一直算存活
+        let reaper = thread::spawn(move || child.wait().unwrap());
+        let pid = stop(&path, Duration::from_secs(5)).unwrap();
+        assert!(!reaper.join().unwrap().success());
+        assert!(pid > 0);
+        assert!(!Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,266 @@
+// 废弃路由：接口要下线之前先告诉调用方，并记下还有谁在用，方便逐个通知迁移
+//
+//     [deprecated_routes.orders_v1]
+//     since = "2026-01-01"
+//     sunset = "2026-12-31"
+//     link = "https://example.com/docs/migrate-orders"
+//
+// 命中废弃路由的响应会带上
+//     Deprecation: @1767225600                                 RFC 9745，废弃时间的 Unix 秒数
+//     Sunset: Thu, 31 Dec 2026 00:00:00 GMT                     RFC 8594，计划下线的时间
+//     Link: <https://example.com/docs/...>; rel="deprecation"   迁移说明
+// 每个调用方（客户端 IP）第一次调用某个废弃路由时打一条日志，之后只计数
+// User-Agent 由客户端随意填写，不放进键里；记录的调用方超过 MAX_CALLERS 后，新的调用方都算进 "other"
+use crate::middleware::Middleware;
+use http::clock;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use http::links::Links;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deprecation {
+    since: SystemTime,
+    sunset: Option<SystemTime>,
+    link: Option<String>,
+}
+
+impl Deprecation {
+    // since 是开始废弃的时间，可以在将来，表示预告
+    pub fn new(since: SystemTime) -> Self {
+        Deprecation {
+            since,
+            sunset: None,
+            link: None,
+        }
+    }
+    pub fn sunset(mut self, at: SystemTime) -> Self {
+        self.sunset = Some(at);
+        self
+    }
+    // 迁移说明的地址，必须是编码好的 URI
+    pub fn link(mut self, href: impl Into<String>) -> Self {
+        self.link = Some(href.into());
+        self
+    }
+
+    fn apply(&self, resp: &mut HttpResponse<'static>) {
+        let secs = self
+            .since
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let _ = resp.set_header(names::DEPRECATION, format!("@{}", secs));
+        if let Some(sunset) = self.sunset {
+            let _ = resp.set_header(names::SUNSET, clock::http_date(sunset));
+        }
+        if let Some(href) = &self.link {
+            // 分页接口自己也有 Link，追加而不是覆盖
+            let links = Links::new().link("deprecation", href.as_str());
+            let _ = resp.append_header(names::LINK, links.to_string());
+        }
+    }
+}
+
+// [deprecated_routes.<路由名>]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeprecationConfig {
+    // YYYY-MM-DD，UTC
+    pub since: String,
+    pub sunset: Option<String>,
+    pub link: Option<String>,
+}
+
+impl DeprecationConfig {
+    pub fn validate(&self, route: &str, problems: &mut Vec<String>) {
+        let since = clock::parse_date(&self.since);
+        if since.is_none() {
+            problems.push(format!(
+                "deprecated_routes.{}.since {:?} is not a YYYY-MM-DD date",
+                route, self.since
+            ));
+        }
+        if let Some(sunset) = &self.sunset {
+            match clock::parse_date(sunset) {
+                None => problems.push(format!(
+                    "deprecated_routes.{}.sunset {:?} is not a YYYY-MM-DD date",
+                    route, sunset
+                )),
+                Some(at) if since.is_some_and(|s| at < s) => problems.push(format!(
+                    "deprecated_routes.{}.sunset is before since",
+                    route
+                )),
+                Some(_) => {}
+            }
+        }
+        if let Some(link) = &self.link {
+            if link.is_empty() || link.contains(|c: char| c.is_whitespace() || c == '>') {
+                problems.push(format!(
+                    "deprecated_routes.{}.link {:?} is not a valid URI",
+                    route, link
+                ));
+            }
+        }
+    }
+
+    // 先 validate，日期不合法时返回 None
+    pub fn build(&self) -> Option<Deprecation> {
+        let mut deprecation = Deprecation::new(clock::parse_date(&self.since)?);
+        if let Some(sunset) = &self.sunset {
+            deprecation = deprecation.sunset(clock::parse_date(sunset)?);
+        }
+        if let Some(link) = &self.link {
+            deprecation = deprecation.link(link.as_str());
+        }
+        Some(deprecation)
+    }
+}
+
+// Router 匹配到废弃路由时放进 req.extensions
+pub(crate) struct DeprecatedRoute {
+    pub(crate) name: &'static str,
+    pub(crate) deprecation: Arc<Deprecation>,
+}
+
+// (路由名, 调用方) -> 调用次数
+type Usage = BTreeMap<(String, String), u64>;
+
+// 计数表最多记多少个 (路由, 调用方)，防止大量不同的来源地址把内存占满
+pub const MAX_CALLERS: usize = 10_000;
+// 超过上限之后的调用方
+pub const OTHER_CALLER: &str = "other";
+
+// 克隆的实例共享计数，注册到 Router 之后仍然可以读到
+#[derive(Clone, Default)]
+pub struct DeprecationNotices {
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl DeprecationNotices {
+    pub fn new() -> Self {
+        DeprecationNotices::default()
+    }
+    // 目前为止每个调用方调用每个废弃路由的次数
+    pub fn usage(&self) -> Usage {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+// 调用方：客户端 IP，经过可信代理时是转发头里的地址
+fn caller(req: &HttpRequest) -> String {
+    req.client_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Middleware for DeprecationNotices {
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+        let Some(route) = req.extensions.get::<DeprecatedRoute>() else {
+            return;
+        };
+        route.deprecation.apply(resp);
+        let mut key = (route.name.to_string(), caller(req));
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if usage.len() >= MAX_CALLERS && !usage.contains_key(&key) {
+            key.1 = OTHER_CALLER.to_string();
+        }
+        let caller = key.1.clone();
+        let count = usage.entry(key).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            eprintln!("deprecated route {} called by {}", route.name, caller);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use std::time::Duration;
+
+    #[test]
+    fn test_deprecated_route() {
+        let since = UNIX_EPOCH + Duration::from_secs(784_080_000);
+        let deprecation = Deprecation::new(since)
+            .sunset(since + Duration::from_secs(86_400))
+            .link("/docs/migrate");
+        let notices = DeprecationNotices::new();
+        let router = Router::new("")
+            .middleware(notices.clone())
+            .get("/new", |_req| {
+                HttpResponse::new("200", None, Some("new".into()))
+            })
+            .deprecate("health", deprecation)
+            .unwrap();
+        let route = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        for (ip, agent) in [("10.0.0.1", "a"), ("10.0.0.1", "b"), ("10.0.0.2", "a")] {
+            let mut req = HttpRequest::try_from(
+                format!("GET /health HTTP/1.1\r\nUser-Agent: {}\r\n\r\n", agent).as_bytes(),
+            )
+            .unwrap();
+            req.remote_addr = Some(format!("{}:5000", ip).parse().unwrap());
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            let out = String::from_utf8(out).unwrap();
+            assert!(out.contains("Deprecation:@784080000\r\n"), "{}", out);
+            assert!(out.contains("Sunset:Mon, 07 Nov 1994 00:00:00 GMT\r\n"));
+            assert!(out.contains("Link:</docs/migrate>; rel=\"deprecation\"\r\n"));
+        }
+        let out = route("GET /new HTTP/1.1\r\n\r\n");
+        assert!(!out.contains("Deprecation"));
+        let usage = notices.usage();
+        // 换一个 User-Agent 不算新的调用方
+        assert_eq!(usage[&("health".to_string(), "10.0.0.1".to_string())], 2);
+        assert_eq!(usage[&("health".to_string(), "10.0.0.2".to_string())], 1);
+        assert_eq!(usage.len(), 2);
+        // 超过上限之后的调用方合并计数
+        {
+            let mut usage = notices.usage.lock().unwrap();
+            for i in usage.len()..MAX_CALLERS {
+                usage.insert(("health".to_string(), i.to_string()), 1);
+            }
+        }
+        route("GET /health HTTP/1.1\r\n\r\n");
+        let usage = notices.usage();
+        assert_eq!(usage.len(), MAX_CALLERS + 1);
+        assert_eq!(usage[&("health".to_string(), OTHER_CALLER.to_string())], 1);
+        assert!(Router::new("")
+            .deprecate("missing", Deprecation::new(since))
+            .is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let config = DeprecationConfig {
+            since: "2026-01-01".into(),
+            sunset: Some("2025-12-31".into()),
+            link: Some("bad link".into()),
+        };
+        let mut problems = Vec::new();
+        config.validate("orders", &mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        let config = DeprecationConfig {
+            sunset: Some("2026-12-31".into()),
+            link: None,
+            ..config
+        };
+        let mut problems = Vec::new();
+        config.validate("orders", &mut problems);
+        assert!(problems.is_empty());
+        assert_eq!(
+            config.build().unwrap(),
+            Deprecation::new(clock::parse_date("2026-01-01").unwrap())
+                .sunset(clock::parse_date("2026-12-31").unwrap())
+        );
+    }
+}
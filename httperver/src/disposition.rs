@@ -0,0 +1,132 @@
+// 静态文件的 Content-Disposition：浏览器直接打开（inline）还是弹出保存对话框（attachment）
+// 按扩展名决定，不区分大小写；保存时的默认文件名是请求路径的最后一段，
+// 非 ASCII 的文件名按 RFC 5987 编码
+use http::headers::content_disposition;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    #[default]
+    Inline,
+    Attachment,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        }
+    }
+}
+
+// [disposition]
+// default = "inline"
+// attachment = ["zip", "csv"]
+// inline = ["pdf"]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DispositionConfig {
+    // 没有列出的扩展名用这个
+    pub default: Disposition,
+    // 作为附件下载的扩展名，不带点
+    pub attachment: Vec<String>,
+    // default 是 attachment 时仍然直接打开的扩展名
+    pub inline: Vec<String>,
+}
+
+impl DispositionConfig {
+    // 都是默认值时不加 Content-Disposition 头部，和以前的行为一致
+    pub fn enabled(&self) -> bool {
+        self.default == Disposition::Attachment || !self.attachment.is_empty()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        for ext in self.attachment.iter().chain(&self.inline) {
+            if ext.is_empty() || ext.starts_with('.') || ext.contains('/') {
+                problems.push(format!(
+                    "disposition: invalid extension {:?}, expected e.g. \"zip\"",
+                    ext
+                ));
+            }
+        }
+        for ext in &self.attachment {
+            if self.inline.iter().any(|e| e.eq_ignore_ascii_case(ext)) {
+                problems.push(format!(
+                    "disposition: extension {:?} is listed as both attachment and inline",
+                    ext
+                ));
+            }
+        }
+    }
+
+    pub fn for_file(&self, file_name: &str) -> Disposition {
+        let ext = Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let listed = |list: &[String]| list.iter().any(|e| e.eq_ignore_ascii_case(ext));
+        if listed(&self.attachment) {
+            Disposition::Attachment
+        } else if listed(&self.inline) {
+            Disposition::Inline
+        } else {
+            self.default
+        }
+    }
+
+    // 静态文件响应的 Content-Disposition 值
+    pub fn header(&self, file_name: &str) -> String {
+        content_disposition(self.for_file(file_name).as_str(), file_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disposition_policy() {
+        let policy = DispositionConfig {
+            default: Disposition::Inline,
+            attachment: vec!["zip".into(), "CSV".into()],
+            inline: Vec::new(),
+        };
+        assert!(policy.enabled());
+        assert_eq!(policy.for_file("a/b/data.csv"), Disposition::Attachment);
+        assert_eq!(policy.for_file("index.html"), Disposition::Inline);
+        assert_eq!(
+            policy.header("files/报告.ZIP"),
+            "attachment; filename=\"__.ZIP\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A.ZIP"
+        );
+
+        let policy = DispositionConfig {
+            default: Disposition::Attachment,
+            attachment: Vec::new(),
+            inline: vec!["pdf".into()],
+        };
+        assert_eq!(policy.for_file("a.pdf"), Disposition::Inline);
+        assert_eq!(policy.for_file("a.bin"), Disposition::Attachment);
+        assert!(!DispositionConfig::default().enabled());
+    }
+
+    #[test]
+    fn test_disposition_config_validate() {
+        let mut problems = Vec::new();
+        DispositionConfig {
+            default: Disposition::Inline,
+            attachment: vec![".zip".into(), "pdf".into()],
+            inline: vec!["PDF".into()],
+        }
+        .validate(&mut problems);
+        assert_eq!(
+            problems,
+            vec![
+                "disposition: invalid extension \".zip\", expected e.g. \"zip\"",
+                "disposition: extension \"pdf\" is listed as both attachment and inline",
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// 路由的说明文档：注册路由时附上标题、说明和示例，GET /docs 从路由表生成一页给人看的 API 参考
+// （机器读的 OpenAPI 文档见 httperver export）
+//
+//     #[route(GET, "/api/orders/:id", title = "查询订单", response = r#"{"order_id": 1}"#)]
+//     fn order(req: &HttpRequest, id: u32) -> HttpResponse<'static> { ... }
+//
+//     router.post("/api/hits", hit).describe(RouteDoc::new("计数").description("每次调用加一"))
+use crate::router::{RouteInfo, Router, ADMIN_HANDLER};
+use http::html::{SafeHtml, Template};
+use http::httpresponse::HttpResponse;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RouteDoc {
+    pub title: &'static str,
+    pub description: &'static str,
+    // 请求体和响应体的示例，一般是 JSON
+    pub request_example: Option<&'static str>,
+    pub response_example: Option<&'static str>,
+}
+
+impl RouteDoc {
+    pub fn new(title: &'static str) -> Self {
+        RouteDoc {
+            title,
+            ..RouteDoc::default()
+        }
+    }
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+    pub fn request_example(mut self, example: &'static str) -> Self {
+        self.request_example = Some(example);
+        self
+    }
+    pub fn response_example(mut self, example: &'static str) -> Self {
+        self.response_example = Some(example);
+        self
+    }
+}
+
+// 内容都来自代码，仍然转义，示例 JSON 里的 < 不会破坏页面
+const DOCS_PAGE: &str = "<!DOCTYPE html>\n<html>\n<head><title>API reference</title></head>\n<body>\n<h1>API reference</h1>\n{{ routes }}</body>\n</html>\n";
+const DOC_ROUTE: &str = "<section>\n<h2><code>{{ method }} {{ path }}</code> {{ title }}</h2>\n{{ description }}{{ examples }}</section>\n";
+const DOC_PARAGRAPH: &str = "<p>{{ text }}</p>\n";
+const DOC_EXAMPLE: &str = "<h3>{{ label }}</h3>\n<pre>{{ example }}</pre>\n";
+
+// 带上挂载前缀的完整路径；任意方法的兜底路由和本机的管理接口不列出
+fn collect<'r>(router: &'r Router, prefix: &str, out: &mut Vec<(String, &'r RouteInfo)>) {
+    let prefix = format!("{}{}", prefix, router.base_path());
+    let listed = |r: &&RouteInfo| r.method != "*" && r.handler != ADMIN_HANDLER;
+    for r in router.routes().iter().filter(listed) {
+        out.push((format!("{}{}", prefix, r.path), r));
+    }
+    for (path, versions) in router.versions() {
+        for (version, sub) in versions.routers() {
+            collect(sub, &format!("{}{}/v{}", prefix, path, version), out);
+        }
+    }
+    for (mount, sub) in router.mounts() {
+        collect(sub, &format!("{}{}", prefix, mount), out);
+    }
+}
+
+pub fn page<'a>(router: &Router) -> HttpResponse<'a> {
+    let mut routes = Vec::new();
+    collect(router, "", &mut routes);
+    let route = Template::parse(DOC_ROUTE).unwrap();
+    let paragraph = Template::parse(DOC_PARAGRAPH).unwrap();
+    let example = Template::parse(DOC_EXAMPLE).unwrap();
+    let mut html = String::new();
+    for (path, r) in routes {
+        // 没有写标题时用路由名，至少能对上代码
+        let title = match r.doc.title {
+            "" => r.name.unwrap_or(r.handler),
+            title => title,
+        };
+        let description = match r.doc.description {
+            "" => SafeHtml::default(),
+            text => paragraph
+                .render(&[("text", &SafeHtml::escaped(text))])
+                .unwrap(),
+        };
+        let mut examples = String::new();
+        for (label, text) in [
+            ("Request", r.doc.request_example),
+            ("Response", r.doc.response_example),
+        ] {
+            if let Some(text) = text {
+                let html = example
+                    .render(&[
+                        ("label", &SafeHtml::escaped(label)),
+                        ("example", &SafeHtml::escaped(text)),
+                    ])
+                    .unwrap();
+                examples.push_str(html.as_str());
+            }
+        }
+        let section = route
+            .render(&[
+                ("method", &SafeHtml::escaped(r.method)),
+                ("path", &path),
+                ("title", &SafeHtml::escaped(title)),
+                ("description", &description),
+                ("examples", &SafeHtml::trusted(examples)),
+            ])
+            .unwrap();
+        html.push_str(section.as_str());
+    }
+    let page = Template::parse(DOCS_PAGE)
+        .unwrap()
+        .render(&[("routes", &SafeHtml::trusted(html))])
+        .unwrap();
+    HttpResponse::new("200", None, Some(page.into_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::versions::ApiVersions;
+    use http::httprequest::HttpRequest;
+
+    #[test]
+    fn test_docs_page() {
+        let v2 = Router::new("")
+            .get("/orders", |_req| HttpResponse::new("200", None, None))
+            .describe(RouteDoc::new("List orders, v2"));
+        let router = Router::new("")
+            .post("/api/hits", |_req| HttpResponse::new("201", None, None))
+            .describe(
+                RouteDoc::new("Count a hit")
+                    .description("Adds one & returns the total.")
+                    .request_example("{}")
+                    .response_example("<n>"),
+            )
+            .versioned("/api", ApiVersions::new().version(2, v2));
+        let mut out = Vec::new();
+        let req = HttpRequest::try_from(&b"GET /docs HTTP/1.1\r\n\r\n"[..]).unwrap();
+        router.route(req, &mut out);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200"));
+        assert!(out.contains(
+            "<h2><code>POST /api/hits</code> Count a hit</h2>\n\
+             <p>Adds one &amp; returns the total.</p>\n\
+             <h3>Request</h3>\n<pre>{}</pre>\n\
+             <h3>Response</h3>\n<pre>&lt;n&gt;</pre>\n"
+        ));
+        assert!(out.contains("<code>GET /api/v2/orders</code> List orders, v2</h2>"));
+        // 内置路由
+        assert!(out.contains("<code>GET /api/shipping/orders/:id</code> Get an order</h2>"));
+        assert!(out.contains("<code>GET /health</code> health</h2>"));
+        assert!(!out.contains("<code>* /*</code>"));
+    }
+}
@@ -0,0 +1,105 @@
+// 固定不变的小响应：健康检查、favicon 的 204、重定向，注册时就序列化好，
+// 请求来了直接把字节写到连接上，不构造 HttpResponse，也不分配内存
+//
+//     let router = Router::new("")
+//         .fast("/health", FastResponse::text("ok"))
+//         .fast("/favicon.ico", FastResponse::no_content())
+//         .fast("/old", FastResponse::redirect(StatusCode::MovedPermanently, "/new")?);
+//
+// 只匹配 GET 和 HEAD，路径完全相同（不含查询字符串）；不经过中间件，访问日志、认证、限流都看不到这些请求，
+// 也没有 ETag 和条件请求，所以只适合谁都可以看、内容永远不变的响应
+// 基准测试：cargo bench -p httperver --bench fast_path
+use http::headers::{self, names, HeaderError};
+use http::status::StatusCode;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastResponse {
+    // 状态行、头部和 body
+    bytes: Box<[u8]>,
+    // 头部到空行为止的长度，HEAD 只写这一段
+    head_len: usize,
+}
+
+impl FastResponse {
+    // 头部按 HttpResponse 的格式写出；Content-Length 自动加上，204 和 304 除外
+    pub fn new(
+        status: StatusCode,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Self, HeaderError> {
+        let mut head = format!("HTTP/1.1 {}\r\n", status);
+        for (name, value) in headers {
+            headers::validate(name, value)?;
+            head.push_str(&format!("{}:{}\r\n", name, value));
+        }
+        if !matches!(status.as_u16(), 204 | 304) {
+            head.push_str(&format!("{}: {}\r\n", names::CONTENT_LENGTH, body.len()));
+        }
+        head.push_str("\r\n");
+        let head_len = head.len();
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(body);
+        Ok(FastResponse {
+            bytes: bytes.into_boxed_slice(),
+            head_len,
+        })
+    }
+
+    // 200 text/plain，一般用于健康检查
+    pub fn text(body: &str) -> Self {
+        let headers = [
+            (names::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (names::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ];
+        FastResponse::new(StatusCode::Ok, &headers, body.as_bytes()).expect("valid headers")
+    }
+
+    pub fn no_content() -> Self {
+        FastResponse::new(StatusCode::NoContent, &[], &[]).expect("no headers")
+    }
+
+    // location 里有控制字符时返回错误
+    pub fn redirect(status: StatusCode, location: &str) -> Result<Self, HeaderError> {
+        FastResponse::new(status, &[(names::LOCATION, location)], &[])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn write(&self, stream: &mut impl Write, head_only: bool) -> io::Result<()> {
+        if head_only {
+            stream.write_all(&self.bytes[..self.head_len])
+        } else {
+            stream.write_all(&self.bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_response() {
+        let ok = FastResponse::text("ok");
+        assert_eq!(
+            ok.as_bytes(),
+            b"HTTP/1.1 200 OK\r\nContent-Type:text/plain; charset=utf-8\r\nX-Content-Type-Options:nosniff\r\nContent-Length: 2\r\n\r\nok"
+        );
+        let mut head = Vec::new();
+        ok.write(&mut head, true).unwrap();
+        assert!(head.ends_with(b"Content-Length: 2\r\n\r\n"));
+        assert_eq!(
+            FastResponse::no_content().as_bytes(),
+            b"HTTP/1.1 204 No Content\r\n\r\n"
+        );
+        let moved = FastResponse::redirect(StatusCode::MovedPermanently, "/new").unwrap();
+        assert_eq!(
+            moved.as_bytes(),
+            b"HTTP/1.1 301 Moved Permanently\r\nLocation:/new\r\nContent-Length: 0\r\n\r\n"
+        );
+        assert!(FastResponse::redirect(StatusCode::Found, "/x\r\nSet-Cookie: a=b").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// 文件描述符耗尽（EMFILE / ENFILE）时 accept 会一直失败，
+// 连接留在内核 backlog 里，监听 fd 一直可读，不处理的话 accept 循环会空转
+// 这里负责退避：每次失败暂停的时间翻倍，成功后恢复
+// 另外预留一个备用 fd，耗尽时先释放它，接受一个排队的连接回复 503 后关闭，让客户端尽快知道
+// 退避期间等待下一个请求的 keep-alive 连接不再等下去，关掉把 fd 让给新连接（见 Server::await_next_request）
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FdStats {
+    // 当前打开的 fd 数，只在 Linux 上能取到
+    pub open: Option<u64>,
+    pub limit: Option<u64>,
+    // accept 因为 fd 耗尽失败的总次数
+    pub exhausted: u64,
+    // 用备用 fd 接受后直接回复 503 的连接数
+    pub shed: u64,
+    // 当前的退避时间，0 表示正常
+    pub backoff_ms: u64,
+    // 为了腾出 fd 关掉的空闲 keep-alive 连接数
+    pub idle_closed: u64,
+}
+
+pub struct FdPressure {
+    exhausted: AtomicU64,
+    shed: AtomicU64,
+    backoff_ms: AtomicU64,
+    idle_closed: AtomicU64,
+    spare: Mutex<Option<File>>,
+}
+
+impl Default for FdPressure {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FdPressure {
+    pub fn new() -> Self {
+        FdPressure {
+            exhausted: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+            backoff_ms: AtomicU64::new(0),
+            idle_closed: AtomicU64::new(0),
+            spare: Mutex::new(reserve()),
+        }
+    }
+
+    // 进程或系统的 fd 用完了
+    pub fn is_exhausted(e: &io::Error) -> bool {
+        #[cfg(unix)]
+        {
+            matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = e;
+            false
+        }
+    }
+
+    // accept 失败后调用，返回这次应该暂停多久
+    pub fn on_exhausted(&self) -> Duration {
+        self.exhausted.fetch_add(1, Ordering::Relaxed);
+        let prev = Duration::from_millis(self.backoff_ms.load(Ordering::Relaxed));
+        let next = (prev * 2).clamp(MIN_BACKOFF, MAX_BACKOFF);
+        self.backoff_ms
+            .store(next.as_millis() as u64, Ordering::Relaxed);
+        if prev.is_zero() {
+            eprintln!(
+                "accept: out of file descriptors (open {}, limit {}), pausing accept",
+                show(open_fds()),
+                show(fd_limit())
+            );
+        }
+        next
+    }
+
+    // accept 成功后调用，从退避状态恢复时打印一次
+    pub fn on_success(&self) {
+        if self.backoff_ms.swap(0, Ordering::Relaxed) > 0 {
+            eprintln!("accept: file descriptors available again, resuming");
+        }
+    }
+
+    // 正在退避，空闲的长连接应该关掉
+    pub fn under_pressure(&self) -> bool {
+        self.backoff_ms.load(Ordering::Relaxed) > 0
+    }
+
+    pub fn on_idle_closed(&self) {
+        self.idle_closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 释放备用 fd 执行 shed（通常是接受一个连接并回复 503），再重新预留
+    // shed 返回 true 表示确实拒绝了一个连接
+    pub fn shed_with_spare(&self, shed: impl FnOnce() -> bool) {
+        let mut spare = self.spare.lock().unwrap_or_else(|e| e.into_inner());
+        if spare.take().is_none() {
+            return;
+        }
+        if shed() {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        *spare = reserve();
+    }
+
+    pub fn stats(&self) -> FdStats {
+        FdStats {
+            open: open_fds(),
+            limit: fd_limit(),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+            shed: self.shed.load(Ordering::Relaxed),
+            backoff_ms: self.backoff_ms.load(Ordering::Relaxed),
+            idle_closed: self.idle_closed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn reserve() -> Option<File> {
+    #[cfg(unix)]
+    {
+        File::open("/dev/null").ok()
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+fn show(v: Option<u64>) -> String {
+    v.map_or("?".into(), |v| v.to_string())
+}
+
+pub fn open_fds() -> Option<u64> {
+    // read_dir 本身也占一个 fd，减掉
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?;
+    Some((entries.count() as u64).saturating_sub(1))
+}
+
+pub fn fd_limit() -> Option<u64> {
+    #[cfg(unix)]
+    {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: limit 是有效的可写 rlimit
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0 {
+            // rlim_t 在 Linux 上就是 u64，其他平台不一定
+            #[allow(clippy::unnecessary_cast)]
+            return Some(limit.rlim_cur as u64);
+        }
+        None
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_resets() {
+        let fds = FdPressure::new();
+        assert!(!fds.under_pressure());
+        assert_eq!(fds.on_exhausted(), MIN_BACKOFF);
+        assert!(fds.under_pressure());
+        assert_eq!(fds.on_exhausted(), MIN_BACKOFF * 2);
+        for _ in 0..20 {
+            fds.on_exhausted();
+        }
+        assert_eq!(fds.on_exhausted(), MAX_BACKOFF);
+        fds.on_success();
+        let stats = fds.stats();
+        assert_eq!(stats.exhausted, 23);
+        assert_eq!(stats.backoff_ms, 0);
+        assert!(!fds.under_pressure());
+        assert_eq!(fds.on_exhausted(), MIN_BACKOFF);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exhausted_errors() {
+        assert!(FdPressure::is_exhausted(&io::Error::from_raw_os_error(
+            libc::EMFILE
+        )));
+        assert!(FdPressure::is_exhausted(&io::Error::from_raw_os_error(
+            libc::ENFILE
+        )));
+        assert!(!FdPressure::is_exhausted(&io::Error::from_raw_os_error(
+            libc::ECONNABORTED
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shed_with_spare() {
+        let fds = FdPressure::new();
+        fds.shed_with_spare(|| true);
+        fds.shed_with_spare(|| false);
+        assert_eq!(fds.stats().shed, 1);
+        // 备用 fd 重新预留了
+        assert!(fds.spare.lock().unwrap().is_some());
+    }
+}
@@ -0,0 +1,168 @@
+// GeoIP：用 MaxMind 的 mmdb 数据库查询客户端 IP 所在国家和 ASN，
+// 结果放进 req.extensions，可选地写入请求头，供日志、封禁和本地化使用
+use serde::{Deserialize, Serialize};
+
+// 请求头的名字，add_headers = true 时写入
+pub const COUNTRY_HEADER: &str = "X-Geo-Country";
+pub const ASN_HEADER: &str = "X-Geo-ASN";
+
+// [geoip]
+// country_db = "/var/lib/GeoIP/GeoLite2-Country.mmdb"
+// asn_db = "/var/lib/GeoIP/GeoLite2-ASN.mmdb"
+// block_countries = ["XX"]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoIpConfig {
+    pub country_db: Option<String>,
+    pub asn_db: Option<String>,
+    pub add_headers: bool,
+    // ISO 3166 两位国家码，来自这些国家的请求返回 403
+    pub block_countries: Vec<String>,
+}
+
+impl GeoIpConfig {
+    pub fn enabled(&self) -> bool {
+        self.country_db.is_some() || self.asn_db.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if !self.enabled() {
+            if !self.block_countries.is_empty() {
+                problems.push("geoip.block_countries requires geoip.country_db".to_string());
+            }
+            return;
+        }
+        if cfg!(not(feature = "geoip")) {
+            problems.push("geoip requires building with --features geoip".to_string());
+        }
+        for db in self.country_db.iter().chain(self.asn_db.iter()) {
+            if !std::path::Path::new(db).is_file() {
+                problems.push(format!("geoip database {:?} does not exist", db));
+            }
+        }
+        if !self.block_countries.is_empty() && self.country_db.is_none() {
+            problems.push("geoip.block_countries requires geoip.country_db".to_string());
+        }
+    }
+}
+
+// 查询结果，查不到的字段为 None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+#[cfg(feature = "geoip")]
+pub use reader::GeoIp;
+
+#[cfg(feature = "geoip")]
+mod reader {
+    use super::*;
+    use http::headers::validate_value;
+    use http::httprequest::HttpRequest;
+    use http::httpresponse::HttpResponse;
+    use maxminddb::{geoip2, MaxMindDbError, Reader};
+    use std::net::IpAddr;
+
+    pub struct GeoIp {
+        country: Option<Reader<Vec<u8>>>,
+        asn: Option<Reader<Vec<u8>>>,
+        add_headers: bool,
+        block_countries: Vec<String>,
+    }
+
+    impl GeoIp {
+        // 数据库整个读进内存，之后查询不再访问磁盘
+        pub fn open(config: &GeoIpConfig) -> Result<Self, MaxMindDbError> {
+            Ok(GeoIp {
+                country: config
+                    .country_db
+                    .as_ref()
+                    .map(Reader::open_readfile)
+                    .transpose()?,
+                asn: config
+                    .asn_db
+                    .as_ref()
+                    .map(Reader::open_readfile)
+                    .transpose()?,
+                add_headers: config.add_headers,
+                block_countries: config
+                    .block_countries
+                    .iter()
+                    .map(|c| c.to_ascii_uppercase())
+                    .collect(),
+            })
+        }
+
+        // 私有地址、数据库里没有的地址或损坏的记录都返回空结果，不影响请求
+        pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+            let mut info = GeoInfo::default();
+            if let Some(reader) = &self.country {
+                if let Ok(Some(c)) = reader
+                    .lookup(ip)
+                    .and_then(|r| r.decode::<geoip2::Country>())
+                {
+                    info.country = c.country.iso_code.map(String::from);
+                }
+            }
+            if let Some(reader) = &self.asn {
+                if let Ok(Some(a)) = reader.lookup(ip).and_then(|r| r.decode::<geoip2::Asn>()) {
+                    info.asn = a.autonomous_system_number;
+                    info.as_org = a.autonomous_system_organization.map(String::from);
+                }
+            }
+            info
+        }
+
+        // 查询并标注请求；来自被封禁国家的请求返回 403 响应
+        pub fn apply<'a>(&self, req: &mut HttpRequest) -> Option<HttpResponse<'a>> {
+            let ip = req.client_ip()?;
+            let info = self.lookup(ip);
+            let blocked = info
+                .country
+                .as_ref()
+                .is_some_and(|c| self.block_countries.contains(c));
+            if self.add_headers {
+                annotate_headers(req, &info);
+            }
+            req.extensions.insert(info);
+            if blocked {
+                return Some(HttpResponse::new("403", None, Some("Forbidden".into())));
+            }
+            None
+        }
+    }
+
+    // 先去掉客户端自己带的同名头，避免伪造
+    fn annotate_headers(req: &mut HttpRequest, info: &GeoInfo) {
+        req.headers.remove(COUNTRY_HEADER);
+        req.headers.remove(ASN_HEADER);
+        if let Some(c) = info.country.as_ref().filter(|c| validate_value(c).is_ok()) {
+            req.headers.insert(COUNTRY_HEADER, c.clone());
+        }
+        if let Some(asn) = info.asn {
+            req.headers.insert(ASN_HEADER, asn.to_string());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        #[test]
+        fn test_annotate_headers() {
+            let mut req: HttpRequest =
+                String::from("GET / HTTP/1.1\r\nX-Geo-Country: US\r\n\r\n").into();
+            let info = GeoInfo {
+                country: Some("NL".into()),
+                asn: Some(1136),
+                as_org: None,
+            };
+            annotate_headers(&mut req, &info);
+            assert_eq!(req.header(COUNTRY_HEADER), Some("NL"));
+            assert_eq!(req.header(ASN_HEADER), Some("1136"));
+            assert_eq!(req.headers.len(), 2);
+        }
+    }
+}
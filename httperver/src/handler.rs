@@ -1,85 +1,442 @@
+use crate::assets::IMMUTABLE_CACHE;
+use crate::orders::{JsonFileStore, OrderInput, OrderStatus, OrderStore};
+use crate::router::RequestParams;
+use crate::tenant::Tenant;
+use http::codec::CodecError;
+use http::headers::names;
+use http::html::{SafeHtml, Template};
+use http::httprequest::Method;
+use http::links::Links;
+use http::mime;
+use http::query::percent_decode;
+use http::status::StatusCode;
 use http::{httprequest::HttpRequest, httpresponse::HttpResponse};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::Serialize;
 use std::env;
 use std::fs;
+use std::io;
+use std::path::PathBuf;
 
 pub trait Handler {
     // 因为HttpResponse  包含了引用 所以rust要知道 引用来自哪里
     // 在这种情况下，HttpResponse需要一个生命周期参数，因为它包含了一个引用
-    //
-    fn handle(req: &HttpRequest) -> HttpResponse;
+    // 响应里的引用都不来自 req，中间件在 after 里还要同时拿到 req 和响应
+    // 处理器是无状态的函数，共享状态通过 req.state::<T>() 取出（见 state.rs）
+    fn handle<'a>(req: &HttpRequest) -> HttpResponse<'a>;
     fn load_file(file_name: &str) -> Option<String> {
-        let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
-        let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-        let full_path = format!("{}/{}", public_path, file_name);
+        Self::load_file_from(&public_path(), file_name)
+    }
+    // 从指定的静态目录读取，挂载的子应用可以有自己的静态目录
+    fn load_file_from(root: &str, file_name: &str) -> Option<String> {
+        let full_path = format!("{}/{}", root, file_name);
         let contents = fs::read_to_string(full_path);
         contents.ok()
     }
+    // 按原始字节读取，图片、字体等二进制文件用这个
+    fn load_bytes_from(root: &str, file_name: &str) -> Option<Vec<u8>> {
+        fs::read(format!("{}/{}", root, file_name)).ok()
+    }
+}
+
+// 默认静态目录，可以用 PUBLIC_PATH 环境变量覆盖
+pub fn public_path() -> String {
+    let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
+    env::var("PUBLIC_PATH").unwrap_or(default_path)
+}
+// 数据目录：多租户时是租户自己的目录，否则用 DATA_PATH 环境变量，默认 data/
+pub fn data_path(req: &HttpRequest) -> String {
+    if let Some(dir) = req
+        .extensions
+        .get::<Tenant>()
+        .and_then(|t| t.data_dir.as_ref())
+    {
+        return dir.to_string_lossy().into_owned();
+    }
+    let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
+    env::var("DATA_PATH").unwrap_or(default_path)
 }
 pub struct StaticPageHandler;
 pub struct PageNotFoundHandler;
 pub struct WebServiceHandler;
-#[derive(Serialize, Deserialize)]
-pub struct OrderStatus {
-    order_id: i32,
-    order_date: String,
-    order_status: String,
-}
 impl Handler for PageNotFoundHandler {
-    fn handle(_req: &HttpRequest) -> HttpResponse {
+    fn handle<'a>(_req: &HttpRequest) -> HttpResponse<'a> {
         HttpResponse::new("404", None, Self::load_file("404.html"))
     }
 }
 impl Handler for StaticPageHandler {
-    fn handle(req: &HttpRequest) -> HttpResponse {
-        let http::httprequest::Resource::Path(s) = &req.resource;
-        let route: Vec<&str> = s.split("/").collect();
-        match route[1] {
-            "" => HttpResponse::new("200", None, Self::load_file("index.html")),
-            "health" => HttpResponse::new("200", None, Self::load_file("health.html")),
-            path => match Self::load_file(path) {
-                Some(contents) => {
-                    let mut map: HashMap<&str, &str> = HashMap::new();
-                    if path.ends_with(".css") {
-                        map.insert("Content-Tvpe", "text/css");
-                    } else if path.ends_with(".js") {
-                        map.insert("Content-Type", "text/javascript");
-                    } else {
-                        map.insert("Content-Type", "text/html");
-                    }
-                    HttpResponse::new("200", Some(map), Some(contents))
-                }
-                None => HttpResponse::new("404", None, Self::load_file("404.html")),
-            },
+    fn handle<'a>(req: &HttpRequest) -> HttpResponse<'a> {
+        Self::serve(&public_path(), req)
+    }
+}
+impl StaticPageHandler {
+    // 请求路径（去掉开头的 /）对应的文件名
+    pub fn file_name(path: &str) -> &str {
+        match path {
+            "" => "index.html",
+            "health" => "health.html",
+            path => path,
+        }
+    }
+    // 以 root 为静态目录处理请求，试图跳出 root 的请求返回 403
+    pub fn serve<'a>(root: &str, req: &HttpRequest) -> HttpResponse<'a> {
+        let name = Self::file_name(req.path().trim_start_matches('/'));
+        // 文件不读进内存，发送时分块复制到连接上，二进制文件和大文件都一样
+        let file = match resolve_static(root, name) {
+            Ok(path) => HttpResponse::file(path).ok(),
+            Err(PathError::Forbidden) => {
+                return HttpResponse::new("403", None, Some("Forbidden".into()));
+            }
+            Err(PathError::NotFound) => None,
+        };
+        match file {
+            // 真实路径可能是符号链接的目标，Content-Type 按请求的文件名推断
+            Some(resp) => resp
+                .with_header(names::CONTENT_TYPE, mime::from_path(name))
+                .expect("mime types are valid header values"),
+            None => HttpResponse::new("404", None, Self::load_file_from(root, "404.html")),
         }
     }
+
+    // 带哈希的资源：内容不会变，允许浏览器永久缓存
+    pub fn serve_asset<'a>(root: &str, file_name: &str) -> HttpResponse<'a> {
+        match HttpResponse::file(format!("{}/{}", root, file_name)) {
+            Ok(resp) => resp
+                .with_header(names::CACHE_CONTROL, IMMUTABLE_CACHE)
+                .expect("valid header"),
+            Err(_) => HttpResponse::new("404", None, Self::load_file_from(root, "404.html")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    // 路径里有 ..、反斜杠等，或者真实路径（跟随符号链接后）不在 root 下面
+    Forbidden,
+    NotFound,
+}
+
+// 静态目录下的相对路径 -> 真实路径
+// 先解码再逐段检查，%2e%2e%2f 这样编码过的 ../ 也会被拦下
+pub fn resolve_static(root: &str, rel: &str) -> Result<PathBuf, PathError> {
+    let decoded = percent_decode(rel, false);
+    let mut path = PathBuf::from(root);
+    for segment in decoded.split('/') {
+        if segment == ".." || segment == "." || segment.contains(['\\', '\0', ':']) {
+            return Err(PathError::Forbidden);
+        }
+        path.push(segment);
+    }
+    let root = fs::canonicalize(root).map_err(|_| PathError::NotFound)?;
+    let path = fs::canonicalize(&path).map_err(|_| PathError::NotFound)?;
+    if !path.starts_with(&root) {
+        return Err(PathError::Forbidden);
+    }
+    if !path.is_file() {
+        return Err(PathError::NotFound);
+    }
+    Ok(path)
+}
+
+// 默认的订单存储：数据目录下的 orders.json
+pub fn order_file_store(req: &HttpRequest) -> JsonFileStore {
+    JsonFileStore::new(format!("{}/{}", data_path(req), "orders.json"))
+}
+
+// 订单接口的错误和正常响应一样按 Accept 选格式（CBOR 客户端收到的也是 CBOR），
+// 选出的格式表示不了（例如表单里的嵌套对象）时退回 JSON
+fn api_error<'a>(
+    req: &HttpRequest,
+    status: StatusCode,
+    body: serde_json::Value,
+) -> HttpResponse<'a> {
+    let resp = HttpResponse::encode(req, &body);
+    let resp = if resp.status().is_success() {
+        resp
+    } else {
+        HttpResponse::json(&body)
+    };
+    resp.with_status(status)
+}
+
+// {"error": "..."}
+fn json_error<'a>(req: &HttpRequest, status: StatusCode, error: &str) -> HttpResponse<'a> {
+    api_error(req, status, serde_json::json!({ "error": error }))
+}
+
+fn store_error<'a>(req: &HttpRequest, e: io::Error) -> HttpResponse<'a> {
+    eprintln!("Cannot access orders: {}", e);
+    json_error(req, StatusCode::InternalServerError, "cannot access orders")
+}
+
+fn order_not_found<'a>(req: &HttpRequest) -> HttpResponse<'a> {
+    json_error(req, StatusCode::NotFound, "order not found")
 }
+
+// 分页时每页的默认条数和上限
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+// 分页的订单列表
+#[derive(Serialize)]
+struct OrdersPage<'o> {
+    orders: &'o [OrderStatus],
+    page: usize,
+    per_page: usize,
+    total: usize,
+    #[serde(rename = "_links")]
+    links: &'o Links,
+}
+
+// 订单页面的模板，订单字段来自数据文件，渲染时自动转义
+const ORDERS_PAGE: &str = "<!DOCTYPE html>\n<html>\n<head><title>Orders</title></head>\n<body>\n<table>\n<tr><th>ID</th><th>Date</th><th>Status</th></tr>\n{{ rows }}</table>\n</body>\n</html>\n";
+const ORDER_ROW: &str =
+    "<tr><td>{{ order_id }}</td><td>{{ order_date }}</td><td>{{ order_status }}</td></tr>\n";
+
 impl WebServiceHandler {
-    fn load_json() -> Vec<OrderStatus> {
-        let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
-        let data_path = env::var("DATA_PATH").unwrap_or(default_path);
-        let full_path = format!("{}/{}", data_path, "orders.json");
-        let json_contents = fs::read_to_string(full_path);
-        let orders: Vec<OrderStatus> =
-            serde_json::from_str(json_contents.unwrap().as_str()).unwrap();
-        orders
+    // GET /orders：以 HTML 表格展示订单
+    pub fn orders_page<'a>(store: &dyn OrderStore) -> HttpResponse<'a> {
+        let orders = match store.load() {
+            Ok(orders) => orders,
+            // 页面是 HTML，不返回 JSON 错误
+            Err(e) => {
+                eprintln!("Cannot access orders: {}", e);
+                return HttpResponse::new("500", None, Some(String::new()));
+            }
+        };
+        let row = Template::parse(ORDER_ROW).unwrap();
+        let mut rows = String::new();
+        for o in orders {
+            let html = row
+                .render(&[
+                    ("order_id", &o.order_id),
+                    ("order_date", &o.order_date),
+                    ("order_status", &o.order_status),
+                ])
+                .unwrap();
+            rows.push_str(html.as_str());
+        }
+        let page = Template::parse(ORDERS_PAGE)
+            .unwrap()
+            .render(&[("rows", &SafeHtml::trusted(rows))])
+            .unwrap();
+        HttpResponse::new("200", None, Some(page.into_string()))
     }
 }
 
 impl Handler for WebServiceHandler {
-    fn handle(req: &HttpRequest) -> HttpResponse {
-        let http::httprequest::Resource::Path(s) = &req.resource;
-        let route: Vec<&str> = s.split("/").collect();
-
-        match route[2] {
-            "shipping" if route.len() > 2 && route[3] == "orders" => {
-                let body = Some(serde_json::to_string(&Self::load_json()).unwrap());
-                let mut headers: HashMap<&str, &str> = HashMap::new();
-                headers.insert("Content-Type", "application/json");
-                HttpResponse::new("2oo", Some(headers), body)
+    fn handle<'a>(req: &HttpRequest) -> HttpResponse<'a> {
+        Self::api(&order_file_store(req), req)
+    }
+}
+
+impl WebServiceHandler {
+    // /api/shipping/orders 和 /api/shipping/orders/:id 的增删改查
+    pub fn api<'a>(store: &dyn OrderStore, req: &HttpRequest) -> HttpResponse<'a> {
+        let route: Vec<&str> = req.path().split("/").collect();
+
+        match route.get(2).copied() {
+            // /api/shipping/orders/:id
+            Some("shipping")
+                if route.get(3) == Some(&"orders")
+                    && route.get(4).is_some_and(|id| !id.is_empty()) =>
+            {
+                let Some(id) = req.params().get("id").and_then(|id| id.parse::<i32>().ok()) else {
+                    return order_not_found(req);
+                };
+                match req.method {
+                    Method::Get | Method::Head => match store.load() {
+                        Ok(orders) => match orders.into_iter().find(|o| o.order_id == id) {
+                            Some(o) => HttpResponse::encode(req, &o),
+                            None => order_not_found(req),
+                        },
+                        Err(e) => store_error(req, e),
+                    },
+                    Method::Put => Self::update_order(store, req, id, false),
+                    Method::Patch => Self::update_order(store, req, id, true),
+                    Method::Delete => Self::delete_order(store, req, id),
+                    _ => HttpResponse::new("404", None, Self::load_file("404.html")),
+                }
             }
+            Some("shipping") if route.get(3) == Some(&"orders") => match req.method {
+                Method::Post => Self::create_order(store, req),
+                _ => match store.load() {
+                    Ok(orders) => Self::list_orders(req, &orders),
+                    Err(e) => store_error(req, e),
+                },
+            },
             _ => HttpResponse::new("404", None, Self::load_file("404.html")),
         }
     }
+
+    // 不带 page / per_page 时返回全部订单（数组），带了就分页，
+    // 前后页的地址同时放在 Link 头部和 body 的 _links 里
+    fn list_orders<'a>(req: &HttpRequest, orders: &[OrderStatus]) -> HttpResponse<'a> {
+        let query = req.query_params();
+        if query.first("page").is_none() && query.first("per_page").is_none() {
+            return HttpResponse::encode(req, &orders);
+        }
+        let positive = |key: &str, default: usize| match query.first(key) {
+            None => Some(default),
+            Some(v) => v.parse::<usize>().ok().filter(|n| *n > 0),
+        };
+        let Some(page) = positive("page", 1) else {
+            return json_error(
+                req,
+                StatusCode::BadRequest,
+                "page must be a positive integer",
+            );
+        };
+        let Some(per_page) = positive("per_page", DEFAULT_PER_PAGE) else {
+            return json_error(
+                req,
+                StatusCode::BadRequest,
+                "per_page must be a positive integer",
+            );
+        };
+        let per_page = per_page.min(MAX_PER_PAGE);
+        let start = (page - 1).saturating_mul(per_page).min(orders.len());
+        let end = start.saturating_add(per_page).min(orders.len());
+        let links = Links::paginate(page, orders.len().div_ceil(per_page), |p| {
+            format!("/api/shipping/orders?page={}&per_page={}", p, per_page)
+        });
+        let body = OrdersPage {
+            orders: &orders[start..end],
+            page,
+            per_page,
+            total: orders.len(),
+            links: &links,
+        };
+        let mut resp = HttpResponse::encode(req, &body);
+        if resp.status().is_success() && !links.is_empty() {
+            resp.set_header(names::LINK, links.to_string())
+                .expect("generated links are valid header values");
+        }
+        resp
+    }
+
+    // 请求体按 Content-Type 解码（JSON、表单……），失败返回 415 / 400，
+    // 字段不合法返回 422 和每个字段的问题
+    fn order_input<'a>(req: &HttpRequest, partial: bool) -> Result<OrderInput, HttpResponse<'a>> {
+        let input: OrderInput = req.decode().map_err(CodecError::into_response)?;
+        let problems = input.validate(partial);
+        if !problems.is_empty() {
+            let body = serde_json::json!({ "error": "validation failed", "fields": problems });
+            return Err(api_error(req, StatusCode::UnprocessableContent, body));
+        }
+        Ok(input)
+    }
+
+    // POST：没有给 order_id 时用现有最大值加一，给了但已存在返回 409；最大值已经到头时也返回 409
+    fn create_order<'a>(store: &dyn OrderStore, req: &HttpRequest) -> HttpResponse<'a> {
+        let input = match Self::order_input(req, false) {
+            Ok(input) => input,
+            Err(resp) => return resp,
+        };
+        let mut input = Some(input);
+        let mut created = None;
+        // 最大的 id 已经是 i32::MAX，分配不出新的 id
+        let mut exhausted = false;
+        let result = store.modify(&mut |orders| {
+            let input = input.take().expect("modify calls the closure once");
+            let next = orders
+                .iter()
+                .map(|o| o.order_id)
+                .max()
+                .unwrap_or(0)
+                .checked_add(1);
+            let id = match (input.order_id, next) {
+                (Some(id), _) if orders.iter().any(|o| o.order_id == id) => return false,
+                (Some(id), _) => id,
+                (None, Some(id)) => id,
+                (None, None) => {
+                    exhausted = true;
+                    return false;
+                }
+            };
+            let mut order = OrderStatus {
+                order_id: id,
+                order_date: String::new(),
+                order_status: String::new(),
+            };
+            input.apply(&mut order);
+            orders.push(order.clone());
+            created = Some(order);
+            true
+        });
+        if let Err(e) = result {
+            return store_error(req, e);
+        }
+        match created {
+            Some(order) => {
+                let location = format!("/api/shipping/orders/{}", order.order_id);
+                let resp = HttpResponse::encode(req, &order);
+                // Accept 里的格式都编码不了（406 / 500）时不能再改成 201
+                if !resp.status().is_success() {
+                    return resp;
+                }
+                resp.with_status(StatusCode::Created)
+                    .with_header(names::LOCATION, location)
+                    .expect("order id is a valid header value")
+            }
+            None if exhausted => json_error(
+                req,
+                StatusCode::Conflict,
+                "no order id left, give order_id explicitly",
+            ),
+            None => json_error(req, StatusCode::Conflict, "order already exists"),
+        }
+    }
+
+    // PUT 要给出全部字段，PATCH 只改给出的字段；请求体里的 order_id 必须和路径一致
+    fn update_order<'a>(
+        store: &dyn OrderStore,
+        req: &HttpRequest,
+        id: i32,
+        partial: bool,
+    ) -> HttpResponse<'a> {
+        let input = match Self::order_input(req, partial) {
+            Ok(input) => input,
+            Err(resp) => return resp,
+        };
+        if input.order_id.is_some_and(|body_id| body_id != id) {
+            let body = serde_json::json!({
+                "error": "validation failed",
+                "fields": { "order_id": "does not match the order in the URL" },
+            });
+            return api_error(req, StatusCode::UnprocessableContent, body);
+        }
+        let mut input = Some(input);
+        let mut updated = None;
+        let result = store.modify(&mut |orders| {
+            let Some(order) = orders.iter_mut().find(|o| o.order_id == id) else {
+                return false;
+            };
+            input
+                .take()
+                .expect("modify calls the closure once")
+                .apply(order);
+            updated = Some(order.clone());
+            true
+        });
+        match (result, updated) {
+            (Err(e), _) => store_error(req, e),
+            (Ok(()), Some(order)) => HttpResponse::encode(req, &order),
+            (Ok(()), None) => order_not_found(req),
+        }
+    }
+
+    fn delete_order<'a>(store: &dyn OrderStore, req: &HttpRequest, id: i32) -> HttpResponse<'a> {
+        let mut removed = false;
+        let result = store.modify(&mut |orders| {
+            let before = orders.len();
+            orders.retain(|o| o.order_id != id);
+            removed = orders.len() < before;
+            removed
+        });
+        match result {
+            Err(e) => store_error(req, e),
+            Ok(()) if removed => HttpResponse::new("204", None, None),
+            Ok(()) => order_not_found(req),
+        }
+    }
 }
@@ -0,0 +1,114 @@
+// 103 Early Hints（RFC 8297）：处理器还在查数据、渲染页面时，先告诉浏览器要预加载哪些资源
+//
+//     #[route(GET, "/dashboard")]
+//     fn dashboard(req: &HttpRequest) -> HttpResponse<'static> {
+//         let _ = req.early_hints(&[hints::preload("/static/app.css", "style")]);
+//         ... 慢的部分 ...
+//     }
+//
+// 线程池服务器的明文 TCP 连接上立刻写出；TLS、异步服务器和测试里先缓存，
+// 由 Router::route 在最终响应之前写出，协议上一样合法，只是没有了提前量
+use http::httprequest::{HttpRequest, Version};
+use http::httpresponse::HttpResponse;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+enum Sink {
+    Direct(Box<dyn Write + Send>),
+    Buffered(Vec<u8>),
+}
+
+// 临时响应的去处，Router::route 放进 req.extensions，一个请求可以发多次
+pub(crate) struct Interim(Mutex<Sink>);
+
+impl Interim {
+    // 直接写到连接上，out 一般是 socket 的 try_clone
+    pub(crate) fn direct(out: impl Write + Send + 'static) -> Self {
+        Interim(Mutex::new(Sink::Direct(Box::new(out))))
+    }
+    pub(crate) fn buffered() -> Self {
+        Interim(Mutex::new(Sink::Buffered(Vec::new())))
+    }
+    // 取出还没写出的临时响应，直接写出的没有剩余
+    pub(crate) fn take(req: &mut HttpRequest) -> Vec<u8> {
+        match req.extensions.remove::<Interim>() {
+            Some(Interim(sink)) => match sink.into_inner().unwrap_or_else(|e| e.into_inner()) {
+                Sink::Buffered(out) => out,
+                Sink::Direct(_) => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+}
+
+// 一个预加载链接，destination 是 style / script / font / image ...
+pub fn preload(href: &str, destination: &str) -> String {
+    format!("<{}>; rel=preload; as={}", href, destination)
+}
+
+pub trait RequestHints {
+    // 返回是否发出：HTTP/1.0 客户端不认识 1xx 响应（RFC 7231 6.2），没有经过 Router 的请求也无处可发
+    // 链接里有 CR / LF 等控制字符时返回 InvalidInput
+    fn early_hints<S: AsRef<str>>(&self, links: &[S]) -> io::Result<bool>;
+}
+
+impl RequestHints for HttpRequest {
+    fn early_hints<S: AsRef<str>>(&self, links: &[S]) -> io::Result<bool> {
+        let hints = HttpResponse::early_hints(links)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if self.version != Version::V1_1 || links.is_empty() {
+            return Ok(false);
+        }
+        let Some(Interim(sink)) = self.extensions.get::<Interim>() else {
+            return Ok(false);
+        };
+        let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+        match &mut *sink {
+            Sink::Direct(out) => {
+                hints.send_interim(out)?;
+                out.flush()?;
+            }
+            Sink::Buffered(out) => hints.send_interim(out)?,
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+
+    #[test]
+    fn test_early_hints() {
+        let router = Router::new("").get("/page", |req| {
+            req.early_hints(&[preload("/static/app.css", "style")])
+                .unwrap();
+            req.early_hints(&[preload("/static/app.js", "script")])
+                .unwrap();
+            assert!(req.early_hints(&["</a>\r\nX-Evil: 1"]).is_err());
+            HttpResponse::new("200", None, Some("page".into()))
+        });
+        let route = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let out = route("GET /page HTTP/1.1\r\n\r\n");
+        assert!(
+            out.starts_with(
+                "HTTP/1.1 103 Early Hints\r\nLink:</static/app.css>; rel=preload; as=style\r\n\r\n\
+                 HTTP/1.1 103 Early Hints\r\nLink:</static/app.js>; rel=preload; as=script\r\n\r\n\
+                 HTTP/1.1 200 OK\r\n"
+            ),
+            "{}",
+            out
+        );
+        assert!(out.ends_with("\r\n\r\npage"));
+        // HTTP/1.0 只有最终响应
+        assert!(route("GET /page HTTP/1.0\r\n\r\n").starts_with("HTTP/1.1 200 OK\r\n"));
+        // 没有经过 Router 的请求
+        let req = HttpRequest::try_from(&b"GET / HTTP/1.1\r\n\r\n"[..]).unwrap();
+        assert!(!req.early_hints(&[preload("/a.css", "style")]).unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+// 本机进程间通信的监听：unix 上是 unix domain socket，Windows 上是命名管道
+// 两边提供同样的 bind / accept / connect，服务器不用关心底层是哪一种
+//
+// unix：name 是 socket 文件路径，bind 时删除上次留下的文件，监听结束时删除
+// Windows：name 是管道名，不带 \\.\pipe\ 前缀时自动补上
+use std::io::{self, Read, Write};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+pub struct IpcListener {
+    name: String,
+    #[cfg(unix)]
+    inner: UnixListener,
+    // 等待客户端连接的管道实例；accept 取走后立刻再建一个，保证客户端总能连上
+    #[cfg(windows)]
+    pending: std::sync::Mutex<std::os::windows::io::OwnedHandle>,
+}
+
+pub struct IpcStream {
+    #[cfg(unix)]
+    inner: UnixStream,
+    #[cfg(windows)]
+    inner: std::fs::File,
+}
+
+impl IpcListener {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(unix)]
+impl IpcListener {
+    pub fn bind(name: &str) -> io::Result<IpcListener> {
+        use std::os::unix::fs::FileTypeExt;
+        // 只删除 socket 文件，路径写错时不会误删普通文件
+        if let Ok(meta) = std::fs::symlink_metadata(name) {
+            if meta.file_type().is_socket() {
+                std::fs::remove_file(name)?;
+            }
+        }
+        Ok(IpcListener {
+            name: name.to_string(),
+            inner: UnixListener::bind(name)?,
+        })
+    }
+
+    pub fn accept(&self) -> io::Result<IpcStream> {
+        let (inner, _) = self.inner.accept()?;
+        Ok(IpcStream { inner })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for IpcListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.name);
+    }
+}
+
+#[cfg(unix)]
+impl IpcStream {
+    pub fn connect(name: &str) -> io::Result<IpcStream> {
+        Ok(IpcStream {
+            inner: UnixStream::connect(name)?,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+    pub fn set_write_timeout(&self, timeout: Option<std::time::Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(windows)]
+mod pipe {
+    use std::io;
+    use std::os::windows::io::{FromRawHandle, OwnedHandle};
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{
+        GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    const BUFFER_SIZE: u32 = 64 * 1024;
+
+    pub fn full_name(name: &str) -> String {
+        if name.starts_with(r"\\.\pipe\") {
+            name.to_string()
+        } else {
+            format!(r"\\.\pipe\{}", name)
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    // first 为 true 时如果同名管道已经存在就失败，避免两个服务器抢同一个名字
+    pub fn create(name: &str, first: bool) -> io::Result<OwnedHandle> {
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        let name = wide(name);
+        // SAFETY: name 以 0 结尾并在调用期间有效，安全属性传空表示使用默认值
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: handle 是刚创建的有效句柄，所有权交给 OwnedHandle
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+    }
+
+    // 阻塞直到有客户端连上；客户端在调用之前就已经连上时返回 ERROR_PIPE_CONNECTED，也算成功
+    pub fn connect(handle: &OwnedHandle) -> io::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+        // SAFETY: 句柄有效，不使用 overlapped
+        let ok = unsafe { ConnectNamedPipe(handle.as_raw_handle(), ptr::null_mut()) };
+        if ok != 0 || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED {
+            return Ok(());
+        }
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+impl IpcListener {
+    pub fn bind(name: &str) -> io::Result<IpcListener> {
+        let name = pipe::full_name(name);
+        let first = pipe::create(&name, true)?;
+        Ok(IpcListener {
+            name,
+            pending: std::sync::Mutex::new(first),
+        })
+    }
+
+    pub fn accept(&self) -> io::Result<IpcStream> {
+        let mut pending = self.pending.lock().unwrap();
+        pipe::connect(&pending)?;
+        let next = pipe::create(&self.name, false)?;
+        let connected = std::mem::replace(&mut *pending, next);
+        Ok(IpcStream {
+            inner: std::fs::File::from(connected),
+        })
+    }
+}
+
+#[cfg(windows)]
+impl IpcStream {
+    pub fn connect(name: &str) -> io::Result<IpcStream> {
+        let inner = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pipe::full_name(name))?;
+        Ok(IpcStream { inner })
+    }
+
+    // 同步读取的命名管道不支持超时，空闲的长连接只能等客户端自己断开
+    pub fn set_read_timeout(&self, _timeout: Option<std::time::Duration>) -> io::Result<()> {
+        Ok(())
+    }
+    pub fn set_write_timeout(&self, _timeout: Option<std::time::Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for IpcStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for IpcStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    #[cfg(unix)]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+    // File::flush 在 Windows 上什么都不做；管道关闭前不等对端读完，没读的数据会丢失
+    #[cfg(windows)]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_round_trip() {
+        let path = crate::testdir::temp_path("ipc").with_extension("sock");
+        let name = path.to_str().unwrap().to_string();
+        // 上次异常退出留下的 socket 文件不影响 bind
+        drop(UnixListener::bind(&name).unwrap());
+        let listener = IpcListener::bind(&name).unwrap();
+        let client = std::thread::spawn(move || {
+            let mut s = IpcStream::connect(path.to_str().unwrap()).unwrap();
+            s.write_all(b"ping").unwrap();
+            let mut reply = String::new();
+            s.read_to_string(&mut reply).unwrap();
+            reply
+        });
+        let mut server = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        server.write_all(b"pong").unwrap();
+        drop(server);
+        assert_eq!(client.join().unwrap(), "pong");
+        drop(listener);
+        assert!(!std::path::Path::new(&name).exists());
+    }
+}
@@ -0,0 +1,365 @@
+// JWT：HS256 签名和校验，检查 exp / nbf / iss / aud，通过之后把 claims 反序列化成调用方自己定义的类型
+//
+//     #[derive(Deserialize)]
+//     struct ApiClaims { sub: String, scope: String }
+//
+//     let jwt = Jwt::hs256(secret.expose().as_bytes(), Arc::new(SystemClock))
+//         .issuer("https://login.example.com")
+//         .audience("shop-api");
+//     let router = router.middleware(Auth::new("api").jwt::<ApiClaims>(jwt).only("/api"));
+//     // 处理器里
+//     let claims = req.claims::<ApiClaims>().unwrap();
+//
+//     [jwt]
+//     secret = { env = "JWT_SECRET" }
+//     issuer = "https://login.example.com"
+//     audience = "shop-api"
+//
+// 只接受 HS256：token 头部声明的 alg 是 none 或者别的算法时直接拒绝，不跟着 token 切换算法
+// 没有 exp 的 token 永不过期，一律当作无效
+use crate::secret::{Secret, SecretSource};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use http::clock::Clock;
+use http::httprequest::HttpRequest;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEFAULT_LEEWAY_SECS: u64 = 60;
+// RFC 7518：HS256 的密钥至少和哈希输出一样长
+pub const MIN_SECRET_BYTES: usize = 32;
+// 签发时固定用这个头部
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+// [jwt]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    // HS256 的密钥从哪里读；不设置表示不启用
+    pub secret: Option<SecretSource>,
+    // 设置后 token 的 iss / aud 必须与之相符
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    // 检查 exp 和 nbf 时允许签发方和本机的时钟差多少秒
+    pub leeway_secs: u64,
+    // 这些路径前缀下的请求必须带有效的 token
+    pub paths: Vec<String>,
+    // 401 响应的 WWW-Authenticate 里的 realm
+    pub realm: String,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            secret: None,
+            issuer: None,
+            audience: None,
+            leeway_secs: DEFAULT_LEEWAY_SECS,
+            paths: vec!["/api".to_string()],
+            realm: "api".to_string(),
+        }
+    }
+}
+
+impl JwtConfig {
+    pub fn enabled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if !self.enabled() {
+            return;
+        }
+        if self.paths.is_empty() {
+            problems.push("jwt.paths must not be empty".to_string());
+        }
+        for path in &self.paths {
+            if !path.starts_with('/') {
+                problems.push(format!("jwt.paths entry {:?} must start with /", path));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwtError {
+    // 不是三段 base64url，或者头部、载荷不是 JSON 对象
+    Malformed,
+    UnsupportedAlgorithm(String),
+    BadSignature,
+    MissingClaim(&'static str),
+    Expired,
+    NotYetValid,
+    WrongIssuer,
+    WrongAudience,
+    // 标准字段都通过了，但是转不成调用方要的类型
+    Claims(String),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::Malformed => write!(f, "malformed token"),
+            JwtError::UnsupportedAlgorithm(alg) => write!(f, "unsupported algorithm {:?}", alg),
+            JwtError::BadSignature => write!(f, "bad signature"),
+            JwtError::MissingClaim(name) => write!(f, "missing claim {}", name),
+            JwtError::Expired => write!(f, "token has expired"),
+            JwtError::NotYetValid => write!(f, "token is not valid yet"),
+            JwtError::WrongIssuer => write!(f, "unexpected issuer"),
+            JwtError::WrongAudience => write!(f, "unexpected audience"),
+            JwtError::Claims(e) => write!(f, "invalid claims: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+#[derive(Clone)]
+pub struct Jwt {
+    key: Secret<Vec<u8>>,
+    clock: Arc<dyn Clock>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: u64,
+}
+
+impl Jwt {
+    pub fn hs256(key: &[u8], clock: Arc<dyn Clock>) -> Self {
+        Jwt {
+            key: Secret::new(key.to_vec()),
+            clock,
+            issuer: None,
+            audience: None,
+            leeway: DEFAULT_LEEWAY_SECS,
+        }
+    }
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway.as_secs();
+        self
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(self.key.expose()).expect("HMAC accepts keys of any length")
+    }
+
+    // 签发 token，exp 等标准字段由调用方放在 claims 里
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+        let payload = serde_json::to_vec(claims).map_err(|e| JwtError::Claims(e.to_string()))?;
+        let mut token = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(HEADER),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let mut mac = self.mac();
+        mac.update(token.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        token.push('.');
+        token.push_str(&signature);
+        Ok(token)
+    }
+
+    // 校验签名和标准字段，返回转换好的 claims
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, JwtError> {
+        let claims = self.verify_value(token)?;
+        serde_json::from_value(claims).map_err(|e| JwtError::Claims(e.to_string()))
+    }
+
+    pub(crate) fn verify_value(&self, token: &str) -> Result<Value, JwtError> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(JwtError::Malformed)?;
+        let (header, payload) = signed.split_once('.').ok_or(JwtError::Malformed)?;
+        let header: Header = decode_json(header)?;
+        if header.alg != "HS256" {
+            return Err(JwtError::UnsupportedAlgorithm(header.alg));
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| JwtError::Malformed)?;
+        // 签名通过之前不看载荷，verify_slice 是常量时间比较
+        let mut mac = self.mac();
+        mac.update(signed.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| JwtError::BadSignature)?;
+        let claims: Value = decode_json(payload)?;
+        if !claims.is_object() {
+            return Err(JwtError::Malformed);
+        }
+        self.check_claims(&claims)?;
+        Ok(claims)
+    }
+
+    fn check_claims(&self, claims: &Value) -> Result<(), JwtError> {
+        let now = self.clock.unix_secs() as f64;
+        let leeway = self.leeway as f64;
+        let exp = claims.get("exp").ok_or(JwtError::MissingClaim("exp"))?;
+        let exp = exp.as_f64().ok_or(JwtError::Malformed)?;
+        if now >= exp + leeway {
+            return Err(JwtError::Expired);
+        }
+        if let Some(nbf) = claims.get("nbf") {
+            if now + leeway < nbf.as_f64().ok_or(JwtError::Malformed)? {
+                return Err(JwtError::NotYetValid);
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+                return Err(JwtError::WrongIssuer);
+            }
+        }
+        // aud 可以是一个字符串，也可以是字符串数组
+        if let Some(audience) = &self.audience {
+            let ok = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !ok {
+                return Err(JwtError::WrongAudience);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(part: &str) -> Result<T, JwtError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| JwtError::Malformed)?;
+    serde_json::from_slice(&bytes).map_err(|_| JwtError::Malformed)
+}
+
+// 让处理器可以写 req.claims::<ApiClaims>()，类型要和 Auth::jwt 指定的一致，否则返回 None
+pub trait RequestClaims {
+    fn claims<T: Send + Sync + 'static>(&self) -> Option<&T>;
+}
+
+impl RequestClaims for HttpRequest {
+    fn claims<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions.get::<T>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use serde_json::json;
+
+    const KEY: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct ApiClaims {
+        sub: String,
+        scope: String,
+    }
+
+    #[test]
+    fn test_jwt() {
+        let clock = Arc::new(MockClock::from_unix_secs(1_000_000));
+        let jwt = Jwt::hs256(KEY, clock.clone())
+            .issuer("login")
+            .audience("shop")
+            .leeway(Duration::from_secs(10));
+        let claims = json!({"sub": "ann", "scope": "orders", "iss": "login",
+            "aud": ["other", "shop"], "exp": 1_000_100, "nbf": 1_000_005});
+        let token = jwt.sign(&claims).unwrap();
+        assert_eq!(
+            jwt.verify::<ApiClaims>(&token).unwrap(),
+            ApiClaims {
+                sub: "ann".into(),
+                scope: "orders".into()
+            }
+        );
+        // 别人的密钥签的、改过载荷的都过不了
+        let other = Jwt::hs256(b"another key", clock.clone());
+        assert_eq!(other.verify::<Value>(&token), Err(JwtError::BadSignature));
+        let (head, rest) = token.split_once('.').unwrap();
+        let signature = rest.split_once('.').unwrap().1;
+        let forged = json!({"sub": "root", "scope": "orders", "iss": "login",
+            "aud": "shop", "exp": 1_000_100});
+        let forged = format!(
+            "{}.{}.{}",
+            head,
+            URL_SAFE_NO_PAD.encode(forged.to_string()),
+            signature
+        );
+        assert_eq!(jwt.verify::<Value>(&forged), Err(JwtError::BadSignature));
+        let none = format!("{}.{}.", URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#), head);
+        assert_eq!(
+            jwt.verify::<Value>(&none),
+            Err(JwtError::UnsupportedAlgorithm("none".into()))
+        );
+        assert_eq!(jwt.verify::<Value>("a.b"), Err(JwtError::Malformed));
+        assert!(matches!(
+            jwt.verify::<ApiClaims>(
+                &jwt.sign(&json!({"sub": "ann", "iss": "login",
+                "aud": "shop", "exp": 1_000_100}))
+                    .unwrap()
+            ),
+            Err(JwtError::Claims(_))
+        ));
+
+        // 时间和签发方
+        let check = |claims: Value| jwt.verify::<Value>(&jwt.sign(&claims).unwrap());
+        clock.advance(Duration::from_secs(109));
+        assert!(check(claims.clone()).is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(check(claims.clone()), Err(JwtError::Expired));
+        let base = json!({"iss": "login", "aud": "shop", "exp": 2_000_000});
+        let with = |key: &str, value: Value| {
+            let mut claims = base.clone();
+            claims[key] = value;
+            claims
+        };
+        assert_eq!(
+            check(with("nbf", json!(1_000_200))),
+            Err(JwtError::NotYetValid)
+        );
+        assert_eq!(
+            check(with("iss", json!("evil"))),
+            Err(JwtError::WrongIssuer)
+        );
+        assert_eq!(
+            check(with("aud", json!(["x"]))),
+            Err(JwtError::WrongAudience)
+        );
+        assert_eq!(
+            check(json!({"iss": "login", "aud": "shop"})),
+            Err(JwtError::MissingClaim("exp"))
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config: JwtConfig = toml::from_str(
+            "secret = { env = \"JWT_SECRET\" }\naudience = \"shop\"\npaths = [\"api\"]",
+        )
+        .unwrap();
+        assert!(config.enabled());
+        assert_eq!(config.leeway_secs, DEFAULT_LEEWAY_SECS);
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(!JwtConfig::default().enabled());
+    }
+}
@@ -0,0 +1,458 @@
+// 按路由统计延迟分位数：统计最近 window_secs 秒的滑动窗口，给出 p50/p95/p99 和直方图，
+// 本机可以通过 GET /_admin/latency 查看
+// 窗口由 SLOTS 个子窗口组成一个环，时间每走过一个子窗口就清掉最旧的那个，分位数不会在边界上突然归零
+// 每个子窗口是一个对数分桶的直方图，内存只和桶数有关，不随流量增长
+// 配置了 SLO 时，每个子窗口结束时检查一次，某条路由连续 N 个窗口长度都超过阈值就打一条警告日志
+// 延迟是排队时间加处理时间，也就是客户端实际等待的时间；只统计有名字的路由
+use http::clock::Clock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_WINDOW_SECS: u64 = 60;
+// 一个窗口分成几个子窗口，子窗口长度是 window_secs / SLOTS 向上取整
+const SLOTS: u64 = 6;
+// 每个 2 的幂区间再等分成这么多个桶，分位数的相对误差不超过 1 / SUB_BUCKETS
+const SUB_BUCKETS: u64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Percentile {
+    P50,
+    P95,
+    P99,
+}
+
+impl fmt::Display for Percentile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Percentile::P50 => "p50",
+            Percentile::P95 => "p95",
+            Percentile::P99 => "p99",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// [[latency.slo]]
+// route = "orders"
+// percentile = "p99"
+// threshold_ms = 200
+// windows = 3
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SloRule {
+    pub route: String,
+    pub percentile: Percentile,
+    pub threshold_ms: f64,
+    // 连续超过阈值多少个窗口长度才报警，避免偶发的慢请求刷屏
+    #[serde(default = "default_slo_windows")]
+    pub windows: u32,
+}
+
+fn default_slo_windows() -> u32 {
+    1
+}
+
+// [latency]
+// enabled = true
+// window_secs = 60
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatencyConfig {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub slo: Vec<SloRule>,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            enabled: false,
+            window_secs: DEFAULT_WINDOW_SECS,
+            slo: Vec::new(),
+        }
+    }
+}
+
+impl LatencyConfig {
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if self.window_secs == 0 {
+            problems.push("latency: window_secs must be positive".to_string());
+        }
+        if !self.enabled && !self.slo.is_empty() {
+            problems.push("latency: slo requires enabled = true".to_string());
+        }
+        for rule in &self.slo {
+            if rule.threshold_ms.is_nan() || rule.threshold_ms <= 0.0 {
+                problems.push(format!(
+                    "latency.slo.{}: threshold_ms must be positive",
+                    rule.route
+                ));
+            }
+            if rule.windows == 0 {
+                problems.push(format!(
+                    "latency.slo.{}: windows must be positive",
+                    rule.route
+                ));
+            }
+        }
+    }
+}
+
+// 滑动窗口内的分位数和直方图，单位毫秒
+// 分位数是所在桶的上界，比实际值大，最多大 1 / SUB_BUCKETS
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    // 累计直方图，和 Prometheus 的 le 一样：延迟不超过 le_ms 的请求数；只列出有请求的桶
+    pub histogram: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub le_ms: f64,
+    pub count: u64,
+}
+
+impl Percentiles {
+    fn get(&self, p: Percentile) -> f64 {
+        match p {
+            Percentile::P50 => self.p50_ms,
+            Percentile::P95 => self.p95_ms,
+            Percentile::P99 => self.p99_ms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencySnapshot {
+    pub window_secs: u64,
+    // 最近 window_secs 秒内有请求的路由
+    pub routes: BTreeMap<String, Percentiles>,
+}
+
+// 微秒值所在的桶：小于 SUB_BUCKETS 的值一个值一个桶，更大的按 2 的幂分段，每段 SUB_BUCKETS 个桶
+fn bucket_of(us: u64) -> u32 {
+    if us < SUB_BUCKETS {
+        return us as u32;
+    }
+    let shift = us.ilog2() - SUB_BUCKETS.ilog2();
+    let sub = (us >> shift) - SUB_BUCKETS;
+    (shift + 1) * SUB_BUCKETS as u32 + sub as u32
+}
+
+// 桶里最大的微秒值
+fn bucket_upper(bucket: u32) -> u64 {
+    let sub_buckets = SUB_BUCKETS as u32;
+    if bucket < sub_buckets {
+        return bucket as u64;
+    }
+    let shift = bucket / sub_buckets - 1;
+    let sub = (bucket % sub_buckets) as u128;
+    // 最后一个桶的上界是 u64::MAX，算的时候会溢出
+    ((((SUB_BUCKETS as u128 + sub + 1) << shift) - 1).min(u64::MAX as u128)) as u64
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    // 桶 -> 请求数，只存有请求的桶
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+}
+
+impl Histogram {
+    fn add(&mut self, us: u64) {
+        *self.buckets.entry(bucket_of(us)).or_default() += 1;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (bucket, n) in &other.buckets {
+            *self.buckets.entry(*bucket).or_default() += n;
+        }
+        self.count += other.count;
+    }
+
+    // nearest-rank：第 ceil(p * n) 个请求所在的桶
+    fn percentile(&self, p: f64) -> f64 {
+        let rank = ((p * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (bucket, n) in &self.buckets {
+            seen += n;
+            if seen >= rank {
+                return bucket_upper(*bucket) as f64 / 1000.0;
+            }
+        }
+        0.0
+    }
+
+    // 没有请求时返回 None
+    fn percentiles(&self) -> Option<Percentiles> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut seen = 0;
+        let histogram = self
+            .buckets
+            .iter()
+            .map(|(bucket, n)| {
+                seen += n;
+                HistogramBucket {
+                    le_ms: bucket_upper(*bucket) as f64 / 1000.0,
+                    count: seen,
+                }
+            })
+            .collect();
+        Some(Percentiles {
+            count: self.count,
+            p50_ms: self.percentile(0.50),
+            p95_ms: self.percentile(0.95),
+            p99_ms: self.percentile(0.99),
+            histogram,
+        })
+    }
+}
+
+struct State {
+    // 当前子窗口的编号，unix 秒数 / 子窗口长度
+    slot: u64,
+    // 每条路由一个环，下标是子窗口编号 % SLOTS
+    rings: HashMap<String, Vec<Histogram>>,
+    // 和 slo 一一对应：连续超过阈值的子窗口数
+    streaks: Vec<u64>,
+}
+
+impl State {
+    // 整个滑动窗口合在一起的直方图
+    fn window(&self, route: &str) -> Histogram {
+        let mut total = Histogram::default();
+        for slot in self.rings.get(route).into_iter().flatten() {
+            total.merge(slot);
+        }
+        total
+    }
+}
+
+pub struct Latency {
+    config: LatencyConfig,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+impl Latency {
+    pub fn new(config: LatencyConfig, clock: Arc<dyn Clock>) -> Self {
+        let state = State {
+            slot: clock.unix_secs() / slot_secs(&config),
+            rings: HashMap::new(),
+            streaks: vec![0; config.slo.len()],
+        };
+        Latency {
+            config,
+            clock,
+            state: Mutex::new(state),
+        }
+    }
+
+    // SLO 里引用的路由名，启动时检查它们是否存在
+    pub fn routes(&self) -> impl Iterator<Item = &str> {
+        self.config.slo.iter().map(|r| r.route.as_str())
+    }
+
+    pub fn record(&self, route: &str, latency: Duration) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for warning in self.rotate(&mut state) {
+            eprintln!("{}", warning);
+        }
+        let at = (state.slot % SLOTS) as usize;
+        let ring = state
+            .rings
+            .entry(route.to_string())
+            .or_insert_with(|| vec![Histogram::default(); SLOTS as usize]);
+        ring[at].add(us);
+    }
+
+    pub fn snapshot(&self) -> LatencySnapshot {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for warning in self.rotate(&mut state) {
+            eprintln!("{}", warning);
+        }
+        let routes = state
+            .rings
+            .keys()
+            .filter_map(|route| Some((route.clone(), state.window(route).percentiles()?)))
+            .collect();
+        LatencySnapshot {
+            window_secs: self.config.window_secs,
+            routes,
+        }
+    }
+
+    // 时间走过了子窗口：每走过一个，先按当时的滑动窗口检查 SLO，再清掉最旧的子窗口
+    // 返回需要打出的 SLO 警告
+    fn rotate(&self, state: &mut State) -> Vec<String> {
+        let now = self.clock.unix_secs() / slot_secs(&self.config);
+        let mut warnings = Vec::new();
+        // 超过一整圈之后所有子窗口都已经清空，不用再一个个走
+        let steps = now.saturating_sub(state.slot).min(SLOTS + 1);
+        for step in 1..=steps {
+            self.check_slo(state, &mut warnings);
+            let at = ((state.slot + step) % SLOTS) as usize;
+            for ring in state.rings.values_mut() {
+                ring[at] = Histogram::default();
+            }
+        }
+        state.slot = state.slot.max(now);
+        warnings
+    }
+
+    fn check_slo(&self, state: &mut State, warnings: &mut Vec<String>) {
+        for (i, rule) in self.config.slo.iter().enumerate() {
+            let value = state
+                .window(&rule.route)
+                .percentiles()
+                .map(|p| p.get(rule.percentile));
+            let streak = &mut state.streaks[i];
+            match value {
+                Some(ms) if ms > rule.threshold_ms => *streak += 1,
+                _ => *streak = 0,
+            }
+            // SLOTS 个子窗口是一个窗口长度；刚达到 N 个窗口长度时报一次，之后每多一个窗口长度再报一次
+            let needed = rule.windows as u64 * SLOTS;
+            if *streak >= needed && (*streak - needed).is_multiple_of(SLOTS) {
+                warnings.push(format!(
+                    "SLO breached: {} {} {:.2}ms > {}ms for {} consecutive windows",
+                    rule.route,
+                    rule.percentile,
+                    value.unwrap_or_default(),
+                    rule.threshold_ms,
+                    *streak / SLOTS
+                ));
+            }
+        }
+    }
+}
+
+fn slot_secs(config: &LatencyConfig) -> u64 {
+    config.window_secs.div_ceil(SLOTS).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+
+    fn config(windows: u32) -> LatencyConfig {
+        LatencyConfig {
+            enabled: true,
+            window_secs: 60,
+            slo: vec![SloRule {
+                route: "orders".into(),
+                percentile: Percentile::P99,
+                threshold_ms: 50.0,
+                windows,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_buckets() {
+        for us in [
+            0,
+            1,
+            7,
+            8,
+            9,
+            15,
+            16,
+            1000,
+            50_000,
+            99_999,
+            u64::MAX / 2,
+            u64::MAX,
+        ] {
+            let bucket = bucket_of(us);
+            assert!(bucket_upper(bucket) >= us, "{}", us);
+            // 相对误差不超过 1 / SUB_BUCKETS
+            assert!(bucket_upper(bucket) - us <= us / SUB_BUCKETS, "{}", us);
+            if bucket > 0 {
+                assert!(bucket_upper(bucket - 1) < us, "{}", us);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sliding_percentiles() {
+        let clock = MockClock::from_unix_secs(600);
+        let latency = Latency::new(config(1), Arc::new(clock.clone()));
+        for ms in 1..=100 {
+            latency.record("orders", Duration::from_millis(ms));
+        }
+        let close = |got: f64, want: f64| {
+            assert!(got >= want && got - want <= want / 8.0, "{} {}", got, want)
+        };
+        let p = latency.snapshot().routes["orders"].clone();
+        assert_eq!(p.count, 100);
+        close(p.p50_ms, 50.0);
+        close(p.p95_ms, 95.0);
+        close(p.p99_ms, 99.0);
+        assert_eq!(p.histogram.last().unwrap().count, 100);
+        assert!(p.histogram.windows(2).all(|w| w[0].le_ms < w[1].le_ms));
+        // 窗口往前滑，旧的请求还在窗口里，和新的一起统计
+        clock.advance(Duration::from_secs(50));
+        latency.record("orders", Duration::from_millis(500));
+        assert_eq!(latency.snapshot().routes["orders"].count, 101);
+        // 再过一个子窗口，最早的那批滑出窗口
+        clock.advance(Duration::from_secs(10));
+        let p = latency.snapshot().routes["orders"].clone();
+        assert_eq!(p.count, 1);
+        close(p.p50_ms, 500.0);
+        clock.advance(Duration::from_secs(3600));
+        assert!(latency.snapshot().routes.is_empty());
+    }
+
+    #[test]
+    fn test_slo_consecutive_windows() {
+        let clock = MockClock::from_unix_secs(600);
+        let latency = Latency::new(config(2), Arc::new(clock.clone()));
+        // 每个子窗口一个请求，返回走过这个子窗口时的警告
+        let slot = |ms: u64| {
+            latency.record("orders", Duration::from_millis(ms));
+            clock.advance(Duration::from_secs(10));
+            let mut state = latency.state.lock().unwrap();
+            latency.rotate(&mut state)
+        };
+        // 两个窗口长度（12 个子窗口）都超标才报警
+        for _ in 0..11 {
+            assert!(slot(80).is_empty());
+        }
+        let warnings = slot(80);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("orders p99 8"), "{}", warnings[0]);
+        assert!(warnings[0].contains("> 50ms for 2 consecutive windows"));
+        // 慢请求滑出窗口之前 p99 仍然超标
+        for _ in 0..5 {
+            assert!(slot(10).is_empty());
+        }
+        // 恢复正常后重新计数
+        assert!(slot(10).is_empty());
+        for _ in 0..11 {
+            assert!(slot(80).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut problems = Vec::new();
+        let mut c = config(0);
+        c.enabled = false;
+        c.validate(&mut problems);
+        assert_eq!(problems.len(), 2);
+    }
+}
@@ -0,0 +1,64 @@
+// 让 #[route] 生成的 ::httperver::... 路径在本 crate 内也能解析
+extern crate self as httperver;
+
+pub use httperver_macros::{register_routes, route};
+
+pub mod accesslog;
+pub mod assets;
+#[cfg(feature = "async-server")]
+pub mod asyncserver;
+pub mod auth;
+pub mod body;
+pub mod bots;
+pub mod breaker;
+pub mod chaos;
+pub mod config;
+pub mod connlimit;
+pub mod content;
+#[cfg(unix)]
+pub mod daemon;
+pub mod deprecation;
+#[cfg(feature = "dev-cert")]
+pub mod devcert;
+pub mod disposition;
+pub mod docs;
+pub mod fastpath;
+pub mod fds;
+pub mod geoip;
+pub mod handler;
+pub mod hints;
+pub mod ipc;
+pub mod jwt;
+pub mod latency;
+pub mod listener;
+pub mod memory;
+pub mod metrics;
+pub mod middleware;
+pub mod minify;
+pub mod mirror;
+pub mod neterror;
+pub mod orders;
+pub mod otel;
+pub mod priority;
+pub mod prometheus;
+pub mod ratelimit;
+pub mod record;
+pub mod router;
+pub mod secret;
+pub mod server;
+pub mod session;
+pub mod shutdown;
+pub mod singleflight;
+pub mod state;
+pub mod tenant;
+#[cfg(test)]
+mod testdir;
+pub mod throttle;
+pub mod thumb;
+pub mod timewindow;
+pub mod tls;
+#[cfg(unix)]
+pub mod upgrade;
+pub mod uploads;
+pub mod upstream;
+pub mod versions;
@@ -0,0 +1,112 @@
+// 监听套接字的获取：优先使用 systemd 传入的 fd，否则自己 bind
+use std::env;
+use std::io;
+use std::net::TcpListener;
+
+// sd_listen_fds(3)：传入的 fd 从 3 开始连续编号
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+// 读取 LISTEN_PID / LISTEN_FDS，返回 systemd 预先 bind 好的监听套接字
+// 读取后清除环境变量，避免被子进程继承
+#[cfg(unix)]
+pub fn activated_listeners() -> Vec<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let fds = listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    fds.filter(|&fd| is_socket(fd))
+        .map(|fd| {
+            // SAFETY: fd 由 systemd 传入且已确认是套接字，所有权交给 TcpListener
+            unsafe {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                TcpListener::from_raw_fd(fd)
+            }
+        })
+        .collect()
+}
+
+// 传给这个进程的 fd 编号；LISTEN_PID 不是自己（变量是父进程留下的）、
+// 缺少变量或者值不是数字时为空
+#[cfg(unix)]
+fn listen_fds(pid: Option<&str>, count: Option<&str>, own_pid: u32) -> std::ops::Range<i32> {
+    let pid_matches = pid.and_then(|p| p.parse::<u32>().ok()) == Some(own_pid);
+    let count = count.and_then(|n| n.parse::<i32>().ok()).unwrap_or(0);
+    if !pid_matches || count <= 0 {
+        return SD_LISTEN_FDS_START..SD_LISTEN_FDS_START;
+    }
+    SD_LISTEN_FDS_START..SD_LISTEN_FDS_START.saturating_add(count)
+}
+
+#[cfg(not(unix))]
+pub fn activated_listeners() -> Vec<TcpListener> {
+    Vec::new()
+}
+
+#[cfg(unix)]
+pub(crate) fn is_socket(fd: i32) -> bool {
+    // SAFETY: stat 是纯输出参数，fstat 失败时不会读取它
+    unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        libc::fstat(fd, &mut stat) == 0 && (stat.st_mode & libc::S_IFMT) == libc::S_IFSOCK
+    }
+}
+
+// 等待监听套接字可读（有新连接），超时返回 false
+// 让 accept 循环可以定期检查升级等标志，而不是永远阻塞在 accept 上
+#[cfg(unix)]
+pub fn wait_readable(listener: &TcpListener, timeout: std::time::Duration) -> bool {
+    use std::os::unix::io::AsRawFd;
+    let mut fds = libc::pollfd {
+        fd: listener.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: fds 在调用期间一直有效
+    unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+#[cfg(not(unix))]
+pub fn wait_readable(_listener: &TcpListener, _timeout: std::time::Duration) -> bool {
+    true
+}
+
+// 优先级：升级时旧进程交过来的 fd > systemd socket 激活 > 自己 bind addr
+pub fn bind(addr: &str) -> io::Result<TcpListener> {
+    #[cfg(unix)]
+    if let Some(listener) = crate::upgrade::inherited_listener() {
+        println!("Using listener inherited from previous process");
+        return Ok(listener);
+    }
+    match activated_listeners().into_iter().next() {
+        Some(listener) => {
+            println!("Using socket-activated listener");
+            Ok(listener)
+        }
+        None => TcpListener::bind(addr),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 3..5);
+        // 变量是给别的进程的
+        assert!(listen_fds(Some("41"), Some("2"), 42).is_empty());
+        assert!(listen_fds(Some("42"), Some("0"), 42).is_empty());
+        assert!(listen_fds(Some("42"), Some("-1"), 42).is_empty());
+        assert!(listen_fds(Some("x"), Some("2"), 42).is_empty());
+        assert!(listen_fds(Some("42"), Some("two"), 42).is_empty());
+        assert!(listen_fds(None, Some("2"), 42).is_empty());
+        assert!(listen_fds(Some("42"), None, 42).is_empty());
+    }
+}
@@ -1,8 +1,541 @@
-mod handler;
-mod router;
-mod server;
-use server::Server;
-fn main() {
-    let server = Server::new("localhost:3000");
-    server.run();
+use clap::{Parser, Subcommand};
+use http::clock::SystemClock;
+use http::proxy::TrustedProxies;
+use httperver::bots::BotGuard;
+use httperver::config::{self, Config};
+use httperver::connlimit::ConnLimiter;
+#[cfg(unix)]
+use httperver::daemon;
+use httperver::latency::Latency;
+use httperver::memory::MemoryGuard;
+use httperver::record;
+use httperver::router::Router;
+use httperver::server::{KeepAlive, Server};
+use httperver::throttle::Throttle;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "memory-guard")]
+#[global_allocator]
+static GLOBAL: httperver::memory::TrackingAllocator = httperver::memory::TrackingAllocator;
+
+#[derive(Parser)]
+#[command(name = "httperver", version, about = "A tiny HTTP server")]
+struct Cli {
+    // global = true 让这些参数可以写在子命令前后任意位置
+    /// Path to the TOML config file (defaults to ./httperver.toml if present)
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+    /// Overlay <config stem>.<PROFILE>.toml on top of the config file
+    /// (defaults to $HTTPERVER_PROFILE).
+    /// Precedence: defaults < config file < profile < HTTPERVER_* env vars < flags
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Override the listen address, e.g. 0.0.0.0:8080
+    #[arg(long, global = true)]
+    addr: Option<String>,
+    /// Also listen on a unix socket path (or a named pipe on Windows)
+    #[arg(long, global = true)]
+    ipc: Option<String>,
+    /// Number of worker threads reading and handling requests
+    #[arg(long, global = true)]
+    workers: Option<usize>,
+    /// Override the static files directory
+    #[arg(long, global = true)]
+    public_path: Option<String>,
+    /// Override the data directory
+    #[arg(long, global = true)]
+    data_path: Option<String>,
+    /// Print one access log line per request (Common Log Format on stdout)
+    #[arg(long, global = true)]
+    access_log: bool,
+    /// Fork into the background (unix only)
+    #[arg(long, global = true)]
+    daemon: bool,
+    /// Write the server pid to this file; also used by stop/reload
+    #[arg(long, global = true)]
+    pid_file: Option<String>,
+    /// Redirect stdout/stderr to this file (reopened on SIGHUP)
+    #[arg(long, global = true)]
+    log_file: Option<String>,
+    /// Record sanitized request/response pairs into this directory
+    #[arg(long, global = true)]
+    record_dir: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Start the server (default)
+    Serve,
+    /// Load and validate the config, then exit
+    CheckConfig {
+        /// Print the merged config from all layers as TOML
+        #[arg(long)]
+        print_effective: bool,
+    },
+    /// Print the routing table
+    Routes,
+    /// Export the routing table as an OpenAPI JSON document
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Stop the instance recorded in the pid file
+    Stop,
+    /// Ask the running instance to reopen its log file
+    Reload,
+    /// Replace the running instance with a freshly started binary
+    Upgrade,
+    /// Generate a self-signed certificate and key for local HTTPS testing
+    DevCert {
+        /// Directory to write cert.pem and key.pem into
+        #[arg(short, long, default_value = ".")]
+        out: PathBuf,
+        /// Host name or IP the certificate is valid for; repeatable
+        /// (defaults to localhost, 127.0.0.1 and ::1)
+        #[arg(long = "host")]
+        hosts: Vec<String>,
+    },
+    /// Feed recorded requests back through the router and compare responses
+    Replay {
+        /// A recorded exchange file or a record directory
+        path: PathBuf,
+    },
+}
+
+impl Cli {
+    // 命令行参数优先级高于配置文件
+    fn load_config(&self) -> Result<Config, config::ConfigError> {
+        let profile = self
+            .profile
+            .clone()
+            .or_else(|| env::var(config::PROFILE_ENV).ok());
+        let mut config = Config::load(self.config.as_deref(), profile.as_deref())?;
+        if let Some(addr) = &self.addr {
+            config.addr = addr.clone();
+        }
+        if let Some(ipc) = &self.ipc {
+            config.ipc = Some(ipc.clone());
+        }
+        if let Some(workers) = self.workers {
+            config.workers = workers;
+        }
+        if let Some(p) = &self.public_path {
+            config.public_path = p.clone();
+        }
+        if let Some(p) = &self.data_path {
+            config.data_path = p.clone();
+        }
+        if self.access_log {
+            config.access_log = true;
+        }
+        if self.daemon {
+            config.daemon = true;
+        }
+        if let Some(p) = &self.pid_file {
+            config.pid_file = Some(p.clone());
+        }
+        if let Some(p) = &self.log_file {
+            config.log_file = Some(p.clone());
+        }
+        if let Some(p) = &self.record_dir {
+            config.record_dir = Some(p.clone());
+        }
+        Ok(config)
+    }
+}
+
+// OpenAPI 的路径参数写作 {name}
+fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(
+            |seg| match seg.strip_prefix(':').or_else(|| seg.strip_prefix('*')) {
+                Some(p) if !p.is_empty() => format!("{{{}}}", p),
+                _ => seg.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// 子应用的路由带上挂载前缀，operationId 也加上前缀避免重名
+fn collect_paths(
+    router: &Router,
+    prefix: &str,
+    paths: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    let prefix = format!("{}{}", prefix, router.base_path());
+    for r in router.routes().iter().filter(|r| r.method != "*") {
+        let entry = paths
+            .entry(format!("{}{}", prefix, openapi_path(r.path)))
+            .or_insert_with(|| serde_json::json!({}));
+        let id = format!("{}{}", prefix, r.name.unwrap_or(r.handler));
+        let mut operation = serde_json::json!({
+            "operationId": id,
+            "responses": { "200": { "description": "OK" } },
+        });
+        // 和 GET /docs 页面用同一份说明
+        if !r.doc.title.is_empty() {
+            operation["summary"] = r.doc.title.into();
+        }
+        if !r.doc.description.is_empty() {
+            operation["description"] = r.doc.description.into();
+        }
+        entry[r.method.to_lowercase()] = operation;
+    }
+    for (path, versions) in router.versions() {
+        for (version, sub) in versions.routers() {
+            collect_paths(sub, &format!("{}{}/v{}", prefix, path, version), paths);
+        }
+    }
+    for (mount, sub) in router.mounts() {
+        collect_paths(sub, &format!("{}{}", prefix, mount), paths);
+    }
+}
+
+fn export_openapi(router: &Router) -> serde_json::Value {
+    let mut paths = serde_json::Map::new();
+    collect_paths(router, "", &mut paths);
+    serde_json::json!({
+        "openapi": "3.0.3",
+        "info": { "title": "httperver", "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+    })
+}
+
+fn build_server(config: &Config) -> Result<Server<'_>, String> {
+    let router = config.router().map_err(|e| e.to_string())?;
+    let throttle = Throttle::new(&config.bandwidth, Arc::new(SystemClock));
+    for name in throttle.routes() {
+        if !router.routes().iter().any(|r| r.name == Some(name)) {
+            return Err(format!("bandwidth.routes: unknown route {:?}", name));
+        }
+    }
+    for name in config.time_windows.iter().flat_map(|w| &w.routes) {
+        if !router.routes().iter().any(|r| r.name == Some(name)) {
+            return Err(format!("time_windows.routes: unknown route {:?}", name));
+        }
+    }
+    let latency = Latency::new(config.latency.clone(), Arc::new(SystemClock));
+    for name in latency.routes() {
+        if !router.routes().iter().any(|r| r.name == Some(name)) {
+            return Err(format!("latency.slo.route: unknown route {:?}", name));
+        }
+    }
+    let mut server = Server::new(&config.addr)
+        .router(router)
+        .bandwidth(throttle)
+        .queue_capacity(config.queue_capacity)
+        .workers(config.workers)
+        .handle_signals(true)
+        .drain_timeout(Duration::from_secs(config.drain_timeout_secs))
+        .keep_alive(KeepAlive {
+            max_requests: config.keep_alive_max_requests,
+            idle_timeout: Duration::from_secs(config.keep_alive_timeout_secs),
+        })
+        .timeouts(config.timeouts())
+        .limits(config.limits());
+    if let Some(ipc) = &config.ipc {
+        server = server.ipc(ipc.clone());
+    }
+    if let Some(addr) = &config.tls.addr {
+        let tls = config.tls.load().map_err(|e| format!("tls: {}", e))?;
+        server = server.tls(addr.clone(), tls);
+    }
+    if config.chaos.enabled {
+        println!("Chaos mode enabled: {:?}", config.chaos);
+    }
+    let proxies = TrustedProxies::parse(&config.trusted_proxies).map_err(|e| e.to_string())?;
+    server = server.trusted_proxies(proxies);
+    if config.latency.enabled {
+        server = server.latency(latency);
+    }
+    if !config.bots.is_empty() {
+        server = server.bots(BotGuard::new(config.bots.clone(), Arc::new(SystemClock)));
+    }
+    #[cfg(feature = "geoip")]
+    if config.geoip.enabled() {
+        let geoip = httperver::geoip::GeoIp::open(&config.geoip)
+            .map_err(|e| format!("cannot open geoip database: {}", e))?;
+        server = server.geoip(geoip);
+    }
+    #[cfg(not(feature = "geoip"))]
+    if config.geoip.enabled() {
+        return Err("geoip requires building with --features geoip".into());
+    }
+    #[cfg(feature = "otel")]
+    if config.otel.enabled() {
+        let telemetry = httperver::otel::Telemetry::new(
+            config.otel.clone(),
+            Arc::new(SystemClock),
+            Arc::new(http::random::OsRandom),
+        );
+        server = server.telemetry(telemetry);
+    }
+    #[cfg(not(feature = "otel"))]
+    if config.otel.enabled() {
+        return Err("otel requires building with --features otel".into());
+    }
+    if let Some(limiter) =
+        ConnLimiter::from_config(&config.conn_limit).map_err(|e| e.to_string())?
+    {
+        server = server.conn_limit(limiter);
+    }
+    if let Some(mb) = config.memory_high_water_mb {
+        server = server.memory_guard(MemoryGuard::new(mb as usize * 1024 * 1024));
+    }
+    if let Some(dir) = &config.record_dir {
+        let recorder = record::Recorder::new(dir, Arc::new(SystemClock))
+            .map_err(|e| format!("cannot record to {}: {}", dir, e))?;
+        server = server.recorder(recorder);
+    }
+    Ok(server)
+}
+
+fn replay(config: &Config, path: &Path) -> Result<(), String> {
+    let router = config.router().map_err(|e| e.to_string())?;
+    let results = record::replay(&router, path)
+        .map_err(|e| format!("cannot replay {}: {}", path.display(), e))?;
+    let mut mismatches = 0;
+    for r in &results {
+        if r.matches() {
+            println!("ok       {}", r.path.display());
+        } else {
+            mismatches += 1;
+            println!("mismatch {}", r.path.display());
+            println!("--- recorded\n{}\n--- actual\n{}", r.recorded, r.actual);
+        }
+    }
+    println!("{} replayed, {} mismatched", results.len(), mismatches);
+    if mismatches > 0 {
+        return Err("replay produced different responses".into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve(config: &Config) -> Result<(), String> {
+    if config.daemon {
+        if config.log_file.is_none() {
+            return Err("--daemon requires --log-file".into());
+        }
+        daemon::daemonize().map_err(|e| format!("cannot daemonize: {}", e))?;
+    }
+    if let Some(log) = &config.log_file {
+        daemon::redirect_output(log).map_err(|e| format!("cannot open log {}: {}", log, e))?;
+    }
+    if let Some(pid) = &config.pid_file {
+        daemon::write_pid_file(pid).map_err(|e| format!("cannot write pid {}: {}", pid, e))?;
+    }
+    daemon::install_reload_handler(config.log_file.clone());
+    config.apply_env();
+    if config.async_io {
+        return serve_async(config);
+    }
+    build_server(config)?.run().map_err(|e| e.to_string())
+}
+
+#[cfg(not(unix))]
+fn serve(config: &Config) -> Result<(), String> {
+    if config.daemon || config.pid_file.is_some() || config.log_file.is_some() {
+        return Err("--daemon, --pid-file and --log-file are only supported on unix".into());
+    }
+    config.apply_env();
+    if config.async_io {
+        return serve_async(config);
+    }
+    build_server(config)?.run().map_err(|e| e.to_string())
+}
+
+// validate 已经检查过只用到了异步服务器支持的配置
+#[cfg(feature = "async-server")]
+fn serve_async(config: &Config) -> Result<(), String> {
+    let router = config.router().map_err(|e| e.to_string())?;
+    let proxies = TrustedProxies::parse(&config.trusted_proxies).map_err(|e| e.to_string())?;
+    httperver::asyncserver::AsyncServer::new(&config.addr)
+        .router(router)
+        .workers(config.workers)
+        .trusted_proxies(proxies)
+        .handle_signals(true)
+        .drain_timeout(Duration::from_secs(config.drain_timeout_secs))
+        .keep_alive(KeepAlive {
+            max_requests: config.keep_alive_max_requests,
+            idle_timeout: Duration::from_secs(config.keep_alive_timeout_secs),
+        })
+        .timeouts(config.timeouts())
+        .limits(config.limits())
+        .run_blocking()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "async-server"))]
+fn serve_async(_config: &Config) -> Result<(), String> {
+    Err("async_io requires building with --features async-server".into())
+}
+
+#[cfg(unix)]
+fn control(config: &Config, command: &Command) -> Result<(), String> {
+    let pid_file = config
+        .pid_file
+        .as_deref()
+        .ok_or("no pid file configured, pass --pid-file")?;
+    match command {
+        Command::Stop => {
+            // 服务器先等排队的请求处理完才退出
+            let wait = std::time::Duration::from_secs(config.drain_timeout_secs + 5);
+            let pid =
+                daemon::stop(pid_file, wait).map_err(|e| format!("cannot stop server: {}", e))?;
+            println!("stopped {}", pid);
+        }
+        Command::Reload => {
+            let pid =
+                daemon::reload(pid_file).map_err(|e| format!("cannot reload server: {}", e))?;
+            println!("sent reload to {}", pid);
+        }
+        _ => {
+            let pid =
+                daemon::upgrade(pid_file).map_err(|e| format!("cannot upgrade server: {}", e))?;
+            println!("sent upgrade to {}", pid);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn control(_config: &Config, _command: &Command) -> Result<(), String> {
+    Err("stop/reload/upgrade are only supported on unix".into())
+}
+
+#[cfg(feature = "dev-cert")]
+fn dev_cert(out: &Path, hosts: Vec<String>) -> Result<(), String> {
+    use httperver::devcert;
+    let hosts = if hosts.is_empty() {
+        devcert::DEFAULT_HOSTS
+            .iter()
+            .map(|h| h.to_string())
+            .collect()
+    } else {
+        hosts
+    };
+    let cert = devcert::generate(&hosts).map_err(|e| format!("cannot generate: {}", e))?;
+    let (cert_path, key_path) = cert
+        .write(out)
+        .map_err(|e| format!("cannot write to {}: {}", out.display(), e))?;
+    println!("certificate: {}", cert_path.display());
+    println!("private key: {}", key_path.display());
+    println!("valid for: {}", hosts.join(", "));
+    Ok(())
+}
+
+#[cfg(not(feature = "dev-cert"))]
+fn dev_cert(_out: &Path, _hosts: Vec<String>) -> Result<(), String> {
+    Err("dev-cert requires building with --features dev-cert".into())
+}
+
+fn print_routes(router: &Router, prefix: &str) {
+    let prefix = format!("{}{}", prefix, router.base_path());
+    for r in router.routes() {
+        let path = format!("{}{}", prefix, r.path);
+        println!(
+            "{:<6} {:<28} {:<20} {}",
+            r.method,
+            path,
+            r.handler,
+            r.name.unwrap_or("-")
+        );
+    }
+    for (mount, sub) in router.mounts() {
+        print_routes(sub, &format!("{}{}", prefix, mount));
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match cli.load_config() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let router = || match config.router() {
+        Ok(r) => Some(r),
+        Err(e) => {
+            eprint!("{}", e);
+            None
+        }
+    };
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            // 启动前把配置问题一次报告完，而不是运行到一半才失败
+            if let Err(e) = config.validate() {
+                eprint!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            if let Err(e) = serve(&config) {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        cmd @ (Command::Stop | Command::Reload | Command::Upgrade) => {
+            if let Err(e) = control(&config, &cmd) {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::CheckConfig { print_effective } => {
+            if print_effective {
+                print!("{}", config.to_toml());
+            }
+            match config.validate() {
+                Ok(()) => println!("config ok"),
+                Err(e) => {
+                    eprint!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Command::Replay { path } => {
+            config.apply_env();
+            if let Err(e) = replay(&config, &path) {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::DevCert { out, hosts } => {
+            if let Err(e) = dev_cert(&out, hosts) {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        Command::Routes => {
+            let Some(router) = router() else {
+                return ExitCode::FAILURE;
+            };
+            print_routes(&router, "");
+        }
+        Command::Export { output } => {
+            let Some(router) = router() else {
+                return ExitCode::FAILURE;
+            };
+            let doc = serde_json::to_string_pretty(&export_openapi(&router)).unwrap();
+            match output {
+                Some(path) => {
+                    if let Err(e) = fs::write(&path, doc) {
+                        eprintln!("error: cannot write {}: {}", path.display(), e);
+                        return ExitCode::FAILURE;
+                    }
+                }
+                None => println!("{}", doc),
+            }
+        }
+    }
+    ExitCode::SUCCESS
 }
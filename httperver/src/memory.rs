@@ -0,0 +1,145 @@
+// 内存护栏：可选的全局分配器包装，统计当前使用的堆内存，
+// 超过配置的高水位后服务器拒绝新连接（503），已有的长连接回复完当前请求后关闭、空闲的直接关闭，
+// 把缓冲区还回去，避免被 OOM killer 杀掉
+// 需要 memory-guard feature，二进制里才会安装这个分配器
+use serde::Serialize;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+// 在 System 分配器外面记账，额外开销只有两次原子操作
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            add(layout.size());
+        }
+        ptr
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            add(layout.size());
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+            add(new_size);
+        }
+        new
+    }
+}
+
+fn add(size: usize) {
+    let now = IN_USE.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+// 没有安装 TrackingAllocator 时两个值都是 0
+pub fn in_use() -> usize {
+    IN_USE.load(Ordering::Relaxed)
+}
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MemoryStats {
+    pub in_use: usize,
+    pub peak: usize,
+    pub high_water: usize,
+    pub rejected: usize,
+    // 因为内存紧张关闭的已有连接
+    pub closed: usize,
+}
+
+pub struct MemoryGuard {
+    high_water: usize,
+    rejected: AtomicUsize,
+    closed: AtomicUsize,
+}
+
+impl MemoryGuard {
+    pub fn new(high_water: usize) -> Self {
+        MemoryGuard {
+            high_water,
+            rejected: AtomicUsize::new(0),
+            closed: AtomicUsize::new(0),
+        }
+    }
+
+    // 新连接进来时调用，超过高水位返回 false 并计数
+    pub fn admit(&self) -> bool {
+        self.admit_at(in_use())
+    }
+
+    fn admit_at(&self, in_use: usize) -> bool {
+        if in_use > self.high_water {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    // 已有的连接处理完一个请求或空闲时调用，超过高水位返回 true 并计数，调用方关闭连接
+    pub fn should_close(&self) -> bool {
+        self.should_close_at(in_use())
+    }
+
+    fn should_close_at(&self, in_use: usize) -> bool {
+        if in_use > self.high_water {
+            self.closed.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            in_use: in_use(),
+            peak: peak(),
+            high_water: self.high_water,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_tracking_allocator() {
+        // 测试二进制没有安装这个分配器，计数只来自这里的直接调用
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        let before = in_use();
+        unsafe {
+            let ptr = TrackingAllocator.alloc(layout);
+            assert_eq!(in_use(), before + 4096);
+            let ptr = TrackingAllocator.realloc(ptr, layout, 8192);
+            assert_eq!(in_use(), before + 8192);
+            TrackingAllocator.dealloc(ptr, Layout::from_size_align(8192, 8).unwrap());
+        }
+        assert_eq!(in_use(), before);
+        assert!(peak() >= 8192);
+
+        let guard = MemoryGuard::new(1000);
+        assert!(guard.admit_at(1000));
+        assert!(!guard.admit_at(1001));
+        assert_eq!(guard.stats().rejected, 1);
+        assert!(!guard.should_close_at(1000));
+        assert!(guard.should_close_at(1001));
+        assert_eq!(guard.stats().closed, 1);
+    }
+}
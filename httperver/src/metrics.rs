@@ -0,0 +1,104 @@
+// 请求耗时统计：排队时间（accept 到处理器开始）和处理时间分开记录，
+// 排队时间长说明工作线程不够，处理时间长说明处理器本身慢，加线程也没用
+// 本机可以通过 GET /_admin/metrics 查看
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Timer {
+    // 微秒
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Timer {
+    fn record(&self, d: Duration) {
+        let us = d.as_micros().min(u64::MAX as u128) as u64;
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    fn stats(&self, count: u64) -> TimerStats {
+        let total = self.total_us.load(Ordering::Relaxed);
+        TimerStats {
+            avg_ms: if count == 0 {
+                0.0
+            } else {
+                total as f64 / count as f64 / 1000.0
+            },
+            max_ms: self.max_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            total_ms: total as f64 / 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimerStats {
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub requests: u64,
+    pub queue_time: TimerStats,
+    pub handler_time: TimerStats,
+    // 下面两项由服务器填入，和耗时放在一起方便判断线程数是否合适
+    pub workers: usize,
+    pub queued: usize,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    queue: Timer,
+    handler: Timer,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn record(&self, queue: Duration, handler: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.queue.record(queue);
+        self.handler.record(handler);
+    }
+
+    pub fn snapshot(&self, workers: usize, queued: usize) -> MetricsSnapshot {
+        let requests = self.requests.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            requests,
+            queue_time: self.queue.stats(requests),
+            handler_time: self.handler.stats(requests),
+            workers,
+            queued,
+        }
+    }
+}
+
+// 请求的排队时间，服务器在交给路由之前放进 req.extensions，访问日志和处理时间记在同一行
+#[derive(Debug, Clone, Copy)]
+pub struct QueueTime(pub Duration);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.snapshot(4, 0).queue_time.avg_ms, 0.0);
+        metrics.record(Duration::from_millis(2), Duration::from_millis(10));
+        metrics.record(Duration::from_millis(4), Duration::from_millis(30));
+        let s = metrics.snapshot(4, 1);
+        assert_eq!(s.requests, 2);
+        assert_eq!(s.queue_time.avg_ms, 3.0);
+        assert_eq!(s.queue_time.max_ms, 4.0);
+        assert_eq!(s.handler_time.avg_ms, 20.0);
+        assert_eq!(s.handler_time.total_ms, 40.0);
+    }
+}
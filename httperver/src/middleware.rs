@@ -0,0 +1,15 @@
+// 中间件：在路由前后对所有请求统一做的事，例如日志、认证、CORS，不用改每个处理器
+// 按注册顺序执行 before，再按相反顺序执行 after，像洋葱一样一层包一层
+// before 返回响应时短路：后面的中间件和处理器都不再执行，
+// 已经执行过 before 的中间件（包括短路的这个）仍然会执行 after
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+
+pub trait Middleware: Send + Sync {
+    // 可以改写请求或往 req.extensions 里放数据，返回 Some 时直接用这个响应
+    fn before(&self, _req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        None
+    }
+    // 响应写出之前检查或改写，例如加头部
+    fn after(&self, _req: &HttpRequest, _resp: &mut HttpResponse<'static>) {}
+}
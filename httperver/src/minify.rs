@@ -0,0 +1,358 @@
+// 响应压缩（去空白和注释）：不需要前端构建工具，HTML / CSS / JS 在返回前就地精简
+// 静态文件的结果按修改时间缓存，文件没变就不会重复处理
+// 所有规则都偏保守，宁可少压一点也不能改变页面行为
+use crate::singleflight::SingleFlight;
+use http::headers::names;
+use http::httpresponse::HttpResponse;
+use http::status::StatusCode;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Html,
+    Css,
+    Js,
+}
+
+impl Kind {
+    // 按 Content-Type 判断，其他类型不处理
+    pub fn from_content_type(content_type: &str) -> Option<Kind> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime {
+            "text/html" => Some(Kind::Html),
+            "text/css" => Some(Kind::Css),
+            "text/javascript" | "application/javascript" => Some(Kind::Js),
+            _ => None,
+        }
+    }
+}
+
+pub fn minify(kind: Kind, src: &str) -> String {
+    match kind {
+        Kind::Html => minify_html(src),
+        Kind::Css => minify_css(src),
+        Kind::Js => minify_js(src),
+    }
+}
+
+#[derive(Default)]
+pub struct Minifier {
+    // 文件路径 -> (修改时间, 精简后的内容)
+    cache: Mutex<HashMap<PathBuf, (SystemTime, Arc<str>)>>,
+    flight: SingleFlight<(PathBuf, SystemTime), Arc<str>>,
+}
+
+impl Minifier {
+    pub fn new() -> Self {
+        Minifier::default()
+    }
+
+    // 只处理 200 的文本响应；source 是响应对应的静态文件，用于缓存
+    // 动态生成的页面（模板渲染）传 None，每次都重新精简
+    pub fn transform<'a>(&self, resp: HttpResponse<'a>, source: Option<&Path>) -> HttpResponse<'a> {
+        if resp.status() != StatusCode::Ok {
+            return resp;
+        }
+        let kind = match resp
+            .header(names::CONTENT_TYPE)
+            .and_then(Kind::from_content_type)
+        {
+            Some(k) => k,
+            None => return resp,
+        };
+        // 静态文件是文件 body，精简需要整个内容，读进来
+        let loaded;
+        let body = match (resp.body_text(), resp.file_path()) {
+            (Some(b), _) => b,
+            (None, Some(path)) => match fs::read_to_string(path) {
+                Ok(text) => {
+                    loaded = text;
+                    &loaded
+                }
+                Err(_) => return resp,
+            },
+            (None, None) => return resp,
+        };
+        let mtime = source.and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+        if let (Some(path), Some(mtime)) = (source, mtime) {
+            if let Some((cached_at, out)) = self.cache.lock().unwrap().get(path) {
+                if *cached_at == mtime {
+                    let out = out.to_string();
+                    return resp.with_body(out);
+                }
+            }
+            // 不持有缓存锁做精简；同一个文件的并发请求只精简一次
+            let (out, _) = self.flight.run((path.to_path_buf(), mtime), || {
+                Arc::from(minify(kind, body))
+            });
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), (mtime, out.clone()));
+            return resp.with_body(out.to_string());
+        }
+        let out = minify(kind, body);
+        resp.with_body(out)
+    }
+}
+
+// 内容需要原样保留的元素
+const RAW_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+// 去掉注释（保留 IE 条件注释），连续空白合并成一个（含换行时保留一个换行），
+// 引号内的属性值和 RAW_ELEMENTS 的内容不动
+pub fn minify_html(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") && !rest.starts_with("<!--[if") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+        if rest.starts_with('<') {
+            if let Some(tag) = raw_element(rest) {
+                let close = format!("</{}", tag);
+                let end = find_ignore_case(rest, &close)
+                    .and_then(|at| rest[at..].find('>').map(|gt| at + gt + 1))
+                    .unwrap_or(rest.len());
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            let end = tag_end(rest);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        collapse_whitespace(&rest[..text_end], &mut out);
+        rest = &rest[text_end..];
+    }
+    out.trim().to_string()
+}
+
+// <pre ...> / <script> 等需要原样保留的开始标签，返回小写的标签名
+fn raw_element(s: &str) -> Option<&'static str> {
+    RAW_ELEMENTS.into_iter().find(|tag| {
+        let n = tag.len() + 1;
+        s.len() > n
+            && s[1..n].eq_ignore_ascii_case(tag)
+            && matches!(s.as_bytes()[n], b'>' | b' ' | b'\t' | b'\n' | b'\r' | b'/')
+    })
+}
+
+// 标签结束位置（包含 >），跳过引号内的 >
+fn tag_end(s: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .to_ascii_lowercase()
+        .find(&needle.to_ascii_lowercase())
+}
+
+fn collapse_whitespace(text: &str, out: &mut String) {
+    let mut pending: Option<char> = None;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if c == '\n' || pending.is_none() {
+                pending = Some(if c == '\n' { '\n' } else { ' ' });
+            }
+        } else {
+            if let Some(ws) = pending.take() {
+                push_whitespace(out, ws);
+            }
+            out.push(c);
+        }
+    }
+    if let Some(ws) = pending {
+        push_whitespace(out, ws);
+    }
+}
+
+// 去掉注释后两边的空白会连在一起，这里再合并一次
+fn push_whitespace(out: &mut String, ws: char) {
+    match out.chars().last() {
+        Some(' ') if ws == '\n' => {
+            out.pop();
+            out.push('\n');
+        }
+        Some(' ' | '\n') => {}
+        _ => out.push(ws),
+    }
+}
+
+// 去掉注释和多余空白，{ } ; , > 两边的空白可以安全去掉；
+// 冒号前的空白要保留（a :hover 和 a:hover 含义不同）
+pub fn minify_css(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut chars = src.chars().peekable();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                pending_space = true;
+            }
+            '"' | '\'' => {
+                flush_space(&mut out, &mut pending_space);
+                out.push(c);
+                let mut escaped = false;
+                for s in chars.by_ref() {
+                    out.push(s);
+                    if escaped {
+                        escaped = false;
+                    } else if s == '\\' {
+                        escaped = true;
+                    } else if s == c {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => pending_space = true,
+            '{' | '}' | ';' | ',' | '>' => {
+                pending_space = false;
+                if c == '}' && out.ends_with(';') {
+                    out.pop();
+                }
+                out.push(c);
+                while chars.peek().is_some_and(|n| n.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            _ => {
+                flush_space(&mut out, &mut pending_space);
+                out.push(c);
+            }
+        }
+    }
+    out.trim().to_string()
+}
+
+fn flush_space(out: &mut String, pending: &mut bool) {
+    if *pending && !out.is_empty() && !out.ends_with(['{', '}', ';', ',', '>']) {
+        out.push(' ');
+    }
+    *pending = false;
+}
+
+// JS 的语法（正则字面量、自动分号插入）太复杂，这里只做逐行处理：
+// 去掉行首尾空白、空行和整行的 // 注释，换行全部保留；多行模板字符串内部不动
+pub fn minify_js(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut in_template = false;
+    for line in src.lines() {
+        let was_in_template = in_template;
+        if count_backticks(line) % 2 == 1 {
+            in_template = !in_template;
+        }
+        if was_in_template {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() || (trimmed.starts_with("//") && !in_template) {
+            continue;
+        }
+        if in_template {
+            // 模板字符串从这一行开始，行尾的空白属于字符串内容
+            out.push_str(line.trim_start());
+        } else {
+            out.push_str(trimmed);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn count_backticks(line: &str) -> usize {
+    let mut n = 0;
+    let mut escaped = false;
+    for c in line.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '`' {
+            n += 1;
+        }
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_minify_html() {
+        let src = "<html>\n  <!-- note -->\n  <body class=\"a  b\">\n    <p>Hello   <b>world</b></p>\n<pre>  keep\n   this </pre>\n<script>\n  var x = 1;\n</script>\n</body>\n</html>\n";
+        assert_eq!(
+            minify_html(src),
+            "<html>\n<body class=\"a  b\">\n<p>Hello <b>world</b></p>\n<pre>  keep\n   this </pre>\n<script>\n  var x = 1;\n</script>\n</body>\n</html>"
+        );
+    }
+    #[test]
+    fn test_minify_css() {
+        let src = "/* header */\nbody ,  p {\n  color : red;\n  font-family: \"A  B\";\n}\na :hover > b { margin: 0 auto; }\n";
+        assert_eq!(
+            minify_css(src),
+            "body,p{color : red;font-family: \"A  B\"}a :hover>b{margin: 0 auto}"
+        );
+    }
+    #[test]
+    fn test_minify_js() {
+        let src = "// comment\nfunction f() {\n    return 1;\n}\n\nconst t = `a\n   b`;\n";
+        assert_eq!(
+            minify_js(src),
+            "function f() {\nreturn 1;\n}\nconst t = `a\n   b`;\n"
+        );
+    }
+    #[test]
+    fn test_transform_cache() {
+        let path = crate::testdir::temp_path("minify").with_extension("css");
+        fs::write(&path, "a {  }").unwrap();
+        let minifier = Minifier::new();
+        let mut headers = HashMap::new();
+        headers.insert(names::CONTENT_TYPE, "text/css");
+        let resp = HttpResponse::new("200", Some(headers.clone()), Some("a {  }".into()));
+        let resp = minifier.transform(resp, Some(&path));
+        assert_eq!(resp.body_text(), Some("a{}"));
+        // 修改时间没变，直接用缓存
+        let resp = HttpResponse::new("200", Some(headers), Some("ignored".into()));
+        assert_eq!(
+            minifier.transform(resp, Some(&path)).body_text(),
+            Some("a{}")
+        );
+        let resp = HttpResponse::new("404", None, Some("<p>  x </p>".into()));
+        assert_eq!(
+            minifier.transform(resp, None).body_text(),
+            Some("<p>  x </p>")
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+// 流量镜像：按比例把线上请求复制一份发给影子服务，用真实流量试新版本，影子服务的响应直接丢弃
+//
+//     [mirror]
+//     upstream = "http://10.0.0.7:3001"
+//     percent = 5
+//
+// 复制发生在路由之前，发送在后台线程上，线上请求不用等影子服务；
+// 影子服务慢或者挂了时队列会满，之后的复制丢弃并计数，不会拖慢线上
+use crate::middleware::Middleware;
+use http::httpclient::{ClientError, HttpClient, Url};
+use http::httprequest::{HttpRequest, Resource};
+use http::httpresponse::HttpResponse;
+use http::random::RandomSource;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_QUEUE: usize = 256;
+pub const DEFAULT_TIMEOUT_SECS: u64 = 5;
+// 影子服务的响应不看，只读这么多，读不完也算发送成功
+const MAX_RESPONSE_BODY: usize = 64 * 1024;
+// HttpClient 自己会写 Host、Connection 和 Content-Length；其余的只对原来那条连接有意义
+pub(crate) const SKIPPED_HEADERS: [&str; 7] = [
+    "Host",
+    "Connection",
+    "Keep-Alive",
+    "Content-Length",
+    "Transfer-Encoding",
+    "TE",
+    "Upgrade",
+];
+
+// [mirror]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MirrorConfig {
+    // 影子服务的 http:// 地址，可以带路径前缀；不设置表示不镜像
+    pub upstream: Option<String>,
+    // 复制多少比例的请求，0 到 100
+    pub percent: f64,
+    // 等待发送的复制最多排多少个
+    pub queue: usize,
+    // 发给影子服务的连接、读、写超时
+    pub timeout_secs: u64,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        MirrorConfig {
+            upstream: None,
+            percent: 100.0,
+            queue: DEFAULT_QUEUE,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl MirrorConfig {
+    pub fn enabled(&self) -> bool {
+        self.upstream.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        let Some(upstream) = &self.upstream else {
+            return;
+        };
+        if Url::parse(upstream).is_err() {
+            problems.push(format!(
+                "mirror.upstream {:?} must be an http:// URL",
+                upstream
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.percent) {
+            problems.push("mirror.percent must be between 0 and 100".to_string());
+        }
+        if self.queue == 0 {
+            problems.push("mirror.queue must be positive".to_string());
+        }
+        if self.timeout_secs == 0 {
+            problems.push("mirror.timeout_secs must be positive".to_string());
+        }
+    }
+}
+
+// 发给影子服务的副本
+struct MirroredRequest {
+    method: &'static str,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MirrorStats {
+    // 影子服务回了响应，状态码不论
+    pub sent: u64,
+    // 连不上、超时或者响应不是 HTTP
+    pub failed: u64,
+    // 队列满了没有复制
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+// 克隆的实例共用同一个队列和计数；所有实例都释放后发送线程退出
+#[derive(Clone)]
+pub struct Mirror {
+    percent: f64,
+    rng: Arc<dyn RandomSource>,
+    queue: SyncSender<MirroredRequest>,
+    counters: Arc<Counters>,
+}
+
+impl Mirror {
+    // 启动发送线程；config.upstream 不是 http:// 地址时返回错误
+    pub fn new(config: &MirrorConfig, rng: Arc<dyn RandomSource>) -> Result<Self, ClientError> {
+        let upstream = config.upstream.clone().unwrap_or_default();
+        Url::parse(&upstream)?;
+        let upstream = upstream.trim_end_matches('/').to_string();
+        let client = HttpClient::new()
+            .decode(false)
+            .max_body_size(MAX_RESPONSE_BODY)
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        let (queue, requests) = mpsc::sync_channel::<MirroredRequest>(config.queue);
+        let counters = Arc::new(Counters::default());
+        let shared = counters.clone();
+        thread::spawn(move || {
+            // 只在影子服务从可用变成不可用时打日志，挂掉期间不刷屏
+            let mut healthy = true;
+            for req in requests {
+                let headers: Vec<(&str, &str)> = req
+                    .headers
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                let url = format!("{}{}", upstream, req.target);
+                let body = (!req.body.is_empty()).then_some(req.body.as_slice());
+                match client.send(req.method, &url, &headers, body) {
+                    Ok(_) | Err(ClientError::BodyTooLarge(_)) => {
+                        healthy = true;
+                        shared.sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        if healthy {
+                            eprintln!("Cannot mirror request to {}: {}", upstream, e);
+                        }
+                        healthy = false;
+                        shared.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+        Ok(Mirror {
+            percent: config.percent,
+            rng,
+            queue,
+            counters,
+        })
+    }
+
+    pub fn stats(&self) -> MirrorStats {
+        MirrorStats {
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Middleware for Mirror {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        if self.rng.next_f64() * 100.0 >= self.percent {
+            return None;
+        }
+        // OPTIONS * 和代理形式的完整 URL 拼不到 upstream 后面
+        let Resource::Path(target) = &req.resource;
+        if !target.starts_with('/') {
+            return None;
+        }
+        let headers = req
+            .headers
+            .iter()
+            .filter(|(k, _)| !SKIPPED_HEADERS.iter().any(|s| s.eq_ignore_ascii_case(k)))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let copy = MirroredRequest {
+            method: req.method.as_str(),
+            target: target.clone(),
+            headers,
+            body: req.msg_body.clone(),
+        };
+        if self.queue.try_send(copy).is_err() {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use http::random::SeededRandom;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    fn mirror(upstream: String, percent: f64) -> Mirror {
+        let config = MirrorConfig {
+            upstream: Some(upstream),
+            percent,
+            ..MirrorConfig::default()
+        };
+        Mirror::new(&config, Arc::new(SeededRandom::new(7))).unwrap()
+    }
+
+    #[test]
+    fn test_mirror() {
+        let shadow = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}/shadow/", shadow.local_addr().unwrap());
+        let mirror = mirror(upstream, 100.0);
+        let router = Router::new("")
+            .middleware(mirror.clone())
+            .post("/api/hits", |_req| {
+                HttpResponse::new("201", None, Some("live".into()))
+            });
+        let raw = "POST /api/hits?n=1 HTTP/1.1\r\nHost: shop\r\nX-Trace: abc\r\nContent-Length: 5\r\n\r\nhello";
+        let mut out = Vec::new();
+        router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+        // 线上的响应不受影响
+        assert!(String::from_utf8(out).unwrap().ends_with("live"));
+
+        let (mut conn, _) = shadow.accept().unwrap();
+        let mut received = Vec::new();
+        let mut chunk = [0; 1024];
+        while !received.ends_with(b"hello") {
+            let n = conn.read(&mut chunk).unwrap();
+            assert!(n > 0);
+            received.extend_from_slice(&chunk[..n]);
+        }
+        let received = String::from_utf8(received).unwrap();
+        assert!(received.starts_with("POST /shadow/api/hits?n=1 HTTP/1.1\r\n"));
+        assert!(received.contains("X-Trace: abc\r\n"));
+        assert!(!received.contains("Host: shop"));
+        conn.write_all(b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        drop(conn);
+        let started = Instant::now();
+        while mirror.stats().sent == 0 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            mirror.stats(),
+            MirrorStats {
+                sent: 1,
+                failed: 0,
+                dropped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_percent() {
+        // 0% 时一个都不复制，连不上的影子服务也不会被碰到
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let mirror = mirror(upstream, 0.0);
+        let router = Router::new("").middleware(mirror.clone());
+        let mut out = Vec::new();
+        let req = HttpRequest::try_from(&b"GET /health HTTP/1.1\r\n\r\n"[..]).unwrap();
+        router.route(req, &mut out);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(mirror.stats(), MirrorStats::default());
+
+        let config = MirrorConfig {
+            upstream: Some("https://example.com".into()),
+            percent: 120.0,
+            ..MirrorConfig::default()
+        };
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(Mirror::new(&config, Arc::new(SeededRandom::new(1))).is_err());
+    }
+}
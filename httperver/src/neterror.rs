@@ -0,0 +1,85 @@
+// 网络错误分类：决定遇到 io 错误后是重试、只丢弃这个连接，还是停止服务
+use std::io::{self, ErrorKind};
+
+use crate::fds::FdPressure;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    // 被信号打断、暂时没有数据、内核缓冲不足，稍后重试即可
+    Transient,
+    // 文件描述符用完，需要退避，见 FdPressure
+    Exhausted,
+    // 只和这一个连接有关，比如对端重置或者在 accept 之前就断开了
+    Connection,
+    // 监听套接字本身坏了，继续循环也不会好转
+    Fatal,
+}
+
+pub fn classify(e: &io::Error) -> ErrorClass {
+    if FdPressure::is_exhausted(e) {
+        return ErrorClass::Exhausted;
+    }
+    #[cfg(unix)]
+    if let Some(code) = e.raw_os_error() {
+        match code {
+            libc::ENOBUFS | libc::ENOMEM => return ErrorClass::Transient,
+            libc::EBADF | libc::EINVAL | libc::ENOTSOCK | libc::EFAULT => return ErrorClass::Fatal,
+            _ => {}
+        }
+    }
+    match e.kind() {
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            ErrorClass::Transient
+        }
+        ErrorKind::InvalidInput => ErrorClass::Fatal,
+        // accept(2) 的手册要求把 EPROTO、ENETDOWN 等错误当作 EAGAIN 处理，
+        // 都是排队的连接出了问题，连同其他未知错误一起只放弃当前连接
+        _ => ErrorClass::Connection,
+    }
+}
+
+// 对端断开是正常情况不打印，其他错误打印出来方便排查
+pub fn log_connection_error(context: &str, e: &io::Error) {
+    if classify(e) != ErrorClass::Connection {
+        eprintln!("{}: {}", context, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_kinds() {
+        for (kind, class) in [
+            (ErrorKind::Interrupted, ErrorClass::Transient),
+            (ErrorKind::WouldBlock, ErrorClass::Transient),
+            (ErrorKind::ConnectionReset, ErrorClass::Connection),
+            (ErrorKind::ConnectionAborted, ErrorClass::Connection),
+            (ErrorKind::BrokenPipe, ErrorClass::Connection),
+            (ErrorKind::InvalidInput, ErrorClass::Fatal),
+        ] {
+            assert_eq!(classify(&io::Error::from(kind)), class, "{:?}", kind);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_errno() {
+        for (code, class) in [
+            (libc::EMFILE, ErrorClass::Exhausted),
+            (libc::ENOBUFS, ErrorClass::Transient),
+            (libc::ECONNABORTED, ErrorClass::Connection),
+            (libc::EPROTO, ErrorClass::Connection),
+            (libc::EBADF, ErrorClass::Fatal),
+            (libc::ENOTSOCK, ErrorClass::Fatal),
+        ] {
+            assert_eq!(
+                classify(&io::Error::from_raw_os_error(code)),
+                class,
+                "errno {}",
+                code
+            );
+        }
+    }
+}
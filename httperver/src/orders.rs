@@ -0,0 +1,182 @@
+// 订单数据的存取，/api/shipping/orders 的增删改查都通过 OrderStore
+// 默认是数据目录下的 orders.json（多租户时每个租户一份），也可以换成别的实现
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// 日期和状态是自由文本，太长的拒绝，防止一个请求把数据文件撑大
+pub const MAX_FIELD_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderStatus {
+    pub order_id: i32,
+    pub order_date: String,
+    pub order_status: String,
+}
+
+pub trait OrderStore: Send + Sync {
+    fn load(&self) -> io::Result<Vec<OrderStatus>>;
+    // 读出全部订单交给 f 修改，f 返回 true 时写回
+    // 实现要保证读和写之间不会插进别的修改
+    fn modify(&self, f: &mut dyn FnMut(&mut Vec<OrderStatus>) -> bool) -> io::Result<()>;
+}
+
+// 所有 JsonFileStore 共用一把锁，同一个文件不会被两个请求同时读改写
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonFileStore { path: path.into() }
+    }
+
+    fn read(&self) -> io::Result<Vec<OrderStatus>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other),
+            // 还没有订单时文件可以不存在，第一次创建订单时写出
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 先写临时文件再改名，写到一半崩溃也不会留下半个 JSON
+    fn write(&self, orders: &[OrderStatus]) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let json = serde_json::to_vec_pretty(orders).map_err(io::Error::other)?;
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl OrderStore for JsonFileStore {
+    fn load(&self) -> io::Result<Vec<OrderStatus>> {
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        self.read()
+    }
+
+    fn modify(&self, f: &mut dyn FnMut(&mut Vec<OrderStatus>) -> bool) -> io::Result<()> {
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut orders = self.read()?;
+        if f(&mut orders) {
+            self.write(&orders)?;
+        }
+        Ok(())
+    }
+}
+
+// POST / PUT / PATCH 的请求体，字段都可以省略，由 validate 按操作检查
+// 多余的字段直接报错，拼错的字段名不会被悄悄忽略
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderInput {
+    pub order_id: Option<i32>,
+    pub order_date: Option<String>,
+    pub order_status: Option<String>,
+}
+
+impl OrderInput {
+    // 字段名 -> 问题；partial 为 true（PATCH）时缺少的字段不算错
+    pub fn validate(&self, partial: bool) -> BTreeMap<&'static str, String> {
+        let mut problems = BTreeMap::new();
+        if let Some(id) = self.order_id {
+            if id <= 0 {
+                problems.insert("order_id", "must be a positive integer".to_string());
+            }
+        }
+        for (name, value) in [
+            ("order_date", &self.order_date),
+            ("order_status", &self.order_status),
+        ] {
+            match value {
+                None if !partial => {
+                    problems.insert(name, "is required".to_string());
+                }
+                Some(v) if v.trim().is_empty() => {
+                    problems.insert(name, "must not be empty".to_string());
+                }
+                Some(v) if v.chars().count() > MAX_FIELD_LEN => {
+                    problems.insert(
+                        name,
+                        format!("must be at most {} characters", MAX_FIELD_LEN),
+                    );
+                }
+                _ => {}
+            }
+        }
+        problems
+    }
+
+    // 把给出的字段写进已有订单，order_id 不变
+    pub fn apply(self, order: &mut OrderStatus) {
+        if let Some(date) = self.order_date {
+            order.order_date = date;
+        }
+        if let Some(status) = self.order_status {
+            order.order_status = status;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_file_store() {
+        let dir = crate::testdir::temp_path("orders");
+        fs::create_dir_all(&dir).unwrap();
+        let store = JsonFileStore::new(dir.join("orders.json"));
+        // 文件不存在时是空列表
+        assert_eq!(store.load().unwrap(), Vec::new());
+        let order = OrderStatus {
+            order_id: 1,
+            order_date: "21Jan2020".into(),
+            order_status: "Shipped".into(),
+        };
+        store
+            .modify(&mut |orders| {
+                orders.push(order.clone());
+                true
+            })
+            .unwrap();
+        assert_eq!(store.load().unwrap(), vec![order.clone()]);
+        // 返回 false 时不写回
+        store
+            .modify(&mut |orders| {
+                orders.clear();
+                false
+            })
+            .unwrap();
+        assert_eq!(store.load().unwrap(), vec![order]);
+        assert!(!dir.join("orders.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_order_input_validate() {
+        let input = OrderInput {
+            order_id: Some(0),
+            order_date: Some(" ".into()),
+            order_status: None,
+        };
+        let problems = input.validate(false);
+        assert_eq!(problems["order_id"], "must be a positive integer");
+        assert_eq!(problems["order_date"], "must not be empty");
+        assert_eq!(problems["order_status"], "is required");
+        let patch = OrderInput {
+            order_status: Some("x".repeat(MAX_FIELD_LEN + 1)),
+            ..OrderInput::default()
+        };
+        assert_eq!(
+            patch.validate(true).into_iter().collect::<Vec<_>>(),
+            vec![("order_status", "must be at most 64 characters".to_string())]
+        );
+    }
+}
@@ -0,0 +1,451 @@
+// OpenTelemetry 导出：按 OTLP/HTTP 的 JSON 编码，定期把请求的 span 和耗时统计推给 collector
+// 不引入 opentelemetry SDK，直接用 http::httpclient POST 到 <endpoint>/v1/traces 和 /v1/metrics
+// 请求带 W3C traceparent 头时沿用它的 trace id，span 挂在调用方的 span 下面
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_SERVICE_NAME: &str = "httperver";
+pub const DEFAULT_EXPORT_INTERVAL_SECS: u64 = 10;
+
+// [otel]
+// endpoint = "http://localhost:4318"
+// service_name = "shop"
+// sample_ratio = 0.1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtelConfig {
+    // collector 的 OTLP/HTTP 地址，不设置表示不导出
+    pub endpoint: Option<String>,
+    pub service_name: String,
+    pub export_interval_secs: u64,
+    // 没有 traceparent 的请求按这个比例采样；调用方已经采样的请求总是导出
+    pub sample_ratio: f64,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        OtelConfig {
+            endpoint: None,
+            service_name: DEFAULT_SERVICE_NAME.to_string(),
+            export_interval_secs: DEFAULT_EXPORT_INTERVAL_SECS,
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl OtelConfig {
+    pub fn enabled(&self) -> bool {
+        self.endpoint.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        let Some(endpoint) = &self.endpoint else {
+            return;
+        };
+        if cfg!(not(feature = "otel")) {
+            problems.push("otel requires building with --features otel".to_string());
+        }
+        if !endpoint.starts_with("http://") {
+            problems.push(format!(
+                "otel.endpoint {:?} must be an http:// URL",
+                endpoint
+            ));
+        }
+        if self.export_interval_secs == 0 {
+            problems.push("otel.export_interval_secs must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            problems.push("otel.sample_ratio must be between 0 and 1".to_string());
+        }
+    }
+}
+
+// traceparent: 00-<32 位 trace id>-<16 位 parent id>-<flags>
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceParent {
+    pub trace_id: [u8; 16],
+    pub parent_id: [u8; 8],
+    pub sampled: bool,
+}
+
+impl TraceParent {
+    pub fn parse(value: &str) -> Option<TraceParent> {
+        let mut parts = value.trim().split('-');
+        let (version, trace, parent, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if version != "00" || parts.next().is_some() {
+            return None;
+        }
+        let trace_id: [u8; 16] = decode_hex(trace)?.try_into().ok()?;
+        let parent_id: [u8; 8] = decode_hex(parent)?.try_into().ok()?;
+        let flags = decode_hex(flags)?;
+        // 全零的 id 无效
+        if flags.len() != 1 || trace_id == [0; 16] || parent_id == [0; 8] {
+            return None;
+        }
+        Some(TraceParent {
+            trace_id,
+            parent_id,
+            sampled: flags[0] & 1 == 1,
+        })
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(feature = "otel")]
+pub use exporter::{RequestSpan, Telemetry};
+
+#[cfg(feature = "otel")]
+mod exporter {
+    use super::{OtelConfig, TraceParent};
+    use crate::metrics::MetricsSnapshot;
+    use http::clock::Clock;
+    use http::httpclient::{ClientError, HttpClient};
+    use http::random::RandomSource;
+    use serde_json::{json, Value};
+    use std::net::IpAddr;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // 两次导出之间最多缓存这么多 span，collector 不可用时多出来的丢弃并计数
+    const MAX_BUFFERED_SPANS: usize = 4096;
+    // 导出线程多久检查一次退出标志
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+    // OTLP 的 SpanKind.SERVER 和 Status.ERROR
+    const SPAN_KIND_SERVER: u8 = 2;
+    const STATUS_ERROR: u8 = 2;
+
+    // 服务器处理完一个请求后交给 Telemetry::record
+    pub struct RequestSpan<'a> {
+        pub method: &'a str,
+        pub path: &'a str,
+        // 路由名，没有名字的路由用路径做 span 名
+        pub route: Option<&'a str>,
+        pub traceparent: Option<&'a str>,
+        pub client: Option<IpAddr>,
+        pub status: Option<u16>,
+        // 排队时间加处理时间
+        pub duration: Duration,
+    }
+
+    struct Span {
+        trace_id: [u8; 16],
+        span_id: [u8; 8],
+        parent_id: Option<[u8; 8]>,
+        name: String,
+        start: SystemTime,
+        end: SystemTime,
+        attributes: Vec<(&'static str, Value)>,
+        error: bool,
+    }
+
+    pub struct Telemetry {
+        config: OtelConfig,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+        client: HttpClient,
+        spans: Mutex<Vec<Span>>,
+        dropped: AtomicU64,
+        // 累计指标的起点
+        started: SystemTime,
+    }
+
+    impl Telemetry {
+        pub fn new(config: OtelConfig, clock: Arc<dyn Clock>, rng: Arc<dyn RandomSource>) -> Self {
+            let started = clock.now();
+            Telemetry {
+                config,
+                clock,
+                rng,
+                client: HttpClient::new().timeout(Some(Duration::from_secs(5))),
+                spans: Mutex::new(Vec::new()),
+                dropped: AtomicU64::new(0),
+                started,
+            }
+        }
+
+        pub fn record(&self, req: &RequestSpan) {
+            let parent = req.traceparent.and_then(TraceParent::parse);
+            let sampled = match parent {
+                Some(p) => p.sampled,
+                None => self.rng.next_f64() < self.config.sample_ratio,
+            };
+            if !sampled {
+                return;
+            }
+            let trace_id = parent.map(|p| p.trace_id).unwrap_or_else(|| {
+                let mut id = [0; 16];
+                self.rng.fill_bytes(&mut id);
+                id
+            });
+            let mut span_id = [0; 8];
+            self.rng.fill_bytes(&mut span_id);
+            let end = self.clock.now();
+            let mut attributes = vec![
+                ("http.request.method", json!(req.method)),
+                ("url.path", json!(req.path)),
+            ];
+            if let Some(route) = req.route {
+                attributes.push(("http.route", json!(route)));
+            }
+            if let Some(status) = req.status {
+                attributes.push(("http.response.status_code", json!(status)));
+            }
+            if let Some(ip) = req.client {
+                attributes.push(("client.address", json!(ip.to_string())));
+            }
+            let span = Span {
+                trace_id,
+                span_id,
+                parent_id: parent.map(|p| p.parent_id),
+                name: format!("{} {}", req.method, req.route.unwrap_or(req.path)),
+                start: end.checked_sub(req.duration).unwrap_or(end),
+                end,
+                attributes,
+                error: req.status.is_some_and(|s| s >= 500),
+            };
+            let mut spans = self.spans.lock().unwrap();
+            if spans.len() >= MAX_BUFFERED_SPANS {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            spans.push(span);
+        }
+
+        // 推送缓存的 span 和当前的指标；失败时这一批 span 丢弃
+        pub fn export(&self, metrics: &MetricsSnapshot) -> Result<(), ClientError> {
+            let spans = std::mem::take(&mut *self.spans.lock().unwrap());
+            if !spans.is_empty() {
+                self.post("/v1/traces", &self.traces_body(&spans))?;
+            }
+            self.post("/v1/metrics", &self.metrics_body(metrics))
+        }
+
+        // 在单独的线程上运行，stop 变成 true 后再导出一次就返回
+        pub fn export_loop(&self, stop: &AtomicBool, metrics: impl Fn() -> MetricsSnapshot) {
+            let interval = Duration::from_secs(self.config.export_interval_secs);
+            loop {
+                let mut waited = Duration::ZERO;
+                while waited < interval && !stop.load(Ordering::SeqCst) {
+                    thread::sleep(POLL_INTERVAL);
+                    waited += POLL_INTERVAL;
+                }
+                if let Err(e) = self.export(&metrics()) {
+                    eprintln!("Cannot export telemetry: {}", e);
+                }
+                if stop.load(Ordering::SeqCst) {
+                    return;
+                }
+            }
+        }
+
+        fn post(&self, path: &str, body: &Value) -> Result<(), ClientError> {
+            let endpoint = self.config.endpoint.as_deref().unwrap_or_default();
+            let url = format!("{}{}", endpoint.trim_end_matches('/'), path);
+            let body = body.to_string();
+            let headers = [("Content-Type", "application/json")];
+            let resp = self
+                .client
+                .send("POST", &url, &headers, Some(body.as_bytes()))?;
+            if resp.status_code >= 300 {
+                eprintln!("Collector rejected {}: {}", path, resp.status_code);
+            }
+            Ok(())
+        }
+
+        fn resource(&self) -> Value {
+            json!({ "attributes": [attribute("service.name", json!(self.config.service_name))] })
+        }
+
+        fn traces_body(&self, spans: &[Span]) -> Value {
+            let spans: Vec<Value> = spans
+                .iter()
+                .map(|s| {
+                    let mut span = json!({
+                        "traceId": hex(&s.trace_id),
+                        "spanId": hex(&s.span_id),
+                        "name": s.name,
+                        "kind": SPAN_KIND_SERVER,
+                        "startTimeUnixNano": unix_nanos(s.start),
+                        "endTimeUnixNano": unix_nanos(s.end),
+                        "attributes": s.attributes.iter().map(|(k, v)| attribute(k, v.clone())).collect::<Vec<_>>(),
+                    });
+                    if let Some(parent) = s.parent_id {
+                        span["parentSpanId"] = json!(hex(&parent));
+                    }
+                    if s.error {
+                        span["status"] = json!({ "code": STATUS_ERROR });
+                    }
+                    span
+                })
+                .collect();
+            json!({
+                "resourceSpans": [{
+                    "resource": self.resource(),
+                    "scopeSpans": [{ "scope": { "name": "httperver" }, "spans": spans }],
+                }]
+            })
+        }
+
+        fn metrics_body(&self, m: &MetricsSnapshot) -> Value {
+            let start = unix_nanos(self.started);
+            let now = unix_nanos(self.clock.now());
+            // 累计值：collector 自己算速率
+            let sum = |name: &str, unit: &str, value: Value| {
+                let point = data_point(
+                    json!({ "startTimeUnixNano": start, "timeUnixNano": now }),
+                    value,
+                );
+                json!({
+                    "name": name,
+                    "unit": unit,
+                    "sum": { "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": [point] },
+                })
+            };
+            let gauge = |name: &str, unit: &str, value: Value| {
+                let point = data_point(json!({ "timeUnixNano": now }), value);
+                json!({ "name": name, "unit": unit, "gauge": { "dataPoints": [point] } })
+            };
+            let metrics = vec![
+                sum("http.server.requests", "1", json!(m.requests.to_string())),
+                sum("http.server.queue_time", "ms", json!(m.queue_time.total_ms)),
+                sum(
+                    "http.server.handler_time",
+                    "ms",
+                    json!(m.handler_time.total_ms),
+                ),
+                gauge("http.server.queued", "1", json!(m.queued.to_string())),
+                sum(
+                    "httperver.otel.dropped_spans",
+                    "1",
+                    json!(self.dropped.load(Ordering::Relaxed).to_string()),
+                ),
+            ];
+            json!({
+                "resourceMetrics": [{
+                    "resource": self.resource(),
+                    "scopeMetrics": [{ "scope": { "name": "httperver" }, "metrics": metrics }],
+                }]
+            })
+        }
+    }
+
+    // OTLP JSON 里 64 位整数写成字符串
+    fn unix_nanos(t: SystemTime) -> String {
+        t.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0)
+            .to_string()
+    }
+
+    // 整数以字符串传入，放进 asInt；浮点数放进 asDouble
+    fn data_point(mut point: Value, value: Value) -> Value {
+        let key = if value.is_string() {
+            "asInt"
+        } else {
+            "asDouble"
+        };
+        point[key] = value;
+        point
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn attribute(key: &str, value: Value) -> Value {
+        let value = match value {
+            Value::String(s) => json!({ "stringValue": s }),
+            Value::Number(n) if n.is_f64() => json!({ "doubleValue": n }),
+            Value::Number(n) => json!({ "intValue": n.to_string() }),
+            other => json!({ "stringValue": other.to_string() }),
+        };
+        json!({ "key": key, "value": value })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::metrics::Metrics;
+        use http::clock::MockClock;
+        use http::random::SeededRandom;
+
+        #[test]
+        fn test_span_encoding() {
+            let config = OtelConfig {
+                endpoint: Some("http://127.0.0.1:4318".into()),
+                sample_ratio: 0.0,
+                ..OtelConfig::default()
+            };
+            let clock = Arc::new(MockClock::from_unix_secs(1_700_000_000));
+            let telemetry = Telemetry::new(config, clock, Arc::new(SeededRandom::new(1)));
+            let span = |traceparent| RequestSpan {
+                method: "GET",
+                path: "/api/orders/7",
+                route: Some("order"),
+                traceparent,
+                client: None,
+                status: Some(503),
+                duration: Duration::from_millis(250),
+            };
+            // 没有 traceparent 时按 sample_ratio = 0 丢弃
+            telemetry.record(&span(None));
+            telemetry.record(&span(Some(
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )));
+            let spans = telemetry.spans.lock().unwrap();
+            let body = telemetry.traces_body(&spans);
+            let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .unwrap();
+            assert_eq!(spans.len(), 1);
+            let s = &spans[0];
+            assert_eq!(s["traceId"], "0af7651916cd43dd8448eb211c80319c");
+            assert_eq!(s["parentSpanId"], "b7ad6b7169203331");
+            assert_eq!(s["name"], "GET order");
+            assert_eq!(s["startTimeUnixNano"], "1699999999750000000");
+            assert_eq!(s["endTimeUnixNano"], "1700000000000000000");
+            assert_eq!(s["status"]["code"], 2);
+
+            let metrics = Metrics::new();
+            metrics.record(Duration::from_millis(2), Duration::from_millis(10));
+            let body = telemetry.metrics_body(&metrics.snapshot(4, 0));
+            let first = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+            assert_eq!(first["name"], "http.server.requests");
+            assert_eq!(first["sum"]["dataPoints"][0]["asInt"], "1");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_traceparent() {
+        let p =
+            TraceParent::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01").unwrap();
+        assert_eq!(p.trace_id[..2], [0x0a, 0xf7]);
+        assert_eq!(p.parent_id[7], 0x31);
+        assert!(p.sampled);
+        for bad in [
+            "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "garbage",
+        ] {
+            assert_eq!(TraceParent::parse(bad), None, "{}", bad);
+        }
+    }
+}
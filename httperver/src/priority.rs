@@ -0,0 +1,183 @@
+// 请求优先级：健康检查和管理接口 > API > 静态文件
+// 服务器忙不过来时先处理高优先级的请求，队列满了先丢弃低优先级的请求，
+// 这样事故期间健康检查不会因为排在一堆静态文件后面而超时
+use serde::{Deserialize, Serialize};
+use std::collections::BinaryHeap;
+use std::sync::{Condvar, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+struct Entry<T> {
+    priority: Priority,
+    // 同优先级先进先出
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+impl<T> Eq for Entry<T> {}
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+// BinaryHeap 是大顶堆：优先级高的在前，seq 小的在前
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+struct State<T> {
+    heap: BinaryHeap<Entry<T>>,
+    seq: u64,
+    closed: bool,
+}
+
+// 有界的优先级队列，accept 线程放入，工作线程取出
+pub struct PriorityQueue<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl<T> PriorityQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        PriorityQueue {
+            state: Mutex::new(State {
+                heap: BinaryHeap::new(),
+                seq: 0,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    // 队列满时丢弃一个优先级最低、最新到达的请求：可能是排队中的，也可能就是这个新请求
+    // 返回被丢弃的请求，由调用方回复 503
+    pub fn push(&self, priority: Priority, item: T) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        state.seq += 1;
+        let entry = Entry {
+            priority,
+            seq: state.seq,
+            item,
+        };
+        if state.heap.len() < self.capacity {
+            state.heap.push(entry);
+            self.ready.notify_one();
+            return None;
+        }
+        let lowest = state
+            .heap
+            .iter()
+            .min()
+            .map(|e| (e.priority, e.seq))
+            .expect("queue is full");
+        if priority <= lowest.0 {
+            return Some(entry.item);
+        }
+        // 用 into_vec 取出最低的那个再重建堆，队列不大，开销可以接受
+        let mut entries = std::mem::take(&mut state.heap).into_vec();
+        let at = entries
+            .iter()
+            .position(|e| (e.priority, e.seq) == lowest)
+            .unwrap();
+        let shed = entries.swap_remove(at);
+        entries.push(entry);
+        state.heap = entries.into();
+        self.ready.notify_one();
+        Some(shed.item)
+    }
+
+    // 阻塞直到有请求；队列关闭并且已经取空时返回 None
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(entry) = state.heap.pop() {
+                return Some(entry.item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    // 不再接收新请求，已经排队的请求仍会被取出处理
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+
+    // 取出所有还在排队的请求，优雅退出超时时用来丢弃剩下的请求
+    pub fn drain(&self) -> Vec<T> {
+        let mut state = self.state.lock().unwrap();
+        std::mem::take(&mut state.heap)
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|e| e.item)
+            .collect()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().heap.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_priority_queue() {
+        let queue = PriorityQueue::new(3);
+        assert_eq!(queue.push(Priority::Low, "style.css"), None);
+        assert_eq!(queue.push(Priority::Normal, "orders"), None);
+        assert_eq!(queue.push(Priority::Low, "app.js"), None);
+        // 满了：高优先级挤掉最新的低优先级请求
+        assert_eq!(queue.push(Priority::High, "health"), Some("app.js"));
+        // 新请求优先级不高于队列里最低的，直接丢弃新请求
+        assert_eq!(queue.push(Priority::Low, "logo.png"), Some("logo.png"));
+        queue.close();
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, vec!["health", "orders", "style.css"]);
+    }
+
+    #[test]
+    fn test_close_stops_all_consumers() {
+        let queue = PriorityQueue::new(100);
+        for i in 0..50 {
+            queue.push(Priority::Normal, i);
+        }
+        // 多个工作线程一起取，关闭后每个线程都能退出，排队的请求一个不少
+        let total: usize = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| std::iter::from_fn(|| queue.pop()).count()))
+                .collect();
+            queue.close();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(total, 50);
+    }
+}
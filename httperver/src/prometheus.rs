@@ -0,0 +1,367 @@
+// Prometheus 指标：按方法 / 路由 / 状态码统计请求数，按方法 / 路由统计耗时直方图，
+// 再加上正在处理的请求数和打开的连接数，以 Prometheus 的文本格式输出
+//
+//     [prometheus]
+//     path = "/metrics"
+//
+//     let metrics = Arc::new(Prometheus::new(DEFAULT_BUCKETS.to_vec(), clock));
+//     let router = Router::new("").prometheus("/metrics", metrics);
+//
+// path 标签用路由表里的模式（/api/orders/:id），不用实际路径，时间序列的个数不随 URL 增长；
+// 路由表里没有匹配的（被中间件短路的、指标端点本身）请求记作 "other"，挂载的子应用记的是相对挂载点的模式
+// 端点和普通路由一样经过前面注册的中间件，不想公开时用 Auth 之类的中间件保护
+// 和 GET /_admin/metrics 不同，这里的耗时从这个中间件的 before 算到 after，不含排队时间
+use crate::middleware::Middleware;
+use crate::router::RoutePattern;
+use http::clock::Clock;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// 秒，和 Prometheus 客户端库的默认值相同
+pub const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// 文本格式 0.0.4
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// 没有匹配到路由的请求的 path 标签
+const OTHER: &str = "other";
+
+// [prometheus]
+// path = "/metrics"
+// buckets = [0.01, 0.1, 1]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrometheusConfig {
+    // 不设置表示不输出指标；相对 base_path
+    pub path: Option<String>,
+    // 直方图各个桶的上限（秒），从小到大
+    pub buckets: Vec<f64>,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        PrometheusConfig {
+            path: None,
+            buckets: DEFAULT_BUCKETS.to_vec(),
+        }
+    }
+}
+
+impl PrometheusConfig {
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if self.path.as_ref().is_some_and(|p| !p.starts_with('/')) {
+            problems.push("prometheus.path must start with '/'".to_string());
+        }
+        if self.buckets.iter().any(|b| !(b.is_finite() && *b > 0.0)) {
+            problems.push("prometheus.buckets must be positive".to_string());
+        } else if self.buckets.windows(2).any(|w| w[0] >= w[1]) {
+            problems.push("prometheus.buckets must be in increasing order".to_string());
+        }
+    }
+}
+
+// before 放进 req.extensions：开始的时间，after 取出来算耗时；
+// 请求被丢弃时正在处理的请求数减一，处理器 panic、after 没有执行时也一样
+struct InFlight {
+    started: SystemTime,
+    gauge: Arc<AtomicI64>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Histogram {
+    // 每个桶自己的计数，输出时再累加；最后一个是 +Inf
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: usize) -> Self {
+        Histogram {
+            counts: vec![0; buckets + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+pub struct Prometheus {
+    buckets: Vec<f64>,
+    clock: Arc<dyn Clock>,
+    // BTreeMap 让每次输出的顺序一致
+    requests: Mutex<BTreeMap<(&'static str, &'static str, u16), u64>>,
+    durations: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>,
+    in_flight: Arc<AtomicI64>,
+    connections: AtomicI64,
+}
+
+// 跟着连接走，连接关闭（被丢弃）时打开的连接数减一
+pub struct OpenConnection(Arc<Prometheus>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 标签值里的 \、" 和换行要转义
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Prometheus {
+    pub fn new(buckets: Vec<f64>, clock: Arc<dyn Clock>) -> Self {
+        Prometheus {
+            buckets,
+            clock,
+            requests: Mutex::new(BTreeMap::new()),
+            durations: Mutex::new(BTreeMap::new()),
+            in_flight: Arc::new(AtomicI64::new(0)),
+            connections: AtomicI64::new(0),
+        }
+    }
+
+    // 服务器每接受一个连接调用一次，返回值和连接一起丢弃
+    pub fn connection(self: &Arc<Self>) -> OpenConnection {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        OpenConnection(self.clone())
+    }
+
+    fn record(&self, method: &'static str, path: &'static str, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((method, path, status))
+            .or_default() += 1;
+        let mut durations = self.durations.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = durations
+            .entry((method, path))
+            .or_insert_with(|| Histogram::new(self.buckets.len()));
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(self.buckets.len());
+        histogram.counts[bucket] += 1;
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    // Prometheus 文本格式
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP httperver_http_requests_total Requests handled, by method, route and status.\n",
+        );
+        out.push_str("# TYPE httperver_http_requests_total counter\n");
+        for ((method, path, status), n) in self
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "httperver_http_requests_total{{method=\"{}\",path=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(path),
+                status,
+                n
+            );
+        }
+        out.push_str(
+            "# HELP httperver_http_requests_in_flight Requests currently being handled.\n",
+        );
+        out.push_str("# TYPE httperver_http_requests_in_flight gauge\n");
+        let _ = writeln!(
+            out,
+            "httperver_http_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+        out.push_str("# HELP httperver_open_connections Client connections currently open.\n");
+        out.push_str("# TYPE httperver_open_connections gauge\n");
+        let _ = writeln!(
+            out,
+            "httperver_open_connections {}",
+            self.connections.load(Ordering::Relaxed)
+        );
+        out.push_str(
+            "# HELP httperver_http_request_duration_seconds Time spent handling requests.\n",
+        );
+        out.push_str("# TYPE httperver_http_request_duration_seconds histogram\n");
+        for ((method, path), h) in self
+            .durations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let labels = format!("method=\"{}\",path=\"{}\"", method, escape(path));
+            let mut cumulative = 0;
+            let bounds = self.buckets.iter().map(|b| b.to_string());
+            for (le, n) in bounds.chain(["+Inf".to_string()]).zip(&h.counts) {
+                cumulative += n;
+                let _ = writeln!(
+                    out,
+                    "httperver_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "httperver_http_request_duration_seconds_sum{{{}}} {}",
+                labels, h.sum
+            );
+            let _ = writeln!(
+                out,
+                "httperver_http_request_duration_seconds_count{{{}}} {}",
+                labels, h.count
+            );
+        }
+        out
+    }
+
+    pub(crate) fn respond(&self) -> HttpResponse<'static> {
+        HttpResponse::new("200", None, Some(self.render()))
+            .with_header(names::CONTENT_TYPE, CONTENT_TYPE)
+            .expect("valid content type")
+    }
+}
+
+impl Middleware for Prometheus {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        req.extensions.insert(InFlight {
+            started: self.clock.now(),
+            gauge: self.in_flight.clone(),
+        });
+        None
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+        let seconds = req
+            .extensions
+            .get::<InFlight>()
+            .and_then(|f| self.clock.now().duration_since(f.started).ok())
+            .unwrap_or_default()
+            .as_secs_f64();
+        let path = req.extensions.get::<RoutePattern>().map_or(OTHER, |p| p.0);
+        self.record(req.method.as_str(), path, resp.status().as_u16(), seconds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{RequestParams, Router};
+    use http::clock::MockClock;
+    use std::time::Duration;
+
+    // 拒绝 /shop/private 下的请求，模拟被中间件短路
+    struct Private;
+
+    impl Middleware for Private {
+        fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+            let private = req.path().starts_with("/shop/private");
+            private.then(|| HttpResponse::new("403", None, Some(String::new())))
+        }
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let clock = Arc::new(MockClock::from_unix_secs(1_000));
+        let metrics = Arc::new(Prometheus::new(vec![0.1, 1.0], clock.clone()));
+        let slow = clock.clone();
+        let router = Router::new("/shop")
+            .prometheus("/metrics", metrics.clone())
+            .middleware(Private)
+            .get("/items/:id", move |req| {
+                if req.params().get("id") == Some("slow") {
+                    slow.advance(Duration::from_millis(1500));
+                }
+                HttpResponse::new("200", None, Some("item".into()))
+            })
+            .get("/boom", |_req| panic!("handler failed"));
+        let send = |line: &str| {
+            let req = HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        send("GET /shop/items/1");
+        send("GET /shop/items/2");
+        send("GET /shop/items/slow");
+        send("GET /shop/private/1");
+        send("DELETE /shop/no/such/page");
+        let _conn = metrics.connection();
+        drop(metrics.connection());
+
+        let out = send("GET /shop/metrics");
+        assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+        assert!(out.contains("Content-Type:text/plain; version=0.0.4; charset=utf-8\r\n"));
+        for line in [
+            "httperver_http_requests_total{method=\"GET\",path=\"/items/:id\",status=\"200\"} 3\n",
+            "httperver_http_requests_total{method=\"DELETE\",path=\"/*\",status=\"404\"} 1\n",
+            "httperver_http_requests_total{method=\"GET\",path=\"other\",status=\"403\"} 1\n",
+            // 正在处理的就是这次抓取
+            "httperver_http_requests_in_flight 1\n",
+            "httperver_open_connections 1\n",
+            "httperver_http_request_duration_seconds_bucket{method=\"GET\",path=\"/items/:id\",le=\"0.1\"} 2\n",
+            "httperver_http_request_duration_seconds_bucket{method=\"GET\",path=\"/items/:id\",le=\"1\"} 2\n",
+            "httperver_http_request_duration_seconds_bucket{method=\"GET\",path=\"/items/:id\",le=\"+Inf\"} 3\n",
+            "httperver_http_request_duration_seconds_sum{method=\"GET\",path=\"/items/:id\"} 1.5\n",
+            "httperver_http_request_duration_seconds_count{method=\"GET\",path=\"/items/:id\"} 3\n",
+        ] {
+            assert!(out.contains(line), "missing {:?} in\n{}", line, out);
+        }
+        // 抓取本身在输出之后才记录
+        assert!(metrics
+            .render()
+            .contains("path=\"other\",status=\"200\"} 1\n"));
+        assert_eq!(metrics.in_flight.load(Ordering::Relaxed), 0);
+        // 处理器 panic 时 after 不会执行，正在处理的请求数也要减回去
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            send("GET /shop/boom");
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(metrics.in_flight.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_config() {
+        let config: PrometheusConfig = toml::from_str("path = \"/metrics\"").unwrap();
+        assert!(config.enabled());
+        assert_eq!(config.buckets, DEFAULT_BUCKETS);
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert!(problems.is_empty());
+        PrometheusConfig {
+            path: Some("metrics".to_string()),
+            buckets: vec![1.0, 0.5],
+        }
+        .validate(&mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+}
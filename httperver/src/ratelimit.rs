@@ -0,0 +1,313 @@
+// 限流：令牌桶，默认按客户端 IP 分桶，也可以自己指定按什么分（用户、租户等）
+//
+//     [rate_limit]
+//     requests_per_second = 10
+//     burst = 20
+//
+//     let limiter = RateLimit::new(Rate::new(10.0, 20), Arc::new(MemoryBuckets::new()), clock)
+//         .by_identity();
+//
+// 不要直接拿没验证过的请求头（X-API-Key 等）分桶：客户端每次换一个值就是一个新的满桶
+//
+// 每个桶最多存 burst 个令牌，每秒补充 requests_per_second 个，每个请求取一个；
+// 取不到时回 429，Retry-After 是下一个令牌到来还要等的秒数
+// 桶的状态放在 RateLimitStore 里，默认在进程内存里，多个实例共享限额时可以换成 Redis 的实现
+use crate::auth::RequestIdentity;
+use crate::middleware::Middleware;
+use http::clock::Clock;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// 桶的个数上限，防止大量不同的 IP 把内存撑爆
+pub const MAX_BUCKETS: usize = 10_000;
+
+// [rate_limit]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    // 不设置表示不限流
+    pub requests_per_second: Option<f64>,
+    // 允许的突发请求数，0 表示和 requests_per_second 相同（至少 1）
+    pub burst: u32,
+    // 按认证后的身份分桶，需要 [jwt]，限流放在认证之后；没有身份的请求仍然按 IP
+    pub by_identity: bool,
+}
+
+impl RateLimitConfig {
+    pub fn enabled(&self) -> bool {
+        self.requests_per_second.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if let Some(rate) = self.requests_per_second {
+            if !(rate.is_finite() && rate > 0.0) {
+                problems.push("rate_limit.requests_per_second must be positive".to_string());
+            }
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        let per_second = self.requests_per_second.unwrap_or(1.0);
+        let burst = match self.burst {
+            0 => per_second.ceil().max(1.0) as u32,
+            burst => burst,
+        };
+        Rate::new(per_second, burst)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl Rate {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        Rate { per_second, burst }
+    }
+}
+
+// 令牌桶的存储；take 必须是原子的，Redis 的实现可以用一段 Lua 脚本完成
+pub trait RateLimitStore: Send + Sync {
+    // 从 key 的桶里取一个令牌：成功时返回剩下的令牌数，没有令牌时返回还要等多久
+    fn take(&self, key: &str, rate: Rate, now: SystemTime) -> Result<u32, Duration>;
+}
+
+// 进程内存里的实现，每个实例各自计数
+pub struct MemoryBuckets {
+    // key -> (令牌数, 上次更新的时间)
+    buckets: Mutex<HashMap<String, (f64, SystemTime)>>,
+    max: usize,
+}
+
+impl Default for MemoryBuckets {
+    fn default() -> Self {
+        MemoryBuckets::with_capacity(MAX_BUCKETS)
+    }
+}
+
+impl MemoryBuckets {
+    pub fn new() -> Self {
+        MemoryBuckets::default()
+    }
+    // 最多保留 max 个桶
+    pub fn with_capacity(max: usize) -> Self {
+        MemoryBuckets {
+            buckets: Mutex::new(HashMap::new()),
+            max: max.max(1),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// 从 since 到 now 补充之后的令牌数
+fn refill(tokens: f64, since: SystemTime, now: SystemTime, rate: Rate) -> f64 {
+    let elapsed = now.duration_since(since).unwrap_or_default();
+    (tokens + elapsed.as_secs_f64() * rate.per_second).min(rate.burst as f64)
+}
+
+// 桶满了：先删已经补满的（和新建的没有区别），还多就按上次使用的时间删掉最旧的，
+// 一次删到上限的 3/4，分摊到每个新 key 上的开销不随桶的个数增长
+fn evict(
+    buckets: &mut HashMap<String, (f64, SystemTime)>,
+    max: usize,
+    now: SystemTime,
+    rate: Rate,
+) {
+    buckets.retain(|_, (tokens, since)| refill(*tokens, *since, now, rate) < rate.burst as f64);
+    let keep = max * 3 / 4;
+    if buckets.len() <= keep {
+        return;
+    }
+    let mut times: Vec<SystemTime> = buckets.values().map(|(_, since)| *since).collect();
+    let (_, cutoff, _) = times.select_nth_unstable(buckets.len() - keep - 1);
+    let cutoff = *cutoff;
+    buckets.retain(|_, (_, since)| *since > cutoff);
+}
+
+impl RateLimitStore for MemoryBuckets {
+    fn take(&self, key: &str, rate: Rate, now: SystemTime) -> Result<u32, Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= self.max && !buckets.contains_key(key) {
+            evict(&mut buckets, self.max, now, rate);
+        }
+        let (tokens, since) = buckets
+            .entry(key.to_string())
+            .or_insert((rate.burst as f64, now));
+        let available = refill(*tokens, *since, now, rate);
+        *since = now;
+        if available >= 1.0 {
+            *tokens = available - 1.0;
+            Ok(*tokens as u32)
+        } else {
+            *tokens = available;
+            Err(Duration::from_secs_f64((1.0 - available) / rate.per_second))
+        }
+    }
+}
+
+type KeyFn = Box<dyn Fn(&HttpRequest) -> Option<String> + Send + Sync>;
+
+pub struct RateLimit {
+    rate: Rate,
+    store: Arc<dyn RateLimitStore>,
+    clock: Arc<dyn Clock>,
+    key: KeyFn,
+}
+
+impl RateLimit {
+    // 默认按客户端 IP 分桶，经过可信代理时是代理给的真实 IP
+    pub fn new(rate: Rate, store: Arc<dyn RateLimitStore>, clock: Arc<dyn Clock>) -> Self {
+        RateLimit {
+            rate,
+            store,
+            clock,
+            key: Box::new(|req| req.client_ip().map(|ip| ip.to_string())),
+        }
+    }
+    // 自定义分桶方式，返回 None 的请求不限流；用来分桶的值必须是客户端不能随意伪造的
+    pub fn key(
+        mut self,
+        key: impl Fn(&HttpRequest) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.key = Box::new(key);
+        self
+    }
+    // 通过认证的请求按身份分桶，其他请求按 IP；要注册在 Auth 之后
+    pub fn by_identity(self) -> Self {
+        self.key(|req| match req.identity() {
+            Some(identity) => Some(format!("identity:{}", identity.name)),
+            None => req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
+
+impl Middleware for RateLimit {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let key = (self.key)(req)?;
+        let wait = self.store.take(&key, self.rate, self.clock.now()).err()?;
+        // 向上取整，至少 1 秒
+        let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        Some(
+            HttpResponse::new("429", None, Some("Too Many Requests".into()))
+                .with_header(names::RETRY_AFTER, secs.max(1).to_string())
+                .expect("digits are a valid header value"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Auth;
+    use crate::router::Router;
+    use http::clock::MockClock;
+
+    #[test]
+    fn test_token_bucket() {
+        let clock = MockClock::from_unix_secs(1_000);
+        let store = MemoryBuckets::new();
+        let rate = Rate::new(2.0, 3);
+        let take = |key: &str| store.take(key, rate, clock.now());
+        assert_eq!(take("a"), Ok(2));
+        assert_eq!(take("a"), Ok(1));
+        assert_eq!(take("a"), Ok(0));
+        assert_eq!(take("a"), Err(Duration::from_millis(500)));
+        // 别的 key 有自己的桶
+        assert_eq!(take("b"), Ok(2));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(take("a"), Err(Duration::from_millis(250)));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(take("a"), Ok(0));
+        // 补满之后不再增加
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(take("a"), Ok(2));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_bucket_eviction() {
+        let clock = MockClock::from_unix_secs(1_000);
+        let store = MemoryBuckets::with_capacity(8);
+        let rate = Rate::new(1.0, 5);
+        // 每个 key 都只用掉一个令牌，没有补满的桶可以删
+        for i in 0..100 {
+            clock.advance(Duration::from_millis(10));
+            assert_eq!(store.take(&format!("k{}", i), rate, clock.now()), Ok(4));
+            assert!(store.len() <= 8);
+        }
+        // 留下的是最近用过的
+        assert_eq!(store.take("k99", rate, clock.now()), Ok(3));
+        assert_eq!(store.take("k0", rate, clock.now()), Ok(4));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let clock = Arc::new(MockClock::from_unix_secs(1_000));
+        let config: RateLimitConfig =
+            toml::from_str("requests_per_second = 0.5\nby_identity = true").unwrap();
+        assert_eq!(config.rate(), Rate::new(0.5, 1));
+        // 两个 token 属于同一个用户
+        let auth = Auth::new("api")
+            .bearer(|token| match token {
+                "t1" | "t1b" => Some("alice".to_string()),
+                "t2" => Some("bob".to_string()),
+                _ => None,
+            })
+            .only("/api");
+        let limiter = RateLimit::new(config.rate(), Arc::new(MemoryBuckets::new()), clock.clone())
+            .by_identity();
+        let router = Router::new("")
+            .middleware(auth)
+            .middleware(limiter)
+            .get("/api/x", |_req| {
+                HttpResponse::new("200", None, Some("ok".into()))
+            })
+            .get("/x", |_req| {
+                HttpResponse::new("200", None, Some("ok".into()))
+            });
+        let send = |path: &str, token: Option<&str>, ip: &str| {
+            let mut raw = format!("GET {} HTTP/1.1\r\n", path);
+            if let Some(token) = token {
+                raw.push_str(&format!("Authorization: Bearer {}\r\n", token));
+            }
+            raw.push_str("\r\n");
+            let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+            req.remote_addr = Some(format!("{}:5000", ip).parse().unwrap());
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        assert!(send("/api/x", Some("t1"), "10.0.0.1").starts_with("HTTP/1.1 200"));
+        // 换 token、换 IP 都还是 alice 的桶
+        let out = send("/api/x", Some("t1b"), "10.0.0.2");
+        assert!(out.starts_with("HTTP/1.1 429"), "{}", out);
+        assert!(out.contains("Retry-After:2\r\n"));
+        assert!(send("/api/x", Some("t2"), "10.0.0.1").starts_with("HTTP/1.1 200"));
+        // 没有身份的请求按 IP
+        assert!(send("/x", None, "10.0.0.1").starts_with("HTTP/1.1 200"));
+        assert!(send("/x", None, "10.0.0.1").starts_with("HTTP/1.1 429"));
+        assert!(send("/x", None, "10.0.0.3").starts_with("HTTP/1.1 200"));
+        clock.advance(Duration::from_secs(2));
+        assert!(send("/api/x", Some("t1"), "10.0.0.1").starts_with("HTTP/1.1 200"));
+
+        let mut problems = Vec::new();
+        RateLimitConfig {
+            requests_per_second: Some(0.0),
+            ..RateLimitConfig::default()
+        }
+        .validate(&mut problems);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+    }
+}
@@ -0,0 +1,221 @@
+// 录制 / 回放：把完整的请求和响应（脱敏后）存成 JSON 文件，
+// 回放时把录下的请求重新送进 Router，在进程内复现线上问题
+use crate::router::Router;
+use http::clock::Clock;
+use http::httprequest::HttpRequest;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+// 这些头部的值不会写进录制文件
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: String,
+    pub response: String,
+    // 解析后的请求（同样脱敏），带上客户端地址；旧的录制文件没有这一项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<HttpRequest>,
+}
+
+pub struct Recorder {
+    dir: PathBuf,
+    counter: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Recorder {
+    // 文件名里的时间戳取自 clock，测试里注入 MockClock 就是确定的
+    pub fn new(dir: impl Into<PathBuf>, clock: Arc<dyn Clock>) -> io::Result<Recorder> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Recorder {
+            dir,
+            counter: AtomicU64::new(0),
+            clock,
+        })
+    }
+
+    pub fn record(
+        &self,
+        request: &[u8],
+        remote_addr: Option<SocketAddr>,
+        response: &[u8],
+    ) -> io::Result<PathBuf> {
+        let request = sanitize(&String::from_utf8_lossy(request));
+        let parsed = HttpRequest::try_from(request.as_bytes())
+            .ok()
+            .map(|mut req| {
+                req.remote_addr = remote_addr;
+                req
+            });
+        let exchange = Exchange {
+            request,
+            response: sanitize(&String::from_utf8_lossy(response)),
+            parsed,
+        };
+        let millis = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let n = self.counter.fetch_add(1, Ordering::SeqCst);
+        // 时间戳 + 序号，文件名按录制顺序排序
+        let path = self.dir.join(format!("{}-{:06}.json", millis, n));
+        let json = serde_json::to_string_pretty(&exchange).map_err(io::Error::other)?;
+        fs::write(&path, json)?;
+        Ok(path)
+    }
+}
+
+// 只处理头部部分，空行之后的 body 原样保留
+pub fn sanitize(message: &str) -> String {
+    let (head, body) = match message.find("\r\n\r\n") {
+        Some(i) => (&message[..i], &message[i..]),
+        None => (message, ""),
+    };
+    let head: Vec<String> = head
+        .split("\r\n")
+        .map(|line| match line.split_once(':') {
+            Some((name, _)) if SENSITIVE_HEADERS.contains(&name.trim().to_lowercase().as_str()) => {
+                format!("{}: [REDACTED]", name)
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+    format!("{}{}", head.join("\r\n"), body)
+}
+
+// 写入 stream 的同时保留一份副本，供录制使用
+pub struct TeeWriter<'a, W: Write> {
+    inner: &'a mut W,
+    pub copy: Vec<u8>,
+}
+
+impl<'a, W: Write> TeeWriter<'a, W> {
+    pub fn new(inner: &'a mut W) -> Self {
+        TeeWriter {
+            inner,
+            copy: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for TeeWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.copy.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct ReplayResult {
+    pub path: PathBuf,
+    pub recorded: String,
+    pub actual: String,
+}
+
+impl ReplayResult {
+    pub fn matches(&self) -> bool {
+        self.recorded == self.actual
+    }
+}
+
+pub fn replay_file(router: &Router, path: &Path) -> io::Result<ReplayResult> {
+    let contents = fs::read_to_string(path)?;
+    let exchange: Exchange = serde_json::from_str(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    // 优先用解析好的请求，回放时客户端地址和录制时一致，按来源判断的逻辑（管理接口等）才能复现
+    let req = match exchange.parsed {
+        Some(req) => req,
+        None => HttpRequest::try_from(exchange.request.as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+    };
+    let mut out: Vec<u8> = Vec::new();
+    router.route(req, &mut out);
+    Ok(ReplayResult {
+        path: path.to_path_buf(),
+        recorded: exchange.response,
+        actual: sanitize(&String::from_utf8_lossy(&out)),
+    })
+}
+
+// 参数可以是单个文件，也可以是录制目录（按文件名顺序回放）
+pub fn replay(router: &Router, path: &Path) -> io::Result<Vec<ReplayResult>> {
+    let mut files = if path.is_dir() {
+        fs::read_dir(path)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+            .collect::<Vec<_>>()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+    files.iter().map(|f| replay_file(router, f)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use http::httpresponse::HttpResponse;
+
+    #[test]
+    fn test_sanitize() {
+        let raw = "GET /orders HTTP/1.1\r\nHost: shop\r\nauthorization: Bearer secret\r\nCookie: sid=abc\r\nX-Api-Key : k\r\n\r\nAuthorization: body is kept";
+        assert_eq!(
+            sanitize(raw),
+            "GET /orders HTTP/1.1\r\nHost: shop\r\nauthorization: [REDACTED]\r\nCookie: [REDACTED]\r\nX-Api-Key : [REDACTED]\r\n\r\nAuthorization: body is kept"
+        );
+        // 没有空行的响应头也能处理
+        assert_eq!(
+            sanitize("HTTP/1.1 200 OK\r\nSet-Cookie: sid=abc"),
+            "HTTP/1.1 200 OK\r\nSet-Cookie: [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = crate::testdir::temp_path("record");
+        let clock = MockClock::from_unix_secs(1_700_000_000);
+        let recorder = Recorder::new(&dir, Arc::new(clock)).unwrap();
+        // 只回应本机的请求，回放时客户端地址也要和录制时一样
+        let router = Router::new("").get("/whoami", |req| {
+            let local = req.remote_addr.is_some_and(|a| a.ip().is_loopback());
+            HttpResponse::new("200", None, Some(format!("local={}", local)))
+        });
+        let raw = b"GET /whoami HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n";
+        let mut req = HttpRequest::try_from(&raw[..]).unwrap();
+        let remote_addr = Some("127.0.0.1:4000".parse().unwrap());
+        req.remote_addr = remote_addr;
+        let mut sink = Vec::new();
+        let mut tee = TeeWriter::new(&mut sink);
+        router.route(req, &mut tee);
+        let path = recorder.record(raw, remote_addr, &tee.copy).unwrap();
+        assert!(path.ends_with("1700000000000-000000.json"));
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("secret"));
+
+        let results = replay(&router, &dir).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].matches(), "{}", results[0].actual);
+        assert!(results[0].actual.ends_with("local=true"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
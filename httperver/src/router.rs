@@ -1,37 +1,2081 @@
-use super::handler::{Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler};
+use super::handler::{
+    order_file_store, public_path, resolve_static, Handler, PageNotFoundHandler, StaticPageHandler,
+    WebServiceHandler,
+};
+use crate::assets::{AssetManifests, ASSET_PREFIX};
+use crate::chaos::Fault;
+use crate::content::ContentRoots;
+use crate::deprecation::{DeprecatedRoute, Deprecation};
+use crate::disposition::DispositionConfig;
+use crate::docs::{self, RouteDoc};
+use crate::fastpath::FastResponse;
+use crate::hints::Interim;
+use crate::middleware::Middleware;
+use crate::minify::Minifier;
+use crate::orders::OrderStore;
+use crate::priority::Priority;
+use crate::prometheus::Prometheus;
+use crate::state::{StateLayers, StateMap};
+use crate::tenant::{self, Tenancy};
+use crate::thumb::{ThumbError, Thumbnailer};
+use crate::uploads::{Uploads, RESUMABLE_PREFIX, UPLOAD_PREFIX};
+use crate::upstream::{self, ReverseProxy};
+use crate::versions::ApiVersions;
+use http::codec::{Codec, Codecs};
+use http::etag;
+use http::headers::names;
+use http::mime;
+use http::query::{percent_decode, percent_encode_segment};
+use http::range::{self, RangeRequest};
+use http::status::StatusCode;
 use http::{httprequest, httprequest::HttpRequest, httpresponse::HttpResponse};
+use std::collections::HashMap;
+use std::fmt;
 use std::io::prelude::*;
-// 单元结构体（不包含任何字段）
-pub struct Router;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Router::admin 注册的路由在 RouteInfo::handler 里的名字
+pub const ADMIN_HANDLER: &str = "admin";
+
+pub struct Router {
+    // 整个应用挂载的前缀，例如 "/shop"；空字符串表示挂在根路径
+    base_path: String,
+    routes: Vec<RouteInfo>,
+    // 静态目录，None 时使用全局的 PUBLIC_PATH
+    static_root: Option<String>,
+    // 蓝绿内容目录，设置后优先于 static_root
+    content: Option<Arc<ContentRoots>>,
+    // 静态资源指纹清单，设置后 /static/<带哈希的文件名> 会被永久缓存
+    assets: Option<Arc<AssetManifests>>,
+    // 设置后静态文件响应带 Content-Disposition，按扩展名决定直接打开还是下载
+    disposition: Option<Arc<DispositionConfig>>,
+    // 所有响应带 X-Content-Type-Options: nosniff，默认开启
+    nosniff: bool,
+    // 拒绝发送内容和扩展名对不上的静态文件（例如其实是 HTML 的 .png）
+    sniff_guard: bool,
+    // 设置后 HTML / CSS / JS 响应在返回前精简
+    minifier: Option<Arc<Minifier>>,
+    // 设置后开放 /thumb/<path>?w=&h= 缩略图
+    thumbnails: Option<Arc<Thumbnailer>>,
+    // 设置后开放 POST /uploads 和 GET /uploads/<id>
+    uploads: Option<Arc<Uploads>>,
+    // 订单接口的存储，None 时使用数据目录下的 orders.json
+    orders: Option<Arc<dyn OrderStore>>,
+    // 挂载的子应用：(前缀, 子路由)，前缀已去掉末尾的 /
+    mounts: Vec<(String, Router)>,
+    // 按版本分发的接口，见 Router::versioned
+    versions: Vec<(String, ApiVersions)>,
+    // 通过 register / register_routes! / get 等注册的函数路由，先于内置路由匹配
+    functions: Vec<FnRoute>,
+    // 中间件链，按注册顺序执行 before，相反顺序执行 after
+    middleware: Vec<Arc<dyn Middleware>>,
+    // 通过 state() 注册的共享状态，处理器用 req.state() 或 State<T> 参数取出
+    states: Arc<StateMap>,
+    // 通过 codec() 注册了编解码器时才有，请求体解码和 HttpResponse::encode 按它选格式
+    codecs: Option<Arc<Codecs>>,
+    // 废弃的命名路由，DeprecationNotices 中间件据此加头部
+    deprecations: HashMap<&'static str, Arc<Deprecation>>,
+    // 通过 fast() 注册的预先序列化的响应，键是带 base_path 的完整路径
+    fast: HashMap<String, FastResponse>,
+    // 通过 prometheus() 注册的指标和输出的路径（不含 base_path）
+    prometheus: Option<(String, Arc<Prometheus>)>,
+}
+
+// 函数路由的处理函数，由 #[route] 生成
+pub type RouteFn = fn(&HttpRequest, &PathParams) -> HttpResponse<'static>;
+
+// 注册后统一保存成这个类型，fn 指针和闭包都可以
+type BoxedHandler = Arc<dyn Fn(&HttpRequest, &PathParams) -> HttpResponse<'static> + Send + Sync>;
+
+struct FnRoute {
+    info: RouteInfo,
+    handler: BoxedHandler,
+}
+
+// #[route(GET, "/api/orders/:id")] 生成的路由定义，也可以手写后交给 Router::register
+#[derive(Clone, Copy)]
+pub struct RouteDef {
+    pub name: &'static str,
+    pub method: &'static str,
+    pub path: &'static str,
+    pub handler: RouteFn,
+    pub doc: RouteDoc,
+}
+
+impl RouteDef {
+    fn info(&self) -> RouteInfo {
+        RouteInfo {
+            name: Some(self.name),
+            method: self.method,
+            path: self.path,
+            handler: self.name,
+            priority: Priority::Normal,
+            doc: self.doc,
+        }
+    }
+}
+
+// 匹配到的路由的路径模式，例如 /api/shipping/orders/:id，放在 req.extensions 里，
+// 指标等需要按路由归类又不能用实际路径的地方使用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutePattern(pub &'static str);
+
+// 处理这个请求的路由名，中间件执行之前放进 req.extensions，按路由名配置的中间件使用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteName(pub &'static str);
+
+// 路径参数：路由里 :name 对应的实际路径段，已经做过百分号解码
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PathParams(Vec<(&'static str, String)>);
+
+impl PathParams {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    // 解析失败时返回可以直接发送的 400 响应
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, HttpResponse<'static>> {
+        self.get(name).and_then(|v| v.parse().ok()).ok_or_else(|| {
+            HttpResponse::new(
+                "400",
+                None,
+                Some(format!("invalid path parameter {:?}", name)),
+            )
+        })
+    }
+}
+
+// 路由匹配后路径参数会放进 req.extensions，处理器可以用 req.params() 取出
+pub trait RequestParams {
+    fn params(&self) -> &PathParams;
+}
+
+static NO_PARAMS: PathParams = PathParams(Vec::new());
+
+impl RequestParams for HttpRequest {
+    fn params(&self) -> &PathParams {
+        self.extensions.get::<PathParams>().unwrap_or(&NO_PARAMS)
+    }
+}
+
+// 路由表的描述，供 CLI 的 routes / export 子命令和 url_for 使用
+// path 中 :name 表示一个路径参数，末尾的 *name 匹配剩下的所有部分（可以为空）
+#[derive(Debug, Clone, Copy)]
+pub struct RouteInfo {
+    pub name: Option<&'static str>,
+    pub method: &'static str,
+    pub path: &'static str,
+    pub handler: &'static str,
+    // 服务器繁忙时的调度优先级
+    pub priority: Priority,
+    // GET /docs 页面上的标题、说明和示例
+    pub doc: RouteDoc,
+}
+
+impl RouteInfo {
+    // :name 匹配一个非空的路径段，* 或 *name 匹配剩下的所有部分
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        if self.method != "*" && self.method != method {
+            return false;
+        }
+        let mut actual = path.split('/');
+        for expected in self.path.split('/') {
+            if expected.starts_with('*') {
+                return true;
+            }
+            match actual.next() {
+                Some(seg) if expected.starts_with(':') && !seg.is_empty() => {}
+                Some(seg) if seg == expected => {}
+                _ => return false,
+            }
+        }
+        actual.next().is_none()
+    }
+
+    // 匹配时取出路径参数
+    pub fn params(&self, method: &str, path: &str) -> Option<PathParams> {
+        if !self.matches(method, path) {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut actual = path.split('/');
+        for expected in self.path.split('/') {
+            // 通配的部分按段解码，保留分隔的 /
+            if let Some(name) = expected.strip_prefix('*') {
+                if !name.is_empty() {
+                    let rest: Vec<String> = actual.map(|seg| percent_decode(seg, false)).collect();
+                    params.push((name, rest.join("/")));
+                }
+                break;
+            }
+            let seg = actual.next().unwrap_or("");
+            if let Some(name) = expected.strip_prefix(':') {
+                params.push((name, percent_decode(seg, false)));
+            }
+        }
+        Some(PathParams(params))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UrlError {
+    UnknownRoute(String),
+    MissingParam(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::UnknownRoute(n) => write!(f, "no route named {:?}", n),
+            UrlError::MissingParam(p) => write!(f, "missing route parameter {:?}", p),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new("")
+    }
+}
 
 impl Router {
-    // 为 Router 实现一个 route 方法
+    pub fn new(base_path: &str) -> Self {
+        Router {
+            base_path: base_path.trim_end_matches('/').to_string(),
+            routes: Router::default_routes(),
+            static_root: None,
+            content: None,
+            assets: None,
+            disposition: None,
+            nosniff: true,
+            sniff_guard: false,
+            minifier: None,
+            thumbnails: None,
+            uploads: None,
+            orders: None,
+            mounts: Vec::new(),
+            versions: Vec::new(),
+            functions: Vec::new(),
+            middleware: Vec::new(),
+            states: Arc::new(StateMap::default()),
+            codecs: None,
+            deprecations: HashMap::new(),
+            fast: HashMap::new(),
+            prometheus: None,
+        }
+    }
+    // 注册函数路由，一般通过 register_routes! 调用
+    // 排在内置路由之前，和 route() 中的匹配顺序一致
+    pub fn register(self, def: RouteDef) -> Self {
+        let handler = def.handler;
+        self.push_fn(def.info(), Arc::new(handler))
+    }
+    fn push_fn(mut self, info: RouteInfo, handler: BoxedHandler) -> Self {
+        self.routes.insert(self.functions.len(), info);
+        self.functions.push(FnRoute { info, handler });
+        self
+    }
+    // router.get("/api/shipping/orders/:id", |req| ...)：按方法注册没有名字的路由，
+    // 函数和捕获状态的闭包都可以，路径参数通过 req.params() 读取
+    pub fn get<F>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        self.on("GET", path, handler)
+    }
+    pub fn post<F>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        self.on("POST", path, handler)
+    }
+    pub fn put<F>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        self.on("PUT", path, handler)
+    }
+    pub fn delete<F>(self, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        self.on("DELETE", path, handler)
+    }
+    // 其他方法，method 为 "*" 时匹配任意方法
+    pub fn on<F>(self, method: &'static str, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        let info = RouteInfo {
+            name: None,
+            method,
+            path,
+            handler: "closure",
+            priority: Priority::Normal,
+            doc: RouteDoc::default(),
+        };
+        self.push_fn(info, Arc::new(move |req, _| handler(req)))
+    }
+    // 本机才能访问的管理接口（见 is_local），和其他路由一样经过中间件；IPC 连接没有 remote_addr，也算本机
+    // 其他地址访问时回复 404，不暴露接口是否存在，GET /docs 也不列出
+    pub fn admin<F>(self, method: &'static str, path: &'static str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> HttpResponse<'static> + Send + Sync + 'static,
+    {
+        let info = RouteInfo {
+            name: None,
+            method,
+            path,
+            handler: ADMIN_HANDLER,
+            priority: Priority::High,
+            doc: RouteDoc::default(),
+        };
+        self.push_fn(
+            info,
+            Arc::new(move |req, _| {
+                if is_local(req) {
+                    handler(req)
+                } else {
+                    PageNotFoundHandler::handle(req)
+                }
+            }),
+        )
+    }
+    // 给最近注册的函数路由附上说明文档，显示在 GET /docs 页面上；还没有注册过时忽略
+    pub fn describe(mut self, doc: RouteDoc) -> Self {
+        if let Some(route) = self.functions.last_mut() {
+            route.info.doc = doc;
+            self.routes[self.functions.len() - 1].doc = doc;
+        }
+        self
+    }
+    // 注册一份共享状态，每种类型一份，同类型再次注册时替换
+    // 这个路由器和挂载在它下面的子应用处理的每个请求都能取到
+    pub fn state<T: Send + Sync + 'static>(mut self, state: Arc<T>) -> Self {
+        Arc::make_mut(&mut self.states).insert(state);
+        self
+    }
+    // 注册一种请求体 / 响应体格式，同一媒体类型的内置编解码器被替换
+    // 挂载的子应用自己注册过编解码器时用它自己的（内置的加上它注册的）
+    pub fn codec(mut self, codec: Arc<dyn Codec>) -> Self {
+        let codecs = self
+            .codecs
+            .get_or_insert_with(|| Arc::new(Codecs::builtin()));
+        Arc::make_mut(codecs).register(codec);
+        self
+    }
+    // 添加一个中间件，作用于这个路由器的所有请求，包括挂载的子应用
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+    // GET / HEAD path 直接写出预先序列化好的响应，先于中间件和其他路由，见 fastpath.rs
+    // 只在最外层的路由器上生效，挂载的子应用注册的不会被用到
+    pub fn fast(mut self, path: &str, response: FastResponse) -> Self {
+        self.fast
+            .insert(format!("{}{}", self.base_path, path), response);
+        self
+    }
+    // 统计这个路由器（包括挂载的子应用）处理的请求，GET path 输出 Prometheus 文本格式，见 prometheus.rs
+    // 同时注册成中间件，在它之前注册的中间件短路的请求不会被统计
+    pub fn prometheus(mut self, path: &str, metrics: Arc<Prometheus>) -> Self {
+        self.middleware.push(metrics.clone());
+        self.prometheus = Some((path.to_string(), metrics));
+        self
+    }
+    // 反向代理：中间件转发匹配前缀的请求，/_admin/canary 注册成本机才能访问的路由
+    pub fn upstream(mut self, proxy: Arc<ReverseProxy>) -> Self {
+        self.middleware.push(proxy.clone());
+        self.admin("*", upstream::ADMIN_PATH, move |req| proxy.admin(req))
+    }
+    // 多租户：识别租户、检查配额，同时开放 GET /_admin/tenants 查看统计
+    pub fn tenancy(mut self, tenancy: Arc<Tenancy>) -> Self {
+        self.middleware.push(tenancy.clone());
+        self.admin("GET", tenant::ADMIN_PATH, move |_| {
+            HttpResponse::json(&tenancy.stats())
+        })
+    }
+    // 服务器据此统计打开的连接数
+    pub fn prometheus_metrics(&self) -> Option<&Arc<Prometheus>> {
+        self.prometheus.as_ref().map(|(_, metrics)| metrics)
+    }
+    // 找到匹配的函数路由就返回它的响应
+    fn dispatch_fn(&self, method: &str, req: &HttpRequest) -> Option<HttpResponse<'static>> {
+        let path = req.path();
+        self.functions.iter().find_map(|route| {
+            let params = route.info.params(method, path)?;
+            Some((route.handler)(req, &params))
+        })
+    }
+    // 使用可切换的蓝绿内容目录，同时开放管理接口：
+    // GET /_admin/content 查看当前目录，POST /_admin/content/<name> 切换
+    pub fn content_roots(mut self, content: Arc<ContentRoots>) -> Self {
+        self.content = Some(content.clone());
+        let switch = content.clone();
+        self.admin("GET", "/_admin/content", move |_| {
+            HttpResponse::json(&serde_json::json!({
+                "state": content.state(),
+                "roots": content.names(),
+            }))
+        })
+        .admin("POST", "/_admin/content/:name", move |req| {
+            let name = req.params().get("name").unwrap_or_default();
+            match switch.switch(name) {
+                Ok(state) => {
+                    println!("Content root switched to {}", state.current);
+                    HttpResponse::json(&state)
+                }
+                Err(e) => HttpResponse::json(&serde_json::json!({ "error": e.to_string() }))
+                    .with_status(StatusCode::BadRequest),
+            }
+        })
+    }
+    // 静态文件带内容哈希的地址，清单按实际提供文件的目录计算，见 public_root
+    pub fn asset_hashing(mut self, assets: Arc<AssetManifests>) -> Self {
+        self.assets = Some(assets);
+        // 放在 /:file 之前，与 route() 中的匹配顺序一致
+        let at = self
+            .routes
+            .iter()
+            .position(|r| r.name == Some("static_file"))
+            .unwrap_or(self.routes.len());
+        self.routes.insert(
+            at,
+            RouteInfo {
+                name: Some("asset"),
+                method: "GET",
+                path: "/static/:asset",
+                handler: "StaticPageHandler",
+                priority: Priority::Low,
+                doc: RouteDoc::default(),
+            },
+        );
+        self
+    }
+    pub fn disposition(mut self, policy: Arc<DispositionConfig>) -> Self {
+        self.disposition = Some(policy);
+        self
+    }
+    pub fn order_store(mut self, store: Arc<dyn OrderStore>) -> Self {
+        self.orders = Some(store);
+        self
+    }
+    pub fn nosniff(mut self, enabled: bool) -> Self {
+        self.nosniff = enabled;
+        self
+    }
+    pub fn sniff_guard(mut self, enabled: bool) -> Self {
+        self.sniff_guard = enabled;
+        self
+    }
+    pub fn minifier(mut self, minifier: Arc<Minifier>) -> Self {
+        self.minifier = Some(minifier);
+        self
+    }
+    pub fn thumbnails(mut self, thumbnails: Arc<Thumbnailer>) -> Self {
+        self.thumbnails = Some(thumbnails);
+        let at = self
+            .routes
+            .iter()
+            .position(|r| r.name == Some("static_file"))
+            .unwrap_or(self.routes.len());
+        self.routes.insert(
+            at,
+            RouteInfo {
+                name: Some("thumbnail"),
+                method: "GET",
+                path: "/thumb/:path",
+                handler: "Thumbnailer",
+                priority: Priority::Low,
+                doc: RouteDoc::default(),
+            },
+        );
+        self
+    }
+    pub fn uploads(mut self, uploads: Arc<Uploads>) -> Self {
+        self.uploads = Some(uploads);
+        let at = self
+            .routes
+            .iter()
+            .position(|r| r.name == Some("static_file"))
+            .unwrap_or(self.routes.len());
+        // 按列出的顺序一次插入，前面的路由先匹配
+        let routes = [
+            ("upload", "POST", UPLOAD_PREFIX),
+            ("upload_file", "GET", "/uploads/:id"),
+            ("upload_create", "POST", RESUMABLE_PREFIX),
+            ("upload_status", "GET", "/uploads/resumable/:token"),
+            ("upload_append", "PATCH", "/uploads/resumable/:token"),
+            ("upload_cancel", "DELETE", "/uploads/resumable/:token"),
+        ]
+        .map(|(name, method, path)| RouteInfo {
+            name: Some(name),
+            method,
+            path,
+            handler: "Uploads",
+            priority: Priority::Normal,
+            doc: RouteDoc::default(),
+        });
+        self.routes.splice(at..at, routes);
+        self
+    }
+    // 只给成功的静态文件响应加，404 页面不应该被当成附件下载
+    fn apply_disposition<'a>(
+        &self,
+        mut resp: HttpResponse<'a>,
+        file_name: &str,
+    ) -> HttpResponse<'a> {
+        if let Some(policy) = &self.disposition {
+            if resp.status() == StatusCode::Ok {
+                let _ = resp.set_header(names::CONTENT_DISPOSITION, policy.header(file_name));
+            }
+        }
+        resp
+    }
+    // 上传到静态目录的文件可能伪装成图片，浏览器按内容猜类型时会把它当成 HTML 执行
+    // nosniff 让浏览器按 Content-Type 处理，这里再拒绝发送内容和扩展名对不上的文件
+    fn guard_sniff<'a>(&self, resp: HttpResponse<'a>, file_name: &str) -> HttpResponse<'a> {
+        if !self.sniff_guard || resp.status() != StatusCode::Ok {
+            return resp;
+        }
+        let claimed = resp.header(names::CONTENT_TYPE).unwrap_or_default();
+        // 文件 body 只读开头几百字节，认类型用不到更多
+        let prefix = match resp.file_path() {
+            Some(path) => read_prefix(path, mime::SNIFF_LEN),
+            None => resp.body_bytes().unwrap_or_default().to_vec(),
+        };
+        if mime::contradicts(claimed, &prefix) {
+            eprintln!(
+                "Refusing to serve {}: content does not match {}",
+                file_name, claimed
+            );
+            return HttpResponse::new("403", None, Some("Forbidden".into()));
+        }
+        resp
+    }
+    // 多租户时默认存储按请求落到各自租户的数据目录
+    fn order_store_for(&self, req: &HttpRequest) -> Arc<dyn OrderStore> {
+        match &self.orders {
+            Some(store) => store.clone(),
+            None => Arc::new(order_file_store(req)),
+        }
+    }
+    fn minify<'a>(&self, resp: HttpResponse<'a>, source: Option<&Path>) -> HttpResponse<'a> {
+        match &self.minifier {
+            Some(m) => m.transform(resp, source),
+            None => resp,
+        }
+    }
+    // 模板辅助函数：asset("app.css") -> /shop/static/app.9f3a1b2c.css
+    // 没有清单时返回普通的静态文件地址
+    pub fn asset(&self, name: &str) -> String {
+        let manifest = self
+            .assets
+            .as_ref()
+            .and_then(|assets| assets.for_root(&self.public_root()).ok());
+        let url = match manifest {
+            Some(manifest) => manifest.asset(name),
+            None => format!("/{}", name.trim_start_matches('/')),
+        };
+        format!("{}{}", self.base_path, url)
+    }
+    pub fn static_root(mut self, root: &str) -> Self {
+        self.static_root = Some(root.to_string());
+        self
+    }
+    // 把整个子应用挂到 prefix 下，分发前会去掉 prefix
+    // 例如挂在 /admin 的子应用收到 /admin/health 时看到的是 /health
+    pub fn mount(mut self, prefix: &str, router: Router) -> Self {
+        self.mounts
+            .push((prefix.trim_end_matches('/').to_string(), router));
+        // 长前缀优先，/admin/tools 要先于 /admin 匹配
+        self.mounts.sort_by_key(|m| std::cmp::Reverse(m.0.len()));
+        self
+    }
+    // 把多个版本的接口挂到 prefix 下，/prefix/v2/... 或者带 Accept-Version 的 /prefix/... 交给对应版本
+    pub fn versioned(mut self, prefix: &str, versions: ApiVersions) -> Self {
+        self.versions
+            .push((prefix.trim_end_matches('/').to_string(), versions));
+        self.versions.sort_by_key(|v| std::cmp::Reverse(v.0.len()));
+        self
+    }
+    pub fn versions(&self) -> &[(String, ApiVersions)] {
+        &self.versions
+    }
+    pub fn mounts(&self) -> &[(String, Router)] {
+        &self.mounts
+    }
+
+    // 必须与 route() 中的匹配逻辑保持一致
+    fn default_routes() -> Vec<RouteInfo> {
+        vec![
+            RouteInfo {
+                name: Some("index"),
+                method: "GET",
+                path: "/",
+                handler: "StaticPageHandler",
+                priority: Priority::Low,
+                doc: RouteDoc::default(),
+            },
+            RouteInfo {
+                name: Some("health"),
+                method: "GET",
+                path: "/health",
+                handler: "StaticPageHandler",
+                priority: Priority::High,
+                doc: RouteDoc::default(),
+            },
+            RouteInfo {
+                name: Some("orders"),
+                method: "GET",
+                path: "/api/shipping/orders",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("List orders")
+                    .description("All orders as JSON. With page or per_page the list is paginated and the Link header points to the neighbouring pages.")
+                    .response_example(r#"[{"order_id": 1, "order_date": "21 Jan 2020", "order_status": "Delivered"}]"#),
+            },
+            RouteInfo {
+                name: Some("create_order"),
+                method: "POST",
+                path: "/api/shipping/orders",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Create an order")
+                    .request_example(r#"{"order_date": "21 Jan 2020", "order_status": "Pending"}"#),
+            },
+            RouteInfo {
+                name: Some("order"),
+                method: "GET",
+                path: "/api/shipping/orders/:id",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Get an order"),
+            },
+            RouteInfo {
+                name: Some("replace_order"),
+                method: "PUT",
+                path: "/api/shipping/orders/:id",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Replace an order"),
+            },
+            RouteInfo {
+                name: Some("update_order"),
+                method: "PATCH",
+                path: "/api/shipping/orders/:id",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Update an order")
+                    .description("Only the fields present in the body are changed."),
+            },
+            RouteInfo {
+                name: Some("delete_order"),
+                method: "DELETE",
+                path: "/api/shipping/orders/:id",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Delete an order"),
+            },
+            RouteInfo {
+                name: Some("orders_page"),
+                method: "GET",
+                path: "/orders",
+                handler: "WebServiceHandler",
+                priority: Priority::Normal,
+                doc: RouteDoc::new("Orders page").description("The orders as an HTML table."),
+            },
+            RouteInfo {
+                name: Some("docs"),
+                method: "GET",
+                path: "/docs",
+                handler: "docs",
+                priority: Priority::Low,
+                doc: RouteDoc::new("API reference").description("This page."),
+            },
+            RouteInfo {
+                name: Some("static_file"),
+                method: "GET",
+                path: "/:file",
+                handler: "StaticPageHandler",
+                priority: Priority::Low,
+                doc: RouteDoc::default(),
+            },
+            RouteInfo {
+                name: None,
+                method: "*",
+                path: "/*",
+                handler: "PageNotFoundHandler",
+                priority: Priority::Low,
+                doc: RouteDoc::default(),
+            },
+        ]
+    }
+
+    // 修改某个命名路由的优先级
+    pub fn priority(mut self, name: &str, priority: Priority) -> Result<Self, UrlError> {
+        let route = self
+            .routes
+            .iter_mut()
+            .find(|r| r.name == Some(name))
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_string()))?;
+        route.priority = priority;
+        Ok(self)
+    }
+
+    // 把某个命名路由标记为废弃，需要同时注册 DeprecationNotices 中间件才会加头部
+    pub fn deprecate(mut self, name: &str, deprecation: Deprecation) -> Result<Self, UrlError> {
+        let name = self
+            .routes
+            .iter()
+            .find_map(|r| r.name.filter(|n| *n == name))
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_string()))?;
+        self.deprecations.insert(name, Arc::new(deprecation));
+        Ok(self)
+    }
+
+    // 请求会被哪条路由处理，就用那条路由的优先级；管理接口总是最高
+    // 只看路径不修改请求，accept 线程在排队之前调用
+    pub fn priority_of(&self, req: &HttpRequest) -> Priority {
+        self.priority_for_path(req.method.as_str(), req.path())
+    }
+
+    fn priority_for_path(&self, method: &str, path: &str) -> Priority {
+        let path = match strip_path_prefix(&self.base_path, path) {
+            Some(p) => p,
+            None => return Priority::Low,
+        };
+        if path.starts_with("/_admin") {
+            return Priority::High;
+        }
+        for (prefix, versions) in &self.versions {
+            if let Some(rest) = strip_path_prefix(prefix, &path) {
+                return match versions.router_for_path(&rest) {
+                    Some((sub, rest)) => sub.priority_for_path(method, &rest),
+                    None => Priority::Low,
+                };
+            }
+        }
+        for (prefix, sub) in &self.mounts {
+            if let Some(rest) = strip_path_prefix(prefix, &path) {
+                return sub.priority_for_path(method, &rest);
+            }
+        }
+        self.routes
+            .iter()
+            .find(|r| r.matches(method, &path))
+            .map(|r| r.priority)
+            .unwrap_or(Priority::Low)
+    }
+
+    // 处理这个请求的路由名，限速等按路由配置的功能使用
+    pub fn route_name_of(&self, req: &HttpRequest) -> Option<&'static str> {
+        self.find_route(req.method.as_str(), req.path())?.name
+    }
+
+    fn find_route(&self, method: &str, path: &str) -> Option<&RouteInfo> {
+        let path = strip_path_prefix(&self.base_path, path)?;
+        for (prefix, versions) in &self.versions {
+            if let Some(rest) = strip_path_prefix(prefix, &path) {
+                let (sub, rest) = versions.router_for_path(&rest)?;
+                return sub.find_route(method, &rest);
+            }
+        }
+        for (prefix, sub) in &self.mounts {
+            if let Some(rest) = strip_path_prefix(prefix, &path) {
+                return sub.find_route(method, &rest);
+            }
+        }
+        self.routes.iter().find(|r| r.matches(method, &path))
+    }
+
+    pub fn routes(&self) -> &[RouteInfo] {
+        &self.routes
+    }
+    pub fn base_path(&self) -> &str {
+        &self.base_path
+    }
+
+    // 根据路由名生成相对 URL（含 base_path），参数值按路径段编码
+    // 自己的路由表里找不到时，再到挂载的子应用里找
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let route = match self.routes.iter().find(|r| r.name == Some(name)) {
+            Some(r) => r,
+            None => {
+                for (prefix, sub) in &self.mounts {
+                    match sub.url_for(name, params) {
+                        Ok(url) => return Ok(format!("{}{}{}", self.base_path, prefix, url)),
+                        Err(UrlError::UnknownRoute(_)) => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+                return Err(UrlError::UnknownRoute(name.to_string()));
+            }
+        };
+        let mut url = self.base_path.clone();
+        let value = |param: &str| {
+            params
+                .iter()
+                .find(|(k, _)| *k == param)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| UrlError::MissingParam(param.to_string()))
+        };
+        for segment in route.path.split('/').skip(1) {
+            url.push('/');
+            if let Some(param) = segment.strip_prefix(':') {
+                url.push_str(&percent_encode_segment(value(param)?));
+            } else if let Some(param) = segment.strip_prefix('*').filter(|p| !p.is_empty()) {
+                // 通配参数可以包含多段，每段分别编码
+                let segments: Vec<String> = value(param)?
+                    .split('/')
+                    .map(percent_encode_segment)
+                    .collect();
+                url.push_str(&segments.join("/"));
+            } else {
+                url.push_str(segment);
+            }
+        }
+        Ok(url)
+    }
+
+    // 绝对 URL，协议和主机取自请求（经过可信代理时取转发头）
+    pub fn absolute_url_for(
+        &self,
+        req: &HttpRequest,
+        name: &str,
+        params: &[(&str, &str)],
+    ) -> Result<String, UrlError> {
+        Ok(format!(
+            "{}{}",
+            req.effective_origin(),
+            self.url_for(name, params)?
+        ))
+    }
+
+    // 去掉 base_path 前缀，不在 base_path 下的请求返回 false
+    fn strip_base_path(&self, req: &mut HttpRequest) -> bool {
+        strip_prefix(&self.base_path, req)
+    }
+
+    // 实际使用的静态目录，每个请求只取一次
+    fn public_root(&self) -> String {
+        match &self.content {
+            Some(content) => content.current_path(),
+            None => self.static_root.clone().unwrap_or_else(public_path),
+        }
+    }
+
     // 实现了 Write trait 的可变引用，用于写入响应，impl Write 允许这个方法接受任何实现了 Write trait 的类型，提高了灵活性
-    pub fn route(req: HttpRequest, stream: &mut impl Write) {
-        // 只处理Get请求
+    pub fn route(&self, mut req: HttpRequest, stream: &mut impl Write) {
+        let head = req.method == httprequest::Method::Head;
+        if head || req.method == httprequest::Method::Get {
+            if let Some(fast) = self.fast.get(req.path()) {
+                let _ = fast.write(stream, head);
+                return;
+            }
+        }
+        // 服务器没有提供直接写到连接的通道时，处理器发的 103 先缓存，在最终响应之前写出
+        if req.extensions.get::<Interim>().is_none() {
+            req.extensions.insert(Interim::buffered());
+        }
+        let resp = self.respond(&mut req);
+        let resp = apply_conditional(&req, resp);
+        let interim = Interim::take(&mut req);
+        if !interim.is_empty() && stream.write_all(&interim).is_err() {
+            return;
+        }
+        // Chaos 中间件注入的故障：什么都不写，或者只写一半
+        match req.extensions.get::<Fault>() {
+            Some(Fault::Drop) => return,
+            Some(Fault::Truncate) => {
+                let out: Vec<u8> = resp.into();
+                let _ = stream.write_all(&out[..out.len() / 2]);
+                return;
+            }
+            _ => {}
+        }
+        // HEAD 和 GET 走同样的处理，只发送状态行和头部
+        let _ = if req.method == httprequest::Method::Head {
+            resp.send_head(stream)
+        } else {
+            resp.send_with(stream, req.accepts_chunked())
+        };
+    }
+
+    // 经过中间件链得到响应，还没有写出
+    pub(crate) fn respond(&self, req: &mut HttpRequest) -> HttpResponse<'static> {
+        if !self.states.is_empty() {
+            StateLayers::push(req, &self.states);
+        }
+        if let Some(codecs) = &self.codecs {
+            req.extensions.insert(codecs.clone());
+        }
+        let mut ran = 0;
+        let mut early = None;
+        // 中间件可能改写路径（例如按路径区分租户），路由名按改写后的路径重新查
+        let mut named: Option<String> = None;
+        for m in &self.middleware {
+            if named.as_deref() != Some(req.path()) {
+                req.extensions.remove::<RouteName>();
+                if let Some(name) = self.route_name_of(req) {
+                    req.extensions.insert(RouteName(name));
+                }
+                named = Some(req.path().to_string());
+            }
+            ran += 1;
+            early = m.before(req);
+            if early.is_some() {
+                break;
+            }
+        }
+        let mut resp = match early {
+            Some(resp) => resp,
+            None => self.dispatch(req),
+        };
+        for m in self.middleware[..ran].iter().rev() {
+            m.after(req, &mut resp);
+        }
+        if self.nosniff && resp.header(names::X_CONTENT_TYPE_OPTIONS).is_none() {
+            let _ = resp.set_header(names::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        }
+        resp
+    }
+
+    fn dispatch(&self, req: &mut HttpRequest) -> HttpResponse<'static> {
+        if !self.strip_base_path(req) {
+            return PageNotFoundHandler::handle(req);
+        }
+        if let Some((path, metrics)) = &self.prometheus {
+            let get = matches!(
+                req.method,
+                httprequest::Method::Get | httprequest::Method::Head
+            );
+            if get && req.path() == path {
+                return metrics.respond();
+            }
+        }
+        // 先交给匹配的子应用
+        for (prefix, versions) in &self.versions {
+            if strip_prefix(prefix, req) {
+                return versions.respond(req);
+            }
+        }
+        for (prefix, sub) in &self.mounts {
+            if strip_prefix(prefix, req) {
+                return sub.respond(req);
+            }
+        }
+        // 路由表里匹配的路由取出路径参数，处理器通过 req.params() 读取
+        let method = match req.method {
+            httprequest::Method::Head => "GET",
+            ref m => m.as_str(),
+        };
+        if let Some((route, params)) = self
+            .routes
+            .iter()
+            .find_map(|r| Some((r, r.params(method, req.path())?)))
+        {
+            req.extensions.insert(params);
+            req.extensions.insert(RoutePattern(route.path));
+            if let Some((name, deprecation)) =
+                route.name.and_then(|n| self.deprecations.get_key_value(n))
+            {
+                req.extensions.insert(DeprecatedRoute {
+                    name,
+                    deprecation: deprecation.clone(),
+                });
+            }
+        }
         match req.method {
-            // 如果是 GET 方法，进一步匹配请求的资源。
-            // &&req.resource 中的双引用  匹配模式
-            httprequest::Method::Get => match &req.resource {
-                httprequest::Resource::Path(s) => {
-                    // localhost  /  xxx/xxx/xxx
-                    let route: Vec<&str> = s.split("/").collect();
-                    match route[1] {
-                        "api" => {
-                            let resp: HttpResponse = WebServiceHandler::handle(&req);
-                            let _ = resp.send_response(stream);
-                        }
-                        _ => {
-                            let resp: HttpResponse = StaticPageHandler::handle(&req);
-                            let _ = resp.send_response(stream);
-                        }
+            httprequest::Method::Get | httprequest::Method::Head => self.route_get(req),
+            _ => self.route_other(req),
+        }
+    }
+
+    // 如果是 GET 方法，进一步匹配请求的资源。
+    fn route_get(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        // localhost  /  xxx/xxx/xxx，查询字符串不参与匹配
+        if let Some(resp) = self.dispatch_fn("GET", req) {
+            return resp;
+        }
+        let s = req.path();
+        let route: Vec<&str> = s.split("/").collect();
+        match route.get(1).copied().unwrap_or_default() {
+            "static" if self.assets.is_some() => {
+                let hashed = s[ASSET_PREFIX.len()..].trim_start_matches('/');
+                // 清单和文件来自同一个目录，内容目录切换后哈希跟着变
+                let root = self.public_root();
+                let manifest = self.assets.as_ref().unwrap().for_root(&root).ok();
+                match manifest.as_ref().and_then(|m| m.original(hashed)) {
+                    Some(name) => {
+                        let resp = StaticPageHandler::serve_asset(&root, name);
+                        let resp = self.guard_sniff(resp, name);
+                        let resp = self.apply_disposition(resp, name);
+                        let resp = self.minify(resp, Some(&Path::new(&root).join(name)));
+                        apply_range(req, resp)
                     }
+                    None => PageNotFoundHandler::handle(req),
                 }
-            },
+            }
+            "thumb" if self.thumbnails.is_some() => {
+                let thumbs = self.thumbnails.as_ref().unwrap();
+                match thumbs.handle(&self.public_root(), req) {
+                    Ok(thumb) => {
+                        let mut headers = std::collections::HashMap::new();
+                        headers.insert(names::CONTENT_TYPE, thumb.content_type);
+                        headers.insert(names::CACHE_CONTROL, "public, max-age=86400");
+                        HttpResponse::new("200", Some(headers), None).with_bytes(thumb.bytes)
+                    }
+                    Err(ThumbError::BadRequest(msg)) => HttpResponse::new("400", None, Some(msg)),
+                    Err(ThumbError::NotFound) => PageNotFoundHandler::handle(req),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        HttpResponse::new("500", None, Some(String::new()))
+                    }
+                }
+            }
+            "uploads" if self.uploads.is_some() => self.uploads.as_ref().unwrap().handle(req),
+            "docs" if s == "/docs" => docs::page(self),
+            "orders" => {
+                let store = self.order_store_for(req);
+                self.minify(WebServiceHandler::orders_page(store.as_ref()), None)
+            }
+            "api" => WebServiceHandler::api(self.order_store_for(req).as_ref(), req),
             _ => {
-                let resp: HttpResponse = PageNotFoundHandler::handle(&req);
-                let _ = resp.send_response(stream);
+                let root = self.public_root();
+                let resp = StaticPageHandler::serve(&root, req);
+                let name = StaticPageHandler::file_name(s.trim_start_matches('/'));
+                let resp = self.guard_sniff(resp, name);
+                let resp = self.apply_disposition(resp, &percent_decode(name, false));
+                let source = resolve_static(&root, name).ok();
+                let resp = self.minify(resp, source.as_deref());
+                apply_range(req, resp)
             }
         }
     }
+
+    // 路由表里能处理这个路径的方法，没有任何路由匹配时为空
+    // 有 GET 就有 HEAD，OPTIONS 总是允许
+    pub fn allowed_methods(&self, path: &str) -> Vec<&'static str> {
+        let mut methods: Vec<&'static str> = Vec::new();
+        for r in self.routes.iter().filter(|r| r.method != "*") {
+            if r.matches(r.method, path) && !methods.contains(&r.method) {
+                methods.push(r.method);
+            }
+        }
+        if methods.is_empty() {
+            return methods;
+        }
+        if methods.contains(&"GET") {
+            methods.push("HEAD");
+        }
+        methods.push("OPTIONS");
+        methods
+    }
+
+    // OPTIONS 返回 Allow；路径存在但方法不支持时返回 405，否则 404
+    fn route_other(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        if let Some(resp) = self.dispatch_fn(req.method.as_str(), req) {
+            return resp;
+        }
+        if let Some(uploads) = &self.uploads {
+            let path = req.path();
+            let matched = self
+                .routes
+                .iter()
+                .any(|r| r.handler == "Uploads" && r.matches(req.method.as_str(), path));
+            if matched {
+                return uploads.handle(req);
+            }
+        }
+        let path = req.path();
+        let matched = self.routes.iter().any(|r| {
+            r.handler == "WebServiceHandler"
+                && r.method != "GET"
+                && r.matches(req.method.as_str(), path)
+        });
+        if matched {
+            return WebServiceHandler::api(self.order_store_for(req).as_ref(), req);
+        }
+        let allowed = self.allowed_methods(req.path());
+        if allowed.is_empty() {
+            PageNotFoundHandler::handle(req)
+        } else {
+            let status = if req.method == httprequest::Method::Options {
+                "204"
+            } else {
+                "405"
+            };
+            HttpResponse::new(status, None, Some(String::new()))
+                .with_header(names::ALLOW, allowed.join(", "))
+                .expect("method names are valid header values")
+        }
+    }
+}
+
+// 与 strip_prefix 的规则相同，只作用于路径字符串
+fn strip_path_prefix(prefix: &str, path: &str) -> Option<String> {
+    if prefix.is_empty() {
+        return Some(path.to_string());
+    }
+    match path.strip_prefix(prefix) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            Some(format!("/{}", rest.trim_start_matches('/')))
+        }
+        _ => None,
+    }
+}
+
+// 管理接口的访问检查：经过可信代理时按转发头里的客户端地址判断，
+// 否则本机代理转发来的外部请求也会被当成本机
+fn is_local(req: &HttpRequest) -> bool {
+    match &req.forwarded {
+        Some(forwarded) => forwarded.client_ip.is_some_and(|ip| ip.is_loopback()),
+        None => req.remote_addr.is_none_or(|a| a.ip().is_loopback()),
+    }
+}
+
+// prefix 为空时总是匹配；/admin 匹配 /admin、/admin/x、/admin?x，不匹配 /administrator
+fn strip_prefix(prefix: &str, req: &mut HttpRequest) -> bool {
+    if prefix.is_empty() {
+        return true;
+    }
+    let httprequest::Resource::Path(path) = &mut req.resource;
+    match path.strip_prefix(prefix) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') || rest.starts_with('?') => {
+            *path = format!("/{}", rest.trim_start_matches('/'));
+            true
+        }
+        _ => false,
+    }
+}
+
+// GET / HEAD 的 200 响应都带 ETag（静态文件在 HttpResponse::file 里生成，其他的按 body 内容生成），
+// 客户端缓存的版本还是最新的时回 304；206 的 ETag 是整个文件的，同样适用
+fn apply_conditional<'a>(req: &HttpRequest, resp: HttpResponse<'a>) -> HttpResponse<'a> {
+    if !matches!(
+        req.method,
+        httprequest::Method::Get | httprequest::Method::Head
+    ) {
+        return resp;
+    }
+    let resp = match resp.status() {
+        StatusCode::Ok => resp.with_etag(),
+        StatusCode::PartialContent => resp,
+        _ => return resp,
+    };
+    let fresh = etag::is_fresh(
+        req.header(names::IF_NONE_MATCH),
+        req.header(names::IF_MODIFIED_SINCE),
+        resp.header(names::ETAG),
+        resp.header(names::LAST_MODIFIED),
+    );
+    if fresh {
+        resp.not_modified()
+    } else {
+        resp
+    }
+}
+
+// 静态文件支持 Range，下载工具可以断点续传，播放器可以拖动进度条
+// If-Range 是 ETag 时做强比较，是日期时和 Last-Modified 比较；对不上说明文件已经变了，忽略 Range 返回整个文件
+fn apply_range<'a>(req: &HttpRequest, mut resp: HttpResponse<'a>) -> HttpResponse<'a> {
+    if resp.status() != StatusCode::Ok {
+        return resp;
+    }
+    let Some(len) = resp.body_len() else {
+        return resp;
+    };
+    let _ = resp.set_header(names::ACCEPT_RANGES, "bytes");
+    let unchanged = |validator: &str| {
+        if validator.starts_with('"') || validator.starts_with("W/") {
+            resp.header(names::ETAG)
+                .is_some_and(|tag| etag::strong_match(validator, tag))
+        } else {
+            resp.header(names::LAST_MODIFIED) == Some(validator)
+        }
+    };
+    let header = match req.header(names::IF_RANGE) {
+        Some(validator) if !unchanged(validator) => None,
+        _ => req.header(names::RANGE),
+    };
+    match range::resolve(header, len) {
+        RangeRequest::Full => resp,
+        RangeRequest::Partial(r) => resp.with_range(r),
+        RangeRequest::Unsatisfiable => HttpResponse::new("416", None, Some(String::new()))
+            .with_header(names::CONTENT_RANGE, range::unsatisfiable_range(len))
+            .expect("valid header"),
+    }
+}
+
+// 文件开头最多 len 个字节，读不了时为空
+fn read_prefix(path: &Path, len: usize) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(len);
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(len as u64).read_to_end(&mut prefix);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_url_for() {
+        let router = Router::new("/shop/");
+        assert_eq!(
+            router.url_for("orders", &[]).unwrap(),
+            "/shop/api/shipping/orders"
+        );
+        assert_eq!(
+            router
+                .url_for("static_file", &[("file", "my app.css")])
+                .unwrap(),
+            "/shop/my%20app.css"
+        );
+        assert_eq!(
+            router.url_for("static_file", &[]),
+            Err(UrlError::MissingParam("file".into()))
+        );
+        assert_eq!(
+            router.url_for("nope", &[]),
+            Err(UrlError::UnknownRoute("nope".into()))
+        );
+        let req = HttpRequest::try_from("GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            router.absolute_url_for(&req, "health", &[]).unwrap(),
+            "http://example.com/shop/health"
+        );
+    }
+    #[test]
+    fn test_strip_base_path() {
+        let router = Router::new("/shop");
+        let mut req =
+            HttpRequest::try_from("GET /shop/health HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+        assert!(router.strip_base_path(&mut req));
+        assert_eq!(req.resource, httprequest::Resource::Path("/health".into()));
+        let mut req = HttpRequest::try_from("GET /shopping HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+        assert!(!router.strip_base_path(&mut req));
+    }
+    #[test]
+    fn test_hashed_asset() {
+        let dir = crate::testdir::temp_path("assets");
+        let mut roots = std::collections::BTreeMap::new();
+        for (name, css) in [("blue", "body {}"), ("green", "body { color: red }")] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            std::fs::write(dir.join(name).join("app.css"), css).unwrap();
+            roots.insert(
+                name.to_string(),
+                dir.join(name).to_string_lossy().into_owned(),
+            );
+        }
+        // public_path 下没有 app.css，清单必须来自当前的内容目录
+        let content = Arc::new(ContentRoots::new(roots, "blue").unwrap());
+        let router = Router::new("/shop")
+            .content_roots(content.clone())
+            .asset_hashing(Arc::new(AssetManifests::new()));
+        let get = |url: &str| {
+            let req =
+                HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", url).as_bytes()).unwrap();
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let blue = router.asset("app.css");
+        assert!(blue.starts_with("/shop/static/app."));
+        let out = get(&blue);
+        assert!(out.starts_with("HTTP/1.1 200"));
+        assert!(out.contains("immutable"));
+        assert!(out.contains("Content-Type:text/css; charset=utf-8"));
+        assert!(out.ends_with("body {}"));
+        // 切换之后换成新目录的哈希，旧地址不再提供
+        content.switch("green").unwrap();
+        let green = router.asset("app.css");
+        assert_ne!(green, blue);
+        assert!(get(&green).ends_with("body { color: red }"));
+        assert!(get(&blue).starts_with("HTTP/1.1 404"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_static_file_streamed() {
+        let root = crate::testdir::temp_path("stream");
+        std::fs::create_dir_all(&root).unwrap();
+        // 不是 UTF-8，以前读成 String 时会变成 404
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(root.join("blob.bin"), &data).unwrap();
+        let router = Router::new("").static_root(&root.to_string_lossy());
+        let send = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            out
+        };
+        let out = send("GET /blob.bin HTTP/1.0\r\n\r\n");
+        let head = String::from_utf8_lossy(head_end(&out)).into_owned();
+        assert!(head.starts_with("HTTP/1.1 200"));
+        assert!(head.contains("Content-Length: 200000\r\n"));
+        assert!(head.contains("Last-Modified:"));
+        assert_eq!(&out[head.len()..], &data[..]);
+        assert!(head.contains("Accept-Ranges:bytes\r\n"));
+        let out = send("HEAD /blob.bin HTTP/1.1\r\n\r\n");
+        assert_eq!(out.len(), head.len());
+        // 断点续传
+        let out = send("GET /blob.bin HTTP/1.1\r\nRange: bytes=100000-\r\n\r\n");
+        let head = String::from_utf8_lossy(head_end(&out)).into_owned();
+        assert!(head.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+        assert!(head.contains("Content-Range:bytes 100000-199999/200000\r\n"));
+        assert!(head.contains("Content-Length: 100000\r\n"));
+        assert_eq!(&out[head.len()..], &data[100_000..]);
+        let out = send("GET /blob.bin HTTP/1.1\r\nRange: bytes=-10\r\n\r\n");
+        assert_eq!(&out[head_end(&out).len()..], &data[199_990..]);
+        let out = send("GET /blob.bin HTTP/1.1\r\nRange: bytes=200000-\r\n\r\n");
+        let head = String::from_utf8_lossy(head_end(&out)).into_owned();
+        assert!(head.starts_with("HTTP/1.1 416"));
+        assert!(head.contains("Content-Range:bytes */200000\r\n"));
+        // 文件变了（If-Range 对不上）时返回整个文件
+        let out = send(
+            "GET /blob.bin HTTP/1.1\r\nRange: bytes=0-9\r\n\
+             If-Range: Mon, 01 Jan 1990 00:00:00 GMT\r\n\r\n",
+        );
+        assert!(out.starts_with(b"HTTP/1.1 200"));
+        assert_eq!(out.len() - head_end(&out).len(), data.len());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    fn head_end(out: &[u8]) -> &[u8] {
+        let end = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        &out[..end + 4]
+    }
+    #[test]
+    fn test_conditional_get() {
+        let root = crate::testdir::temp_path("etag");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("app.css"), "body{}").unwrap();
+        let router = Router::new("")
+            .static_root(&root.to_string_lossy())
+            .get("/api/greeting", |_req| {
+                HttpResponse::new("200", None, Some("hello".into()))
+            });
+        let send = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let header = |out: &str, name: &str| {
+            out.lines()
+                .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+                .unwrap()
+                .to_string()
+        };
+        let out = send("GET /app.css HTTP/1.1\r\n\r\n");
+        let (tag, modified) = (header(&out, "ETag"), header(&out, "Last-Modified"));
+        let out = send(&format!(
+            "GET /app.css HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n",
+            tag
+        ));
+        assert!(out.starts_with("HTTP/1.1 304 Not Modified\r\n"), "{}", out);
+        assert!(out.ends_with("\r\n\r\n") && !out.contains("Content-Length"));
+        let out = send(&format!(
+            "HEAD /app.css HTTP/1.1\r\nIf-Modified-Since: {}\r\n\r\n",
+            modified
+        ));
+        assert!(out.starts_with("HTTP/1.1 304"));
+        let out = send("GET /app.css HTTP/1.1\r\nIf-None-Match: \"old\"\r\n\r\n");
+        assert!(out.ends_with("body{}"));
+        // If-Range 用 ETag
+        let out = send(&format!(
+            "GET /app.css HTTP/1.1\r\nRange: bytes=0-3\r\nIf-Range: {}\r\n\r\n",
+            tag
+        ));
+        assert!(out.starts_with("HTTP/1.1 206") && out.ends_with("\r\n\r\nbody"));
+        let out = send("GET /app.css HTTP/1.1\r\nRange: bytes=0-3\r\nIf-Range: \"old\"\r\n\r\n");
+        assert!(out.starts_with("HTTP/1.1 200"));
+        // API 响应按内容生成 ETag
+        let out = send("GET /api/greeting HTTP/1.1\r\n\r\n");
+        let tag = header(&out, "ETag");
+        let out = send(&format!(
+            "GET /api/greeting HTTP/1.1\r\nIf-None-Match: W/{}\r\n\r\n",
+            tag
+        ));
+        assert!(out.starts_with("HTTP/1.1 304"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn test_sniff_guard() {
+        let root = crate::testdir::temp_path("sniff");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("evil.png"), "<html><script>alert(1)</script>").unwrap();
+        std::fs::write(root.join("ok.png"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        let send = |router: &Router, path: &str| {
+            let req = HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8_lossy(&out).into_owned()
+        };
+        let plain = Router::new("").static_root(&root.to_string_lossy());
+        assert!(send(&plain, "/evil.png").starts_with("HTTP/1.1 200"));
+        let guarded = Router::new("")
+            .static_root(&root.to_string_lossy())
+            .sniff_guard(true);
+        let resp = send(&guarded, "/evil.png");
+        assert!(resp.starts_with("HTTP/1.1 403"));
+        assert!(resp.contains("X-Content-Type-Options:nosniff"));
+        assert!(send(&guarded, "/ok.png").starts_with("HTTP/1.1 200"));
+        let off = Router::new("").nosniff(false);
+        assert!(!send(&off, "/missing").contains("X-Content-Type-Options"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[test]
+    fn test_static_disposition() {
+        let root = crate::testdir::temp_path("disposition");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("数据.csv"), "a,b").unwrap();
+        std::fs::write(root.join("page.txt"), "page").unwrap();
+        let policy = crate::disposition::DispositionConfig {
+            attachment: vec!["csv".into()],
+            ..Default::default()
+        };
+        let router = Router::new("")
+            .static_root(&root.to_string_lossy())
+            .disposition(Arc::new(policy));
+        let send = |path: &str| {
+            let req = HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let resp = send("/%E6%95%B0%E6%8D%AE.csv");
+        assert!(resp.contains(
+            "Content-Disposition:attachment; filename=\"__.csv\"; filename*=UTF-8''%E6%95%B0%E6%8D%AE.csv"
+        ));
+        assert!(send("/page.txt").contains("Content-Disposition:inline; filename=\"page.txt\""));
+        assert!(!send("/missing.csv").contains("Content-Disposition"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[test]
+    fn test_static_traversal() {
+        let dir = crate::testdir::temp_path("traversal");
+        let root = dir.join("public");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/guide.txt"), "guide").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+        let router = Router::new("").static_root(&root.to_string_lossy());
+        let send = |path: &str| {
+            let req = HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let resp = send("/docs/guide.txt");
+        assert!(resp.starts_with("HTTP/1.1 200"));
+        assert!(resp.contains("Content-Type:text/plain; charset=utf-8"));
+        assert!(resp.contains("X-Content-Type-Options:nosniff"));
+        for bad in [
+            "/../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/docs/..%2f..%2fsecret.txt",
+            "/docs/%2E%2E/%2E%2E/secret.txt",
+            "/docs\\..\\..\\secret.txt",
+        ] {
+            assert!(send(bad).starts_with("HTTP/1.1 403"), "{}", bad);
+        }
+        #[cfg(unix)]
+        assert!(send("/link.txt").starts_with("HTTP/1.1 403"));
+        assert!(send("/docs/missing.txt").starts_with("HTTP/1.1 404"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_content_admin() {
+        let dir = crate::testdir::temp_path("content-admin");
+        let mut roots = std::collections::BTreeMap::new();
+        for name in ["blue", "green"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            roots.insert(
+                name.to_string(),
+                dir.join(name).to_string_lossy().into_owned(),
+            );
+        }
+        let content = Arc::new(ContentRoots::new(roots, "blue").unwrap());
+        let router = Router::new("").content_roots(content.clone());
+        let send = |raw: &str, remote: Option<&str>| {
+            let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+            req.remote_addr = remote.map(|a| a.parse().unwrap());
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        // 本机 IPC 没有对端地址，和其他管理接口一样当作本机
+        let out = send("GET /_admin/content HTTP/1.1\r\n\r\n", None);
+        assert!(out.contains(r#""current":"blue""#), "{}", out);
+        let out = send(
+            "GET /_admin/content HTTP/1.1\r\n\r\n",
+            Some("10.0.0.1:5000"),
+        );
+        assert!(out.starts_with("HTTP/1.1 404"));
+        let out = send(
+            "POST /_admin/content/green HTTP/1.1\r\n\r\n",
+            Some("127.0.0.1:5000"),
+        );
+        assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+        assert_eq!(content.state().current, "green");
+        let out = send("POST /_admin/content/red HTTP/1.1\r\n\r\n", None);
+        assert!(out.starts_with("HTTP/1.1 400"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_admin_behind_proxy() {
+        let router = Router::new("").admin("GET", "/_admin/ping", |_| {
+            HttpResponse::new("200", None, Some("pong".into()))
+        });
+        let proxies = http::proxy::TrustedProxies::parse(&["127.0.0.1/32"]).unwrap();
+        let send = |forwarded_for: Option<&str>| {
+            let raw = match forwarded_for {
+                Some(ip) => format!(
+                    "GET /_admin/ping HTTP/1.1\r\nX-Forwarded-For: {}\r\n\r\n",
+                    ip
+                ),
+                None => "GET /_admin/ping HTTP/1.1\r\n\r\n".to_string(),
+            };
+            let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+            req.remote_addr = Some("127.0.0.1:5000".parse().unwrap());
+            proxies.apply(&mut req);
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        // 本机代理转发来的外部请求不算本机
+        assert!(send(Some("203.0.113.9")).starts_with("HTTP/1.1 404"));
+        assert!(send(Some("127.0.0.1")).ends_with("pong"));
+        assert!(send(None).ends_with("pong"));
+    }
+    #[test]
+    fn test_upload_route_order() {
+        let dir = crate::testdir::temp_path("upload-routes");
+        let uploads = Uploads::new(&dir).unwrap();
+        let router = Router::new("").uploads(Arc::new(uploads));
+        let names: Vec<_> = router
+            .routes()
+            .iter()
+            .filter_map(|r| r.name.filter(|n| n.starts_with("upload")))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "upload",
+                "upload_file",
+                "upload_create",
+                "upload_status",
+                "upload_append",
+                "upload_cancel"
+            ]
+        );
+        // 都在 /:file 之前
+        let static_file = router
+            .routes()
+            .iter()
+            .position(|r| r.name == Some("static_file"));
+        let upload = router
+            .routes()
+            .iter()
+            .position(|r| r.name == Some("upload"));
+        assert!(upload < static_file);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_request_targets() {
+        let router = Router::new("").get("/ping", |_| {
+            HttpResponse::new("200", None, Some("pong".into()))
+        });
+        let send = |req: HttpRequest| {
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8_lossy(&out).into_owned()
+        };
+        let parse = |raw: &str| HttpRequest::try_from(raw.as_bytes()).unwrap();
+        // 完整 URL 形式按路径路由
+        let out = send(parse("GET http://x/ping?a=1 HTTP/1.1\r\n\r\n"));
+        assert!(out.ends_with("pong"), "{}", out);
+        // 不以 / 开头的路径也不能让路由 panic
+        let mut req = parse("OPTIONS * HTTP/1.1\r\n\r\n");
+        req.method = httprequest::Method::Get;
+        assert!(send(req).starts_with("HTTP/1.1 404"));
+    }
+    #[test]
+    fn test_route_ignores_query() {
+        let data = crate::testdir::temp_path("query");
+        std::fs::create_dir_all(&data).unwrap();
+        let file = data.join("orders.json");
+        std::fs::write(
+            &file,
+            r#"[{"order_id":1,"order_date":"2024-01-01","order_status":"Shipped"}]"#,
+        )
+        .unwrap();
+        let store = crate::orders::JsonFileStore::new(&file);
+        let req =
+            HttpRequest::try_from("GET /api/shipping/orders?sort=id HTTP/1.1\r\n\r\n".as_bytes())
+                .unwrap();
+        let mut out = Vec::new();
+        Router::new("")
+            .order_store(Arc::new(store))
+            .route(req, &mut out);
+        assert!(String::from_utf8(out).unwrap().contains(r#""order_id":1"#));
+        std::fs::remove_dir_all(&data).unwrap();
+    }
+    #[test]
+    fn test_orders_crud() {
+        let dir = crate::testdir::temp_path("crud");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("orders.json");
+        std::fs::write(
+            &file,
+            r#"[{"order_id":1,"order_date":"21Jan2020","order_status":"Delivered"}]"#,
+        )
+        .unwrap();
+        let store = crate::orders::JsonFileStore::new(&file);
+        let router = Router::new("").order_store(Arc::new(store));
+        let send = |line: &str, body: &str| {
+            let raw = format!(
+                "{} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                line,
+                body.len(),
+                body
+            );
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let resp = send(
+            "POST /api/shipping/orders",
+            r#"{"order_date":"22Jan2020","order_status":"Pending"}"#,
+        );
+        assert!(resp.starts_with("HTTP/1.1 201 Created"), "{}", resp);
+        assert!(resp.contains("Location:/api/shipping/orders/2"));
+        let resp = send(
+            "POST /api/shipping/orders",
+            r#"{"order_id":1,"order_date":"x","order_status":"y"}"#,
+        );
+        assert!(resp.starts_with("HTTP/1.1 409 Conflict"));
+        let resp = send("POST /api/shipping/orders", r#"{"order_date":""}"#);
+        assert!(resp.starts_with("HTTP/1.1 422"));
+        assert!(resp.contains(r#""order_status":"is required""#));
+        let resp = send(
+            "PATCH /api/shipping/orders/2",
+            r#"{"order_status":"Shipped"}"#,
+        );
+        assert!(resp.contains(r#""order_date":"22Jan2020","order_status":"Shipped""#));
+        let resp = send(
+            "PUT /api/shipping/orders/2",
+            r#"{"order_id":3,"order_date":"a","order_status":"b"}"#,
+        );
+        assert!(resp.starts_with("HTTP/1.1 422"));
+        assert!(send(
+            "PUT /api/shipping/orders/9",
+            r#"{"order_date":"a","order_status":"b"}"#
+        )
+        .starts_with("HTTP/1.1 404"));
+        assert!(send("DELETE /api/shipping/orders/1", "").starts_with("HTTP/1.1 204"));
+        assert!(send("DELETE /api/shipping/orders/1", "").starts_with("HTTP/1.1 404"));
+        let orders: Vec<crate::orders::OrderStatus> =
+            serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_id, 2);
+        assert_eq!(orders[0].order_status, "Shipped");
+        // 最大的 id 到头了，自动分配不出新 id
+        let resp = send(
+            "POST /api/shipping/orders",
+            r#"{"order_id":2147483647,"order_date":"a","order_status":"b"}"#,
+        );
+        assert!(resp.starts_with("HTTP/1.1 201"), "{}", resp);
+        let resp = send(
+            "POST /api/shipping/orders",
+            r#"{"order_date":"a","order_status":"b"}"#,
+        );
+        assert!(resp.starts_with("HTTP/1.1 409"), "{}", resp);
+        assert!(resp.contains("no order id left"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_orders_pagination() {
+        let dir = crate::testdir::temp_path("pages");
+        std::fs::create_dir_all(&dir).unwrap();
+        let orders: Vec<_> = (1..=5)
+            .map(|id| {
+                serde_json::json!({ "order_id": id, "order_date": "21Jan2020", "order_status": "Pending" })
+            })
+            .collect();
+        std::fs::write(
+            dir.join("orders.json"),
+            serde_json::to_vec(&orders).unwrap(),
+        )
+        .unwrap();
+        let store = crate::orders::JsonFileStore::new(dir.join("orders.json"));
+        let router = Router::new("").order_store(Arc::new(store));
+        let send = |path: &str| {
+            let raw = format!("GET {} HTTP/1.1\r\n\r\n", path);
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let resp = send("/api/shipping/orders?page=2&per_page=2");
+        assert!(resp.contains(
+            "Link:</api/shipping/orders?page=1&per_page=2>; rel=\"first\", \
+             </api/shipping/orders?page=1&per_page=2>; rel=\"prev\", \
+             </api/shipping/orders?page=3&per_page=2>; rel=\"next\", \
+             </api/shipping/orders?page=3&per_page=2>; rel=\"last\"\r\n"
+        ));
+        let body: serde_json::Value =
+            serde_json::from_str(resp.split_once("\r\n\r\n").unwrap().1).unwrap();
+        let ids: Vec<_> = body["orders"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|o| o["order_id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(ids, vec![3, 4]);
+        assert_eq!(body["total"], 5);
+        assert_eq!(
+            body["_links"]["next"]["href"],
+            "/api/shipping/orders?page=3&per_page=2"
+        );
+        // 最后一页没有 next
+        let resp = send("/api/shipping/orders?page=3&per_page=2");
+        assert!(!resp.contains("rel=\"next\""));
+        assert!(resp.contains(r#""orders":[{"order_id":5"#));
+        // 不分页时仍然是数组
+        assert!(send("/api/shipping/orders").contains("\r\n\r\n[{"));
+        assert!(send("/api/shipping/orders?page=0").starts_with("HTTP/1.1 400"));
+        assert!(send("/api/shipping/orders?per_page=x").starts_with("HTTP/1.1 400"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_orders_cbor() {
+        use crate::orders::OrderStatus;
+        use http::codec::Codecs;
+        let dir = crate::testdir::temp_path("cbor");
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = crate::orders::JsonFileStore::new(dir.join("orders.json"));
+        let router = Router::new("").order_store(Arc::new(store));
+        let codecs = Codecs::builtin();
+        let cbor = codecs.for_accept(Some("application/cbor")).unwrap();
+        let order = OrderStatus {
+            order_id: 5,
+            order_date: "1Feb2020".into(),
+            order_status: "Pending".into(),
+        };
+        let body = cbor.encode(&order).unwrap();
+        let mut raw = format!(
+            "POST /api/shipping/orders HTTP/1.1\r\nContent-Type: application/cbor\r\n\
+             Accept: application/cbor\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(&body);
+        let mut out = Vec::new();
+        router.route(HttpRequest::try_from(raw.as_slice()).unwrap(), &mut out);
+        let head = String::from_utf8_lossy(&out).into_owned();
+        assert!(head.starts_with("HTTP/1.1 201 Created"), "{}", head);
+        assert!(head.contains("Content-Type:application/cbor\r\n"));
+        assert!(out.ends_with(&body));
+        // 错误也按 Accept 编码
+        let mut out = Vec::new();
+        let req = "GET /api/shipping/orders/9 HTTP/1.1\r\nAccept: application/cbor\r\n\r\n";
+        router.route(HttpRequest::try_from(req.as_bytes()).unwrap(), &mut out);
+        let error = serde_json::json!({ "error": "order not found" });
+        assert!(out.ends_with(&cbor.encode(&error).unwrap()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn test_route_methods() {
+        let router = Router::new("");
+        let send = |line: &str| {
+            let req =
+                HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes()).unwrap();
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let resp = send("OPTIONS /api/shipping/orders");
+        assert!(resp.starts_with("HTTP/1.1 204 No Content"));
+        assert!(resp.contains("Allow:GET, POST, HEAD, OPTIONS"));
+        let resp = send("DELETE /api/shipping/orders");
+        assert!(resp.starts_with("HTTP/1.1 405 Method Not Allowed"));
+        assert_eq!(router.allowed_methods("/a/b/c"), Vec::<&str>::new());
+    }
+    #[test]
+    fn test_priority_of() {
+        let router = Router::new("/shop")
+            .priority("index", Priority::Normal)
+            .unwrap();
+        let priority = |line: &str| {
+            let req =
+                HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes()).unwrap();
+            router.priority_of(&req)
+        };
+        assert_eq!(priority("GET /shop/health"), Priority::High);
+        assert_eq!(priority("GET /shop/_admin/content"), Priority::High);
+        assert_eq!(
+            priority("GET /shop/api/shipping/orders?sort=id"),
+            Priority::Normal
+        );
+        assert_eq!(priority("GET /shop/"), Priority::Normal);
+        assert_eq!(priority("GET /shop/app.css"), Priority::Low);
+        assert_eq!(priority("POST /shop/health"), Priority::Low);
+        assert!(Router::new("").priority("nope", Priority::High).is_err());
+    }
+    #[test]
+    fn test_mounted_router() {
+        let admin = Router::new("");
+        let router = Router::new("").mount("/admin/", admin);
+        assert_eq!(
+            router.url_for("orders", &[]).unwrap(),
+            "/api/shipping/orders"
+        );
+        let only_admin = Router {
+            routes: Vec::new(),
+            ..Router::new("/app")
+        }
+        .mount("/admin", Router::new(""));
+        assert_eq!(
+            only_admin.url_for("health", &[]).unwrap(),
+            "/app/admin/health"
+        );
+    }
+    #[test]
+    fn test_mount_dispatch() {
+        // 子应用看到的是去掉前缀之后的路径，查询字符串保留
+        let echo = |name: &'static str| {
+            Router::new("")
+                .get("/", move |_| {
+                    HttpResponse::new("200", None, Some(format!("{} root", name)))
+                })
+                .get("/echo", move |req| {
+                    let httprequest::Resource::Path(target) = &req.resource;
+                    HttpResponse::new("200", None, Some(format!("{} {}", name, target)))
+                })
+        };
+        let router = Router::new("/app")
+            .mount("/mnt/", echo("mnt"))
+            .mount("/mnt/deep", echo("deep"));
+        let send = |path: &str| {
+            let raw = format!("GET {} HTTP/1.1\r\n\r\n", path);
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            let out = String::from_utf8(out).unwrap();
+            match out.split_once("\r\n\r\n") {
+                Some((head, body)) if head.starts_with("HTTP/1.1 200") => body.to_string(),
+                _ => out[..12].to_string(),
+            }
+        };
+        // 末尾的 / 可有可无，两种写法都到子应用的根路径
+        assert_eq!(send("/app/mnt"), "mnt root");
+        assert_eq!(send("/app/mnt/"), "mnt root");
+        assert_eq!(send("/app/mnt?x=1"), "mnt root");
+        assert_eq!(send("/app/mnt/echo?x=1"), "mnt /echo?x=1");
+        assert_eq!(send("/app/mnt//echo"), "mnt /echo");
+        // 长前缀优先
+        assert_eq!(send("/app/mnt/deep/echo"), "deep /echo");
+        assert_eq!(send("/app/mnt/deeper/echo"), "HTTP/1.1 404");
+        // 前缀只按整段匹配
+        assert_eq!(send("/app/mntx/echo"), "HTTP/1.1 404");
+        assert_eq!(send("/mnt/echo"), "HTTP/1.1 404");
+    }
+    #[crate::route(GET, "/api/items/:id/:name")]
+    fn item(req: &HttpRequest, id: u32, name: String) -> HttpResponse<'static> {
+        let body = format!("{} {} {}", id, name, req.query().len());
+        HttpResponse::new("200", None, Some(body))
+    }
+    #[crate::route(DELETE, "/api/items/:id", title = "Delete an item", response = "3")]
+    fn delete_item(id: u32) -> HttpResponse<'static> {
+        HttpResponse::new("204", None, Some(format!("{}", id)))
+    }
+    #[test]
+    fn test_registered_routes() {
+        let router = crate::register_routes!(Router::new("/shop"), item, delete_item);
+        let send = |line: &str| {
+            let req = HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let out = send("GET /shop/api/items/7/a%20b?x=1");
+        assert!(out.starts_with("HTTP/1.1 200 OK"));
+        assert!(out.ends_with("\r\n\r\n7 a b 1"));
+        // 参数解析失败时不会调用函数
+        assert!(send("GET /shop/api/items/x/a").starts_with("HTTP/1.1 400"));
+        assert!(send("DELETE /shop/api/items/3").starts_with("HTTP/1.1 204"));
+        assert!(send("HEAD /shop/api/items/7/a").ends_with("\r\n\r\n"));
+        assert_eq!(
+            router.allowed_methods("/api/items/3"),
+            vec!["DELETE", "OPTIONS"]
+        );
+        assert_eq!(
+            router
+                .url_for("item", &[("id", "1"), ("name", "x y")])
+                .unwrap(),
+            "/shop/api/items/1/x%20y"
+        );
+        assert_eq!(router.routes()[0].name, Some("item"));
+        assert_eq!(router.routes()[0].doc, RouteDoc::default());
+        assert_eq!(
+            router.routes()[1].doc,
+            RouteDoc::new("Delete an item").response_example("3")
+        );
+    }
+    fn file(req: &HttpRequest) -> HttpResponse<'static> {
+        let params = req.params();
+        let body = format!(
+            "{} {}",
+            params.get("bucket").unwrap(),
+            params.get("rest").unwrap()
+        );
+        HttpResponse::new("200", None, Some(body))
+    }
+    #[test]
+    fn test_pattern_routes() {
+        let router = Router::new("").get("/files/:bucket/*rest", file);
+        let send = |line: &str| {
+            let req = HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        assert!(send("GET /files/b1/a/b%20c.txt").ends_with("\r\n\r\nb1 a/b c.txt"));
+        assert!(send("GET /files/b1/").ends_with("\r\n\r\nb1 "));
+        assert!(send("GET /files").starts_with("HTTP/1.1 404"));
+        // 没有名字的路由不出现在 url_for 里
+        assert_eq!(router.routes()[0].name, None);
+
+        let info = RouteInfo {
+            name: Some("file"),
+            method: "GET",
+            path: "/files/*rest",
+            handler: "fn",
+            priority: Priority::Normal,
+            doc: RouteDoc::default(),
+        };
+        let router = Router {
+            routes: vec![info],
+            ..Router::new("")
+        };
+        assert_eq!(
+            router.url_for("file", &[("rest", "a b/c")]).unwrap(),
+            "/files/a%20b/c"
+        );
+    }
+    #[test]
+    fn test_closure_routes() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // 闭包可以带自己的状态，不需要新建处理器类型
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        let router = Router::new("")
+            .post("/api/hits", move |_req| {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                HttpResponse::new("201", None, Some(n.to_string()))
+            })
+            .on("*", "/echo/:word", |req| {
+                let body = format!(
+                    "{} {}",
+                    req.method.as_str(),
+                    req.params().get("word").unwrap()
+                );
+                HttpResponse::new("200", None, Some(body))
+            });
+        let send = |line: &str| {
+            let req = HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        assert!(send("POST /api/hits").ends_with("\r\n\r\n1"));
+        assert!(send("POST /api/hits").ends_with("\r\n\r\n2"));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(send("GET /api/hits").starts_with("HTTP/1.1 404"));
+        assert!(send("PUT /echo/hi").ends_with("\r\n\r\nPUT hi"));
+        assert!(send("GET /echo/hi").ends_with("\r\n\r\nGET hi"));
+    }
+
+    // after 里把自己的名字追加到 X-Trace，用来检查执行顺序
+    struct Trace(&'static str);
+    impl Middleware for Trace {
+        fn after(&self, _req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+            let trace = match resp.header("X-Trace") {
+                Some(t) => format!("{},{}", t, self.0),
+                None => self.0.to_string(),
+            };
+            resp.set_header("X-Trace", trace).unwrap();
+        }
+    }
+    struct RequireToken;
+    impl Middleware for RequireToken {
+        fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+            match req.header("Authorization") {
+                Some(_) => None,
+                None => Some(HttpResponse::new("401", None, Some(String::new()))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_middleware() {
+        let admin = Router::new("")
+            .middleware(RequireToken)
+            .get("/secret", |_req| {
+                HttpResponse::new("200", None, Some("ok".into()))
+            });
+        let router = Router::new("")
+            .middleware(Trace("outer"))
+            .middleware(Trace("inner"))
+            .mount("/admin", admin);
+        let send = |head: &str| {
+            let req = HttpRequest::try_from(format!("{}\r\n\r\n", head).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        // 子应用的中间件短路，外层的 after 仍然执行，后注册的先执行
+        let resp = send("GET /admin/secret HTTP/1.1");
+        assert!(resp.starts_with("HTTP/1.1 401"));
+        assert!(resp.contains("X-Trace:inner,outer"));
+        let resp = send("GET /admin/secret HTTP/1.1\r\nAuthorization: Bearer t");
+        assert!(resp.starts_with("HTTP/1.1 200"));
+        assert!(resp.ends_with("\r\n\r\nok"));
+        // HEAD 也经过中间件，只是不发送 body
+        let resp = send("HEAD /admin/secret HTTP/1.1\r\nAuthorization: Bearer t");
+        assert!(resp.contains("X-Trace:inner,outer"));
+        assert!(resp.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_fast_path() {
+        let router = Router::new("/shop")
+            .middleware(RequireToken)
+            .fast("/health", FastResponse::text("ok"))
+            .fast("/favicon.ico", FastResponse::no_content());
+        let send = |line: &str| {
+            let req = HttpRequest::try_from(format!("{} HTTP/1.1\r\n\r\n", line).as_bytes());
+            let mut out = Vec::new();
+            router.route(req.unwrap(), &mut out);
+            out
+        };
+        // 不经过中间件，查询字符串不影响匹配
+        let ok = FastResponse::text("ok");
+        assert_eq!(send("GET /shop/health?probe=1"), ok.as_bytes());
+        assert!(send("HEAD /shop/health").ends_with(b"Content-Length: 2\r\n\r\n"));
+        assert!(send("GET /shop/favicon.ico").starts_with(b"HTTP/1.1 204"));
+        // 其他方法和路径照常走中间件
+        assert!(send("POST /shop/health").starts_with(b"HTTP/1.1 401"));
+        assert!(send("GET /shop/health/").starts_with(b"HTTP/1.1 401"));
+    }
 }
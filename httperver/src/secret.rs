@@ -0,0 +1,140 @@
+// 密钥、口令一类的敏感值：TLS 私钥、JWT 密钥、API key、数据库密码
+// 配置文件里只写从哪里读取（环境变量或文件），不写值本身；
+// 读出来的值包在 Secret 里，Debug 不打印内容，释放时清零
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use zeroize::Zeroize;
+
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    // 名字故意起得显眼，用到明文的地方一搜就能找到
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Secret(self.0.clone())
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum SecretError {
+    MissingEnv(String),
+    Read(PathBuf, io::Error),
+    Empty(String),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::MissingEnv(name) => write!(f, "environment variable {} is not set", name),
+            SecretError::Read(p, e) => write!(f, "cannot read secret {}: {}", p.display(), e),
+            SecretError::Empty(from) => write!(f, "secret from {} is empty", from),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+// jwt_secret = { env = "JWT_SECRET" }
+// tls_key = { file = "/run/secrets/tls.key" }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum SecretSource {
+    Env(String),
+    File(PathBuf),
+}
+
+impl SecretSource {
+    pub fn load(&self) -> Result<Secret<String>, SecretError> {
+        let mut value = match self {
+            SecretSource::Env(name) => {
+                env::var(name).map_err(|_| SecretError::MissingEnv(name.clone()))?
+            }
+            SecretSource::File(path) => {
+                fs::read_to_string(path).map_err(|e| SecretError::Read(path.clone(), e))?
+            }
+        };
+        // echo 写出来的文件末尾带换行，不算密钥的一部分
+        let len = value.trim_end_matches(['\r', '\n']).len();
+        value.truncate(len);
+        let secret = Secret::new(value);
+        if secret.expose().is_empty() {
+            return Err(SecretError::Empty(self.to_string()));
+        }
+        Ok(secret)
+    }
+}
+
+// 用于报错和日志，只说明来源
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Env(name) => write!(f, "env {}", name),
+            SecretSource::File(path) => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = Secret::from("hunter2".to_string());
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn test_load_secret() {
+        let path = crate::testdir::temp_path("secret");
+        fs::write(&path, "s3cret\n").unwrap();
+        let source = SecretSource::File(path.clone());
+        assert_eq!(source.load().unwrap().expose(), "s3cret");
+        fs::write(&path, "\n").unwrap();
+        assert!(matches!(source.load(), Err(SecretError::Empty(_))));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(source.load(), Err(SecretError::Read(..))));
+
+        let missing = SecretSource::Env("HTTPERVER_TEST_NO_SUCH_SECRET".into());
+        assert!(matches!(missing.load(), Err(SecretError::MissingEnv(_))));
+
+        let source: SecretSource = toml::from_str::<toml::Table>("s = { env = \"API_KEY\" }")
+            .unwrap()["s"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(source, SecretSource::Env("API_KEY".into()));
+    }
+}
@@ -1,30 +1,1507 @@
 // use super::router::Router;
-use http::httprequest::HttpRequest;
-use std::{io::prelude::*, net::TcpListener};
+use http::headers::{self, names};
+use http::httprequest::{self, HttpRequest, Limits, ParseError};
+use http::httpresponse::HttpResponse;
+use http::proxy::TrustedProxies;
+use std::io::prelude::*;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::router::Router;
+use crate::bots::BotGuard;
+use crate::connlimit::{ConnLimiter, ConnPermit};
+use crate::fds::FdPressure;
+use crate::handler::{Handler, PageNotFoundHandler};
+use crate::hints::Interim;
+use crate::ipc::{IpcListener, IpcStream};
+use crate::latency::Latency;
+use crate::listener;
+use crate::memory::MemoryGuard;
+use crate::metrics::{Metrics, QueueTime};
+use crate::neterror::{self, ErrorClass};
+use crate::priority::{Priority, PriorityQueue};
+use crate::prometheus::OpenConnection;
+use crate::record::{Recorder, TeeWriter};
+use crate::router::{RequestParams, Router};
+use crate::shutdown::{self, ShutdownHandle};
+use crate::throttle::Throttle;
+use crate::tls;
+use rustls::ServerConfig;
 
 pub struct Server<'a> {
     socket_addr: &'a str,
+    recorder: Option<Recorder>,
+    trusted_proxies: TrustedProxies,
+    bots: Option<Arc<BotGuard>>,
+    latency: Option<Arc<Latency>>,
+    #[cfg(feature = "geoip")]
+    geoip: Option<crate::geoip::GeoIp>,
+    #[cfg(feature = "otel")]
+    telemetry: Option<crate::otel::Telemetry>,
+    router: Router,
+    queue_capacity: usize,
+    workers: usize,
+    keep_alive: KeepAlive,
+    timeouts: Timeouts,
+    // 请求行、头部和 body 的大小上限
+    limits: Limits,
+    memory: Option<Arc<MemoryGuard>>,
+    // 单个 IP 的并发连接上限，本机可以通过 GET /_admin/conn_limit 查看
+    conn_limit: Option<ConnLimiter>,
+    // fd 耗尽时的退避和统计，本机可以通过 GET /_admin/fds 查看
+    fds: Arc<FdPressure>,
+    ipc: Option<String>,
+    throttle: Throttle,
+    // 排队时间和处理时间，本机可以通过 GET /_admin/metrics 查看
+    metrics: Arc<Metrics>,
+    shutdown: ShutdownHandle,
+    // 收到 SIGINT / SIGTERM 时优雅退出，独立运行时打开
+    handle_signals: bool,
+    // 停止接受连接后，最多等这么久让排队的请求处理完
+    drain_timeout: Duration,
+    // HTTPS 的监听地址和证书
+    tls: Option<(String, Arc<ServerConfig>)>,
 }
+
+// 错误信息里带上绑定的地址，启动失败时知道是哪个监听
+fn bind_error(addr: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("cannot listen on {}: {}", addr, e))
+}
+
+// 空闲的长连接每隔这么久检查一次是否需要关闭
+const IDLE_POLL: Duration = Duration::from_millis(200);
+// 内核缓冲不足等暂时性错误后稍等再 accept，避免空转
+const TRANSIENT_PAUSE: Duration = Duration::from_millis(10);
+
+// 默认最多排队这么多请求
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+// 默认的工作线程数
+pub const DEFAULT_WORKERS: usize = 4;
+// 默认的优雅退出等待时间，要短于 httperver stop 的等待时间
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 5;
+
+// 长连接的限制：一个连接最多处理多少个请求，两个请求之间最多空闲多久
+// 空闲的连接会占住一个工作线程等待下一个请求，所以空闲时间不宜太长
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepAlive {
+    pub max_requests: usize,
+    pub idle_timeout: Duration,
+}
+
+pub const DEFAULT_KEEP_ALIVE_MAX_REQUESTS: usize = 100;
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT_SECS: u64 = 5;
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            max_requests: DEFAULT_KEEP_ALIVE_MAX_REQUESTS,
+            idle_timeout: Duration::from_secs(DEFAULT_KEEP_ALIVE_TIMEOUT_SECS),
+        }
+    }
+}
+
+// 读写超时，防止慢速攻击（slowloris）：客户端连上之后一个字节一个字节地慢慢发，或者干脆不发，
+// 没有超时的话每个这样的连接都会一直占住一个工作线程
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    // 从收到请求的第一个字节起，头部必须在这么长时间内读完，隔一会儿发一个字节也逃不过
+    pub header: Duration,
+    // 每次读最多等多久：新连接上的第一个字节、头部和 body 都受它限制
+    // 长连接上两个请求之间的等待由 KeepAlive::idle_timeout 决定
+    pub read: Duration,
+    // 从收到请求的第一个字节起，整个请求（包括 body）必须在这么长时间内读完，
+    // 防止慢慢上传 body 的客户端每次都赶在 read 超时之前发几个字节
+    pub request: Duration,
+    // 每次写最多等多久，对端一直不收数据时放弃这个连接
+    pub write: Duration,
+}
+
+pub const DEFAULT_HEADER_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+pub const DEFAULT_WRITE_TIMEOUT_SECS: u64 = 30;
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            header: Duration::from_secs(DEFAULT_HEADER_TIMEOUT_SECS),
+            read: Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS),
+            request: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            write: Duration::from_secs(DEFAULT_WRITE_TIMEOUT_SECS),
+        }
+    }
+}
+impl Timeouts {
+    // 从 started 收到第一个字节起还剩多少时间：头部没读完时受 header 限制，之后只受 request 限制
+    pub(crate) fn left(&self, started: Instant, head_done: bool) -> Duration {
+        let limit = if head_done {
+            self.request
+        } else {
+            self.header.min(self.request)
+        };
+        limit.saturating_sub(started.elapsed())
+    }
+}
+
 impl<'a> Server<'a> {
     pub fn new(socket_addr: &'a str) -> Self {
-        Server { socket_addr }
-    }
-    pub fn run(&self) {
-        let connection_listener = TcpListener::bind(self.socket_addr).unwrap();
-        println!("Running on {}", self.socket_addr);
-        // 取出stream
-        for stream in connection_listener.incoming() {
-            let mut stream = stream.unwrap();
-            // 访问数据存入
-            let mut buffer = [0; 1024];
-            // 访问数据写入
-            stream.read(&mut buffer).unwrap();
-            // 字符串反向推断为 HttpRequest
-            let req: HttpRequest = String::from_utf8(buffer.to_vec()).unwrap().into();
-            // 使用req 和 流的引用  调用router
-            Router::route(req, &mut stream);
+        Server {
+            socket_addr,
+            recorder: None,
+            trusted_proxies: TrustedProxies::default(),
+            bots: None,
+            latency: None,
+            #[cfg(feature = "geoip")]
+            geoip: None,
+            #[cfg(feature = "otel")]
+            telemetry: None,
+            router: Router::default().admin("GET", "/_admin/:name", status_page),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            workers: DEFAULT_WORKERS,
+            keep_alive: KeepAlive::default(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            memory: None,
+            conn_limit: None,
+            fds: Arc::new(FdPressure::new()),
+            ipc: None,
+            throttle: Throttle::default(),
+            metrics: Arc::new(Metrics::new()),
+            shutdown: ShutdownHandle::new(),
+            handle_signals: false,
+            drain_timeout: Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECS),
+            tls: None,
+        }
+    }
+    // 运行状态的管理接口注册在最后，应用自己的 /_admin 路由优先
+    pub fn router(mut self, router: Router) -> Self {
+        self.router = router.admin("GET", "/_admin/:name", status_page);
+        self
+    }
+    // 来自这些地址的请求才会解析 Forwarded / X-Forwarded-* 头
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+    // 按 User-Agent 分类，对爬虫限速或拒绝
+    pub fn bots(mut self, bots: BotGuard) -> Self {
+        self.bots = Some(Arc::new(bots));
+        self
+    }
+    // 按路由统计延迟分位数，本机可以通过 GET /_admin/latency 查看
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.latency = Some(Arc::new(latency));
+        self
+    }
+    // 查询客户端的国家 / ASN，结果放进 req.extensions
+    #[cfg(feature = "geoip")]
+    pub fn geoip(mut self, geoip: crate::geoip::GeoIp) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+    // 每个请求记录一个 span，和耗时统计一起定期推给 collector
+    #[cfg(feature = "otel")]
+    pub fn telemetry(mut self, telemetry: crate::otel::Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+    // 开启录制模式，每个请求/响应对写入 recorder 的目录
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+    // 堆内存超过高水位时拒绝新连接、关闭已有的长连接，本机可以通过 GET /_admin/memory 查看
+    pub fn memory_guard(mut self, guard: MemoryGuard) -> Self {
+        self.memory = Some(Arc::new(guard));
+        self
+    }
+    // 同一个 IP 超过上限的新连接回复 429 后关闭
+    pub fn conn_limit(mut self, limiter: ConnLimiter) -> Self {
+        self.conn_limit = Some(limiter);
+        self
+    }
+    // 同时在本机 IPC 上提供服务：unix 上是 socket 文件路径，Windows 上是命名管道名
+    pub fn ipc(mut self, name: impl Into<String>) -> Self {
+        self.ipc = Some(name.into());
+        self
+    }
+    // 响应限速，按连接和按路由
+    pub fn bandwidth(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+    // 收到 SIGINT / SIGTERM 时优雅退出；嵌入到其他程序时一般不打开，用 shutdown_handle
+    pub fn handle_signals(mut self, enabled: bool) -> Self {
+        self.handle_signals = enabled;
+        self
+    }
+    // 优雅退出时等待排队请求的时间，超时后剩下的请求回复 503；正在处理的请求总会处理完
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+    // 在其他线程上调用 shutdown() 让 run() 返回
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+    fn stop_requested(&self) -> bool {
+        self.shutdown.is_requested() || (self.handle_signals && shutdown::signalled())
+    }
+    // 同时在 addr 上提供 HTTPS，和明文连接共用队列、工作线程和路由
+    // 不停机升级时只交接明文的监听套接字
+    pub fn tls(mut self, addr: impl Into<String>, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some((addr.into(), config));
+        self
+    }
+    // 排队上限，超过后按优先级丢弃请求
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+    // 固定数量的工作线程，读取请求和处理请求都在工作线程上进行，一个慢客户端只占住一个线程
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+    // max_requests 为 1 时每个连接只处理一个请求
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    // accept 线程只负责接受连接，连接放进队列交给工作线程读取和解析，
+    // 解析出的请求按优先级重新排队，再由工作线程取出处理
+    // 监听地址绑定失败（端口被占用、IPC 路径不可用等）时返回错误，不再启动
+    pub fn run(&self) -> io::Result<()> {
+        let connection_listener =
+            listener::bind(self.socket_addr).map_err(|e| bind_error(self.socket_addr, e))?;
+        println!("Running on {}", connection_listener.local_addr()?);
+        #[cfg(unix)]
+        crate::upgrade::install_upgrade_handler();
+        if self.handle_signals {
+            shutdown::install_signal_handler();
+        }
+        let ipc_listener = match self.ipc.as_deref() {
+            Some(name) => {
+                let listener = IpcListener::bind(name).map_err(|e| bind_error(name, e))?;
+                println!("Running on {}", listener.name());
+                Some(listener)
+            }
+            None => None,
+        };
+        let tls_listener = match &self.tls {
+            Some((addr, config)) => {
+                let listener = TcpListener::bind(addr).map_err(|e| bind_error(addr, e))?;
+                println!("Running on {} (TLS)", listener.local_addr()?);
+                Some((listener, config))
+            }
+            None => None,
+        };
+        let queue = Arc::new(PriorityQueue::new(self.queue_capacity));
+        let status = Arc::new(self.status(queue.clone()));
+        let stopping = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..self.workers {
+                s.spawn(|| {
+                    while let Some(job) = queue.pop() {
+                        // 处理器 panic 只影响这一个连接，工作线程继续处理队列
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| match job {
+                            Job::Accepted { stream, conn } => {
+                                self.admit(stream, conn, &queue, &status)
+                            }
+                            Job::Request {
+                                stream,
+                                req,
+                                raw,
+                                conn,
+                            } => self.handle(stream, *req, raw, conn, &queue),
+                        }));
+                        if result.is_err() {
+                            eprintln!("Request handler panicked, connection dropped");
+                        }
+                    }
+                });
+            }
+            if let Some(listener) = &ipc_listener {
+                s.spawn(|| self.ipc_loop(listener, &queue, &stopping));
+            }
+            #[cfg(feature = "otel")]
+            if let Some(telemetry) = &self.telemetry {
+                s.spawn(|| {
+                    telemetry.export_loop(&stopping, || {
+                        self.metrics.snapshot(self.workers, queue.len())
+                    })
+                });
+            }
+            if let Some((listener, config)) = &tls_listener {
+                s.spawn(|| self.accept_loop(listener, &queue, Some(config), &stopping));
+            }
+            self.accept_loop(&connection_listener, &queue, None, &stopping);
+            // IPC 线程阻塞在 accept 上，自己连一次把它唤醒；HTTPS 线程最多等一个轮询周期
+            stopping.store(true, Ordering::SeqCst);
+            if let Some(listener) = &ipc_listener {
+                let _ = IpcStream::connect(listener.name());
+            }
+            // 已经排队的连接和请求处理完工作线程才退出，scope 结束时等待所有工作线程
+            queue.close();
+            self.drain(&queue);
+        });
+        println!("Server stopped");
+        Ok(())
+    }
+
+    // GET /_admin/<name> 用到的运行状态，和服务器共用统计
+    fn status(&self, queue: Arc<PriorityQueue<Job>>) -> ServerStatus {
+        ServerStatus {
+            memory: self.memory.clone(),
+            conn_limit: self.conn_limit.clone(),
+            fds: self.fds.clone(),
+            metrics: self.metrics.clone(),
+            latency: self.latency.clone(),
+            bots: self.bots.clone(),
+            workers: self.workers,
+            queue,
+        }
+    }
+
+    // 等排队的请求处理完，超时后剩下的回复 503
+    fn drain(&self, queue: &PriorityQueue<Job>) {
+        let deadline = Instant::now() + self.drain_timeout;
+        while !queue.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
         }
+        let left = queue.drain();
+        if !left.is_empty() {
+            eprintln!("Drain timeout, dropping {} queued requests", left.len());
+        }
+        for mut job in left {
+            let _ = busy().send_response(job.stream());
+        }
+    }
+
+    // tls 为 Some 时是 HTTPS 监听：升级和退出由明文监听的循环负责，这里跟着 stopping 一起停
+    fn accept_loop(
+        &self,
+        connection_listener: &TcpListener,
+        queue: &PriorityQueue<Job>,
+        tls: Option<&Arc<ServerConfig>>,
+        stopping: &AtomicBool,
+    ) {
+        loop {
+            if tls.is_some() && stopping.load(Ordering::SeqCst) {
+                return;
+            }
+            #[cfg(unix)]
+            if tls.is_none() && crate::upgrade::take_request() {
+                match crate::upgrade::spawn_successor(connection_listener) {
+                    // 新进程接管监听，旧进程处理完队列里的请求后退出
+                    Ok(pid) => {
+                        println!("Upgrade: started new process {}, exiting", pid);
+                        return;
+                    }
+                    Err(e) => eprintln!("Upgrade failed, keep serving: {}", e),
+                }
+            }
+            if tls.is_none() && self.stop_requested() {
+                println!("Shutting down, finishing in-flight requests");
+                return;
+            }
+            if !listener::wait_readable(connection_listener, Duration::from_millis(200)) {
+                continue;
+            }
+            // 取出stream
+            let (mut stream, peer) = match connection_listener.accept() {
+                Ok(conn) => {
+                    self.fds.on_success();
+                    conn
+                }
+                Err(e) => match neterror::classify(&e) {
+                    ErrorClass::Transient => {
+                        thread::sleep(TRANSIENT_PAUSE);
+                        continue;
+                    }
+                    // 排队的连接在 accept 之前就断开了，不影响其他连接
+                    ErrorClass::Connection => continue,
+                    // fd 用完时暂停 accept，先拒绝一个排队的连接，避免客户端一直挂着
+                    ErrorClass::Exhausted => {
+                        let pause = self.fds.on_exhausted();
+                        self.fds
+                            .shed_with_spare(|| match connection_listener.accept() {
+                                Ok((mut stream, _)) => {
+                                    // HTTPS 客户端看不懂明文的 503，直接关闭
+                                    if tls.is_none() {
+                                        let _ = busy().send_response(&mut stream);
+                                    }
+                                    true
+                                }
+                                Err(_) => false,
+                            });
+                        thread::sleep(pause);
+                        continue;
+                    }
+                    ErrorClass::Fatal => {
+                        eprintln!("Listener failed, stop accepting: {}", e);
+                        return;
+                    }
+                },
+            };
+            let permit = match self.conn_limit.as_ref().map(|l| l.acquire(peer.ip())) {
+                Some(None) => {
+                    // HTTPS 还没握手，只能直接关闭
+                    if tls.is_none() {
+                        let _ = too_many_connections().send_response(&mut stream);
+                    }
+                    continue;
+                }
+                Some(permit) => permit,
+                None => None,
+            };
+            let stream = match tls {
+                Some(config) => match tls::accept(config, stream) {
+                    Ok(stream) => Conn::Tls(Box::new(stream)),
+                    Err(e) => {
+                        eprintln!("Cannot start TLS session with {}: {}", peer, e);
+                        continue;
+                    }
+                },
+                None => Conn::Tcp(stream),
+            };
+            let mut conn = ConnState::new(Some(peer));
+            conn.permit = permit;
+            conn.open = self.router.prometheus_metrics().map(|m| m.connection());
+            self.dispatch(stream, conn, queue);
+        }
+    }
+
+    // 新连接上先看第一个字节，认出用错协议的客户端：
+    // 明文端口收到 TLS 握手时回一个 TLS 告警，HTTPS 端口收到明文请求时用明文回复重定向或 400
+    // 返回 true 表示已经处理，连接直接关闭
+    fn wrong_protocol(&self, stream: &mut Conn, peer: Option<SocketAddr>) -> bool {
+        let mut first = [0; 3];
+        match stream {
+            Conn::Tcp(s) => {
+                let n = s.peek(&mut first).unwrap_or(0);
+                if !tls::is_client_hello(&first[..n]) {
+                    return false;
+                }
+                let hint = match &self.tls {
+                    Some((addr, _)) => format!("HTTPS is served on {}", addr),
+                    None => "HTTPS is not enabled".to_string(),
+                };
+                eprintln!(
+                    "TLS handshake from {} on the plain HTTP port ({})",
+                    peer.map_or("-".to_string(), |p| p.to_string()),
+                    hint
+                );
+                let _ = s.write_all(&tls::UNEXPECTED_MESSAGE_ALERT);
+                true
+            }
+            Conn::Tls(s) => {
+                let n = s.sock.peek(&mut first).unwrap_or(0);
+                if !tls::is_plain_http(&first[..n]) {
+                    return false;
+                }
+                eprintln!(
+                    "Plain HTTP request from {} on the HTTPS port",
+                    peer.map_or("-".to_string(), |p| p.to_string())
+                );
+                let port = s.sock.local_addr().map_or(443, |a| a.port());
+                let resp = read_request(&mut s.sock, Vec::new(), &self.timeouts, &self.limits)
+                    .ok()
+                    .and_then(|raw| HttpRequest::parse(&raw, &self.limits).ok())
+                    .map(|req| tls::plain_http_response(&req, port))
+                    .unwrap_or_else(|| {
+                        HttpResponse::new(
+                            "400",
+                            None,
+                            Some("A plain HTTP request was sent to an HTTPS port.".into()),
+                        )
+                    });
+                let _ = resp.send_response(&mut s.sock);
+                // 握手没有开始，关闭前不能再发 TLS 的 close_notify
+                let _ = s.sock.shutdown(std::net::Shutdown::Write);
+                true
+            }
+            Conn::Ipc(_) => false,
+        }
+    }
+
+    // 本机 IPC 连接走和 TCP 一样的排队流程，没有对端地址
+    fn ipc_loop(&self, listener: &IpcListener, queue: &PriorityQueue<Job>, stopping: &AtomicBool) {
+        loop {
+            let stream = listener.accept();
+            if stopping.load(Ordering::SeqCst) {
+                return;
+            }
+            match stream {
+                Ok(stream) => {
+                    let mut conn = ConnState::new(None);
+                    conn.open = self.router.prometheus_metrics().map(|m| m.connection());
+                    self.dispatch(Conn::Ipc(stream), conn, queue)
+                }
+                Err(e) => match neterror::classify(&e) {
+                    ErrorClass::Fatal => {
+                        eprintln!("IPC listener failed, stop accepting: {}", e);
+                        return;
+                    }
+                    _ => thread::sleep(TRANSIENT_PAUSE),
+                },
+            }
+        }
+    }
+
+    // 刚接受的连接和等待下一个请求的长连接以高优先级排队，尽快被读取；
+    // 读出请求之后才知道它真正的优先级
+    fn dispatch(&self, stream: Conn, conn: ConnState, queue: &PriorityQueue<Job>) {
+        if let Some(mut shed) = queue.push(Priority::High, Job::Accepted { stream, conn }) {
+            let _ = busy().send_response(shed.stream());
+        }
+    }
+
+    // 在工作线程上读取并解析请求，通过各项检查后按优先级排队
+    // conn.peer 为 None 表示本机 IPC 连接
+    fn admit(
+        &self,
+        mut stream: Conn,
+        mut conn: ConnState,
+        queue: &PriorityQueue<Job>,
+        status: &Arc<ServerStatus>,
+    ) {
+        let peer = conn.peer;
+        // 正在退出：空闲的长连接直接关闭，不再等下一个请求
+        if conn.served > 0 && queue.is_closed() {
+            return;
+        }
+        // 内存紧张时不再接受新连接；已有的长连接在 await_next_request 里关闭
+        if let Some(guard) = self.memory.as_ref().filter(|_| conn.served == 0) {
+            if !guard.admit() {
+                let _ = busy().send_response(&mut stream);
+                return;
+            }
+        }
+        // 新连接先设好超时，认协议时的 peek 也不会一直等下去
+        if conn.served == 0
+            && (stream.set_read_timeout(Some(self.timeouts.read)).is_err()
+                || stream.set_write_timeout(Some(self.timeouts.write)).is_err())
+        {
+            return;
+        }
+        if conn.served == 0 && self.wrong_protocol(&mut stream, peer) {
+            return;
+        }
+        if conn.served > 0
+            && conn.pending.is_empty()
+            && !self.await_next_request(&mut stream, &mut conn, queue)
+        {
+            return;
+        }
+        // 客户端直接断开或读出错只影响这一个连接
+        let pending = std::mem::take(&mut conn.pending);
+        let mut buffer = match read_request(&mut stream, pending, &self.timeouts, &self.limits) {
+            // 长连接上的请求从读完开始计算排队时间，不算空闲等待的时间
+            Ok(buffer) if conn.served > 0 => {
+                conn.since = Instant::now();
+                buffer
+            }
+            Ok(buffer) => buffer,
+            // 长连接空闲超时，正常关闭
+            Err(ReadError::Timeout { started: false }) if conn.served > 0 => return,
+            Err(ReadError::Timeout { .. }) => {
+                let _ = request_timeout().send_response(&mut stream);
+                return;
+            }
+            Err(ReadError::Io(e))
+                if conn.served > 0 && neterror::classify(&e) == ErrorClass::Transient =>
+            {
+                return
+            }
+            Err(ReadError::Io(e)) => {
+                let from = peer.map_or("ipc".to_string(), |p| p.to_string());
+                neterror::log_connection_error(&format!("read from {}", from), &e);
+                return;
+            }
+        };
+        // 客户端可能不等响应就发来下一个请求，多读到的部分留给下一轮
+        if let Ok(Some(len)) = httprequest::message_len_with(&buffer, &self.limits) {
+            if len < buffer.len() {
+                conn.pending = buffer.split_off(len);
+            }
+        }
+        // 解析失败返回 4xx，不把解析了一半的请求交给路由
+        let mut req = match HttpRequest::parse(&buffer, &self.limits) {
+            Ok(req) => req,
+            Err(ParseError::Empty) => return,
+            Err(e) => {
+                let _ = parse_error_response(e).send_response(&mut stream);
+                return;
+            }
+        };
+        req.remote_addr = peer;
+        req.tls = matches!(stream, Conn::Tls(_));
+        self.trusted_proxies.apply(&mut req);
+        #[cfg(feature = "geoip")]
+        if let Some(resp) = self.geoip.as_ref().and_then(|g| g.apply(&mut req)) {
+            let _ = resp.send_response(&mut stream);
+            return;
+        }
+        if let Some(resp) = self.bots.as_ref().and_then(|b| b.respond(&req)) {
+            let _ = resp.send_response(&mut stream);
+            return;
+        }
+        // 运行状态由路由器上的 /_admin/:name 回复，和其他路由一样经过中间件
+        req.extensions.insert(status.clone());
+        let priority = self.router.priority_of(&req);
+        let job = Job::Request {
+            stream,
+            req: Box::new(req),
+            raw: buffer,
+            conn,
+        };
+        if let Some(mut shed) = queue.push(priority, job) {
+            let _ = busy().send_response(shed.stream());
+        }
+    }
+
+    fn handle(
+        &self,
+        mut stream: Conn,
+        mut req: HttpRequest,
+        raw: Vec<u8>,
+        mut conn: ConnState,
+        queue: &PriorityQueue<Job>,
+    ) {
+        let started = Instant::now();
+        let queue_time = started.duration_since(conn.since);
+        // 达到单连接请求上限或服务器正在退出时，这是连接上的最后一个请求
+        conn.served += 1;
+        let keep_alive = req.keep_alive()
+            && conn.served < self.keep_alive.max_requests
+            && !queue.is_closed()
+            && !self.memory.as_ref().is_some_and(|g| g.should_close());
+        let route = self.router.route_name_of(&req);
+        #[cfg(feature = "otel")]
+        let request_line = format!("{} {}", req.method.as_str(), req.path());
+        #[cfg(feature = "otel")]
+        let client = req.client_ip();
+        // 访问日志（AccessLog 中间件）里带上排队时间
+        req.extensions.insert(QueueTime(queue_time));
+        #[cfg(feature = "otel")]
+        let traceparent = self
+            .telemetry
+            .as_ref()
+            .and_then(|_| req.header("traceparent").map(str::to_string));
+        // 103 Early Hints 直接写到 socket 上，处理器还没返回浏览器就能开始预加载
+        // 录制时走缓存，临时响应和最终响应一起录下来，回放才能逐字节比较
+        if let (Conn::Tcp(s), None) = (&stream, &self.recorder) {
+            if let Ok(direct) = s.try_clone() {
+                req.extensions.insert(Interim::direct(direct));
+            }
+        }
+        let mut throttled = self.throttle.writer(&mut stream, route);
+        let mut out = ConnectionHeader::new(&mut throttled, keep_alive);
+        // 使用req 和 流的引用  调用router
+        match &self.recorder {
+            Some(recorder) => {
+                let remote_addr = req.remote_addr;
+                // 录制的是路由的原始输出，回放时才能逐字节比较
+                let mut tee = TeeWriter::new(&mut out);
+                self.router.route(req, &mut tee);
+                if let Err(e) = recorder.record(&raw, remote_addr, &tee.copy) {
+                    eprintln!("Cannot record exchange: {}", e);
+                }
+            }
+            None => self.router.route(req, &mut out),
+        }
+        #[cfg(feature = "otel")]
+        let status = out.status;
+        let finished = out.finish();
+        let handler_time = started.elapsed();
+        self.metrics.record(queue_time, handler_time);
+        if let (Some(latency), Some(route)) = (&self.latency, route) {
+            latency.record(route, queue_time + handler_time);
+        }
+        #[cfg(feature = "otel")]
+        if let Some(telemetry) = &self.telemetry {
+            let (method, path) = request_line.split_once(' ').unwrap_or_default();
+            telemetry.record(&crate::otel::RequestSpan {
+                method,
+                path,
+                route,
+                traceparent: traceparent.as_deref(),
+                client,
+                status,
+                duration: queue_time + handler_time,
+            });
+        }
+        match finished {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                neterror::log_connection_error("write response", &e);
+                return;
+            }
+        }
+        drop(throttled);
+        // 连接放回队列等待下一个请求
+        self.dispatch(stream, conn, queue);
+    }
+
+    // 长连接等下一个请求的第一段数据，读到的部分放进 conn.pending
+    // 分成小段等待，每段之间检查：fd 不够用或内存紧张时关掉连接把资源让出来，服务器退出时也不再等
+    // 返回 false 表示连接应该关闭
+    fn await_next_request(
+        &self,
+        stream: &mut Conn,
+        conn: &mut ConnState,
+        queue: &PriorityQueue<Job>,
+    ) -> bool {
+        let deadline = Instant::now() + self.keep_alive.idle_timeout;
+        let mut chunk = [0; 1024];
+        loop {
+            if self.fds.under_pressure() {
+                self.fds.on_idle_closed();
+                return false;
+            }
+            if self.memory.as_ref().is_some_and(|g| g.should_close()) {
+                return false;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || queue.is_closed() {
+                return false;
+            }
+            if stream.set_read_timeout(Some(left.min(IDLE_POLL))).is_err() {
+                return false;
+            }
+            match stream.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    conn.pending.extend_from_slice(&chunk[..n]);
+                    return true;
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                    ) => {}
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+// 服务器的运行状态，admit 放进每个请求的 extensions，由 status_page 回复
+pub struct ServerStatus {
+    memory: Option<Arc<MemoryGuard>>,
+    conn_limit: Option<ConnLimiter>,
+    fds: Arc<FdPressure>,
+    metrics: Arc<Metrics>,
+    latency: Option<Arc<Latency>>,
+    bots: Option<Arc<BotGuard>>,
+    workers: usize,
+    queue: Arc<PriorityQueue<Job>>,
+}
+
+impl ServerStatus {
+    // name 是 /_admin/ 后面的部分，没有启用对应功能时返回 None
+    fn page(&self, name: &str) -> Option<String> {
+        match name {
+            "memory" => serde_json::to_string(&self.memory.as_ref()?.stats()).ok(),
+            "conn_limit" => serde_json::to_string(&self.conn_limit.as_ref()?.stats()).ok(),
+            "fds" => serde_json::to_string(&self.fds.stats()).ok(),
+            "metrics" => {
+                let snapshot = self.metrics.snapshot(self.workers, self.queue.len());
+                serde_json::to_string(&snapshot).ok()
+            }
+            "latency" => serde_json::to_string(&self.latency.as_ref()?.snapshot()).ok(),
+            "bots" => serde_json::to_string(&self.bots.as_ref()?.stats()).ok(),
+            _ => None,
+        }
+    }
+}
+
+// GET /_admin/:name，只对本机开放；不经过 Server 调用路由器时没有运行状态，回复 404
+fn status_page(req: &HttpRequest) -> HttpResponse<'static> {
+    let page = req
+        .extensions
+        .get::<Arc<ServerStatus>>()
+        .and_then(|status| {
+            let name = req.params().get("name")?;
+            status.page(name)
+        });
+    match page {
+        Some(body) => {
+            let mut headers = std::collections::HashMap::new();
+            headers.insert(names::CONTENT_TYPE, "application/json");
+            HttpResponse::new("200", Some(headers), Some(body))
+        }
+        None => PageNotFoundHandler::handle(req),
+    }
+}
+
+// 读请求失败的原因
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    // 超时；started 表示已经收到了一部分请求，回 408 再关闭
+    Timeout { started: bool },
+    Io(std::io::Error),
+}
+
+// read_request 按剩余时间调整每次读的超时
+trait TimedRead: Read {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl TimedRead for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl TimedRead for Conn {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        Conn::set_read_timeout(self, timeout)
+    }
+}
+
+// 先读到头部结束，再按 Content-Length 读完 body；
+// 对端提前关闭或请求超出 limits 时返回已经读到的部分，由 HttpRequest::parse 报告原因
+// buffer 是上一个请求之后多读到的字节，已经是完整的请求时不再读取
+// 还没收到任何字节时沿用连接当前的读超时；收到之后头部要在 timeouts.header 内读完
+fn read_request(
+    stream: &mut impl TimedRead,
+    mut buffer: Vec<u8>,
+    timeouts: &Timeouts,
+    limits: &Limits,
+) -> Result<Vec<u8>, ReadError> {
+    let mut chunk = [0; 1024];
+    let mut started = None;
+    loop {
+        let head_done = match httprequest::message_len_with(&buffer, limits) {
+            Ok(None) => false,
+            Ok(Some(len)) if buffer.len() < len => true,
+            _ => return Ok(buffer),
+        };
+        if !buffer.is_empty() {
+            let left = timeouts.left(*started.get_or_insert_with(Instant::now), head_done);
+            if left.is_zero() {
+                return Err(ReadError::Timeout { started: true });
+            }
+            let timeout = left.min(timeouts.read);
+            stream
+                .set_read_timeout(Some(timeout))
+                .map_err(ReadError::Io)?;
+        }
+        let n = match stream.read(&mut chunk) {
+            Ok(0) => return Ok(buffer),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            // 超时在 unix 上是 WouldBlock，在 Windows 上是 TimedOut
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(ReadError::Timeout {
+                    started: !buffer.is_empty(),
+                })
+            }
+            Err(e) => return Err(ReadError::Io(e)),
+        };
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+// 请求解析失败时的回复，两种服务器共用
+pub(crate) fn parse_error_response<'a>(e: ParseError) -> HttpResponse<'a> {
+    let status = match e {
+        ParseError::UnsupportedVersion(_) => "505",
+        ParseError::RequestLineTooLong(_) => "414",
+        ParseError::TooManyHeaders(_) | ParseError::HeadTooLarge(_) => "431",
+        ParseError::BodyTooLarge(_) => "413",
+        ParseError::LengthRequired => "411",
+        _ => "400",
+    };
+    HttpResponse::new(status, None, Some(e.to_string()))
+}
+
+// 过载时的回复，让客户端稍后重试
+fn busy<'a>() -> HttpResponse<'a> {
+    HttpResponse::new("503", None, Some("Server busy".into()))
+        .with_header(names::RETRY_AFTER, "1")
+        .expect("valid header")
+}
+
+// 没有在期限内收到完整的请求，回复之后关闭连接
+pub(crate) fn request_timeout<'a>() -> HttpResponse<'a> {
+    HttpResponse::new("408", None, Some("Request Timeout".into()))
+        .with_header(names::CONNECTION, "close")
+        .expect("valid header")
+}
+
+// 单个 IP 的连接超过上限，让客户端先关掉已有的连接
+fn too_many_connections<'a>() -> HttpResponse<'a> {
+    HttpResponse::new("429", None, Some("Too many connections".into()))
+        .with_header(names::RETRY_AFTER, "1")
+        .expect("valid header")
+}
+
+// 在响应头部末尾补上 Connection: keep-alive 或 close，头部已经带了 Connection 时原样输出
+// 只缓存头部，body 直接写出
+pub(crate) struct ConnectionHeader<'a, W: Write> {
+    inner: &'a mut W,
+    // None 表示头部已经写出
+    head: Option<Vec<u8>>,
+    // 写出头部之前是希望复用连接，写出之后是实际能否复用
+    keep_alive: bool,
+    // 头部写出后才知道，请求的 span 用
+    pub(crate) status: Option<u16>,
+}
+
+impl<'a, W: Write> ConnectionHeader<'a, W> {
+    pub(crate) fn new(inner: &'a mut W, keep_alive: bool) -> Self {
+        ConnectionHeader {
+            inner,
+            head: Some(Vec::new()),
+            keep_alive,
+            status: None,
+        }
+    }
+
+    // 返回连接能否继续处理下一个请求
+    // 响应不完整（没有空行）时把缓存的内容原样写出，连接不能复用
+    pub(crate) fn finish(mut self) -> std::io::Result<bool> {
+        if let Some(head) = self.head.take() {
+            self.inner.write_all(&head)?;
+            self.keep_alive = false;
+        }
+        self.inner.flush()?;
+        Ok(self.keep_alive)
+    }
+}
+
+impl<W: Write> Write for ConnectionHeader<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Some(head) = self.head.as_mut() else {
+            return self.inner.write(buf);
+        };
+        head.extend_from_slice(buf);
+        let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return Ok(buf.len());
+        };
+        let head = self.head.take().unwrap_or_default();
+        let (fields, rest) = head.split_at(end + 2);
+        let text = String::from_utf8_lossy(fields);
+        let field = |name: &str| {
+            text.lines().skip(1).find_map(|line| {
+                let (k, v) = line.split_once(':')?;
+                k.trim().eq_ignore_ascii_case(name).then_some(v.trim())
+            })
+        };
+        let status = text.split(' ').nth(1).unwrap_or("");
+        // 103 等临时响应原样写出，接着等最终响应的头部；101 之后连接交给升级后的协议
+        if status.starts_with('1') && status != "101" {
+            self.inner.write_all(&head[..end + 4])?;
+            self.head = Some(Vec::new());
+            return self.write(&head[end + 4..]).map(|_| buf.len());
+        }
+        // 响应没有声明长度时只能靠关闭连接来表示结束；没有 body 的状态码除外
+        self.status = status.parse().ok();
+        let bodiless = status.starts_with('1') || status == "204" || status == "304";
+        let delimited = bodiless
+            || field(names::CONTENT_LENGTH).is_some()
+            || field(names::TRANSFER_ENCODING).is_some_and(|te| te.contains("chunked"));
+        self.inner.write_all(fields)?;
+        match field(names::CONNECTION) {
+            // 处理器自己决定了，照它的意思
+            Some(value) => self.keep_alive &= !headers::has_option(value, "close"),
+            None => {
+                self.keep_alive &= delimited;
+                let value = if self.keep_alive {
+                    "keep-alive"
+                } else {
+                    "close"
+                };
+                write!(self.inner, "{}: {}\r\n", names::CONNECTION, value)?;
+            }
+        }
+        self.inner.write_all(rest)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 接受的连接：TCP、TLS 或者本机 IPC，后面的处理只需要读写
+enum Conn {
+    Tcp(TcpStream),
+    // 装箱，rustls 的会话状态比较大
+    Tls(Box<tls::TlsStream>),
+    Ipc(IpcStream),
+}
+
+// TLS 连接关闭前发 close_notify，客户端据此区分正常结束和被截断
+impl Drop for Conn {
+    fn drop(&mut self) {
+        if let Conn::Tls(s) = self {
+            s.conn.send_close_notify();
+            while s.conn.wants_write() {
+                if s.conn.write_tls(&mut s.sock).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Conn {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Conn::Tcp(s) => s.set_read_timeout(timeout),
+            Conn::Tls(s) => s.sock.set_read_timeout(timeout),
+            Conn::Ipc(s) => s.set_read_timeout(timeout),
+        }
+    }
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Conn::Tcp(s) => s.set_write_timeout(timeout),
+            Conn::Tls(s) => s.sock.set_write_timeout(timeout),
+            Conn::Ipc(s) => s.set_write_timeout(timeout),
+        }
+    }
+}
+
+// 跟着连接走的状态，长连接上的每个请求都会用到
+struct ConnState {
+    // None 表示本机 IPC 连接
+    peer: Option<SocketAddr>,
+    // 排队时间的起点：第一个请求是 accept 的时间，之后是请求读完的时间
+    since: Instant,
+    // 已经处理了多少个请求
+    served: usize,
+    // 上一个请求之后多读到的字节，属于下一个请求
+    pending: Vec<u8>,
+    // 占用的单 IP 连接名额，连接关闭时随 ConnState 一起释放
+    permit: Option<ConnPermit>,
+    // 路由器注册了 Prometheus 指标时计入打开的连接数，同样随 ConnState 释放
+    open: Option<OpenConnection>,
+}
+
+impl ConnState {
+    fn new(peer: Option<SocketAddr>) -> Self {
+        ConnState {
+            peer,
+            since: Instant::now(),
+            served: 0,
+            pending: Vec::new(),
+            permit: None,
+            open: None,
+        }
+    }
+}
+
+impl Read for Conn {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Conn::Tcp(s) => s.read(buf),
+            Conn::Tls(s) => s.read(buf),
+            Conn::Ipc(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Conn {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Conn::Tcp(s) => s.write(buf),
+            Conn::Tls(s) => s.write(buf),
+            Conn::Ipc(s) => s.write(buf),
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Conn::Tcp(s) => s.flush(),
+            Conn::Tls(s) => s.flush(),
+            Conn::Ipc(s) => s.flush(),
+        }
+    }
+}
+
+// 队列里的任务，都由工作线程取出
+enum Job {
+    // 刚接受的连接或者等待下一个请求的长连接，还没有读取请求
+    Accepted {
+        stream: Conn,
+        conn: ConnState,
+    },
+    // 已经解析好的请求，等待处理
+    Request {
+        stream: Conn,
+        // 装箱，让两种任务在队列里占的空间差不多
+        req: Box<HttpRequest>,
+        // 原始请求，录制时使用
+        raw: Vec<u8>,
+        conn: ConnState,
+    },
+}
+
+impl Job {
+    // 被丢弃时用来回复 503
+    fn stream(&mut self) -> &mut Conn {
+        match self {
+            Job::Accepted { stream, .. } | Job::Request { stream, .. } => stream,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每次只返回几个字节，模拟被拆成多个包的请求
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    impl TimedRead for Trickle<'_> {
+        fn set_read_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_request_waits_for_body() {
+        let body = "x".repeat(5000);
+        let raw = format!(
+            "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let buffer = read_request(
+            &mut Trickle(raw.as_bytes()),
+            Vec::new(),
+            &Timeouts::default(),
+            &Limits::default(),
+        )
+        .unwrap();
+        let req = HttpRequest::try_from(buffer.as_slice()).unwrap();
+        assert_eq!(req.body_text(), Some(body.as_str()));
+        // 对端提前关闭
+        let cut = &raw.as_bytes()[..100];
+        let buffer = read_request(
+            &mut Trickle(cut),
+            Vec::new(),
+            &Timeouts::default(),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            HttpRequest::try_from(buffer.as_slice()).err(),
+            Some(ParseError::Incomplete)
+        );
+    }
+
+    #[test]
+    fn test_read_request_pipelined() {
+        // 上一轮多读到的已经是完整的请求，不再从连接读取
+        let pending = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n".to_vec();
+        let buffer = read_request(
+            &mut Trickle(b""),
+            pending.clone(),
+            &Timeouts::default(),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(buffer, pending);
+        assert_eq!(httprequest::message_len(&buffer).unwrap(), Some(19));
+        // 只有半个请求时继续读
+        let buffer = read_request(
+            &mut Trickle(b"\r\n\r\n"),
+            b"GET /c HTTP/1.1".to_vec(),
+            &Timeouts::default(),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(buffer, b"GET /c HTTP/1.1\r\n\r\n");
+    }
+
+    #[test]
+    fn test_read_request_limits() {
+        let limits = Limits {
+            head: 64,
+            ..Limits::default()
+        };
+        // 头部超出上限之后不再读取，剩下的字节留在连接里
+        let raw = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "x".repeat(1000));
+        let buffer = read_request(
+            &mut Trickle(raw.as_bytes()),
+            Vec::new(),
+            &Timeouts::default(),
+            &limits,
+        )
+        .unwrap();
+        assert!(buffer.len() < 64 + 7);
+        let e = HttpRequest::parse(&buffer, &limits).unwrap_err();
+        assert_eq!(e, ParseError::HeadTooLarge(64));
+        assert_eq!(parse_error_response(e).status().as_u16(), 431);
+        let limits = Limits {
+            body: 10,
+            ..Limits::default()
+        };
+        let raw = "POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world";
+        let buffer = read_request(
+            &mut Trickle(raw.as_bytes()),
+            Vec::new(),
+            &Timeouts::default(),
+            &limits,
+        )
+        .unwrap();
+        let e = HttpRequest::parse(&buffer, &limits).unwrap_err();
+        assert_eq!(parse_error_response(e).status().as_u16(), 413);
+        let e = ParseError::RequestLineTooLong(10);
+        assert_eq!(parse_error_response(e).status().as_u16(), 414);
+    }
+
+    #[test]
+    fn test_read_request_timeouts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let timeouts = Timeouts {
+            header: Duration::from_millis(300),
+            read: Duration::from_millis(100),
+            request: Duration::from_millis(600),
+            write: Duration::from_millis(100),
+        };
+        // 什么都不发
+        server.set_read_timeout(Some(timeouts.read)).unwrap();
+        let err = read_request(&mut server, Vec::new(), &timeouts, &Limits::default()).unwrap_err();
+        assert!(
+            matches!(err, ReadError::Timeout { started: false }),
+            "{:?}",
+            err
+        );
+        // 每次间隔都在 read 之内，但头部总是发不完
+        let sender = thread::spawn(move || {
+            for _ in 0..10 {
+                if client.write_all(b"X").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let started = Instant::now();
+        let err = read_request(&mut server, Vec::new(), &timeouts, &Limits::default()).unwrap_err();
+        assert!(
+            matches!(err, ReadError::Timeout { started: true }),
+            "{:?}",
+            err
+        );
+        assert!(started.elapsed() < Duration::from_millis(450));
+        sender.join().unwrap();
+        // 头部很快发完，body 每次间隔都在 read 之内，但整个请求超过 request
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let sender = thread::spawn(move || {
+            let _ = client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n");
+            for _ in 0..20 {
+                if client.write_all(b"X").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let started = Instant::now();
+        server.set_read_timeout(Some(timeouts.read)).unwrap();
+        let err = read_request(&mut server, Vec::new(), &timeouts, &Limits::default()).unwrap_err();
+        assert!(
+            matches!(err, ReadError::Timeout { started: true }),
+            "{:?}",
+            err
+        );
+        assert!(started.elapsed() < Duration::from_millis(750));
+        drop(server);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_keep_alive_header() {
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, true);
+        w.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .unwrap();
+        assert!(w.finish().unwrap());
+        assert_eq!(
+            out,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: keep-alive\r\n\r\nok"
+        );
+        // 没有长度的响应只能以关闭连接结束
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, true);
+        w.write_all(b"HTTP/1.1 200 OK\r\n\r\nstream").unwrap();
+        assert!(!w.finish().unwrap());
+        assert_eq!(out, b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nstream");
+        // 处理器要求关闭
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, true);
+        w.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        assert!(!w.finish().unwrap());
+    }
+
+    #[test]
+    fn test_connection_header_after_interim() {
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, true);
+        w.write_all(b"HTTP/1.1 103 Early Hints\r\nLink:</a.css>; rel=preload; as=style\r\n\r\nHTTP/1.1 200 OK\r\n")
+            .unwrap();
+        w.write_all(b"Content-Length: 2\r\n\r\nok").unwrap();
+        assert_eq!(w.status, Some(200));
+        assert!(w.finish().unwrap());
+        // Connection 只加在最终响应上
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 103 Early Hints\r\nLink:</a.css>; rel=preload; as=style\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: keep-alive\r\n\r\nok"
+        );
+    }
+
+    #[test]
+    fn test_connection_close_header() {
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, false);
+        // 头部被拆成多次写入
+        w.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r")
+            .unwrap();
+        w.write_all(b"\n\r\nok").unwrap();
+        assert!(!w.finish().unwrap());
+        assert_eq!(
+            out,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+        );
+
+        let mut out = Vec::new();
+        let mut w = ConnectionHeader::new(&mut out, false);
+        w.write_all(b"HTTP/1.1 200 OK\r\nconnection:keep-alive\r\n\r\n")
+            .unwrap();
+        assert!(!w.finish().unwrap());
+        assert_eq!(out, b"HTTP/1.1 200 OK\r\nconnection:keep-alive\r\n\r\n");
+    }
+
+    #[test]
+    fn test_shutdown_handle_stops_run() {
+        let server = Server::new("127.0.0.1:0").drain_timeout(Duration::from_millis(100));
+        let handle = server.shutdown_handle();
+        let started = Instant::now();
+        thread::scope(|s| {
+            let running = s.spawn(|| server.run().unwrap());
+            thread::sleep(Duration::from_millis(50));
+            handle.shutdown();
+            running.join().unwrap();
+        });
+        assert!(handle.is_requested());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+    #[test]
+    fn test_idle_connection_closed_under_fd_pressure() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let addr = addr.to_string();
+        let server = Server::new(&addr).keep_alive(KeepAlive {
+            max_requests: 100,
+            idle_timeout: Duration::from_secs(30),
+        });
+        let handle = server.shutdown_handle();
+        thread::scope(|s| {
+            let running = s.spawn(|| server.run().unwrap());
+            let mut client = loop {
+                match TcpStream::connect(&addr) {
+                    Ok(client) => break client,
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            client.write_all(b"GET /health HTTP/1.1\r\n\r\n").unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            let n = client.read(&mut buf).unwrap();
+            assert!(String::from_utf8_lossy(&buf[..n]).contains("keep-alive"));
+            // 连接在等下一个请求时 fd 用完了
+            server.fds.on_exhausted();
+            let started = Instant::now();
+            assert_eq!(client.read(&mut buf).unwrap(), 0);
+            assert!(started.elapsed() < Duration::from_secs(2));
+            assert_eq!(server.fds.stats().idle_closed, 1);
+            handle.shutdown();
+            running.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_admin_status_goes_through_middleware() {
+        struct RequireToken;
+        impl crate::middleware::Middleware for RequireToken {
+            fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+                match req.header("X-Token") {
+                    Some(_) => None,
+                    None => Some(HttpResponse::new("401", None, None)),
+                }
+            }
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let server = Server::new(&addr).router(Router::default().middleware(RequireToken));
+        let handle = server.shutdown_handle();
+        let send = |raw: &str| loop {
+            let Ok(mut client) = TcpStream::connect(&addr) else {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            };
+            client.write_all(raw.as_bytes()).unwrap();
+            let mut out = String::new();
+            client.read_to_string(&mut out).unwrap();
+            return out;
+        };
+        thread::scope(|s| {
+            let running = s.spawn(|| server.run().unwrap());
+            let out = send("GET /_admin/metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+            assert!(out.starts_with("HTTP/1.1 401"), "{}", out);
+            let out =
+                send("GET /_admin/metrics HTTP/1.1\r\nX-Token: t\r\nConnection: close\r\n\r\n");
+            assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+            assert!(out.contains(r#""workers":"#), "{}", out);
+            // 没有配置内存护栏
+            let out =
+                send("GET /_admin/memory HTTP/1.1\r\nX-Token: t\r\nConnection: close\r\n\r\n");
+            assert!(out.starts_with("HTTP/1.1 404"), "{}", out);
+            handle.shutdown();
+            running.join().unwrap();
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_returns_bind_error() {
+        let server = Server::new("127.0.0.1:0").ipc("/nonexistent-dir/httperver.sock");
+        let err = server.run().unwrap_err();
+        assert!(err.to_string().contains("/nonexistent-dir/httperver.sock"));
     }
 }
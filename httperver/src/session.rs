@@ -0,0 +1,380 @@
+// 会话：用一个随机的 session id cookie 把同一个浏览器的请求关联起来，数据保存在服务端
+// SessionLayer 作为中间件，请求进来时按 cookie 取出会话（没有或已过期就新建一个），
+// 放进 req.extensions，处理器通过 req.session() 读写；响应写出前保存，并下发 / 续期 cookie
+// 没有写过数据的新会话不保存也不下发 cookie，爬虫和一次性请求不会占用存储
+// 过期是滑动的：每次访问都把过期时间往后推 ttl
+use crate::middleware::Middleware;
+use http::clock::Clock;
+use http::cookie::{Cookie, SameSite};
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use http::random::{self, RandomSource};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_COOKIE_NAME: &str = "sid";
+pub const DEFAULT_TTL_SECS: u64 = 30 * 60;
+// 两次清理过期会话之间至少间隔这么久
+const GC_INTERVAL_SECS: u64 = 60;
+
+pub type SessionData = HashMap<String, serde_json::Value>;
+
+// 会话的存储，可以换成数据库或 Redis 的实现
+pub trait SessionStore: Send + Sync {
+    // 不存在或已经过期时返回 None
+    fn load(&self, id: &str, now: SystemTime) -> Option<SessionData>;
+    fn save(&self, id: &str, data: SessionData, expires: SystemTime);
+    fn remove(&self, id: &str);
+    // 删除所有过期的会话，返回删除的个数
+    fn gc(&self, now: SystemTime) -> usize;
+}
+
+// 进程内存里的实现，重启后会话全部失效
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, (SessionData, SystemTime)>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str, now: SystemTime) -> Option<SessionData> {
+        let sessions = self.sessions.lock().unwrap();
+        let (data, expires) = sessions.get(id)?;
+        (*expires > now).then(|| data.clone())
+    }
+    fn save(&self, id: &str, data: SessionData, expires: SystemTime) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), (data, expires));
+    }
+    fn remove(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+    fn gc(&self, now: SystemTime) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, (_, expires)| *expires > now);
+        before - sessions.len()
+    }
+}
+
+struct State {
+    id: String,
+    data: SessionData,
+    // 请求带来的 cookie 对应的是已有会话
+    existing: bool,
+    changed: bool,
+    destroyed: bool,
+    // 响应前换一个新的 id
+    regenerate: bool,
+}
+
+// 处理器拿到的会话句柄，克隆出来的句柄指向同一个会话
+#[derive(Clone)]
+pub struct Session {
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    pub fn id(&self) -> String {
+        self.state.lock().unwrap().id.clone()
+    }
+    // 值不存在或类型不对时返回 None
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        serde_json::from_value(state.data.get(key)?.clone()).ok()
+    }
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) {
+        let value = serde_json::to_value(value).expect("session values are serializable");
+        let mut state = self.state.lock().unwrap();
+        state.data.insert(key.to_string(), value);
+        state.changed = true;
+    }
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.changed |= state.data.remove(key).is_some();
+    }
+    // 登录等权限变化之后换一个新的 id，数据保留，防止会话固定攻击
+    pub fn regenerate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.regenerate = true;
+        state.changed = true;
+    }
+    // 注销：删除服务端数据并让浏览器删掉 cookie
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+// 让处理器可以写 req.session()，没有启用会话时返回 None
+pub trait RequestSession {
+    fn session(&self) -> Option<&Session>;
+}
+
+impl RequestSession for HttpRequest {
+    fn session(&self) -> Option<&Session> {
+        self.extensions.get::<Session>()
+    }
+}
+
+// [sessions]
+// enabled = true
+// cookie_name = "sid"
+// ttl_secs = 1800
+// secure = true
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub enabled: bool,
+    pub cookie_name: String,
+    pub ttl_secs: u64,
+    // 只在 HTTPS 上发送 cookie，生产环境应该打开
+    pub secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            enabled: false,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            ttl_secs: DEFAULT_TTL_SECS,
+            secure: false,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if Cookie::new(self.cookie_name.as_str(), "")
+            .validate()
+            .is_err()
+        {
+            problems.push(format!(
+                "sessions: invalid cookie_name {:?}",
+                self.cookie_name
+            ));
+        }
+        if self.ttl_secs == 0 {
+            problems.push("sessions: ttl_secs must be positive".to_string());
+        }
+    }
+}
+
+pub struct SessionLayer {
+    store: Arc<dyn SessionStore>,
+    config: SessionConfig,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+    last_gc: AtomicU64,
+}
+
+impl SessionLayer {
+    pub fn new(
+        config: SessionConfig,
+        store: Arc<dyn SessionStore>,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Self {
+        SessionLayer {
+            last_gc: AtomicU64::new(clock.unix_secs()),
+            store,
+            config,
+            clock,
+            rng,
+        }
+    }
+
+    fn cookie(&self, value: &str) -> Cookie {
+        Cookie::new(self.config.cookie_name.as_str(), value)
+            .path("/")
+            .http_only(true)
+            .secure(self.config.secure)
+            .same_site(SameSite::Lax)
+    }
+
+    // 最多每 GC_INTERVAL_SECS 清理一次，只有一个请求会抢到
+    fn maybe_gc(&self) {
+        let now = self.clock.unix_secs();
+        let last = self.last_gc.load(Ordering::Relaxed);
+        if now < last + GC_INTERVAL_SECS {
+            return;
+        }
+        if self
+            .last_gc
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.store.gc(self.clock.now());
+        }
+    }
+}
+
+impl Middleware for SessionLayer {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        self.maybe_gc();
+        let now = self.clock.now();
+        let loaded = req
+            .cookie(&self.config.cookie_name)
+            .and_then(|id| Some((self.store.load(&id, now)?, id)));
+        let (data, id, existing) = match loaded {
+            Some((data, id)) => (data, id, true),
+            None => (
+                SessionData::new(),
+                random::session_id(self.rng.as_ref()),
+                false,
+            ),
+        };
+        req.extensions.insert(Session {
+            state: Arc::new(Mutex::new(State {
+                id,
+                data,
+                existing,
+                changed: false,
+                destroyed: false,
+                regenerate: false,
+            })),
+        });
+        None
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'static>) {
+        let Some(session) = req.session() else {
+            return;
+        };
+        let mut state = session.state.lock().unwrap();
+        if state.regenerate && !state.destroyed {
+            if state.existing {
+                self.store.remove(&state.id);
+            }
+            state.id = random::session_id(self.rng.as_ref());
+        }
+        if state.destroyed {
+            if state.existing {
+                self.store.remove(&state.id);
+                let removal = Cookie::removal(self.config.cookie_name.as_str()).path("/");
+                let _ = resp.add_cookie(&removal);
+            }
+            return;
+        }
+        if !state.existing && !state.changed {
+            return;
+        }
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        self.store
+            .save(&state.id, state.data.clone(), self.clock.now() + ttl);
+        // 每次都重新下发，浏览器那边的过期时间跟着续期
+        let cookie = self.cookie(&state.id).max_age(ttl);
+        let _ = resp.add_cookie(&cookie);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use http::random::SeededRandom;
+
+    fn layer(store: Arc<MemoryStore>, clock: &MockClock) -> SessionLayer {
+        SessionLayer::new(
+            SessionConfig {
+                enabled: true,
+                ..SessionConfig::default()
+            },
+            store,
+            Arc::new(clock.clone()),
+            Arc::new(SeededRandom::new(7)),
+        )
+    }
+
+    // 走一遍中间件，返回 Set-Cookie
+    fn run(layer: &SessionLayer, cookie: Option<&str>, handler: impl Fn(&Session)) -> Vec<String> {
+        let raw = match cookie {
+            Some(c) => format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", c),
+            None => "GET / HTTP/1.1\r\n\r\n".to_string(),
+        };
+        let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        assert!(layer.before(&mut req).is_none());
+        handler(req.session().unwrap());
+        let mut resp = HttpResponse::new("200", None, None);
+        layer.after(&req, &mut resp);
+        resp.set_cookies().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let store = Arc::new(MemoryStore::new());
+        let clock = MockClock::from_unix_secs(1_000_000);
+        let layer = layer(store.clone(), &clock);
+
+        // 没写数据的新会话不下发 cookie
+        assert!(run(&layer, None, |_| {}).is_empty());
+        assert!(store.is_empty());
+
+        let set = run(&layer, None, |s| s.insert("user", 42));
+        assert_eq!(set.len(), 1);
+        assert!(set[0].contains("HttpOnly") && set[0].contains("Max-Age=1800"));
+        let pair = set[0].split(';').next().unwrap().to_string();
+
+        run(&layer, Some(&pair), |s| {
+            assert_eq!(s.get::<u32>("user"), Some(42));
+            assert_eq!(s.get::<String>("user"), None);
+        });
+
+        // 过期后是一个新会话
+        clock.advance(Duration::from_secs(1801));
+        run(&layer, Some(&pair), |s| {
+            assert_eq!(s.get::<u32>("user"), None)
+        });
+
+        // 下一次请求时触发清理
+        clock.advance(Duration::from_secs(GC_INTERVAL_SECS));
+        run(&layer, None, |_| {});
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_session_regenerate() {
+        let store = Arc::new(MemoryStore::new());
+        let clock = MockClock::from_unix_secs(1_000_000);
+        let layer = layer(store.clone(), &clock);
+        let set = run(&layer, None, |s| s.insert("user", "alice"));
+        let old = set[0].split(';').next().unwrap().to_string();
+        let set = run(&layer, Some(&old), |s| s.regenerate());
+        let new = set[0].split(';').next().unwrap().to_string();
+        assert_ne!(old, new);
+        assert_eq!(store.len(), 1);
+        run(&layer, Some(&new), |s| {
+            assert_eq!(s.get::<String>("user").as_deref(), Some("alice"))
+        });
+    }
+
+    #[test]
+    fn test_session_destroy() {
+        let store = Arc::new(MemoryStore::new());
+        let clock = MockClock::from_unix_secs(1_000_000);
+        let layer = layer(store.clone(), &clock);
+        let set = run(&layer, None, |s| s.insert("user", "alice"));
+        let pair = set[0].split(';').next().unwrap().to_string();
+        let set = run(&layer, Some(&pair), |s| s.destroy());
+        assert!(set[0].starts_with("sid=; ") && set[0].contains("Max-Age=0"));
+        assert!(store.is_empty());
+    }
+}
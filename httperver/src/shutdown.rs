@@ -0,0 +1,47 @@
+// 优雅退出：停止接受新连接，处理完已经收到的请求后 Server::run 返回
+// 嵌入到其他程序或测试里时用 ShutdownHandle 触发；独立运行时由 SIGINT / SIGTERM 触发
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// 可以在任意线程上调用 shutdown，clone 出来的句柄共享同一个标志
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle(Arc<AtomicBool>);
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        ShutdownHandle::default()
+    }
+
+    pub fn shutdown(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
+
+// 安装后 Ctrl-C 和 kill（httperver stop）不再直接结束进程，而是走优雅退出
+#[cfg(unix)]
+pub fn install_signal_handler() {
+    // SAFETY: on_signal 只做原子写入，是异步信号安全的
+    unsafe {
+        libc::signal(libc::SIGINT, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_signal as *const () as libc::sighandler_t);
+    }
+}
+
+// Windows 上保持默认行为，Ctrl-C 直接结束进程
+#[cfg(not(unix))]
+pub fn install_signal_handler() {}
+
+pub fn signalled() -> bool {
+    SIGNALLED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,119 @@
+// 合并并发的相同请求：同一个 key 的计算正在进行时，后来的调用者等待它的结果，
+// 而不是各自再算一遍。用于缩略图、精简等缓存未命中时比较贵的计算
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex};
+
+struct Call<V> {
+    // None：还在计算；Some(None)：领头的调用 panic 了，没有结果
+    result: Mutex<Option<Option<V>>>,
+    done: Condvar,
+}
+
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Arc<Call<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        SingleFlight {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+// 领头的调用结束（包括 panic）时唤醒等待者并移除记录
+struct Finish<'a, K: Eq + Hash, V> {
+    group: &'a SingleFlight<K, V>,
+    key: Option<K>,
+    call: Arc<Call<V>>,
+    value: Option<V>,
+}
+
+impl<K: Eq + Hash, V> Drop for Finish<'_, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.group.calls.lock().unwrap().remove(&key);
+        }
+        *self.call.result.lock().unwrap() = Some(self.value.take());
+        self.call.done.notify_all();
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        SingleFlight::default()
+    }
+
+    // 返回 (结果, 是否复用了别人的计算)
+    // 领头的调用 panic 时，等待者自己再执行一次 f
+    pub fn run<F: FnOnce() -> V>(&self, key: K, f: F) -> (V, bool) {
+        let (call, leader) = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some(call) => (call.clone(), false),
+                None => {
+                    let call = Arc::new(Call {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    calls.insert(key.clone(), call.clone());
+                    (call, true)
+                }
+            }
+        };
+        if leader {
+            let mut finish = Finish {
+                group: self,
+                key: Some(key),
+                call,
+                value: None,
+            };
+            let value = f();
+            finish.value = Some(value.clone());
+            return (value, false);
+        }
+        let mut result = call.result.lock().unwrap();
+        while result.is_none() {
+            result = call.done.wait(result).unwrap();
+        }
+        match result.as_ref().unwrap() {
+            Some(v) => (v.clone(), true),
+            None => (f(), false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_single_flight() {
+        let group = Arc::new(SingleFlight::<&str, usize>::new());
+        let computed = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (group, computed, barrier) = (group.clone(), computed.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    group.run("thumb", || {
+                        thread::sleep(Duration::from_millis(100));
+                        computed.fetch_add(1, Ordering::SeqCst) + 42
+                    })
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|(v, _)| *v == 42));
+        assert_eq!(results.iter().filter(|(_, shared)| !shared).count(), 1);
+        // 计算结束后记录被移除，下一次重新计算
+        assert_eq!(group.run("thumb", || 7), (7, false));
+    }
+}
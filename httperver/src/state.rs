@@ -0,0 +1,152 @@
+// 应用共享状态：计数器、配置、连接池……在启动时构造一次，所有处理器共用
+//
+//     struct AppState { hits: AtomicUsize }
+//     let router = Router::new("").state(Arc::new(AppState { .. }));
+//
+//     #[route(GET, "/hits")]
+//     fn hits(state: State<AppState>) -> HttpResponse<'static> { ... }
+//
+// 也可以在任何拿得到请求的地方用 req.state::<AppState>() 取出，中间件和 Handler 都一样
+// 按类型区分，每种类型一份；挂载的子应用可以有自己的状态，同类型时里层的优先
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+// 一个路由器上注册的全部状态
+#[derive(Clone, Default)]
+pub(crate) struct StateMap(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl StateMap {
+    pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, value: Arc<T>) {
+        self.0.insert(TypeId::of::<T>(), value);
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.0
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.clone().downcast().ok())
+    }
+}
+
+// 请求经过的各层路由器的状态，外层在前；放在 req.extensions 里
+#[derive(Default)]
+pub(crate) struct StateLayers(Vec<Arc<StateMap>>);
+
+impl StateLayers {
+    pub(crate) fn push(req: &mut HttpRequest, states: &Arc<StateMap>) {
+        match req.extensions.get_mut::<StateLayers>() {
+            Some(layers) => layers.0.push(states.clone()),
+            None => {
+                req.extensions.insert(StateLayers(vec![states.clone()]));
+            }
+        }
+    }
+}
+
+pub trait RequestState {
+    fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>>;
+}
+
+impl RequestState for HttpRequest {
+    fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let layers = self.extensions.get::<StateLayers>()?;
+        layers.0.iter().rev().find_map(|m| m.get::<T>())
+    }
+}
+
+// #[route] 处理器的参数类型，按类型从请求上取出共享状态
+pub struct State<T>(pub Arc<T>);
+
+impl<T: Send + Sync + 'static> State<T> {
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        req.state().map(State)
+    }
+}
+
+impl<T> Deref for State<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// 处理器要的状态没有注册，是启动代码的错误，返回 500 并打印出来
+pub fn missing<'a>(type_name: &str) -> HttpResponse<'a> {
+    eprintln!(
+        "Handler state {} is not registered, call Router::state at startup",
+        type_name
+    );
+    HttpResponse::new("500", None, Some(String::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route;
+    use crate::router::Router;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Hits {
+        count: AtomicUsize,
+    }
+    struct Greeting {
+        text: &'static str,
+    }
+
+    #[route(GET, "/hits")]
+    fn hits(hits: State<Hits>) -> HttpResponse<'static> {
+        let n = hits.count.fetch_add(1, Ordering::SeqCst) + 1;
+        HttpResponse::new("200", None, Some(n.to_string()))
+    }
+
+    #[route(GET, "/greet")]
+    fn greet(greeting: State<Greeting>) -> HttpResponse<'static> {
+        HttpResponse::new("200", None, Some(greeting.text.to_string()))
+    }
+
+    fn body(router: &Router, path: &str) -> String {
+        let req = HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes());
+        let mut out = Vec::new();
+        router.route(req.unwrap(), &mut out);
+        let out = String::from_utf8(out).unwrap();
+        out.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_shared_state() {
+        let shared = Arc::new(Hits {
+            count: AtomicUsize::new(0),
+        });
+        let admin = crate::register_routes!(Router::new(""), greet)
+            .state(Arc::new(Greeting { text: "admin" }));
+        let router = crate::register_routes!(Router::new(""), hits, greet)
+            .state(shared.clone())
+            .state(Arc::new(Greeting { text: "hello" }))
+            .get("/closure", |req| {
+                let hits = req.state::<Hits>().unwrap();
+                HttpResponse::new(
+                    "200",
+                    None,
+                    Some(hits.count.load(Ordering::SeqCst).to_string()),
+                )
+            })
+            .mount("/admin", admin);
+        assert_eq!(body(&router, "/hits"), "1");
+        assert_eq!(body(&router, "/hits"), "2");
+        assert_eq!(body(&router, "/closure"), "2");
+        assert_eq!(shared.count.load(Ordering::SeqCst), 2);
+        assert_eq!(body(&router, "/greet"), "hello");
+        // 子应用里同类型的状态优先，没有的类型用外层的
+        assert_eq!(body(&router, "/admin/greet"), "admin");
+        let bare = crate::register_routes!(Router::new(""), hits);
+        let req = HttpRequest::try_from(&b"GET /hits HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let mut out = Vec::new();
+        bare.route(req, &mut out);
+        assert!(out.starts_with(b"HTTP/1.1 500"));
+    }
+}
@@ -0,0 +1,420 @@
+// 多租户：从子域名、请求头或路径第一段取出租户 ID，
+// 每个租户有自己的数据目录，并按租户限制请求速率和存储用量
+// 解析出的 Tenant 放进 req.extensions，处理器据此找到租户自己的数据
+// 作为中间件挂在路由上，本机可以通过 GET /_admin/tenants 查看统计
+use crate::middleware::Middleware;
+use http::clock::Clock;
+use http::headers::names;
+use http::httprequest::{HttpRequest, Method, Resource};
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_TENANT_HEADER: &str = "X-Tenant-Id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TenantSource {
+    // acme.example.com -> acme，需要 base_domain
+    Subdomain,
+    // X-Tenant-Id: acme
+    Header,
+    // /acme/orders -> acme，路由看到的路径是 /orders
+    Path,
+}
+
+// 单个租户的配额，没填的项使用 [tenancy] 里的默认值
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantQuota {
+    pub requests_per_minute: Option<u32>,
+    pub storage_mb: Option<u64>,
+}
+
+// [tenancy]
+// source = "subdomain"
+// base_domain = "example.com"
+// data_root = "/srv/tenants"
+// requests_per_minute = 600
+// [tenancy.tenants.acme]
+// storage_mb = 100
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenancyConfig {
+    // 不设置表示不启用多租户
+    pub source: Option<TenantSource>,
+    pub header: String,
+    pub base_domain: Option<String>,
+    // 每个租户的数据目录是 data_root/<租户 ID>，不设置时所有租户共用 data_path
+    pub data_root: Option<String>,
+    // 所有租户的默认配额
+    pub requests_per_minute: Option<u32>,
+    pub storage_mb: Option<u64>,
+    // 只服务这里列出的租户
+    pub tenants: BTreeMap<String, TenantQuota>,
+}
+
+impl Default for TenancyConfig {
+    fn default() -> Self {
+        TenancyConfig {
+            source: None,
+            header: DEFAULT_TENANT_HEADER.to_string(),
+            base_domain: None,
+            data_root: None,
+            requests_per_minute: None,
+            storage_mb: None,
+            tenants: BTreeMap::new(),
+        }
+    }
+}
+
+// 租户 ID 会用作目录名，只允许小写字母、数字和 -
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 63
+        && !id.starts_with('-')
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+impl TenancyConfig {
+    pub fn enabled(&self) -> bool {
+        self.source.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        let Some(source) = self.source else {
+            return;
+        };
+        if source == TenantSource::Subdomain && self.base_domain.is_none() {
+            problems.push("tenancy: source = \"subdomain\" requires base_domain".to_string());
+        }
+        if self.tenants.is_empty() {
+            problems.push("tenancy: tenants must not be empty".to_string());
+        }
+        if let Some(root) = &self.data_root {
+            if !Path::new(root).is_dir() {
+                problems.push(format!("tenancy: data_root {:?} is not a directory", root));
+            }
+        }
+        let zero = |q: &TenantQuota| q.requests_per_minute == Some(0) || q.storage_mb == Some(0);
+        let defaults = TenantQuota {
+            requests_per_minute: self.requests_per_minute,
+            storage_mb: self.storage_mb,
+        };
+        if zero(&defaults) {
+            problems.push("tenancy: quotas must be positive".to_string());
+        }
+        for (id, quota) in &self.tenants {
+            if !valid_id(id) {
+                problems.push(format!(
+                    "tenancy.tenants: invalid tenant id {:?}, use lowercase letters, digits and '-'",
+                    id
+                ));
+            }
+            if zero(quota) {
+                problems.push(format!("tenancy.tenants.{}: quotas must be positive", id));
+            }
+        }
+    }
+}
+
+// 统计页面，由路由器回复，见 Router::tenancy
+pub const ADMIN_PATH: &str = "/_admin/tenants";
+
+// 放进 req.extensions 的租户信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub id: String,
+    // None 表示使用全局的 data_path
+    pub data_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TenantStats {
+    pub requests: u64,
+    pub throttled: u64,
+    pub over_storage: u64,
+}
+
+pub struct Tenancy {
+    config: TenancyConfig,
+    clock: Arc<dyn Clock>,
+    // 租户 ID -> (窗口开始的分钟数, 本窗口内的请求数)
+    windows: Mutex<HashMap<String, (u64, u32)>>,
+    stats: Mutex<BTreeMap<String, TenantStats>>,
+}
+
+impl Tenancy {
+    pub fn new(config: TenancyConfig, clock: Arc<dyn Clock>) -> Self {
+        let stats = config
+            .tenants
+            .keys()
+            .map(|id| (id.clone(), TenantStats::default()))
+            .collect();
+        Tenancy {
+            config,
+            clock,
+            windows: Mutex::new(HashMap::new()),
+            stats: Mutex::new(stats),
+        }
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, TenantStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    // 取出租户 ID；按路径区分时顺便去掉路径里的租户部分
+    fn extract(&self, req: &mut HttpRequest) -> Option<String> {
+        match self.config.source? {
+            TenantSource::Header => req.header(&self.config.header).map(str::to_string),
+            TenantSource::Subdomain => {
+                let host = req.header(names::HOST)?;
+                let host = host.rsplit_once(':').map_or(host, |(h, _)| h);
+                let base = self.config.base_domain.as_deref()?;
+                let sub = host.strip_suffix(base)?.strip_suffix('.')?;
+                (!sub.contains('.')).then(|| sub.to_ascii_lowercase())
+            }
+            TenantSource::Path => {
+                let Resource::Path(path) = &mut req.resource;
+                let rest = path.strip_prefix('/')?;
+                let end = rest.find(['/', '?']).unwrap_or(rest.len());
+                let id = rest[..end].to_string();
+                *path = format!("/{}", rest[end..].trim_start_matches('/'));
+                Some(id)
+            }
+        }
+    }
+
+    // 识别租户并检查配额，通过时把 Tenant 放进 req.extensions
+    // 没有租户 400，未知租户 404，超过速率 429，写请求超过存储配额 507
+    pub fn apply<'a>(&self, req: &mut HttpRequest) -> Option<HttpResponse<'a>> {
+        let Some(id) = self.extract(req).filter(|id| !id.is_empty()) else {
+            return Some(error("400", "missing_tenant", None));
+        };
+        let Some(quota) = self.config.tenants.get(&id) else {
+            return Some(error("404", "unknown_tenant", None));
+        };
+        let tenant = Tenant {
+            data_dir: self
+                .config
+                .data_root
+                .as_ref()
+                .map(|r| Path::new(r).join(&id)),
+            id,
+        };
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(tenant.id.clone()).or_default();
+        entry.requests += 1;
+        if let Some(limit) = quota
+            .requests_per_minute
+            .or(self.config.requests_per_minute)
+        {
+            if let Some(wait) = self.throttle(&tenant.id, limit) {
+                entry.throttled += 1;
+                return Some(
+                    error("429", "rate_limited", Some(&tenant.id))
+                        .with_header(names::RETRY_AFTER, wait.to_string())
+                        .expect("digits are a valid header value"),
+                );
+            }
+        }
+        let writes = matches!(req.method, Method::Post | Method::Put | Method::Patch);
+        if let (true, Some(mb), Some(dir)) = (
+            writes,
+            quota.storage_mb.or(self.config.storage_mb),
+            &tenant.data_dir,
+        ) {
+            if dir_size(dir) >= mb * 1024 * 1024 {
+                entry.over_storage += 1;
+                return Some(error("507", "storage_quota_exceeded", Some(&tenant.id)));
+            }
+        }
+        drop(stats);
+        req.extensions.insert(tenant);
+        None
+    }
+
+    // 固定一分钟窗口计数，超过时返回还要等多少秒
+    fn throttle(&self, id: &str, limit: u32) -> Option<u64> {
+        let now = self.clock.unix_secs();
+        let minute = now / 60;
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(id.to_string()).or_insert((minute, 0));
+        if window.0 != minute {
+            *window = (minute, 0);
+        }
+        if window.1 >= limit {
+            return Some((minute + 1) * 60 - now);
+        }
+        window.1 += 1;
+        None
+    }
+}
+
+impl Middleware for Tenancy {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        // 管理接口不属于任何租户
+        if req.path().starts_with("/_admin/") {
+            return None;
+        }
+        self.apply(req)
+    }
+}
+
+// 目录下所有文件的大小之和，目录不存在时是 0
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&e.path()),
+            Ok(t) if t.is_file() => e.metadata().map_or(0, |m| m.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn error<'a>(status: &'a str, error: &str, tenant: Option<&str>) -> HttpResponse<'a> {
+    let mut headers = HashMap::new();
+    headers.insert(names::CONTENT_TYPE, "application/json");
+    let body = serde_json::json!({ "error": error, "tenant": tenant });
+    HttpResponse::new(status, Some(headers), Some(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use http::clock::MockClock;
+    use http::status::StatusCode;
+
+    fn config(source: TenantSource) -> TenancyConfig {
+        TenancyConfig {
+            source: Some(source),
+            base_domain: Some("example.com".into()),
+            data_root: Some("/srv/tenants".into()),
+            tenants: [("acme".to_string(), TenantQuota::default())]
+                .into_iter()
+                .collect(),
+            ..TenancyConfig::default()
+        }
+    }
+
+    fn request(raw: &str) -> HttpRequest {
+        HttpRequest::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_sources() {
+        let clock = Arc::new(MockClock::from_unix_secs(0));
+        let tenancy = Tenancy::new(config(TenantSource::Subdomain), clock.clone());
+        let mut req = request("GET /orders HTTP/1.1\r\nHost: acme.example.com:8080\r\n\r\n");
+        assert!(tenancy.apply(&mut req).is_none());
+        let tenant = req.extensions.get::<Tenant>().unwrap();
+        assert_eq!(tenant.id, "acme");
+        assert_eq!(tenant.data_dir, Some(PathBuf::from("/srv/tenants/acme")));
+
+        let tenancy = Tenancy::new(config(TenantSource::Header), clock.clone());
+        let mut req = request("GET / HTTP/1.1\r\nX-Tenant-Id: other\r\n\r\n");
+        let resp = tenancy.apply(&mut req).unwrap();
+        assert_eq!(resp.status(), StatusCode::NotFound);
+
+        let tenancy = Tenancy::new(config(TenantSource::Path), clock);
+        let mut req = request("GET /acme/api/shipping/orders?x=1 HTTP/1.1\r\n\r\n");
+        assert!(tenancy.apply(&mut req).is_none());
+        assert_eq!(req.path(), "/api/shipping/orders");
+        let mut req = request("GET / HTTP/1.1\r\n\r\n");
+        let resp = tenancy.apply(&mut req).unwrap();
+        assert_eq!(resp.status(), StatusCode::BadRequest);
+    }
+
+    #[test]
+    fn test_rate_quota() {
+        let clock = MockClock::from_unix_secs(0);
+        let mut config = config(TenantSource::Header);
+        config.requests_per_minute = Some(2);
+        let tenancy = Tenancy::new(config, Arc::new(clock.clone()));
+        let status = || {
+            let mut req = request("GET / HTTP/1.1\r\nX-Tenant-Id: acme\r\n\r\n");
+            tenancy.apply(&mut req).map(|r| r.status())
+        };
+        assert_eq!(status(), None);
+        assert_eq!(status(), None);
+        assert_eq!(status(), Some(StatusCode::TooManyRequests));
+        clock.advance(std::time::Duration::from_secs(60));
+        assert_eq!(status(), None);
+        assert_eq!(tenancy.stats()["acme"].throttled, 1);
+    }
+
+    #[test]
+    fn test_storage_quota() {
+        let root = crate::testdir::temp_path("tenant");
+        fs::create_dir_all(root.join("acme")).unwrap();
+        fs::write(root.join("acme/orders.json"), vec![b' '; 1024 * 1024]).unwrap();
+        let mut config = config(TenantSource::Header);
+        config.data_root = Some(root.to_str().unwrap().to_string());
+        config.storage_mb = Some(1);
+        let tenancy = Tenancy::new(config, Arc::new(MockClock::from_unix_secs(0)));
+        let mut req = request("POST / HTTP/1.1\r\nX-Tenant-Id: acme\r\nContent-Length: 0\r\n\r\n");
+        let resp = tenancy.apply(&mut req).unwrap();
+        assert_eq!(resp.status(), StatusCode::InsufficientStorage);
+        // 读请求不受存储配额影响
+        let mut req = request("GET / HTTP/1.1\r\nX-Tenant-Id: acme\r\n\r\n");
+        assert!(tenancy.apply(&mut req).is_none());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_middleware() {
+        let mut config = config(TenantSource::Path);
+        config.requests_per_minute = Some(1);
+        let tenancy = Tenancy::new(config, Arc::new(MockClock::from_unix_secs(0)));
+        let router = Router::new("")
+            .tenancy(Arc::new(tenancy))
+            .get("/whoami", |req| {
+                let id = req.extensions.get::<Tenant>().map(|t| t.id.clone());
+                HttpResponse::new("200", None, id)
+            });
+        let send = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(request(raw), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let out = send("GET /acme/whoami HTTP/1.1\r\n\r\n");
+        assert!(
+            out.starts_with("HTTP/1.1 200") && out.ends_with("acme"),
+            "{}",
+            out
+        );
+        assert!(send("GET /acme/whoami HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 429"));
+        assert!(send("GET /other/whoami HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+        // 统计页面不经过租户识别
+        let out = send("GET /_admin/tenants HTTP/1.1\r\n\r\n");
+        assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+        assert!(
+            out.contains(r#""acme":{"requests":2,"throttled":1"#),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = config(TenantSource::Subdomain);
+        config.base_domain = None;
+        config.data_root = None;
+        config
+            .tenants
+            .insert("Bad_Id".into(), TenantQuota::default());
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+}
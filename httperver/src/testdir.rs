@@ -0,0 +1,12 @@
+// 测试用的临时目录：名字带上进程号和计数器，
+// 同时运行的测试进程、同一进程里并行的测试都不会用到同一个目录
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+// 只生成路径，不创建目录
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("httperver-{}-{}-{}", name, std::process::id(), n))
+}
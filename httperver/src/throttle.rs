@@ -0,0 +1,204 @@
+// 响应限速：令牌桶挡在写路径上，令牌不够时先 sleep 再写
+// per_connection 给每个连接单独一个桶；routes 按路由名共享一个桶，限制这条路由所有连接的总速率，
+// 这样大文件下载不会在带宽有限的链路上挤占 API 请求
+use http::clock::{Clock, SystemClock};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// 一次最多写出这么多字节再检查令牌，限速才比较平滑
+const MAX_PIECE: usize = 16 * 1024;
+
+// [bandwidth] 单位都是 KB/s
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BandwidthConfig {
+    pub per_connection_kbps: Option<u64>,
+    // 路由名 -> 这条路由所有连接加起来的速率，例如 static_file = 2048
+    pub routes: BTreeMap<String, u64>,
+}
+
+impl BandwidthConfig {
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if self.per_connection_kbps == Some(0) {
+            problems.push("bandwidth.per_connection_kbps must be positive".to_string());
+        }
+        for (name, kbps) in &self.routes {
+            if *kbps == 0 {
+                problems.push(format!("bandwidth.routes.{} must be positive", name));
+            }
+        }
+    }
+}
+
+// 令牌可以透支：先扣再算要等多久，多个连接共享一个桶时按到达顺序排队
+pub struct TokenBucket {
+    // 字节/秒
+    rate: u64,
+    // 最多攒一秒的令牌
+    tokens: f64,
+    last: SystemTime,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64, now: SystemTime) -> Self {
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    // 取走 n 个令牌，返回需要等待的时间
+    pub fn reserve(&mut self, n: usize, now: SystemTime) -> Duration {
+        let elapsed = now.duration_since(self.last).unwrap_or_default();
+        self.last = now.max(self.last);
+        let rate = self.rate as f64;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.tokens -= n as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+pub struct Throttle {
+    // 字节/秒
+    per_connection: Option<u64>,
+    routes: HashMap<String, Arc<Mutex<TokenBucket>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle::new(&BandwidthConfig::default(), Arc::new(SystemClock))
+    }
+}
+
+impl Throttle {
+    pub fn new(config: &BandwidthConfig, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Throttle {
+            per_connection: config.per_connection_kbps.map(|k| k * 1024),
+            routes: config
+                .routes
+                .iter()
+                .map(|(name, kbps)| {
+                    let bucket = TokenBucket::new(kbps * 1024, now);
+                    (name.clone(), Arc::new(Mutex::new(bucket)))
+                })
+                .collect(),
+            clock,
+        }
+    }
+
+    pub fn routes(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(|k| k.as_str())
+    }
+
+    // route 是处理这个请求的路由名，没有限速时返回的 writer 直接透传
+    pub fn writer<W: Write>(&self, inner: W, route: Option<&str>) -> ThrottledWriter<W> {
+        let now = self.clock.now();
+        let mut buckets = Vec::new();
+        if let Some(rate) = self.per_connection {
+            buckets.push(Arc::new(Mutex::new(TokenBucket::new(rate, now))));
+        }
+        if let Some(bucket) = route.and_then(|r| self.routes.get(r)) {
+            buckets.push(Arc::clone(bucket));
+        }
+        ThrottledWriter {
+            inner,
+            buckets,
+            clock: Arc::clone(&self.clock),
+        }
+    }
+}
+
+pub struct ThrottledWriter<W: Write> {
+    inner: W,
+    buckets: Vec<Arc<Mutex<TokenBucket>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buckets.is_empty() {
+            return self.inner.write(buf);
+        }
+        let piece = &buf[..buf.len().min(MAX_PIECE)];
+        let now = self.clock.now();
+        // 每个桶都扣令牌，等最慢的那个
+        let wait = self
+            .buckets
+            .iter()
+            .map(|b| b.lock().unwrap().reserve(piece.len(), now))
+            .max()
+            .unwrap_or_default();
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        self.inner.write_all(piece)?;
+        Ok(piece.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::clock::MockClock;
+    use std::time::Instant;
+
+    #[test]
+    fn test_token_bucket() {
+        let clock = MockClock::from_unix_secs(1000);
+        let mut bucket = TokenBucket::new(1000, clock.now());
+        // 一开始有一秒的令牌
+        assert_eq!(bucket.reserve(1000, clock.now()), Duration::ZERO);
+        assert_eq!(bucket.reserve(500, clock.now()), Duration::from_millis(500));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(bucket.reserve(250, clock.now()), Duration::from_millis(250));
+        // 空闲很久也只攒一秒
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(bucket.reserve(1000, clock.now()), Duration::ZERO);
+        assert_eq!(bucket.reserve(100, clock.now()), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_route_bucket_is_shared() {
+        let config = BandwidthConfig {
+            per_connection_kbps: None,
+            routes: [("static_file".to_string(), 1)].into_iter().collect(),
+        };
+        let throttle = Throttle::new(&config, Arc::new(MockClock::from_unix_secs(0)));
+        assert!(throttle
+            .writer(Vec::new(), Some("orders"))
+            .buckets
+            .is_empty());
+        let a = throttle.writer(Vec::new(), Some("static_file"));
+        let b = throttle.writer(Vec::new(), Some("static_file"));
+        assert!(Arc::ptr_eq(&a.buckets[0], &b.buckets[0]));
+    }
+
+    #[test]
+    fn test_writer_is_slowed_down() {
+        let config = BandwidthConfig {
+            per_connection_kbps: Some(100),
+            routes: BTreeMap::new(),
+        };
+        let throttle = Throttle::new(&config, Arc::new(SystemClock));
+        let start = Instant::now();
+        let mut w = throttle.writer(Vec::new(), None);
+        // 一秒的令牌用完后，再写 20KB 需要大约 0.2 秒
+        w.write_all(&vec![0; 120 * 1024]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(180));
+        assert_eq!(w.inner.len(), 120 * 1024);
+    }
+}
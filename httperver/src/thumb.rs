@@ -0,0 +1,252 @@
+// 缩略图：GET /thumb/<path>?w=200&h=200 把 public/ 下的图片缩放后返回，
+// 结果缓存在磁盘上，图片文件修改后缓存自动失效
+use crate::assets::fnv1a;
+use crate::singleflight::SingleFlight;
+use http::httprequest::HttpRequest;
+use http::query::percent_decode;
+use image::ImageFormat;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// 宽高上限，防止请求一张超大的缩略图把内存耗尽
+pub const MAX_DIMENSION: u32 = 2000;
+
+#[derive(Debug, Clone)]
+pub enum ThumbError {
+    // 路径或参数不合法 -> 400
+    BadRequest(String),
+    // 文件不存在或不是支持的图片 -> 404
+    NotFound,
+    // 解码、缩放或写缓存失败 -> 500
+    Failed(String),
+}
+
+impl fmt::Display for ThumbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumbError::BadRequest(m) => write!(f, "bad thumbnail request: {}", m),
+            ThumbError::NotFound => write!(f, "image not found"),
+            ThumbError::Failed(m) => write!(f, "thumbnail failed: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for ThumbError {}
+
+pub struct Thumbnail {
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Thumbnailer {
+    cache_dir: PathBuf,
+    flight: SingleFlight<PathBuf, Result<Vec<u8>, ThumbError>>,
+}
+
+impl Thumbnailer {
+    pub fn new(cache_dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let cache_dir = cache_dir.into();
+        fs::create_dir_all(&cache_dir)?;
+        Ok(Thumbnailer {
+            cache_dir,
+            flight: SingleFlight::new(),
+        })
+    }
+
+    // req 的路径已经去掉了 base_path，形如 /thumb/photos/a.jpg?w=200
+    pub fn handle(&self, root: &str, req: &HttpRequest) -> Result<Thumbnail, ThumbError> {
+        let rel = req
+            .path()
+            .strip_prefix("/thumb/")
+            .ok_or_else(|| ThumbError::BadRequest("expected /thumb/<path>".into()))?;
+        let query = req.query_params();
+        let w = dimension(query.first("w"))?;
+        let h = dimension(query.first("h"))?;
+        if w.is_none() && h.is_none() {
+            return Err(ThumbError::BadRequest("w or h is required".into()));
+        }
+        let source = resolve(root, rel)?;
+        let format = ImageFormat::from_path(&source).map_err(|_| ThumbError::NotFound)?;
+        let content_type = format.to_mime_type();
+        let (w, h) = (w.unwrap_or(MAX_DIMENSION), h.unwrap_or(MAX_DIMENSION));
+
+        // 缓存文件名包含源文件路径、修改时间和尺寸
+        let mtime = fs::metadata(&source)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let key = format!("{}|{}|{}x{}", source.display(), mtime, w, h);
+        let ext = format.extensions_str().first().copied().unwrap_or("img");
+        let cached = self
+            .cache_dir
+            .join(format!("{:016x}.{}", fnv1a(key.as_bytes()), ext));
+        if let Ok(bytes) = fs::read(&cached) {
+            return Ok(Thumbnail {
+                content_type,
+                bytes,
+            });
+        }
+
+        // 同一张缩略图的并发请求只渲染一次
+        let (bytes, _) = self
+            .flight
+            .run(cached.clone(), || render(&source, w, h, format, &cached));
+        Ok(Thumbnail {
+            content_type,
+            bytes: bytes?,
+        })
+    }
+}
+
+fn render(
+    source: &Path,
+    w: u32,
+    h: u32,
+    format: ImageFormat,
+    cached: &Path,
+) -> Result<Vec<u8>, ThumbError> {
+    let img = image::open(source).map_err(|e| ThumbError::Failed(e.to_string()))?;
+    // thumbnail 保持宽高比，结果落在 w x h 的框内；比原图还大时不放大
+    let img = if img.width() > w || img.height() > h {
+        img.thumbnail(w, h)
+    } else {
+        img
+    };
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), format)
+        .map_err(|e| ThumbError::Failed(e.to_string()))?;
+    // 先写临时文件再改名，其他进程不会读到写了一半的缓存
+    let tmp = cached.with_extension(format!("tmp{}", std::process::id()));
+    if fs::write(&tmp, &bytes).is_ok() {
+        let _ = fs::rename(&tmp, cached);
+    }
+    Ok(bytes)
+}
+
+fn dimension(value: Option<&str>) -> Result<Option<u32>, ThumbError> {
+    match value {
+        None => Ok(None),
+        Some(v) => match v.parse::<u32>() {
+            Ok(n) if (1..=MAX_DIMENSION).contains(&n) => Ok(Some(n)),
+            _ => Err(ThumbError::BadRequest(format!(
+                "dimension must be 1..={}, got {:?}",
+                MAX_DIMENSION, v
+            ))),
+        },
+    }
+}
+
+// 解码后逐段检查：不允许空段、. 开头的段（包括 ..）、反斜杠和 NUL，
+// 最后再确认真实路径仍在 root 下面，防止符号链接指到外面
+fn resolve(root: &str, rel: &str) -> Result<PathBuf, ThumbError> {
+    let decoded = percent_decode(rel, false);
+    let mut path = PathBuf::from(root);
+    for segment in decoded.split('/') {
+        if segment.is_empty() || segment.starts_with('.') || segment.contains(['\\', '\0', ':']) {
+            return Err(ThumbError::BadRequest(format!("invalid path {:?}", rel)));
+        }
+        path.push(segment);
+    }
+    let root = fs::canonicalize(root).map_err(|_| ThumbError::NotFound)?;
+    let path = fs::canonicalize(&path).map_err(|_| ThumbError::NotFound)?;
+    if !path.starts_with(&root) || !path.is_file() {
+        return Err(ThumbError::NotFound);
+    }
+    Ok(path)
+}
+
+// 默认的缓存目录
+pub fn default_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("httperver-thumbs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use image::{Rgb, RgbImage};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_thumbnail() {
+        let root = crate::testdir::temp_path("thumb");
+        fs::create_dir_all(root.join("photos")).unwrap();
+        RgbImage::from_pixel(400, 200, Rgb([200, 10, 10]))
+            .save(root.join("photos/red.png"))
+            .unwrap();
+        let root = root.to_string_lossy().into_owned();
+        let thumbs = Thumbnailer::new(root.clone() + "/.cache").unwrap();
+
+        let req = HttpRequest::try_from(
+            "GET /thumb/photos/red.png?w=100&h=100 HTTP/1.1\r\n\r\n".as_bytes(),
+        )
+        .unwrap();
+        let t = thumbs.handle(&root, &req).unwrap();
+        assert_eq!(t.content_type, "image/png");
+        let img = image::load_from_memory(&t.bytes).unwrap();
+        assert_eq!((img.width(), img.height()), (100, 50));
+        // 第二次走缓存，结果一致
+        assert_eq!(thumbs.handle(&root, &req).unwrap().bytes, t.bytes);
+
+        for bad in [
+            "/thumb/../etc/passwd?w=10",
+            "/thumb/photos/%2e%2e/%2e%2e/etc/passwd?w=10",
+            "/thumb/photos/red.png?w=0",
+            "/thumb/photos/red.png?w=99999",
+            "/thumb/photos/red.png",
+        ] {
+            let req =
+                HttpRequest::try_from(format!("GET {} HTTP/1.1\r\n\r\n", bad).as_bytes()).unwrap();
+            assert!(matches!(
+                thumbs.handle(&root, &req),
+                Err(ThumbError::BadRequest(_))
+            ));
+        }
+        let req =
+            HttpRequest::try_from("GET /thumb/photos/nope.png?w=10 HTTP/1.1\r\n\r\n".as_bytes())
+                .unwrap();
+        assert!(matches!(
+            thumbs.handle(&root, &req),
+            Err(ThumbError::NotFound)
+        ));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // 缩略图和其他响应一样是 HttpResponse，HEAD 只发头部，长度和 GET 一致
+    #[test]
+    fn test_thumb_route() {
+        let root = crate::testdir::temp_path("thumb-route");
+        fs::create_dir_all(&root).unwrap();
+        RgbImage::from_pixel(40, 40, Rgb([10, 200, 10]))
+            .save(root.join("green.png"))
+            .unwrap();
+        let thumbs = Thumbnailer::new(root.join(".cache")).unwrap();
+        let router = Router::new("")
+            .static_root(&root.to_string_lossy())
+            .thumbnails(Arc::new(thumbs));
+        let send = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            out
+        };
+        let out = send("GET /thumb/green.png?w=20 HTTP/1.1\r\n\r\n");
+        let end = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&out[..end]).into_owned();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        assert!(head.contains("Content-Type:image/png"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", out.len() - end)));
+        let img = image::load_from_memory(&out[end..]).unwrap();
+        assert_eq!((img.width(), img.height()), (20, 20));
+        // 头部顺序不固定，只比较长度
+        let head_only = send("HEAD /thumb/green.png?w=20 HTTP/1.1\r\n\r\n");
+        assert_eq!(head_only.len(), end);
+        let length = format!("Content-Length: {}\r\n", out.len() - end);
+        assert!(String::from_utf8_lossy(&head_only).contains(&length));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+// 按时间段开放或关闭一组路由，时间都是 UTC
+// allow：只在时间段内开放，其余时间返回 403，例如批量导入接口只在 00:00–05:00 开放
+// maintenance：时间段内是维护窗口，返回 503 并带上 Retry-After
+// 作为中间件挂在路由上，按 RouteName 找到适用的规则
+use crate::middleware::Middleware;
+use crate::router::RouteName;
+use http::clock::Clock;
+use http::headers::names;
+use http::httprequest::HttpRequest;
+use http::httpresponse::HttpResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowKind {
+    Allow,
+    Maintenance,
+}
+
+// [[time_windows]]
+// name = "bulk-import"
+// routes = ["orders"]
+// kind = "allow"
+// start = "00:00"
+// end = "05:00"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeWindowRule {
+    pub name: String,
+    // 路由名，为空表示所有有名字的路由
+    #[serde(default)]
+    pub routes: Vec<String>,
+    pub kind: WindowKind,
+    // HH:MM，end 早于 start 表示跨过午夜
+    pub start: String,
+    pub end: String,
+}
+
+// "05:30" -> 一天中的第几秒
+fn parse_time(s: &str) -> Option<u64> {
+    let (h, m) = s.split_once(':')?;
+    if h.len() != 2 || m.len() != 2 {
+        return None;
+    }
+    let (h, m) = (h.parse::<u64>().ok()?, m.parse::<u64>().ok()?);
+    (h < 24 && m < 60).then_some(h * 3600 + m * 60)
+}
+
+impl TimeWindowRule {
+    pub fn validate(rules: &[TimeWindowRule], problems: &mut Vec<String>) {
+        for rule in rules {
+            if rule.name.is_empty() {
+                problems.push("time_windows: name must be non-empty".to_string());
+            }
+            for (field, value) in [("start", &rule.start), ("end", &rule.end)] {
+                if parse_time(value).is_none() {
+                    problems.push(format!(
+                        "time_windows.{}: {} {:?} is not HH:MM",
+                        rule.name, field, value
+                    ));
+                }
+            }
+            if rule.start == rule.end {
+                problems.push(format!(
+                    "time_windows.{}: start and end must differ",
+                    rule.name
+                ));
+            }
+        }
+    }
+
+    fn applies_to(&self, route: &str) -> bool {
+        self.routes.is_empty() || self.routes.iter().any(|r| r == route)
+    }
+}
+
+// 解析好的时间段，单位是一天中的秒数
+struct Window {
+    rule: TimeWindowRule,
+    start: u64,
+    end: u64,
+}
+
+impl Window {
+    fn contains(&self, secs: u64) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&secs)
+        } else {
+            secs >= self.start || secs < self.end
+        }
+    }
+
+    // 从 secs 开始还要多久到达 target
+    fn until(secs: u64, target: u64) -> u64 {
+        (target + DAY_SECS - secs) % DAY_SECS
+    }
+}
+
+// 拒绝时的 JSON body
+#[derive(Debug, Serialize)]
+struct Rejection<'a> {
+    error: &'static str,
+    window: &'a str,
+    message: String,
+    // 距离下一次开放的秒数
+    retry_after: u64,
+}
+
+pub struct TimeWindows {
+    windows: Vec<Window>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TimeWindows {
+    // 规则应该已经通过 validate，解析不了的时间段会被忽略
+    pub fn new(rules: Vec<TimeWindowRule>, clock: Arc<dyn Clock>) -> Self {
+        let windows = rules
+            .into_iter()
+            .filter_map(|rule| {
+                let start = parse_time(&rule.start)?;
+                let end = parse_time(&rule.end)?;
+                Some(Window { rule, start, end })
+            })
+            .collect();
+        TimeWindows { windows, clock }
+    }
+
+    // 按配置顺序检查，第一个拒绝的规则生效；没有名字的路由不受限制
+    pub fn respond<'a>(&self, route: Option<&str>) -> Option<HttpResponse<'a>> {
+        let route = route?;
+        let secs = self.clock.unix_secs() % DAY_SECS;
+        self.windows
+            .iter()
+            .filter(|w| w.rule.applies_to(route))
+            .find_map(|w| match (w.rule.kind, w.contains(secs)) {
+                (WindowKind::Allow, false) => Some(reject(
+                    "403",
+                    "outside_time_window",
+                    w,
+                    format!(
+                        "{} is only available {}-{} UTC",
+                        route, w.rule.start, w.rule.end
+                    ),
+                    Window::until(secs, w.start),
+                )),
+                (WindowKind::Maintenance, true) => Some(reject(
+                    "503",
+                    "maintenance",
+                    w,
+                    format!("{} is under maintenance until {} UTC", route, w.rule.end),
+                    Window::until(secs, w.end),
+                )),
+                _ => None,
+            })
+    }
+}
+
+impl Middleware for TimeWindows {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        self.respond(req.extensions.get::<RouteName>().map(|r| r.0))
+    }
+}
+
+fn reject<'a>(
+    status: &'a str,
+    error: &'static str,
+    window: &Window,
+    message: String,
+    retry_after: u64,
+) -> HttpResponse<'a> {
+    let body = Rejection {
+        error,
+        window: &window.rule.name,
+        message,
+        retry_after,
+    };
+    let mut headers = HashMap::new();
+    headers.insert(names::CONTENT_TYPE, "application/json");
+    HttpResponse::new(status, Some(headers), serde_json::to_string(&body).ok())
+        .with_header(names::RETRY_AFTER, retry_after.to_string())
+        .expect("digits are a valid header value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use http::clock::MockClock;
+    use http::status::StatusCode;
+    use std::time::Duration;
+
+    fn rule(kind: WindowKind, start: &str, end: &str) -> TimeWindowRule {
+        TimeWindowRule {
+            name: "w".into(),
+            routes: vec!["orders".into()],
+            kind,
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    #[test]
+    fn test_allow_window() {
+        // 1970-01-02 04:00 UTC
+        let clock = MockClock::from_unix_secs(DAY_SECS + 4 * 3600);
+        let windows = TimeWindows::new(
+            vec![rule(WindowKind::Allow, "00:00", "05:00")],
+            Arc::new(clock.clone()),
+        );
+        assert!(windows.respond(Some("orders")).is_none());
+        clock.advance(Duration::from_secs(3600));
+        let resp = windows.respond(Some("orders")).unwrap();
+        assert_eq!(resp.status(), StatusCode::Forbidden);
+        let body: serde_json::Value = serde_json::from_str(resp.body_text().unwrap()).unwrap();
+        assert_eq!(body["error"], "outside_time_window");
+        // 05:00 到第二天 00:00 还有 19 小时
+        assert_eq!(body["retry_after"], 19 * 3600);
+        // 其他路由不受影响
+        assert!(windows.respond(Some("index")).is_none());
+        assert!(windows.respond(None).is_none());
+    }
+
+    #[test]
+    fn test_maintenance_across_midnight() {
+        let clock = MockClock::from_unix_secs(DAY_SECS + 23 * 3600 + 30 * 60);
+        let windows = TimeWindows::new(
+            vec![rule(WindowKind::Maintenance, "23:00", "01:00")],
+            Arc::new(clock.clone()),
+        );
+        let resp = windows.respond(Some("orders")).unwrap();
+        assert_eq!(resp.status(), StatusCode::ServiceUnavailable);
+        assert!(String::from(resp).contains("Retry-After:5400"));
+        clock.advance(Duration::from_secs(2 * 3600));
+        assert!(windows.respond(Some("orders")).is_none());
+    }
+
+    #[test]
+    fn test_middleware() {
+        let clock = MockClock::from_unix_secs(DAY_SECS + 6 * 3600);
+        let windows = TimeWindows::new(
+            vec![rule(WindowKind::Allow, "00:00", "05:00")],
+            Arc::new(clock.clone()),
+        );
+        let router = Router::new("/shop").middleware(windows);
+        let send = |raw: &str| {
+            let mut out = Vec::new();
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        // 路由名 orders 来自路由表，不是请求路径
+        let out = send("GET /shop/api/shipping/orders HTTP/1.1\r\n\r\n");
+        assert!(out.starts_with("HTTP/1.1 403"), "{}", out);
+        assert!(out.contains("outside_time_window"));
+        assert!(!send("GET /shop/ HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 403"));
+        clock.advance(Duration::from_secs(20 * 3600));
+        let out = send("GET /shop/api/shipping/orders HTTP/1.1\r\n\r\n");
+        assert!(!out.starts_with("HTTP/1.1 403"), "{}", out);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut problems = Vec::new();
+        TimeWindowRule::validate(
+            &[
+                rule(WindowKind::Allow, "24:00", "05:00"),
+                rule(WindowKind::Allow, "01:00", "01:00"),
+            ],
+            &mut problems,
+        );
+        assert_eq!(problems.len(), 2);
+    }
+}
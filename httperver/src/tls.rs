@@ -0,0 +1,291 @@
+// HTTPS：在单独的地址上监听，接受的 TcpStream 包进 rustls 的 ServerConnection，
+// 之后和明文连接一样排队、读取、交给同一个 Router 处理
+// 握手在工作线程第一次读取时进行，慢客户端不会卡住 accept 线程
+use crate::secret::{SecretError, SecretSource};
+use http::headers::{self, names};
+use http::html;
+use http::httprequest::{HttpRequest, Method, Resource};
+use http::httpresponse::HttpResponse;
+use http::proxy::split_host_port;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+// [tls]
+// addr = "0.0.0.0:3443"
+// cert = "/etc/httperver/cert.pem"
+// key = "/etc/httperver/key.pem"
+// 开发时可以不写 cert / key，改成 self_signed = true，启动时为 localhost 生成证书
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    // 不设置表示不开 HTTPS
+    pub addr: Option<String>,
+    // PEM 格式，证书链按叶子证书在前的顺序
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    // 需要 dev-cert feature
+    pub self_signed: bool,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Read(PathBuf, io::Error),
+    Key(SecretError),
+    Pem(String),
+    Rustls(rustls::Error),
+    // 生成自签名证书失败
+    Generate(String),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Read(p, e) => write!(f, "cannot read certificate {}: {}", p.display(), e),
+            TlsError::Key(e) => write!(f, "cannot load private key: {}", e),
+            TlsError::Pem(e) => write!(f, "invalid PEM: {}", e),
+            TlsError::Rustls(e) => write!(f, "invalid certificate or key: {}", e),
+            TlsError::Generate(e) => write!(f, "cannot generate self-signed certificate: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.addr.is_some()
+    }
+
+    // plain_addr 是明文监听的地址，两个监听不能绑在同一个地址上
+    pub fn validate(&self, plain_addr: &str, problems: &mut Vec<String>) {
+        let Some(addr) = &self.addr else {
+            if self.cert.is_some() || self.key.is_some() || self.self_signed {
+                problems.push("tls: cert, key and self_signed require tls.addr".to_string());
+            }
+            return;
+        };
+        if addr == plain_addr {
+            problems.push(format!("tls.addr {:?} must differ from addr", addr));
+        }
+        if self.self_signed {
+            if cfg!(not(feature = "dev-cert")) {
+                problems
+                    .push("tls.self_signed requires building with --features dev-cert".to_string());
+            }
+            if self.cert.is_some() || self.key.is_some() {
+                problems.push("tls: self_signed cannot be combined with cert / key".to_string());
+            }
+            return;
+        }
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                for (name, path) in [("cert", cert), ("key", key)] {
+                    if !Path::new(path).is_file() {
+                        problems.push(format!("tls.{} {:?} does not exist", name, path));
+                    }
+                }
+            }
+            _ => problems.push("tls: both cert and key are required".to_string()),
+        }
+    }
+
+    // 读取证书和私钥，或者生成自签名证书
+    pub fn load(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        #[cfg(feature = "dev-cert")]
+        if self.self_signed {
+            let hosts: Vec<String> = crate::devcert::DEFAULT_HOSTS
+                .iter()
+                .map(|h| h.to_string())
+                .collect();
+            let cert =
+                crate::devcert::generate(&hosts).map_err(|e| TlsError::Generate(e.to_string()))?;
+            return server_config(cert.cert_pem.as_bytes(), cert.key_pem.expose().as_bytes());
+        }
+        let cert = PathBuf::from(self.cert.as_deref().unwrap_or_default());
+        let cert_pem = fs::read(&cert).map_err(|e| TlsError::Read(cert, e))?;
+        // 私钥按密钥处理：读出来的内容用完清零
+        let key = SecretSource::File(self.key.as_deref().unwrap_or_default().into())
+            .load()
+            .map_err(TlsError::Key)?;
+        server_config(&cert_pem, key.expose().as_bytes())
+    }
+}
+
+pub fn server_config(cert_pem: &[u8], key_pem: &[u8]) -> Result<Arc<ServerConfig>, TlsError> {
+    let certs = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Pem(e.to_string()))?;
+    if certs.is_empty() {
+        return Err(TlsError::Pem("no certificate found".to_string()));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|e| TlsError::Pem(e.to_string()))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .and_then(|b| b.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(TlsError::Rustls)?;
+    Ok(Arc::new(config))
+}
+
+// 只创建会话，不做握手
+pub fn accept(config: &Arc<ServerConfig>, stream: TcpStream) -> Result<TlsStream, rustls::Error> {
+    Ok(StreamOwned::new(
+        ServerConnection::new(config.clone())?,
+        stream,
+    ))
+}
+
+// 协议用错了的连接：https:// 的客户端连到明文端口，或者 http:// 的客户端连到 HTTPS 端口
+// 默认的行为是一方收到一堆看不懂的字节，客户端只报 "wrong version number" 之类的错误
+// 这里按连接上的头几个字节认出来，给客户端一个能看懂的回复，并在日志里说明原因
+
+// TLS 握手记录：类型 0x16，版本 0x03 0x0?
+pub fn is_client_hello(first: &[u8]) -> bool {
+    matches!(first, [0x16, 0x03, ..] | [0x16])
+}
+
+// 明文 HTTP 以方法名开头
+pub fn is_plain_http(first: &[u8]) -> bool {
+    first.first().is_some_and(u8::is_ascii_uppercase)
+}
+
+// 致命的 unexpected_message 告警，TLS 客户端会报告收到了告警而不是解析出错
+pub const UNEXPECTED_MESSAGE_ALERT: [u8; 7] = [0x15, 0x03, 0x01, 0x00, 0x02, 0x02, 0x0a];
+
+// 明文请求发到了 HTTPS 端口：GET / HEAD 重定向到同一地址的 https://，其余返回说明原因的 400
+pub fn plain_http_response<'a>(req: &HttpRequest, port: u16) -> HttpResponse<'a> {
+    let host = req
+        .header(names::HOST)
+        .and_then(|h| split_host_port(h).0)
+        .filter(|h| headers::validate_value(h).is_ok() && !h.contains(['/', '@']));
+    let https = |host: &str| match port {
+        443 => format!("https://{}", host),
+        _ => format!("https://{}:{}", host, port),
+    };
+    if let (Some(host), Method::Get | Method::Head) = (&host, &req.method) {
+        let Resource::Path(target) = &req.resource;
+        return HttpResponse::redirect("301", &format!("{}{}", https(host), target));
+    }
+    let hint = match &host {
+        Some(host) => format!(" Use {}/ instead.", html::escape(&https(host))),
+        None => String::new(),
+    };
+    HttpResponse::new(
+        "400",
+        None,
+        Some(format!(
+            "<h1>400 Bad Request</h1>\n<p>A plain HTTP request was sent to an HTTPS port.{}</p>\n",
+            hint
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tls_round_trip() {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_pem = generated.cert.pem();
+        let config = server_config(
+            cert_pem.as_bytes(),
+            generated.signing_key.serialize_pem().as_bytes(),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut tls = accept(&config, listener.accept().unwrap().0).unwrap();
+            let mut buf = [0; 5];
+            tls.read_exact(&mut buf).unwrap();
+            tls.write_all(&buf).unwrap();
+            tls.flush().unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(generated.cert.der().clone()).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let client = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let conn =
+            ClientConnection::new(Arc::new(client), "localhost".try_into().unwrap()).unwrap();
+        let mut tls = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+        tls.write_all(b"hello").unwrap();
+        let mut buf = [0; 5];
+        tls.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_wrong_protocol() {
+        assert!(is_client_hello(&[0x16, 0x03, 0x01]));
+        assert!(!is_client_hello(b"GET / HTTP/1.1"));
+        assert!(is_plain_http(b"GET / HTTP/1.1"));
+        assert!(!is_plain_http(&[0x16, 0x03, 0x01]));
+        let request = |raw: &str| HttpRequest::try_from(raw.as_bytes()).unwrap();
+        let resp = plain_http_response(
+            &request("GET /a?b=1 HTTP/1.1\r\nHost: example.com:8443\r\n\r\n"),
+            8443,
+        );
+        assert_eq!(resp.status().as_u16(), 301);
+        assert_eq!(
+            resp.header("Location"),
+            Some("https://example.com:8443/a?b=1")
+        );
+        let resp =
+            plain_http_response(&request("GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"), 443);
+        assert_eq!(resp.header("Location"), Some("https://example.com/"));
+        let resp = plain_http_response(
+            &request("POST /x HTTP/1.1\r\nHost: <b>\r\nContent-Length: 0\r\n\r\n"),
+            8443,
+        );
+        assert_eq!(resp.status().as_u16(), 400);
+        assert!(resp
+            .body_text()
+            .unwrap()
+            .contains("https://&lt;b&gt;:8443/"));
+    }
+
+    #[test]
+    fn test_tls_config_validate() {
+        let mut problems = Vec::new();
+        TlsConfig {
+            addr: Some("0.0.0.0:3443".into()),
+            cert: Some("/no/such/cert.pem".into()),
+            ..TlsConfig::default()
+        }
+        .validate("localhost:3000", &mut problems);
+        assert_eq!(problems, vec!["tls: both cert and key are required"]);
+        problems.clear();
+        TlsConfig {
+            addr: Some("localhost:3000".into()),
+            self_signed: true,
+            ..TlsConfig::default()
+        }
+        .validate("localhost:3000", &mut problems);
+        assert!(problems.contains(&"tls.addr \"localhost:3000\" must differ from addr".to_string()));
+        assert!(matches!(
+            server_config(b"not pem", b""),
+            Err(TlsError::Pem(_))
+        ));
+    }
+}
@@ -0,0 +1,103 @@
+// 不停机升级：收到 SIGUSR2 后用同样的参数启动新的二进制，
+// 通过环境变量把监听套接字的 fd 交给它，旧进程处理完当前连接后退出
+use crate::listener;
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// 新进程从这个环境变量里读取继承的 fd 编号
+const INHERIT_FD_ENV: &str = "HTTPERVER_INHERIT_FD";
+
+static UPGRADE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigusr2(_: libc::c_int) {
+    UPGRADE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn install_upgrade_handler() {
+    // SAFETY: on_sigusr2 只做原子写入，是异步信号安全的
+    unsafe {
+        libc::signal(libc::SIGUSR2, on_sigusr2 as *const () as libc::sighandler_t);
+    }
+}
+
+// 取出并清除升级标志
+pub fn take_request() -> bool {
+    UPGRADE_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+// 新进程启动时调用，拿到旧进程交过来的监听套接字
+pub fn inherited_listener() -> Option<TcpListener> {
+    let fd = parse_fd(&env::var(INHERIT_FD_ENV).ok()?)?;
+    env::remove_var(INHERIT_FD_ENV);
+    adopt(fd)
+}
+
+// 环境变量里的 fd 编号；0 到 2 是标准输入输出，不会是交过来的监听套接字
+fn parse_fd(value: &str) -> Option<i32> {
+    value.parse::<i32>().ok().filter(|fd| *fd > 2)
+}
+
+// 接管 fd，不是套接字时（环境变量被误设）不碰它
+fn adopt(fd: i32) -> Option<TcpListener> {
+    if !listener::is_socket(fd) {
+        return None;
+    }
+    // SAFETY: fd 由父进程显式传入，新进程是它唯一的所有者
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    // 重新打开 CLOEXEC，避免再被无关的子进程继承
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    Some(listener)
+}
+
+// 去掉 CLOEXEC 让 fd 穿过 exec，启动新进程后再恢复
+pub fn spawn_successor(listener: &TcpListener) -> io::Result<u32> {
+    let fd = listener.as_raw_fd();
+    let exe = env::current_exe()?;
+    // SAFETY: 只修改自己持有的 fd 的标志位
+    unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+    let child = Command::new(exe)
+        .args(env::args_os().skip(1))
+        .env(INHERIT_FD_ENV, fd.to_string())
+        .spawn();
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    Ok(child?.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_parse_fd() {
+        assert_eq!(parse_fd("5"), Some(5));
+        for bad in ["", "abc", "-1", "0", "2", " 5", "5.0"] {
+            assert_eq!(parse_fd(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_adopt_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 和 spawn_successor 交给新进程的一样：一个没有 CLOEXEC 的 fd
+        let fd = unsafe { libc::dup(listener.as_raw_fd()) };
+        assert!(fd > 2);
+        let adopted = adopt(fd).unwrap();
+        assert_eq!(adopted.local_addr().unwrap(), addr);
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+        // 旧的关掉之后新的照样接受连接
+        drop(listener);
+        let _client = TcpStream::connect(addr).unwrap();
+        assert!(adopted.accept().is_ok());
+
+        // 不是套接字的 fd 不接管
+        let file = std::fs::File::open("/dev/null").unwrap();
+        assert!(adopt(file.as_raw_fd()).is_none());
+    }
+}
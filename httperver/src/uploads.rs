@@ -0,0 +1,604 @@
+// 文件上传：POST /uploads 保存请求体，GET /uploads/<id> 取回
+// 文件按内容寻址，id 是 SHA-256 的十六进制；同样的内容再上传一次不会多占磁盘，
+// 直接返回已有的文件（200），新文件返回 201
+// 从 Read 按块读取，边写临时文件边计算哈希，写完后改名成 <id>，改名是原子的，同时上传同一个文件也不会写坏
+// 普通上传的请求体由服务器先读完（不超过 max_body_bytes），大文件用下面的可续传上传分段发送
+//
+// 大文件用可续传上传（tus 1.0 协议的子集：core、creation、expiration、checksum、termination）：
+// POST /uploads/resumable 带 Upload-Length 创建，返回 Location
+// HEAD 这个地址查询已经收到多少字节（Upload-Offset）
+// PATCH 从 Upload-Offset 处追加一段，偏移量对不上回复 409，客户端断线后查询偏移量再继续
+// 可以带 Upload-Checksum: sha256 <base64> 校验这一段，不一致回复 460 并丢弃这一段
+// 收齐后和普通上传一样按内容保存，响应的 Location 指向 /uploads/<id>
+// 超过 Upload-Expires 还没传完的上传会被清理；DELETE 主动放弃
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use http::clock::{http_date, Clock, SystemClock};
+use http::headers::names;
+use http::httprequest::{HttpRequest, Method};
+use http::httpresponse::HttpResponse;
+use http::random::{self, OsRandom, RandomSource};
+use http::status::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+pub const UPLOAD_PREFIX: &str = "/uploads";
+pub const RESUMABLE_PREFIX: &str = "/uploads/resumable";
+
+// 没传完的上传保留多久
+pub const DEFAULT_EXPIRY_SECS: u64 = 24 * 60 * 60;
+// 可续传上传的总大小上限
+pub const MAX_RESUMABLE_SIZE: u64 = 1 << 30;
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_RESUMABLE: &str = "Tus-Resumable";
+const UPLOAD_LENGTH: &str = "Upload-Length";
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_EXPIRES: &str = "Upload-Expires";
+const UPLOAD_CHECKSUM: &str = "Upload-Checksum";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+// tus 的 checksum 扩展规定的状态码
+const CHECKSUM_MISMATCH: &str = "460";
+
+// 每次写入和计算哈希的块大小
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stored {
+    pub id: String,
+    pub size: u64,
+    // false 表示内容已经存在，没有写新文件
+    pub created: bool,
+}
+
+// 没传完的可续传上传，数据在 <dir>/partial/<token>
+// 每个上传一把锁：写磁盘时只挡住同一个上传的请求
+struct Resumable {
+    // 创建时就确定，判断过期不用等正在写的 PATCH
+    expires: SystemTime,
+    state: Mutex<Partial>,
+}
+
+struct Partial {
+    length: u64,
+    // 文件里实际写入的字节数
+    offset: u64,
+    // 随收到的数据逐段更新，收齐时就是整个文件的哈希
+    hasher: Sha256,
+    // 已经收齐、过期或者被 DELETE，文件已经不在了；等锁的请求拿到锁后回 404
+    closed: bool,
+}
+
+impl Partial {
+    // 按实际写入的字节推进偏移量和哈希，写到一半失败时客户端查询偏移量后从这里继续
+    fn append(&mut self, path: &Path, mut chunk: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        while !chunk.is_empty() {
+            let n = match file.write(chunk) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.hasher.update(&chunk[..n]);
+            self.offset += n as u64;
+            chunk = &chunk[n..];
+        }
+        Ok(())
+    }
+}
+
+pub struct Uploads {
+    dir: PathBuf,
+    // 临时文件名的序号，同一进程里的并发上传互不覆盖
+    seq: AtomicU64,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+    expiry: Duration,
+    // 进度只保存在内存里，重启后没传完的上传需要重新开始
+    partial: Mutex<HashMap<String, Arc<Resumable>>>,
+    // 下次清理 partial 目录里没有对应进度的文件的时间
+    next_scan: Mutex<SystemTime>,
+}
+
+impl Uploads {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        // 上次运行留下的半截文件不在这里删除：平滑升级时新旧进程共用这个目录，
+        // 旧进程上的上传还在进行，见 remove_orphans
+        fs::create_dir_all(dir.join("partial"))?;
+        Ok(Uploads {
+            dir,
+            seq: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+            rng: Arc::new(OsRandom),
+            expiry: Duration::from_secs(DEFAULT_EXPIRY_SECS),
+            partial: Mutex::new(HashMap::new()),
+            next_scan: Mutex::new(SystemTime::UNIX_EPOCH),
+        })
+    }
+
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    pub fn rng(mut self, rng: Arc<dyn RandomSource>) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    // id 不是 64 位小写十六进制时返回 None，防止路径穿越
+    pub fn path_of(&self, id: &str) -> Option<PathBuf> {
+        let valid = id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        valid.then(|| self.dir.join(id))
+    }
+
+    // body 可以是内存里的请求体，也可以是文件等任何 Read，不需要整个读进内存
+    pub fn store(&self, body: impl Read) -> io::Result<Stored> {
+        let tmp = self.dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            self.seq.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = self.store_via(&tmp, body);
+        // 新文件已经改名，剩下的是重复内容或写了一半的临时文件
+        let _ = fs::remove_file(&tmp);
+        result
+    }
+
+    fn store_via(&self, tmp: &Path, mut body: impl Read) -> io::Result<Stored> {
+        let mut hasher = Sha256::new();
+        let mut file = File::create(tmp)?;
+        let mut buf = vec![0; CHUNK];
+        let mut size = 0;
+        loop {
+            let n = match body.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+        file.sync_all()?;
+        self.commit(tmp, hasher, size)
+    }
+
+    // 写好的临时文件按哈希改名，内容已经存在时保留原来的文件
+    fn commit(&self, tmp: &Path, hasher: Sha256, size: u64) -> io::Result<Stored> {
+        let id = hex(&hasher.finalize());
+        let path = self.dir.join(&id);
+        let created = !path.exists();
+        if created {
+            fs::rename(tmp, &path)?;
+        }
+        Ok(Stored { id, size, created })
+    }
+
+    // req 的路径已经去掉了 base_path
+    pub fn handle(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        if let Some(rest) = req.path().strip_prefix(RESUMABLE_PREFIX) {
+            return self.handle_resumable(req, rest.trim_start_matches('/'));
+        }
+        let rest = req.path()[UPLOAD_PREFIX.len()..].trim_start_matches('/');
+        match (req.method.as_str(), rest) {
+            ("POST", "") => self.upload(req),
+            ("GET" | "HEAD", id) if !id.is_empty() => self.download(id),
+            _ => HttpResponse::new("404", None, Some(String::new())),
+        }
+    }
+
+    fn handle_resumable(&self, req: &HttpRequest, token: &str) -> HttpResponse<'static> {
+        self.sweep();
+        match (&req.method, token) {
+            (Method::Post, "") => self.create(req),
+            (Method::Get | Method::Head, token) => {
+                let Some(upload) = self.lookup(token) else {
+                    return tus("404");
+                };
+                let p = upload.state.lock().unwrap_or_else(|e| e.into_inner());
+                if p.closed {
+                    return tus("404");
+                }
+                tus("200")
+                    .with_header(UPLOAD_OFFSET, p.offset.to_string())
+                    .and_then(|r| r.with_header(UPLOAD_LENGTH, p.length.to_string()))
+                    .and_then(|r| r.with_header(names::CACHE_CONTROL, "no-store"))
+                    .expect("numbers are valid header values")
+            }
+            (Method::Patch, token) => self.append(token, req),
+            (Method::Delete, token) => match self.take(token) {
+                Some(upload) => {
+                    self.close(token, &upload);
+                    tus("204")
+                }
+                None => tus("404"),
+            },
+            _ => tus("404"),
+        }
+    }
+
+    fn partial_path(&self, token: &str) -> PathBuf {
+        self.dir.join("partial").join(token)
+    }
+
+    // 只在查找时持有全局的锁，读写文件时不持有
+    fn lookup(&self, token: &str) -> Option<Arc<Resumable>> {
+        let partial = self.partial.lock().unwrap_or_else(|e| e.into_inner());
+        partial.get(token).cloned()
+    }
+
+    fn take(&self, token: &str) -> Option<Arc<Resumable>> {
+        let mut partial = self.partial.lock().unwrap_or_else(|e| e.into_inner());
+        partial.remove(token)
+    }
+
+    // 等这个上传正在进行的 PATCH 写完再删除文件
+    fn close(&self, token: &str, upload: &Resumable) {
+        let mut p = upload.state.lock().unwrap_or_else(|e| e.into_inner());
+        p.closed = true;
+        let _ = fs::remove_file(self.partial_path(token));
+    }
+
+    // 删除过期的上传
+    fn sweep(&self) {
+        let now = self.clock.now();
+        let expired: Vec<_> = {
+            let mut partial = self.partial.lock().unwrap_or_else(|e| e.into_inner());
+            let tokens: Vec<String> = partial
+                .iter()
+                .filter(|(_, upload)| upload.expires <= now)
+                .map(|(token, _)| token.clone())
+                .collect();
+            tokens
+                .into_iter()
+                .filter_map(|token| partial.remove(&token).map(|upload| (token, upload)))
+                .collect()
+        };
+        for (token, upload) in expired {
+            self.close(&token, &upload);
+        }
+        self.remove_orphans(now);
+    }
+
+    // 重启前留下的半截文件没有对应的进度，修改时间超过保留期限后删除；每个保留期限最多检查一次
+    fn remove_orphans(&self, now: SystemTime) {
+        {
+            let mut next = self.next_scan.lock().unwrap_or_else(|e| e.into_inner());
+            if *next > now {
+                return;
+            }
+            *next = now + self.expiry;
+        }
+        let Ok(entries) = fs::read_dir(self.dir.join("partial")) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let token = entry.file_name().to_string_lossy().into_owned();
+            let stale = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > self.expiry);
+            if stale && self.lookup(&token).is_none() {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    fn create(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        let length = match req.header(UPLOAD_LENGTH).map(str::parse::<u64>) {
+            Some(Ok(n)) if n > 0 => n,
+            _ => return tus("400").with_body("Upload-Length must be a positive integer".into()),
+        };
+        if length > MAX_RESUMABLE_SIZE {
+            return tus("413");
+        }
+        let token = random::request_id(self.rng.as_ref());
+        if let Err(e) = File::create(self.partial_path(&token)) {
+            eprintln!("Cannot start upload: {}", e);
+            return tus("500");
+        }
+        let expires = self.clock.now() + self.expiry;
+        let upload = Resumable {
+            expires,
+            state: Mutex::new(Partial {
+                length,
+                offset: 0,
+                hasher: Sha256::new(),
+                closed: false,
+            }),
+        };
+        self.partial
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token.clone(), Arc::new(upload));
+        tus("201")
+            .with_header(names::LOCATION, format!("{}/{}", RESUMABLE_PREFIX, token))
+            .and_then(|r| r.with_header(UPLOAD_OFFSET, "0"))
+            .and_then(|r| r.with_header(UPLOAD_EXPIRES, http_date(expires)))
+            .expect("token is a valid header value")
+    }
+
+    fn append(&self, token: &str, req: &HttpRequest) -> HttpResponse<'static> {
+        let Some(upload) = self.lookup(token) else {
+            return tus("404");
+        };
+        if req.header(names::CONTENT_TYPE) != Some(OFFSET_CONTENT_TYPE) {
+            return tus("415");
+        }
+        let chunk = req.msg_body.as_slice();
+        // 同一个上传的 PATCH 依次执行，偏移量检查和写入之间不会插进别的请求
+        let mut p = upload.state.lock().unwrap_or_else(|e| e.into_inner());
+        if p.closed {
+            return tus("404");
+        }
+        match req.header(UPLOAD_OFFSET).map(str::parse::<u64>) {
+            Some(Ok(offset)) if offset == p.offset => {}
+            Some(Ok(_)) => return current_offset(tus("409"), p.offset),
+            _ => return tus("400").with_body("Upload-Offset is required".into()),
+        }
+        if p.offset + chunk.len() as u64 > p.length {
+            return tus("413");
+        }
+        if let Some(checksum) = req.header(UPLOAD_CHECKSUM) {
+            match verify_checksum(checksum, chunk) {
+                Some(true) => {}
+                Some(false) => return current_offset(tus(CHECKSUM_MISMATCH), p.offset),
+                None => return tus("400").with_body("unsupported Upload-Checksum".into()),
+            }
+        }
+        let path = self.partial_path(token);
+        if let Err(e) = p.append(&path, chunk) {
+            eprintln!("Cannot append to upload {}: {}", token, e);
+            return current_offset(tus("500"), p.offset);
+        }
+        let offset = p.offset;
+        if offset < p.length {
+            return current_offset(tus("204"), offset);
+        }
+        // 收齐了，按内容保存
+        p.closed = true;
+        self.take(token);
+        let hasher = std::mem::take(&mut p.hasher);
+        let result = self.commit(&path, hasher, p.length);
+        let _ = fs::remove_file(&path);
+        match result {
+            Ok(stored) => current_offset(tus("204"), offset)
+                .with_header(names::LOCATION, format!("{}/{}", UPLOAD_PREFIX, stored.id))
+                .expect("upload id is a valid header value"),
+            Err(e) => {
+                eprintln!("Cannot store upload {}: {}", token, e);
+                tus("500")
+            }
+        }
+    }
+
+    fn upload(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        let stored = match self.store(req.msg_body.as_slice()) {
+            Ok(stored) => stored,
+            Err(e) => {
+                eprintln!("Cannot store upload: {}", e);
+                return HttpResponse::new("500", None, Some(String::new()));
+            }
+        };
+        let status = if stored.created {
+            StatusCode::Created
+        } else {
+            StatusCode::Ok
+        };
+        let location = format!("{}/{}", UPLOAD_PREFIX, stored.id);
+        HttpResponse::json(&stored)
+            .with_status(status)
+            .with_header(names::LOCATION, location)
+            .expect("upload id is a valid header value")
+    }
+
+    fn download(&self, id: &str) -> HttpResponse<'static> {
+        match self.path_of(id).map(fs::read) {
+            Some(Ok(bytes)) => {
+                let mut headers = HashMap::new();
+                headers.insert(names::CONTENT_TYPE, "application/octet-stream");
+                // 内容由 id 决定，永远不会变
+                headers.insert(names::CACHE_CONTROL, "public, max-age=31536000, immutable");
+                HttpResponse::new("200", Some(headers), None).with_bytes(bytes)
+            }
+            _ => HttpResponse::new("404", None, Some(String::new())),
+        }
+    }
+}
+
+fn tus(status: &'static str) -> HttpResponse<'static> {
+    HttpResponse::new(status, Some(HashMap::new()), None)
+        .with_header(TUS_RESUMABLE, TUS_VERSION)
+        .expect("valid header")
+}
+
+fn current_offset(resp: HttpResponse<'static>, offset: u64) -> HttpResponse<'static> {
+    resp.with_header(UPLOAD_OFFSET, offset.to_string())
+        .expect("numbers are valid header values")
+}
+
+// Upload-Checksum: sha256 <base64>，算法不支持或格式不对时返回 None
+fn verify_checksum(header: &str, chunk: &[u8]) -> Option<bool> {
+    let (algorithm, value) = header.trim().split_once(' ')?;
+    if !algorithm.eq_ignore_ascii_case("sha256") {
+        return None;
+    }
+    let expected = STANDARD.decode(value.trim()).ok()?;
+    Some(Sha256::digest(chunk).as_slice() == expected.as_slice())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_dedup() {
+        let dir = crate::testdir::temp_path("uploads");
+        let uploads = Uploads::new(&dir).unwrap();
+        let first = uploads.store(&b"hello"[..]).unwrap();
+        assert_eq!(
+            first.id,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert!(first.created);
+        let again = uploads.store(&b"hello"[..]).unwrap();
+        assert!(!again.created);
+        assert_eq!(again.id, first.id);
+        // 除了 partial 目录只有一个文件，临时文件都清理掉了
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(
+            fs::read(uploads.path_of(&first.id).unwrap()).unwrap(),
+            b"hello"
+        );
+        assert!(uploads.path_of("../etc/passwd").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_upload_status() {
+        let dir = crate::testdir::temp_path("upload-http");
+        let uploads = Uploads::new(&dir).unwrap();
+        let raw = "POST /uploads HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc";
+        let req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+        let resp = uploads.handle(&req);
+        assert_eq!(resp.status().as_u16(), 201);
+        let location = resp.header("Location").unwrap().to_string();
+        assert_eq!(uploads.handle(&req).status().as_u16(), 200);
+
+        let get = format!("GET {} HTTP/1.1\r\n\r\n", location);
+        let resp = uploads.handle(&HttpRequest::try_from(get.as_bytes()).unwrap());
+        assert_eq!(resp.body_bytes(), Some(&b"abc"[..]));
+
+        // 图片等二进制内容原样保存，哈希按原始字节计算
+        let data: Vec<u8> = (0..=255u8).cycle().take(CHUNK + 100).collect();
+        let mut raw = format!(
+            "POST /uploads HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            data.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(&data);
+        let resp = uploads.handle(&HttpRequest::try_from(raw.as_slice()).unwrap());
+        assert_eq!(resp.status().as_u16(), 201);
+        let id = hex(&Sha256::digest(&data));
+        assert_eq!(
+            resp.header("Location"),
+            Some(format!("{}/{}", UPLOAD_PREFIX, id).as_str())
+        );
+        assert_eq!(fs::read(uploads.path_of(&id).unwrap()).unwrap(), data);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn request(raw: &str) -> HttpRequest {
+        HttpRequest::try_from(raw.as_bytes()).unwrap()
+    }
+
+    fn patch(location: &str, offset: u64, body: &str, checksum: Option<&str>) -> HttpRequest {
+        let checksum = checksum
+            .map(|c| format!("Upload-Checksum: {}\r\n", c))
+            .unwrap_or_default();
+        request(&format!(
+            "PATCH {} HTTP/1.1\r\nContent-Type: application/offset+octet-stream\r\n\
+             Upload-Offset: {}\r\n{}Content-Length: {}\r\n\r\n{}",
+            location,
+            offset,
+            checksum,
+            body.len(),
+            body
+        ))
+    }
+
+    #[test]
+    fn test_resumable_upload() {
+        let dir = crate::testdir::temp_path("resumable");
+        let clock = http::clock::MockClock::from_unix_secs(1_000_000);
+        let uploads = Uploads::new(&dir)
+            .unwrap()
+            .clock(Arc::new(clock.clone()))
+            .expiry(Duration::from_secs(60));
+        let create = request("POST /uploads/resumable HTTP/1.1\r\nUpload-Length: 11\r\n\r\n");
+        let resp = uploads.handle(&create);
+        assert_eq!(resp.status().as_u16(), 201);
+        assert_eq!(resp.header("Tus-Resumable"), Some("1.0.0"));
+        let location = resp.header("Location").unwrap().to_string();
+
+        let resp = uploads.handle(&patch(&location, 0, "hello", None));
+        assert_eq!(resp.status().as_u16(), 204);
+        assert_eq!(resp.header("Upload-Offset"), Some("5"));
+        // 客户端以为第一段没传成功，从头重发：偏移量不对
+        let resp = uploads.handle(&patch(&location, 0, "hello", None));
+        assert_eq!(resp.status().as_u16(), 409);
+        assert_eq!(resp.header("Upload-Offset"), Some("5"));
+        // 断线后查询进度
+        let status = uploads.handle(&request(&format!("HEAD {} HTTP/1.1\r\n\r\n", location)));
+        assert_eq!(status.header("Upload-Offset"), Some("5"));
+        assert_eq!(status.header("Upload-Length"), Some("11"));
+        // 校验和不对的一段被丢弃
+        let wrong = format!("sha256 {}", STANDARD.encode(Sha256::digest(b"other")));
+        let resp = uploads.handle(&patch(&location, 5, " world", Some(&wrong)));
+        assert_eq!(resp.status().as_u16(), 460);
+        let right = format!("sha256 {}", STANDARD.encode(Sha256::digest(b" world")));
+        let resp = uploads.handle(&patch(&location, 5, " world", Some(&right)));
+        assert_eq!(resp.status().as_u16(), 204);
+        let file = resp.header("Location").unwrap();
+        let get = uploads.handle(&request(&format!("GET {} HTTP/1.1\r\n\r\n", file)));
+        assert_eq!(get.body_bytes(), Some(&b"hello world"[..]));
+        // 传完后不能再追加
+        assert_eq!(
+            uploads
+                .handle(&patch(&location, 11, "!", None))
+                .status()
+                .as_u16(),
+            404
+        );
+
+        // 过期的上传被清理
+        let resp = uploads.handle(&create);
+        let location = resp.header("Location").unwrap().to_string();
+        clock.advance(Duration::from_secs(61));
+        let resp = uploads.handle(&patch(&location, 0, "late", None));
+        assert_eq!(resp.status().as_u16(), 404);
+        assert_eq!(fs::read_dir(dir.join("partial")).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_files_kept_until_stale() {
+        let dir = crate::testdir::temp_path("orphans");
+        fs::create_dir_all(dir.join("partial")).unwrap();
+        // 另一个进程正在写的上传，和上次运行留下的半截文件
+        fs::write(dir.join("partial").join("recent"), b"abc").unwrap();
+        let old = dir.join("partial").join("old");
+        fs::write(&old, b"abc").unwrap();
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let uploads = Uploads::new(&dir).unwrap().expiry(Duration::from_secs(60));
+        assert!(old.exists());
+        let resp = uploads.handle(&request("HEAD /uploads/resumable/old HTTP/1.1\r\n\r\n"));
+        assert_eq!(resp.status().as_u16(), 404);
+        assert!(!old.exists());
+        assert!(dir.join("partial").join("recent").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,557 @@
+// 反向代理：把一个路径前缀下的请求转发给上游服务，可以分一部分流量给金丝雀版本
+//
+//     [upstream]
+//     prefix = "/api/search"
+//     stable = "http://10.0.0.5:8080"
+//     canary = "http://10.0.0.6:8080"
+//     canary_percent = 5
+//
+// 带 X-Canary: 1 的请求总是发给金丝雀，X-Canary: 0 总是发给稳定版本，其余的按 canary_percent 随机分
+// 比例可以在运行时调整，不用重启；通过 Router::upstream 注册成只接受本机请求的路由，经过其他中间件：
+//     GET  /_admin/canary                  {"percent":5.0,"stable":120,"canary":7,"stable_circuit":"closed",...}
+//     POST /_admin/canary?percent=25       返回调整之后的状态
+// 转发用 http::httpclient，连接按上游地址放在连接池里复用，池的统计也在 /_admin/canary 里；
+// 上游连不上回 502，超时（包括等空闲连接超时）回 504
+// 每个上游有自己的熔断器（见 breaker.rs），连不上、超时和 5xx 算失败，熔断期间直接回 503
+use crate::breaker::{BreakerConfig, BreakerState, CircuitBreaker};
+use crate::middleware::Middleware;
+use crate::mirror::SKIPPED_HEADERS;
+use http::clock::Clock;
+use http::connpool::{self, ConnectionPool, PoolConfig, PoolStats};
+use http::headers::names;
+use http::httpclient::{self, ClientError, ClientResponse, HttpClient, Url};
+use http::httprequest::{HttpRequest, Method, Resource};
+use http::httpresponse::HttpResponse;
+use http::random::RandomSource;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_CANARY_HEADER: &str = "X-Canary";
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+pub const ADMIN_PATH: &str = "/_admin/canary";
+// 只对上游那一跳连接有意义的响应头（RFC 9110 7.6.1），不转发给客户端；
+// Connection 里列出的头部也一样。Content-Length 由服务器按解开之后的 body 重新计算
+const HOP_BY_HOP_HEADERS: [&str; 10] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+    "Content-Length",
+];
+
+// [upstream]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamConfig {
+    // 这个前缀下的请求转发给上游，路径原样保留；不设置 stable 表示不代理
+    pub prefix: String,
+    pub stable: Option<String>,
+    pub canary: Option<String>,
+    // 0 到 100，运行时可以通过 POST /_admin/canary 调整
+    pub canary_percent: f64,
+    // 客户端可以用这个请求头指定版本，1 是金丝雀，0 是稳定版本
+    pub canary_header: String,
+    pub timeout_secs: u64,
+    // 每个上游地址最多同时使用多少个连接（0 表示不限）、最多保留多少个空闲连接、空闲多少秒后关闭
+    pub max_conns_per_host: usize,
+    pub max_idle_per_host: usize,
+    pub idle_timeout_secs: u64,
+    // [upstream.breaker]
+    pub breaker: BreakerConfig,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        UpstreamConfig {
+            prefix: "/".to_string(),
+            stable: None,
+            canary: None,
+            canary_percent: 0.0,
+            canary_header: DEFAULT_CANARY_HEADER.to_string(),
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            max_conns_per_host: connpool::DEFAULT_MAX_PER_HOST,
+            max_idle_per_host: connpool::DEFAULT_MAX_IDLE_PER_HOST,
+            idle_timeout_secs: connpool::DEFAULT_IDLE_TIMEOUT_SECS,
+            breaker: BreakerConfig::default(),
+        }
+    }
+}
+
+impl UpstreamConfig {
+    pub fn enabled(&self) -> bool {
+        self.stable.is_some()
+    }
+
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        if !self.enabled() {
+            if self.canary.is_some() {
+                problems.push("upstream.canary requires upstream.stable".to_string());
+            }
+            return;
+        }
+        if !self.prefix.starts_with('/') {
+            problems.push(format!(
+                "upstream.prefix {:?} must start with /",
+                self.prefix
+            ));
+        }
+        for (name, url) in [("stable", &self.stable), ("canary", &self.canary)] {
+            if let Some(url) = url {
+                if Url::parse(url).is_err() {
+                    problems.push(format!(
+                        "upstream.{} {:?} must be an http:// URL",
+                        name, url
+                    ));
+                }
+            }
+        }
+        if !valid_percent(self.canary_percent) {
+            problems.push("upstream.canary_percent must be between 0 and 100".to_string());
+        }
+        if self.canary_header.is_empty() {
+            problems.push("upstream.canary_header must not be empty".to_string());
+        }
+        if self.timeout_secs == 0 {
+            problems.push("upstream.timeout_secs must be positive".to_string());
+        }
+        if self.idle_timeout_secs == 0 {
+            problems.push("upstream.idle_timeout_secs must be positive".to_string());
+        }
+        self.breaker.validate("upstream.breaker", problems);
+    }
+}
+
+fn valid_percent(percent: f64) -> bool {
+    (0.0..=100.0).contains(&percent)
+}
+
+// GET /_admin/canary 的返回值
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CanaryStatus {
+    pub percent: f64,
+    // 分别转发了多少个请求
+    pub stable: u64,
+    pub canary: u64,
+    pub stable_circuit: BreakerState,
+    pub canary_circuit: Option<BreakerState>,
+    // 稳定版本和金丝雀共用的连接池
+    pub pool: PoolStats,
+}
+
+// 一个上游：地址、转发计数和熔断器
+struct Target {
+    base: String,
+    count: AtomicU64,
+    breaker: CircuitBreaker,
+}
+
+pub struct ReverseProxy {
+    prefix: String,
+    stable: Target,
+    canary: Option<Target>,
+    canary_header: String,
+    // f64 的位，运行时调整不用加锁
+    canary_percent: AtomicU64,
+    rng: Arc<dyn RandomSource>,
+    client: HttpClient,
+    pool: Arc<ConnectionPool>,
+}
+
+impl ReverseProxy {
+    // config.stable 或 config.canary 不是 http:// 地址时返回错误
+    pub fn new(
+        config: &UpstreamConfig,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Result<Self, ClientError> {
+        let target = |url: &str| -> Result<Target, ClientError> {
+            Url::parse(url)?;
+            Ok(Target {
+                base: url.trim_end_matches('/').to_string(),
+                count: AtomicU64::new(0),
+                breaker: CircuitBreaker::new(config.breaker.clone(), clock.clone()),
+            })
+        };
+        let canary = match &config.canary {
+            Some(url) => Some(target(url)?),
+            None => None,
+        };
+        let pool = Arc::new(ConnectionPool::new(PoolConfig {
+            max_idle_per_host: config.max_idle_per_host,
+            max_per_host: config.max_conns_per_host,
+            idle_timeout: Duration::from_secs(config.idle_timeout_secs),
+        }));
+        Ok(ReverseProxy {
+            prefix: config.prefix.trim_end_matches('/').to_string(),
+            stable: target(config.stable.as_deref().unwrap_or_default())?,
+            canary,
+            canary_header: config.canary_header.clone(),
+            canary_percent: AtomicU64::new(config.canary_percent.to_bits()),
+            rng,
+            // 响应原样转给客户端，gzip 也不解开
+            client: HttpClient::new()
+                .decode(false)
+                .timeout(Some(Duration::from_secs(config.timeout_secs)))
+                .pool(pool.clone()),
+            pool,
+        })
+    }
+
+    pub fn canary_percent(&self) -> f64 {
+        f64::from_bits(self.canary_percent.load(Ordering::Relaxed))
+    }
+
+    // 超出 0 到 100 时返回 false，比例不变
+    pub fn set_canary_percent(&self, percent: f64) -> bool {
+        if !valid_percent(percent) {
+            return false;
+        }
+        self.canary_percent
+            .store(percent.to_bits(), Ordering::Relaxed);
+        true
+    }
+
+    pub fn status(&self) -> CanaryStatus {
+        CanaryStatus {
+            percent: self.canary_percent(),
+            stable: self.stable.count.load(Ordering::Relaxed),
+            canary: self
+                .canary
+                .as_ref()
+                .map_or(0, |c| c.count.load(Ordering::Relaxed)),
+            stable_circuit: self.stable.breaker.state(),
+            canary_circuit: self.canary.as_ref().map(|c| c.breaker.state()),
+            pool: self.pool.stats(),
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+
+    // 请求头优先，其余的按比例随机分；没有配置金丝雀时都去稳定版本
+    fn pick_canary(&self, req: &HttpRequest) -> bool {
+        if self.canary.is_none() {
+            return false;
+        }
+        match req.header(&self.canary_header).map(str::trim) {
+            Some("1" | "true") => true,
+            Some("0" | "false") => false,
+            _ => self.rng.next_f64() * 100.0 < self.canary_percent(),
+        }
+    }
+
+    pub fn admin(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        match req.method {
+            Method::Get => HttpResponse::json(&self.status()),
+            Method::Post => {
+                let percent = req.query().get("percent").and_then(|p| p.parse().ok());
+                match percent {
+                    Some(percent) if self.set_canary_percent(percent) => {
+                        eprintln!("Canary traffic set to {}%", percent);
+                        HttpResponse::json(&self.status())
+                    }
+                    _ => HttpResponse::new(
+                        "400",
+                        None,
+                        Some("percent must be a number between 0 and 100".into()),
+                    ),
+                }
+            }
+            _ => HttpResponse::new("405", None, Some("Method Not Allowed".into()))
+                .with_header(names::ALLOW, "GET, POST")
+                .expect("valid header"),
+        }
+    }
+
+    fn forward(&self, req: &HttpRequest) -> HttpResponse<'static> {
+        let target = match &self.canary {
+            Some(canary) if self.pick_canary(req) => canary,
+            _ => &self.stable,
+        };
+        target.count.fetch_add(1, Ordering::Relaxed);
+        let call = match target.breaker.try_acquire() {
+            Ok(call) => call,
+            Err(retry) => {
+                // 向上取整，至少 1 秒
+                let secs = retry.as_secs() + u64::from(retry.subsec_nanos() > 0);
+                return HttpResponse::new("503", None, Some("Upstream unavailable".into()))
+                    .with_header(names::RETRY_AFTER, secs.max(1).to_string())
+                    .expect("digits are a valid header value");
+            }
+        };
+        let mut headers: Vec<(&str, String)> = req
+            .headers
+            .iter()
+            .filter(|(k, _)| {
+                !SKIPPED_HEADERS.iter().any(|s| s.eq_ignore_ascii_case(k))
+                    && !k.eq_ignore_ascii_case(names::X_FORWARDED_FOR)
+            })
+            .map(|(k, v)| (k, v.to_string()))
+            .collect();
+        // 追加在客户端或前一个代理给的 X-Forwarded-For 后面
+        if let Some(ip) = req.remote_addr.map(|a| a.ip()) {
+            let forwarded = match req.header(names::X_FORWARDED_FOR) {
+                Some(prior) => format!("{}, {}", prior, ip),
+                None => ip.to_string(),
+            };
+            headers.push((names::X_FORWARDED_FOR, forwarded));
+        }
+        let headers: Vec<(&str, &str)> = headers.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let Resource::Path(path) = &req.resource;
+        let url = format!("{}{}", target.base, path);
+        let body = (!req.msg_body.is_empty()).then_some(req.msg_body.as_slice());
+        let result = self.client.send(req.method.as_str(), &url, &headers, body);
+        target
+            .breaker
+            .record(call, matches!(&result, Ok(resp) if resp.status_code < 500));
+        match result {
+            Ok(resp) => relay(resp),
+            Err(e) => {
+                eprintln!("Upstream {} failed: {}", target.base, e);
+                let status = match &e {
+                    ClientError::Io(e)
+                        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                    {
+                        "504"
+                    }
+                    _ => "502",
+                };
+                HttpResponse::new(status, None, Some(String::new()))
+            }
+        }
+    }
+}
+
+// 上游的响应转成自己的响应，chunked 先解开，由服务器重新分帧
+fn relay(resp: ClientResponse) -> HttpResponse<'static> {
+    let chunked = resp
+        .header(names::TRANSFER_ENCODING)
+        .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"));
+    let body = if chunked {
+        match httpclient::decode_chunked(resp.raw_body(), httpclient::DEFAULT_MAX_BODY_SIZE) {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("Bad chunked body from upstream: {}", e);
+                return HttpResponse::new("502", None, Some(String::new()));
+            }
+        }
+    } else {
+        resp.raw_body().to_vec()
+    };
+    let mut out = HttpResponse::builder()
+        .status(resp.status_code.into())
+        .body(body)
+        .build()
+        .expect("no headers set yet");
+    let listed: Vec<&str> = resp
+        .headers
+        .get_all(names::CONNECTION)
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    for (name, value) in resp.headers.iter() {
+        let hop = HOP_BY_HOP_HEADERS
+            .iter()
+            .chain(&listed)
+            .any(|h| h.eq_ignore_ascii_case(name));
+        if !hop {
+            // 上游给的名字或值不合法时丢掉这个头部
+            let _ = out.append_header(name.to_string(), value.to_string());
+        }
+    }
+    out
+}
+
+impl Middleware for ReverseProxy {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        // 管理接口由路由器回复，不转发
+        if req.path() == ADMIN_PATH || !self.matches(req.path()) {
+            return None;
+        }
+        Some(self.forward(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use http::clock::SystemClock;
+    use http::random::SeededRandom;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // 回复固定内容的上游，支持长连接，收到的请求头交给 tx
+    fn upstream(name: &'static str) -> (String, std::sync::mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let tx = tx.clone();
+                thread::spawn(move || loop {
+                    let mut received = Vec::new();
+                    let mut chunk = [0; 1024];
+                    while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match conn.read(&mut chunk) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => received.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let _ = tx.send(String::from_utf8(received).unwrap());
+                    let body = format!("from {}", name);
+                    let _ = write!(
+                        conn,
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nServer: up\r\nWWW-Authenticate: Bearer realm=\"search\"\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\nKeep-Alive: timeout=5\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                        body.len(),
+                        body
+                    );
+                });
+            }
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn test_canary() {
+        let (stable, stable_rx) = upstream("stable");
+        let (canary, _canary_rx) = upstream("canary");
+        let config = UpstreamConfig {
+            prefix: "/api/search".into(),
+            stable: Some(stable),
+            canary: Some(canary),
+            ..UpstreamConfig::default()
+        };
+        let router = Router::new("").upstream(Arc::new(
+            ReverseProxy::new(
+                &config,
+                Arc::new(SystemClock),
+                Arc::new(SeededRandom::new(3)),
+            )
+            .unwrap(),
+        ));
+        let send = |head: &str| {
+            let raw = format!("{}\r\n\r\n", head);
+            let mut req = HttpRequest::try_from(raw.as_bytes()).unwrap();
+            req.remote_addr = Some("127.0.0.1:5000".parse().unwrap());
+            let mut out = Vec::new();
+            router.route(req, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let out =
+            send("GET /api/search?q=rust HTTP/1.1\r\nHost: shop\r\nX-Forwarded-For: 10.0.0.9");
+        assert!(out.starts_with("HTTP/1.1 200"), "{}", out);
+        assert!(out.ends_with("from stable"));
+        assert!(out.contains("Content-Type:text/plain"));
+        // 端到端的头部原样带回，只对上游连接有意义的去掉
+        assert!(out.contains("Server:up"));
+        assert!(out.contains(r#"WWW-Authenticate:Bearer realm="search""#));
+        assert!(!out.contains("X-Hop"), "{}", out);
+        assert!(!out.contains("Keep-Alive"));
+        assert!(!out.contains("Transfer-Encoding"));
+        let received = stable_rx.recv().unwrap();
+        assert!(received.starts_with("GET /api/search?q=rust HTTP/1.1\r\n"));
+        assert!(received.contains("X-Forwarded-For: 10.0.0.9, 127.0.0.1\r\n"));
+        assert!(!received.contains("Host: shop"));
+        // 请求头指定金丝雀
+        assert!(send("GET /api/search HTTP/1.1\r\nX-Canary: 1").ends_with("from canary"));
+        // 前缀之外的请求照常路由
+        assert!(send("GET /api/searching HTTP/1.1").starts_with("HTTP/1.1 404"));
+
+        // 运行时把全部流量切到金丝雀
+        let out = send("POST /_admin/canary?percent=100 HTTP/1.1");
+        assert!(
+            out.contains(r#"{"percent":100.0,"stable":1,"canary":1,"#),
+            "{}",
+            out
+        );
+        for _ in 0..5 {
+            assert!(send("GET /api/search/more HTTP/1.1").ends_with("from canary"));
+        }
+        assert!(send("GET /api/search HTTP/1.1\r\nX-Canary: 0").ends_with("from stable"));
+        assert!(send("POST /_admin/canary?percent=101 HTTP/1.1").starts_with("HTTP/1.1 400"));
+        let out = send("GET /_admin/canary HTTP/1.1");
+        assert!(
+            out.contains(r#"{"percent":100.0,"stable":2,"canary":6,"stable_circuit":"closed","canary_circuit":"closed","#),
+            "{}",
+            out
+        );
+        // 其他地址看不到管理接口，也不会转发给上游
+        let mut req = HttpRequest::try_from(&b"GET /_admin/canary HTTP/1.1\r\n\r\n"[..]).unwrap();
+        req.remote_addr = Some("10.0.0.9:5000".parse().unwrap());
+        let mut remote = Vec::new();
+        router.route(req, &mut remote);
+        assert!(remote.starts_with(b"HTTP/1.1 404"));
+        // 每个上游一个连接，之后都是复用
+        assert!(
+            out.ends_with(
+                r#""pool":{"opened":2,"reused":6,"waited":0,"timed_out":0,"active":0,"idle":2}}"#
+            ),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_bad_gateway() {
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = UpstreamConfig {
+            stable: Some(format!("http://{}", closed.local_addr().unwrap())),
+            ..UpstreamConfig::default()
+        };
+        drop(closed);
+        let proxy = ReverseProxy::new(
+            &config,
+            Arc::new(SystemClock),
+            Arc::new(SeededRandom::new(1)),
+        )
+        .unwrap();
+        let send = || {
+            let mut req = HttpRequest::try_from(&b"GET /x HTTP/1.1\r\n\r\n"[..]).unwrap();
+            proxy.before(&mut req).unwrap()
+        };
+        // 默认 10 次调用之后才计算失败率，之后熔断，不再连接上游
+        for _ in 0..10 {
+            assert_eq!(send().status().as_u16(), 502);
+        }
+        let resp = send();
+        assert_eq!(resp.status().as_u16(), 503);
+        assert_eq!(resp.header(names::RETRY_AFTER), Some("30"));
+        assert_eq!(proxy.status().stable_circuit, BreakerState::Open);
+        assert_eq!(proxy.status().stable, 11);
+
+        let config = UpstreamConfig {
+            prefix: "api".into(),
+            canary: Some("https://canary".into()),
+            canary_percent: -1.0,
+            ..config
+        };
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        let config = UpstreamConfig {
+            breaker: BreakerConfig {
+                probes: 0,
+                ..BreakerConfig::default()
+            },
+            ..config
+        };
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+    }
+}
@@ -0,0 +1,222 @@
+// API 版本：同一组接口的多个版本同时在线，破坏性的改动放进新版本，老客户端继续用旧版本
+//
+//     let api = ApiVersions::new()
+//         .version(1, Router::new("").get("/orders", list_orders_v1))
+//         .version(2, Router::new("").get("/orders", list_orders_v2))
+//         .default_version(DefaultVersion::Oldest);
+//     let router = Router::new("").versioned("/api/shipping", api);
+//
+// 版本按以下顺序确定：
+//     GET /api/shipping/v2/orders         路径里的版本，分发前去掉这一段
+//     Accept-Version: v2（或者 2）        请求头
+//     都没有时按 DefaultVersion
+// 不支持的版本回 406，body 里列出支持的版本；每个响应都带 API-Version 头部
+use crate::router::Router;
+use http::headers::names;
+use http::httprequest::{self, HttpRequest};
+use http::httpresponse::HttpResponse;
+use std::collections::BTreeMap;
+
+// 请求没有指定版本时用哪个
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefaultVersion {
+    // 最早的版本，新版本上线不影响没有升级的客户端
+    #[default]
+    Oldest,
+    Latest,
+    Fixed(u32),
+    // 必须指定版本，没有时回 406
+    Required,
+}
+
+#[derive(Default)]
+pub struct ApiVersions {
+    versions: BTreeMap<u32, Router>,
+    default: DefaultVersion,
+}
+
+// v2、V2、2 都是版本 2
+fn parse_version(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let digits = s.strip_prefix(['v', 'V']).unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+// 路径的第一段是 v<数字> 时返回版本号和去掉这一段之后的路径
+fn split_version(path: &str) -> Option<(u32, String)> {
+    let rest = path.strip_prefix('/')?;
+    let (segment, rest) = match rest.find(['/', '?']) {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    if !segment.starts_with(['v', 'V']) {
+        return None;
+    }
+    let version = parse_version(segment)?;
+    Some((version, format!("/{}", rest.trim_start_matches('/'))))
+}
+
+impl ApiVersions {
+    pub fn new() -> Self {
+        ApiVersions::default()
+    }
+    // 注册一个版本的路由，同一个版本再次注册时替换
+    pub fn version(mut self, version: u32, router: Router) -> Self {
+        self.versions.insert(version, router);
+        self
+    }
+    pub fn default_version(mut self, default: DefaultVersion) -> Self {
+        self.default = default;
+        self
+    }
+    // 支持的版本，从小到大
+    pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.versions.keys().copied()
+    }
+
+    pub fn routers(&self) -> impl Iterator<Item = (u32, &Router)> {
+        self.versions.iter().map(|(v, r)| (*v, r))
+    }
+
+    fn fallback(&self) -> Option<u32> {
+        match self.default {
+            DefaultVersion::Oldest => self.versions.keys().next().copied(),
+            DefaultVersion::Latest => self.versions.keys().next_back().copied(),
+            DefaultVersion::Fixed(v) => Some(v),
+            DefaultVersion::Required => None,
+        }
+    }
+
+    // 只看路径时会用哪个版本的路由，供 Router::priority_of 等不读请求头的地方使用
+    pub(crate) fn router_for_path(&self, path: &str) -> Option<(&Router, String)> {
+        let (version, rest) =
+            split_version(path).or_else(|| Some((self.fallback()?, path.to_string())))?;
+        Some((self.versions.get(&version)?, rest))
+    }
+
+    // req 的路径已经去掉了挂载前缀
+    pub(crate) fn respond(&self, req: &mut HttpRequest) -> HttpResponse<'static> {
+        // 路径里的版本优先；按请求头或默认版本选择时，同一个 URL 的响应随 Accept-Version 变化
+        // 按完整的请求目标拆分，查询字符串留给处理器
+        let httprequest::Resource::Path(target) = &req.resource;
+        let (requested, vary) = match split_version(target) {
+            Some((version, rest)) => {
+                let httprequest::Resource::Path(target) = &mut req.resource;
+                *target = rest;
+                (Some(version), false)
+            }
+            None => match req.header(names::ACCEPT_VERSION) {
+                Some(header) => match parse_version(header) {
+                    Some(version) => (Some(version), true),
+                    None => return self.not_acceptable(header),
+                },
+                None => (self.fallback(), true),
+            },
+        };
+        let Some((version, router)) = requested.and_then(|v| Some((v, self.versions.get(&v)?)))
+        else {
+            let requested = requested.map(|v| format!("v{}", v));
+            return self.not_acceptable(requested.as_deref().unwrap_or("none"));
+        };
+        let mut resp = router.respond(req);
+        let _ = resp.set_header(names::API_VERSION, format!("v{}", version));
+        if vary {
+            let _ = resp.append_header(names::VARY, names::ACCEPT_VERSION);
+        }
+        resp
+    }
+
+    fn not_acceptable(&self, requested: &str) -> HttpResponse<'static> {
+        let supported: Vec<String> = self.versions().map(|v| format!("v{}", v)).collect();
+        let body = format!(
+            "unsupported API version {}, supported: {}",
+            requested,
+            supported.join(", ")
+        );
+        // body 里有请求头的原文，不能当 HTML
+        HttpResponse::new("406", None, Some(body))
+            .with_header(names::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .expect("valid header")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        let api = || {
+            ApiVersions::new()
+                .version(
+                    1,
+                    Router::new("").get("/orders", |_req| {
+                        HttpResponse::new("200", None, Some("orders v1".into()))
+                    }),
+                )
+                .version(
+                    2,
+                    Router::new("").get("/orders", |req| {
+                        let page = req.query().get("page").map(|p| format!(" page={}", p));
+                        let body = format!("orders v2 {}{}", req.path(), page.unwrap_or_default());
+                        HttpResponse::new("200", None, Some(body))
+                    }),
+                )
+        };
+        let send = |router: &Router, head: &str| {
+            let mut out = Vec::new();
+            let raw = format!("{}\r\n\r\n", head);
+            router.route(HttpRequest::try_from(raw.as_bytes()).unwrap(), &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        let router = Router::new("").versioned("/api", api());
+        let out = send(&router, "GET /api/v2/orders?page=1 HTTP/1.1");
+        assert!(out.ends_with("orders v2 /orders page=1"), "{}", out);
+        assert!(out.contains("API-Version:v2\r\n"));
+        assert!(!out.contains("Vary"));
+        let out = send(&router, "GET /api/orders HTTP/1.1\r\nAccept-Version: 2");
+        assert!(out.ends_with("orders v2 /orders"));
+        assert!(out.contains("Vary:Accept-Version\r\n"));
+        // 默认最早的版本
+        let out = send(&router, "GET /api/orders HTTP/1.1");
+        assert!(out.ends_with("orders v1"));
+        assert!(out.contains("API-Version:v1\r\n"));
+        for head in [
+            "GET /api/v3/orders HTTP/1.1",
+            "GET /api/orders HTTP/1.1\r\nAccept-Version: v9",
+            "GET /api/orders HTTP/1.1\r\nAccept-Version: latest",
+        ] {
+            let out = send(&router, head);
+            assert!(out.starts_with("HTTP/1.1 406"), "{}", out);
+            assert!(out.ends_with("supported: v1, v2"));
+            assert!(out.contains("Content-Type:text/plain"));
+        }
+        // 版本之外的路径照常处理
+        let router = router.get("/status", |_req| {
+            HttpResponse::new("200", None, Some("ok".into()))
+        });
+        assert!(send(&router, "GET /status HTTP/1.1").ends_with("\r\n\r\nok"));
+
+        let latest =
+            Router::new("").versioned("/api", api().default_version(DefaultVersion::Latest));
+        assert!(send(&latest, "GET /api/orders HTTP/1.1").ends_with("orders v2 /orders"));
+        let required =
+            Router::new("").versioned("/api", api().default_version(DefaultVersion::Required));
+        let out = send(&required, "GET /api/orders HTTP/1.1");
+        assert!(out.starts_with("HTTP/1.1 406"));
+        assert!(out.contains("unsupported API version none"));
+    }
+
+    #[test]
+    fn test_split_version() {
+        assert_eq!(split_version("/v2/orders"), Some((2, "/orders".into())));
+        assert_eq!(split_version("/V10"), Some((10, "/".into())));
+        assert_eq!(split_version("/v1?x=1"), Some((1, "/?x=1".into())));
+        assert_eq!(split_version("/orders"), None);
+        assert_eq!(split_version("/2/orders"), None);
+        assert_eq!(split_version("/vip/orders"), None);
+    }
+}
@@ -1,12 +1,29 @@
 use core::str;
+use http::socks::Socks5Proxy;
 use std::{
+    env,
     io::{Read, Write},
     net::TcpStream,
+    process,
 };
 
 fn main() {
+    // 设置了 SOCKS5_PROXY（[user:pass@]host:port）时通过代理连接
+    // 格式不对时退出，不能悄悄绕过代理直接连接
+    let proxy = env::var("SOCKS5_PROXY").ok().map(|p| {
+        Socks5Proxy::parse(&p).unwrap_or_else(|| {
+            eprintln!(
+                "invalid SOCKS5_PROXY {:?}, expected [user:pass@]host:port",
+                p
+            );
+            process::exit(1);
+        })
+    });
     // 设置为可变
-    let mut stream = TcpStream::connect("localhost:3000").unwrap();
+    let mut stream = match proxy {
+        Some(proxy) => proxy.connect("localhost", 3000, None).unwrap(),
+        None => TcpStream::connect("localhost:3000").unwrap(),
+    };
     // write 需要可变引用：
     // 写操作可能会改变 TcpStream 的内部状态，比如更新缓冲区、改变连接状态等。
     // Rust 通过可变性来保证线程安全和防止数据竞争。
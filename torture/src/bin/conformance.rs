@@ -0,0 +1,437 @@
+// 协议一致性测试：对运行中的服务器发送一组精选的 HTTP/1.1 用例（请求行边界情况、
+// 头部折叠、连接语义……），按章节输出兼容性矩阵，用来跟踪解析器的成熟度
+// 用法：cargo run -p torture --bin conformance -- --addr 127.0.0.1:3000
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+#[command(
+    name = "conformance",
+    about = "Run HTTP/1.1 conformance checks and print a compliance matrix"
+)]
+struct Cli {
+    /// Address of the server under test
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    addr: String,
+    /// Seconds the server has to answer each check
+    #[arg(long, default_value_t = 5)]
+    deadline: u64,
+    /// Exit with failure when fewer than this percentage of checks pass
+    #[arg(long, default_value_t = 0)]
+    min_score: u32,
+    /// Only run checks whose section or name contains this text
+    #[arg(long)]
+    filter: Option<String>,
+}
+
+// 对响应的期望
+enum Expect {
+    // 状态码属于这一类，例如 2 表示 2xx
+    Class(u16),
+    // 状态码是其中之一
+    Status(&'static [u16]),
+    // 成功，并且带有这个头部（值不区分大小写地包含 value）
+    Header(&'static str, &'static str),
+    // 成功，并且没有 body
+    NoBody,
+    // Content-Length 与实际 body 长度一致
+    LengthMatches,
+}
+
+impl std::fmt::Display for Expect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expect::Class(c) => write!(f, "{}xx", c),
+            Expect::Status(codes) => {
+                let codes: Vec<String> = codes.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", codes.join("|"))
+            }
+            Expect::Header(name, value) => write!(f, "{}: {}", name, value),
+            Expect::NoBody => write!(f, "2xx, no body"),
+            Expect::LengthMatches => write!(f, "exact Content-Length"),
+        }
+    }
+}
+
+struct Check {
+    section: &'static str,
+    name: &'static str,
+    // 出处，方便对照规范
+    reference: &'static str,
+    request: &'static [u8],
+    expect: Expect,
+}
+
+const CHECKS: &[Check] = &[
+    // 请求行
+    Check {
+        section: "request-line",
+        name: "origin-form",
+        reference: "RFC 9112 3.2.1",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "origin-form with query",
+        reference: "RFC 9112 3.2.1",
+        request: b"GET /health?probe=1 HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "absolute-form",
+        reference: "RFC 9112 3.2.2",
+        request: b"GET http://conformance/health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "asterisk-form",
+        reference: "RFC 9112 3.2.4",
+        request: b"OPTIONS * HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "methods are case-sensitive",
+        reference: "RFC 9110 9.1",
+        request: b"get /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Status(&[400, 501]),
+    },
+    Check {
+        section: "request-line",
+        name: "unknown method",
+        reference: "RFC 9110 9.1",
+        request: b"BREW /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Status(&[400, 501]),
+    },
+    Check {
+        section: "request-line",
+        name: "double space",
+        reference: "RFC 9112 3",
+        request: b"GET  /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "request-line",
+        name: "missing version",
+        reference: "RFC 9112 3",
+        request: b"GET /health\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "request-line",
+        name: "HTTP/1.0",
+        reference: "RFC 9112 2.3",
+        request: b"GET /health HTTP/1.0\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "unsupported major version",
+        reference: "RFC 9110 15.6.6",
+        request: b"GET /health HTTP/2.0\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Status(&[505]),
+    },
+    Check {
+        section: "request-line",
+        name: "leading empty line",
+        reference: "RFC 9112 2.2",
+        request: b"\r\nGET /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "request-line",
+        name: "bare LF line endings",
+        reference: "RFC 9112 2.2",
+        request: b"GET /health HTTP/1.1\nHost: conformance\n\n",
+        expect: Expect::Class(2),
+    },
+    // 头部
+    Check {
+        section: "headers",
+        name: "names are case-insensitive",
+        reference: "RFC 9110 5.1",
+        request: b"GET /health HTTP/1.1\r\nhOsT: conformance\r\ncontent-length: 0\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "headers",
+        name: "host with port",
+        reference: "RFC 9110 7.2",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance:3000\r\n\r\n",
+        expect: Expect::Class(2),
+    },
+    Check {
+        section: "headers",
+        name: "obs-fold",
+        reference: "RFC 9112 5.2",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\nX-Folded: a\r\n b\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "whitespace before colon",
+        reference: "RFC 9112 5.1",
+        request: b"GET /health HTTP/1.1\r\nHost : conformance\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "field without colon",
+        reference: "RFC 9112 5",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\nbroken\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "missing Host",
+        reference: "RFC 9112 3.2",
+        request: b"GET /health HTTP/1.1\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "duplicate Host",
+        reference: "RFC 9112 3.2",
+        request: b"GET /health HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "invalid Content-Length",
+        reference: "RFC 9112 6.3",
+        request: b"POST /health HTTP/1.1\r\nHost: conformance\r\nContent-Length: abc\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    Check {
+        section: "headers",
+        name: "Content-Length with Transfer-Encoding",
+        reference: "RFC 9112 6.1",
+        request: b"POST /health HTTP/1.1\r\nHost: conformance\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        expect: Expect::Status(&[400]),
+    },
+    // 连接语义
+    Check {
+        section: "connection",
+        name: "close requested",
+        reference: "RFC 9112 9.6",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\nConnection: close\r\n\r\n",
+        expect: Expect::Header("Connection", "close"),
+    },
+    Check {
+        section: "connection",
+        name: "HTTP/1.1 defaults to keep-alive",
+        reference: "RFC 9112 9.3",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::Header("Connection", "keep-alive"),
+    },
+    Check {
+        section: "connection",
+        name: "HTTP/1.0 defaults to close",
+        reference: "RFC 9112 9.3",
+        request: b"GET /health HTTP/1.0\r\n\r\n",
+        expect: Expect::Header("Connection", "close"),
+    },
+    Check {
+        section: "connection",
+        name: "HEAD has no body",
+        reference: "RFC 9110 9.3.2",
+        request: b"HEAD /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::NoBody,
+    },
+    Check {
+        section: "connection",
+        name: "Content-Length matches body",
+        reference: "RFC 9112 6.3",
+        request: b"GET /health HTTP/1.1\r\nHost: conformance\r\n\r\n",
+        expect: Expect::LengthMatches,
+    },
+];
+
+struct Response {
+    status: u16,
+    head: String,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.trim())
+    }
+}
+
+// 发送请求，读到连接关闭、按 Content-Length 收完响应或者超时为止
+// 服务器支持长连接，不能只靠关闭连接判断响应结束
+fn exchange(addr: &str, request: &[u8], deadline: Duration) -> Result<Response, String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {}", e))?;
+    stream.set_write_timeout(Some(deadline)).ok();
+    stream
+        .write_all(request)
+        .map_err(|e| format!("write: {}", e))?;
+    let started = Instant::now();
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let left = deadline.saturating_sub(started.elapsed());
+        if left.is_zero() {
+            break;
+        }
+        stream.set_read_timeout(Some(left)).ok();
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                raw.extend_from_slice(&buf[..n]);
+                if response_complete(&raw, request.starts_with(b"HEAD ")) {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            // 超时或 reset，用已经收到的部分判断
+            Err(_) => break,
+        }
+    }
+    if raw.is_empty() {
+        return Err("no response".into());
+    }
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("response head is incomplete")?;
+    let head = String::from_utf8_lossy(&raw[..end]).into_owned();
+    let status = head
+        .lines()
+        .next()
+        .filter(|line| line.starts_with("HTTP/1."))
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("bad status line in {:?}", head))?;
+    Ok(Response {
+        status,
+        head,
+        body: raw[end + 4..].to_vec(),
+    })
+}
+
+// 头部带 Content-Length 并且 body 已经收齐；HEAD 的响应没有 body
+fn response_complete(raw: &[u8], head_only: bool) -> bool {
+    let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return false;
+    };
+    if head_only {
+        return true;
+    }
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let length = head.lines().skip(1).find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        k.trim()
+            .eq_ignore_ascii_case("Content-Length")
+            .then(|| v.trim().parse::<usize>().ok())?
+    });
+    length.is_some_and(|n| raw.len() >= end + 4 + n)
+}
+
+// 通过返回 Ok，不通过返回实际看到的情况
+fn evaluate(expect: &Expect, resp: &Response) -> Result<(), String> {
+    let success = resp.status / 100 == 2;
+    let pass = match expect {
+        Expect::Class(c) => resp.status / 100 == *c,
+        Expect::Status(codes) => codes.contains(&resp.status),
+        Expect::Header(name, value) => {
+            if !success {
+                false
+            } else {
+                match resp.header(name) {
+                    Some(v) => v.to_ascii_lowercase().contains(&value.to_ascii_lowercase()),
+                    None => return Err(format!("{} without {}", resp.status, name)),
+                }
+            }
+        }
+        Expect::NoBody => success && resp.body.is_empty(),
+        Expect::LengthMatches => match resp.header("Content-Length").map(str::parse::<usize>) {
+            Some(Ok(n)) if n == resp.body.len() => true,
+            Some(Ok(n)) => {
+                return Err(format!(
+                    "Content-Length {} but {} body bytes",
+                    n,
+                    resp.body.len()
+                ))
+            }
+            _ => return Err("missing or invalid Content-Length".into()),
+        },
+    };
+    if pass {
+        Ok(())
+    } else if matches!(expect, Expect::NoBody) && success {
+        Err(format!(
+            "{} with {} body bytes",
+            resp.status,
+            resp.body.len()
+        ))
+    } else {
+        Err(format!("got {}", resp.status))
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let deadline = Duration::from_secs(cli.deadline);
+    let checks: Vec<&Check> = CHECKS
+        .iter()
+        .filter(|c| match &cli.filter {
+            Some(f) => c.section.contains(f.as_str()) || c.name.contains(f.as_str()),
+            None => true,
+        })
+        .collect();
+    println!("conformance: {} checks against {}", checks.len(), cli.addr);
+    // 章节 -> (通过数, 总数)，按章节名排序输出
+    let mut matrix: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+    for check in &checks {
+        let result = exchange(&cli.addr, check.request, deadline)
+            .and_then(|resp| evaluate(&check.expect, &resp));
+        let entry = matrix.entry(check.section).or_default();
+        entry.1 += 1;
+        match result {
+            Ok(()) => {
+                entry.0 += 1;
+                println!(
+                    "PASS  {:<13} {:<38} {}",
+                    check.section, check.name, check.reference
+                );
+            }
+            Err(e) => println!(
+                "FAIL  {:<13} {:<38} {} (expected {}, {})",
+                check.section, check.name, check.reference, check.expect, e
+            ),
+        }
+    }
+    println!();
+    println!(
+        "{:<13} {:>6} {:>6} {:>6}",
+        "section", "pass", "total", "score"
+    );
+    let (mut passed, mut total) = (0, 0);
+    for (section, (p, t)) in &matrix {
+        println!("{:<13} {:>6} {:>6} {:>5}%", section, p, t, p * 100 / t);
+        passed += p;
+        total += t;
+    }
+    // 没有用例时（filter 没匹配到）算满分
+    let score = (passed * 100).checked_div(total).unwrap_or(100);
+    println!("{:<13} {:>6} {:>6} {:>5}%", "total", passed, total, score);
+    if score < cli.min_score {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
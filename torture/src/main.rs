@@ -0,0 +1,247 @@
+// 连接折磨测试：对一个运行中的服务器发送各种畸形流量（慢速头部、随机字节、
+// 超大头部、半关闭……），检查服务器在期限内要么回复要么关闭连接，并且始终存活
+// 用法：cargo run -p torture -- --addr 127.0.0.1:3000 --rounds 20 --seed 42
+// 协议一致性检查见 src/bin/conformance.rs
+use clap::Parser;
+use http::random::{RandomSource, SeededRandom};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+#[command(
+    name = "torture",
+    about = "Send pathological traffic to an HTTP server"
+)]
+struct Cli {
+    /// Address of the server under test
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    addr: String,
+    /// How many times to run the whole set of cases
+    #[arg(long, default_value_t = 5)]
+    rounds: u32,
+    /// Seed for the random payloads, printed so failures can be reproduced
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Seconds the server has to answer or close each connection
+    #[arg(long, default_value_t = 10)]
+    deadline: u64,
+    /// Path used for the liveness check after every case
+    #[arg(long, default_value = "/health")]
+    health_path: String,
+}
+
+// 服务器对一个连接的反应
+#[derive(Debug)]
+enum Outcome {
+    // 收到了响应，记录状态行
+    Answered(String),
+    // 没有任何数据就关闭了连接
+    Closed,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Answered(status) => write!(f, "answered {:?}", status),
+            Outcome::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+struct Case {
+    name: &'static str,
+    run: fn(&mut TcpStream, &dyn RandomSource) -> std::io::Result<()>,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "empty connection",
+        run: |_, _| Ok(()),
+    },
+    Case {
+        name: "slow headers",
+        run: |s, _| {
+            for b in b"GET / HTTP/1.1\r\nHost: torture\r\n\r\n" {
+                s.write_all(&[*b])?;
+                thread::sleep(Duration::from_millis(30));
+            }
+            Ok(())
+        },
+    },
+    Case {
+        name: "random bytes",
+        run: |s, rng| {
+            let mut buf = vec![0u8; 1 + (rng.next_u64() % 4096) as usize];
+            rng.fill_bytes(&mut buf);
+            s.write_all(&buf)
+        },
+    },
+    Case {
+        name: "invalid utf-8 in path",
+        run: |s, _| s.write_all(b"GET /\xff\xfe HTTP/1.1\r\n\r\n"),
+    },
+    Case {
+        name: "giant header",
+        run: |s, _| {
+            let value = "a".repeat(64 * 1024);
+            write!(s, "GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", value)
+        },
+    },
+    Case {
+        name: "many headers",
+        run: |s, _| {
+            let mut req = String::from("GET / HTTP/1.1\r\n");
+            for i in 0..2000 {
+                req.push_str(&format!("X-H{}: {}\r\n", i, i));
+            }
+            req.push_str("\r\n");
+            s.write_all(req.as_bytes())
+        },
+    },
+    Case {
+        name: "half close mid request line",
+        run: |s, _| {
+            s.write_all(b"GET /ind")?;
+            s.shutdown(Shutdown::Write)
+        },
+    },
+    Case {
+        name: "half close after headers",
+        run: |s, _| {
+            s.write_all(b"GET / HTTP/1.1\r\nHost: torture\r\n\r\n")?;
+            s.shutdown(Shutdown::Write)
+        },
+    },
+    Case {
+        name: "bare newlines",
+        run: |s, _| s.write_all(b"GET / HTTP/1.1\nHost: torture\n\n"),
+    },
+    Case {
+        name: "missing request target",
+        run: |s, _| s.write_all(b"GET\r\n\r\n"),
+    },
+    Case {
+        name: "huge content-length",
+        run: |s, _| s.write_all(b"POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\nabc"),
+    },
+    Case {
+        name: "negative content-length",
+        run: |s, _| s.write_all(b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+    },
+    Case {
+        name: "nul bytes",
+        run: |s, _| s.write_all(b"GET /\0 HTTP/1.1\r\nHost: \0\r\n\r\n"),
+    },
+];
+
+// 写完负载后等待服务器回复或关闭，超过 deadline 视为失败
+fn run_case(
+    addr: &str,
+    case: &Case,
+    rng: &dyn RandomSource,
+    deadline: Duration,
+) -> Result<Outcome, String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {}", e))?;
+    let started = Instant::now();
+    stream.set_write_timeout(Some(deadline)).ok();
+    // 服务器提前关闭连接时写会失败，这不算错误，继续看它回复了什么
+    let _ = (case.run)(&mut stream, rng);
+    let mut response = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let left = deadline.saturating_sub(started.elapsed());
+        if left.is_zero() {
+            return Err(format!("no answer or close within {:?}", deadline));
+        }
+        stream.set_read_timeout(Some(left)).ok();
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                response.extend_from_slice(&buf[..n]);
+                // 拿到状态行就够了
+                if response.windows(2).any(|w| w == b"\r\n") {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(format!("no answer or close within {:?}", deadline));
+            }
+            // 对端 reset 也算关闭
+            Err(_) => break,
+        }
+    }
+    if response.is_empty() {
+        return Ok(Outcome::Closed);
+    }
+    let line = String::from_utf8_lossy(&response);
+    let status = line.lines().next().unwrap_or("").to_string();
+    if !status.starts_with("HTTP/1.") {
+        return Err(format!("answered with garbage: {:?}", status));
+    }
+    Ok(Outcome::Answered(status))
+}
+
+// 每个用例之后确认服务器还活着
+fn check_alive(addr: &str, path: &str, deadline: Duration) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("server is down: {}", e))?;
+    stream.set_read_timeout(Some(deadline)).ok();
+    // 一次写完，避免请求被拆成多个包
+    let req = format!("GET {} HTTP/1.1\r\nHost: torture\r\n\r\n", path);
+    stream
+        .write_all(req.as_bytes())
+        .map_err(|e| format!("liveness write: {}", e))?;
+    let mut buf = [0u8; 64];
+    let n = stream
+        .read(&mut buf)
+        .map_err(|e| format!("liveness read: {}", e))?;
+    if buf[..n].starts_with(b"HTTP/1.") {
+        Ok(())
+    } else {
+        Err("liveness check got no response".into())
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let seed = cli.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    });
+    println!(
+        "torture: {} rounds against {}, seed {}",
+        cli.rounds, cli.addr, seed
+    );
+    let rng = SeededRandom::new(seed);
+    let deadline = Duration::from_secs(cli.deadline);
+    let mut failures = 0;
+    for round in 1..=cli.rounds {
+        for case in CASES {
+            let result = run_case(&cli.addr, case, &rng, deadline)
+                .and_then(|o| check_alive(&cli.addr, &cli.health_path, deadline).map(|_| o));
+            match result {
+                Ok(outcome) => println!("ok    [{}] {:<28} {}", round, case.name, outcome),
+                Err(e) => {
+                    failures += 1;
+                    println!("FAIL  [{}] {:<28} {}", round, case.name, e);
+                    // 服务器已经挂了，后面的用例没有意义
+                    if e.starts_with("server is down") {
+                        println!("torture: server died, seed {}", seed);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+        }
+    }
+    println!("torture: {} failures", failures);
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}